@@ -0,0 +1,116 @@
+// benches/risk_checks.rs
+//
+// Microbenchmarks for the pre-trade risk path. `validate_order` must stay under
+// `RISK_CHECK_BUDGET` (10µs) per call.
+
+use std::sync::Arc;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use parking_lot::RwLock;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use uuid::Uuid;
+
+use crypto_exchange::models::{Order, OrderType, Side, TimeInForce, Trade};
+use crypto_exchange::trading_engine::rate_limiter::{OrderRateLimiter, OrderRateLimiterConfig, RateLimitTier};
+use crypto_exchange::trading_engine::risk_management::{RiskManager, RiskManagerConfig};
+use crypto_exchange::kyc::IdentityTier;
+use crypto_exchange::utils::metrics::MetricsCollector;
+
+fn limit_order(side: Side, price: Decimal, quantity: Decimal) -> Arc<RwLock<Order>> {
+    Arc::new(RwLock::new(Order::new(
+        Uuid::new_v4(),
+        "BTC/USD".to_string(),
+        side,
+        OrderType::Limit,
+        Some(price),
+        quantity,
+        TimeInForce::GoodTillCancel,
+        None,
+    )))
+}
+
+/// A risk manager whose rate limiter and order count never interfere with the benchmark
+fn risk_manager(users: usize) -> (RiskManager, Vec<Uuid>) {
+    let mut limiter_config = OrderRateLimiterConfig::default();
+    limiter_config.identity_tiers.insert(
+        IdentityTier::Tier4,
+        RateLimitTier { refill_per_second: f64::MAX, burst: f64::MAX },
+    );
+    let limiter = Arc::new(OrderRateLimiter::new(limiter_config));
+
+    let mut risk_manager = RiskManager::with_rate_limiter(RiskManagerConfig::default(), Arc::clone(&limiter));
+    risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+    risk_manager.update_market_price("ETH/USD".to_string(), dec!(3000));
+
+    let user_ids: Vec<Uuid> = (0..users).map(|_| Uuid::new_v4()).collect();
+    for user_id in &user_ids {
+        risk_manager.register_user(*user_id);
+        limiter.set_user_tier(*user_id, IdentityTier::Tier4, 0);
+        risk_manager.get_user_profile(user_id).unwrap().write().max_order_count = usize::MAX;
+        // Setting a limit also republishes the snapshot with the raised order count
+        risk_manager.set_user_max_position_size(user_id, "BTC/USD".to_string(), dec!(100)).unwrap();
+    }
+
+    (risk_manager, user_ids)
+}
+
+fn bench_validate_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("risk_checks");
+
+    for &users in &[1usize, 10_000] {
+        let (risk_manager, user_ids) = risk_manager(users);
+        let user_id = user_ids[users / 2];
+
+        group.bench_function(format!("validate_order/accepted/{}_users", users), |b| {
+            b.iter_batched(
+                || limit_order(Side::Buy, dec!(50000), dec!(0.001)),
+                |order| black_box(risk_manager.validate_order(order, &user_id).unwrap()),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    // Rejections exit early and should be well inside the budget
+    let (risk_manager, user_ids) = risk_manager(1);
+    group.bench_function("validate_order/rejected_tick_size", |b| {
+        b.iter_batched(
+            || limit_order(Side::Buy, dec!(50000.1), dec!(0.01)),
+            |order| black_box(risk_manager.validate_order(order, &user_ids[0]).unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+
+    // Metrics recording is part of the budget in production
+    let (risk_manager, user_ids) = risk_manager(1);
+    let risk_manager = risk_manager.with_metrics(Arc::new(MetricsCollector::new("bench")));
+    group.bench_function("validate_order/with_metrics", |b| {
+        b.iter_batched(
+            || limit_order(Side::Buy, dec!(50000), dec!(0.001)),
+            |order| black_box(risk_manager.validate_order(order, &user_ids[0]).unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_snapshot_refresh(c: &mut Criterion) {
+    // Per-trade bookkeeping runs off the order path, but must keep up with the trade rate
+    let (risk_manager, _) = risk_manager(1);
+    c.bench_function("risk_checks/process_trade", |b| {
+        b.iter(|| {
+            let trade = Trade::new(
+                "BTC/USD".to_string(),
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                dec!(50000),
+                dec!(0.001),
+                Side::Buy,
+            );
+            black_box(risk_manager.process_trade(&trade).unwrap())
+        })
+    });
+}
+
+criterion_group!(benches, bench_validate_order, bench_snapshot_refresh);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+-- Trade history and persisted candles for market data range queries
+
+DO $$ BEGIN
+    CREATE TYPE order_side AS ENUM ('buy', 'sell');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS trades (
+    id UUID PRIMARY KEY,
+    symbol VARCHAR(20) NOT NULL,
+    price NUMERIC(28, 8) NOT NULL,
+    quantity NUMERIC(28, 8) NOT NULL,
+    side order_side NOT NULL,
+    order_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    executed_at TIMESTAMPTZ NOT NULL,
+    fee NUMERIC(28, 8) NOT NULL DEFAULT 0
+);
+
+-- Keyset pagination of a symbol's history by time, then id
+CREATE INDEX IF NOT EXISTS trades_symbol_time ON trades (symbol, executed_at, id);
+
+CREATE TABLE IF NOT EXISTS candles (
+    symbol VARCHAR(20) NOT NULL,
+    interval VARCHAR(4) NOT NULL,
+    open_time TIMESTAMPTZ NOT NULL,
+    close_time TIMESTAMPTZ NOT NULL,
+    open NUMERIC(28, 8) NOT NULL,
+    high NUMERIC(28, 8) NOT NULL,
+    low NUMERIC(28, 8) NOT NULL,
+    close NUMERIC(28, 8) NOT NULL,
+    volume NUMERIC(28, 8) NOT NULL,
+    quote_volume NUMERIC(38, 8) NOT NULL,
+    trade_count BIGINT NOT NULL,
+    PRIMARY KEY (symbol, interval, open_time)
+);
@@ -0,0 +1,5 @@
+-- Per-symbol trade ids published to market data clients, continued after restarts
+
+ALTER TABLE trades ADD COLUMN IF NOT EXISTS sequence BIGINT;
+
+CREATE INDEX IF NOT EXISTS trades_symbol_sequence ON trades (symbol, sequence);
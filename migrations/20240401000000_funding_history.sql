@@ -0,0 +1,23 @@
+-- Settled perpetual funding intervals and the payments made at each
+
+CREATE TABLE IF NOT EXISTS funding_settlements (
+    contract_id VARCHAR(40) NOT NULL,
+    funding_time TIMESTAMPTZ NOT NULL,
+    settled_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- One settlement per contract and funding time
+    PRIMARY KEY (contract_id, funding_time)
+);
+
+CREATE TABLE IF NOT EXISTS funding_payments (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    contract_id VARCHAR(40) NOT NULL,
+    position_id UUID NOT NULL,
+    funding_rate NUMERIC(28, 12) NOT NULL,
+    payment_amount NUMERIC(28, 8) NOT NULL,
+    funding_time TIMESTAMPTZ NOT NULL,
+    FOREIGN KEY (contract_id, funding_time) REFERENCES funding_settlements (contract_id, funding_time)
+);
+
+CREATE INDEX IF NOT EXISTS funding_payments_user ON funding_payments (user_id, contract_id, funding_time);
+CREATE INDEX IF NOT EXISTS funding_payments_contract ON funding_payments (contract_id, funding_time);
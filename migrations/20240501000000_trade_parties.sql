@@ -0,0 +1,7 @@
+-- Both parties' rows of a trade, and at most one public trade per symbol and trade id
+
+ALTER TABLE trades ADD COLUMN IF NOT EXISTS is_maker BOOLEAN NOT NULL DEFAULT false;
+
+-- The maker's row repeats the trade id of the taker's
+DROP INDEX IF EXISTS trades_symbol_sequence;
+CREATE UNIQUE INDEX IF NOT EXISTS trades_symbol_sequence ON trades (symbol, sequence) WHERE NOT is_maker;
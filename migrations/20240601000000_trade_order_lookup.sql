@@ -0,0 +1,3 @@
+-- An order's fills, in execution order
+
+CREATE INDEX IF NOT EXISTS trades_order_time ON trades (order_id, executed_at, id);
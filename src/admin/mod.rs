@@ -1,5 +1,6 @@
 
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
@@ -12,6 +13,7 @@ use async_trait::async_trait;
 use crate::kyc::{UserProfile, UserStatus, IdentityTier, VerificationStatus, DocumentType, RiskLevel};
 use crate::kyc::{UserDocument, VerificationAttempt, AmlCheckResult, AmlResult, AmlMatchStatus};
 use crate::trading_engine::matching_engine::{Side, OrderType, Order, OrderStatus};
+use crate::trading_engine::rate_limiter::OrderRateLimiter;
 
 // Type definitions
 pub type UserId = Uuid;
@@ -248,6 +250,8 @@ pub struct SecurityIncident {
     pub indicators: Vec<String>,
     pub action_taken: Option<String>,
     pub related_actions: Vec<AdminActionId>,
+    #[serde(default)]
+    pub evidence: serde_json::Value,
 }
 
 // Incident severity
@@ -280,6 +284,7 @@ pub enum IncidentType {
     SuspiciousActivity,
     SystemAnomaly,
     ComplianceViolation,
+    MarketAbuse,
     Other,
 }
 
@@ -345,6 +350,19 @@ pub struct FeeTier {
     pub description: String,
 }
 
+impl FeeSchedule {
+    // Highest tier level whose volume and holding thresholds are met; 0 if none
+    pub fn tier_level_for(&self, volume_30d: Decimal, token_holdings: Decimal) -> i32 {
+        self.tier_levels
+            .iter()
+            .filter(|tier| volume_30d >= tier.min_30d_volume)
+            .filter(|tier| tier.min_token_holdings.map_or(true, |min| token_holdings >= min))
+            .map(|tier| tier.tier_level)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
 // Report type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReportType {
@@ -1038,6 +1056,52 @@ impl SecurityIncidentStore {
     }
 }
 
+// User fee tier store
+pub struct UserFeeTierStore {
+    levels: RwLock<HashMap<UserId, i32>>,
+    // Order-entry limits scale with the VIP level when set
+    rate_limiter: Option<Arc<OrderRateLimiter>>,
+}
+
+impl UserFeeTierStore {
+    pub fn new() -> Self {
+        UserFeeTierStore {
+            levels: RwLock::new(HashMap::new()),
+            rate_limiter: None,
+        }
+    }
+    
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<OrderRateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+    
+    pub async fn get_level(&self, user_id: &UserId) -> i32 {
+        self.levels.read().await.get(user_id).copied().unwrap_or(0)
+    }
+    
+    pub async fn set_level(&self, user_id: UserId, level: i32) {
+        self.levels.write().await.insert(user_id, level);
+        
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.set_vip_level(user_id, level);
+        }
+    }
+    
+    // Re-rate a user from their trailing volume and token holdings
+    pub async fn assign_from_schedule(
+        &self,
+        schedule: &FeeSchedule,
+        user_id: UserId,
+        volume_30d: Decimal,
+        token_holdings: Decimal,
+    ) -> i32 {
+        let level = schedule.tier_level_for(volume_30d, token_holdings);
+        self.set_level(user_id, level).await;
+        level
+    }
+}
+
 // Trading pair store
 pub struct TradingPairStore {
     pairs: RwLock<Vec<TradingPair>>,
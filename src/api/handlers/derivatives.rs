@@ -0,0 +1,91 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::trading_engine::derivatives::DerivativesEngine;
+use crate::trading_engine::derivatives::vol_surface::VolSurfaceService;
+use super::user::TokenClaims;
+use super::path_symbol;
+
+// Get the latest volatility surface for an underlying
+pub async fn get_vol_surface(
+    path: web::Path<String>,
+    vol_surfaces: web::Data<Arc<VolSurfaceService>>,
+) -> impl Responder {
+    let underlying = path_symbol(&path.into_inner());
+
+    match vol_surfaces.get_surface(&underlying).await {
+        Some(surface) => HttpResponse::Ok().json(surface),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No volatility surface for {}", underlying)
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImpliedVolQuery {
+    pub strike: f64,
+    pub expiry: DateTime<Utc>,
+}
+
+// Get the surface's implied volatility at a strike and expiry
+pub async fn get_implied_vol(
+    path: web::Path<String>,
+    query: web::Query<ImpliedVolQuery>,
+    vol_surfaces: web::Data<Arc<VolSurfaceService>>,
+) -> impl Responder {
+    let underlying = path_symbol(&path.into_inner());
+
+    let surface = match vol_surfaces.get_surface(&underlying).await {
+        Some(surface) => surface,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("No volatility surface for {}", underlying)
+            }));
+        }
+    };
+
+    match surface.implied_vol(query.strike, query.expiry) {
+        Some(volatility) => HttpResponse::Ok().json(serde_json::json!({
+            "underlying": underlying,
+            "strike": query.strike,
+            "expiry": query.expiry,
+            "implied_volatility": volatility,
+            "built_at": surface.built_at,
+            "arbitrage_free": surface.is_arbitrage_free()
+        })),
+        None => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Strike must be positive and expiry after the surface build time"
+        })),
+    }
+}
+
+// Get the authenticated user's risk limit tier on a contract
+pub async fn get_risk_limits(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    derivatives: web::Data<Arc<DerivativesEngine>>,
+) -> impl Responder {
+    let user_id = http_req
+        .extensions()
+        .get::<TokenClaims>()
+        .and_then(|claims| Uuid::parse_str(&claims.sub).ok());
+    
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Authentication required"
+            }));
+        }
+    };
+    
+    match derivatives.risk_limit_status(&user_id, &path.into_inner()).await {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string()
+        })),
+    }
+}
@@ -1,9 +1,37 @@
 use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
 
-// Get all markets
-pub async fn get_markets() -> impl Responder {
-    // Implementation will go here
-    HttpResponse::Ok().json(vec![])
+use crate::admin::TradingPairStore;
+use crate::db::models::{OrderSide, Trade};
+use crate::db::repositories::trade_repository::{TradeCursor, TradeHistoryQuery};
+use crate::trading_engine::market_data::MarketDataService;
+use crate::trading_engine::market_data::candles::CandleInterval;
+use crate::trading_engine::market_data::depth::DepthFeed;
+use crate::trading_engine::market_data::l3::L3Feed;
+use crate::trading_engine::market_data::liquidity::LiquidityAnalytics;
+use crate::trading_engine::market_data::summary;
+use super::path_symbol;
+
+// Get every listed market with its trading rules and mini ticker
+pub async fn get_markets(
+    trading_pairs: web::Data<Arc<TradingPairStore>>,
+    market_data: web::Data<Arc<MarketDataService>>,
+) -> impl Responder {
+    let pairs = match trading_pairs.get_all_pairs().await {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+    };
+
+    HttpResponse::Ok().json(summary::market_summaries(&pairs, &market_data).await)
 }
 
 // Get specific market details
@@ -13,28 +41,199 @@ pub async fn get_market_details(path: web::Path<String>) -> impl Responder {
 }
 
 // Get market ticker
-pub async fn get_ticker(path: web::Path<String>) -> impl Responder {
-    // Implementation will go here
-    HttpResponse::Ok().json(serde_json::json!({}))
+pub async fn get_ticker(
+    path: web::Path<String>,
+    market_data: web::Data<Arc<MarketDataService>>,
+) -> impl Responder {
+    let symbol = path_symbol(&path.into_inner());
+
+    match market_data.ticker(&symbol).await {
+        Some(ticker) => HttpResponse::Ok().json(ticker),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No trades for {}", symbol)
+        })),
+    }
 }
 
-// Get order book
-pub async fn get_orderbook(path: web::Path<String>) -> impl Responder {
-    // Implementation will go here
-    HttpResponse::Ok().json(serde_json::json!({}))
+#[derive(Debug, Deserialize)]
+pub struct OrderBookQuery {
+    pub limit: Option<usize>,
 }
 
-// Get trades
-pub async fn get_trades(path: web::Path<String>) -> impl Responder {
-    // Implementation will go here
-    HttpResponse::Ok().json(vec![])
+// Get order book snapshot, with the update id the WebSocket depth feed continues from
+pub async fn get_orderbook(
+    path: web::Path<String>,
+    query: web::Query<OrderBookQuery>,
+    depth_feed: web::Data<Arc<DepthFeed>>,
+) -> impl Responder {
+    let symbol = path_symbol(&path.into_inner());
+
+    match depth_feed.snapshot(&symbol, query.limit.unwrap_or(100)).await {
+        Some(snapshot) => HttpResponse::Ok().json(snapshot),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No order book for {}", symbol)
+        })),
+    }
+}
+
+// Get every resting order in queue order, with the sequence the WebSocket L3 feed continues from
+pub async fn get_orderbook_l3(
+    path: web::Path<String>,
+    l3_feed: web::Data<Arc<L3Feed>>,
+) -> impl Responder {
+    let symbol = path_symbol(&path.into_inner());
+
+    match l3_feed.snapshot(&symbol).await {
+        Some(snapshot) => HttpResponse::Ok().json(snapshot),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No order book for {}", symbol)
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LiquidityQuery {
+    /// Base quantity to price the cost of trading on each side
+    pub size: Option<String>,
+    /// Comma-separated distances from the mid, in percent, to report depth within
+    pub percents: Option<String>,
+}
+
+// Get depth near the mid, book imbalance, cost to trade a size and the rolling realized spread
+pub async fn get_liquidity(
+    path: web::Path<String>,
+    query: web::Query<LiquidityQuery>,
+    analytics: web::Data<Arc<LiquidityAnalytics>>,
+) -> impl Responder {
+    let symbol = path_symbol(&path.into_inner());
+
+    let size = match query.size.as_deref().map(Decimal::from_str) {
+        None => None,
+        Some(Ok(size)) if size > Decimal::ZERO => Some(size),
+        Some(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "size must be a positive number"
+            }));
+        }
+    };
+    let percents = match query.percents.as_deref()
+        .map(|percents| percents.split(',').map(|p| Decimal::from_str(p.trim())).collect::<Result<Vec<_>, _>>())
+        .transpose()
+    {
+        Ok(percents) => percents.unwrap_or_default(),
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "percents must be comma-separated numbers"
+            }));
+        }
+    };
+
+    match analytics.snapshot(&symbol, size, &percents).await {
+        Some(snapshot) => HttpResponse::Ok().json(snapshot),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No order book for {}", symbol)
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TradeQuery {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub from_id: Option<Uuid>,
+}
+
+// A stored trade as the public sees it, without the parties' orders or fees
+#[derive(Debug, Serialize)]
+pub struct PublicTradeRecord {
+    pub id: Uuid,
+    pub sequence: Option<i64>,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub side: OrderSide,
+    pub executed_at: DateTime<Utc>,
+}
+
+impl From<Trade> for PublicTradeRecord {
+    fn from(trade: Trade) -> Self {
+        PublicTradeRecord {
+            id: trade.id,
+            sequence: trade.sequence,
+            price: trade.price,
+            quantity: trade.quantity,
+            side: trade.side,
+            executed_at: trade.executed_at,
+        }
+    }
+}
+
+// Get trade history, oldest first; pass `next_cursor` back as `cursor` for the next page
+pub async fn get_trades(
+    path: web::Path<String>,
+    query: web::Query<TradeQuery>,
+    market_data: web::Data<Arc<MarketDataService>>,
+) -> impl Responder {
+    let symbol = path_symbol(&path.into_inner());
+
+    let after = match query.cursor.as_deref().map(str::parse::<TradeCursor>).transpose() {
+        Ok(after) => after,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            }));
+        }
+    };
+    let history_query = TradeHistoryQuery {
+        start: query.start,
+        end: query.end,
+        after,
+        from_id: query.from_id,
+        limit: query.limit.unwrap_or(500),
+    };
+
+    match market_data.trade_history(&symbol, &history_query).await {
+        Ok(page) => HttpResponse::Ok().json(serde_json::json!({
+            "trades": page.trades.into_iter().map(PublicTradeRecord::from).collect::<Vec<_>>(),
+            "next_cursor": page.next_cursor.map(|cursor| cursor.to_string()),
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CandleQuery {
+    pub interval: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
 }
 
 // Get candles
 pub async fn get_candles(
     path: web::Path<String>,
-    query: web::Query<serde_json::Value>,
+    query: web::Query<CandleQuery>,
+    market_data: web::Data<Arc<MarketDataService>>,
 ) -> impl Responder {
-    // Implementation will go here
-    HttpResponse::Ok().json(vec![])
+    let symbol = path_symbol(&path.into_inner());
+
+    let interval = match query.interval.as_deref().unwrap_or("1m").parse::<CandleInterval>() {
+        Ok(interval) => interval,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            }));
+        }
+    };
+    let limit = query.limit.unwrap_or(market_data.config().max_query_candles);
+
+    match market_data.candles(&symbol, interval, query.start, query.end, limit).await {
+        Some(candles) => HttpResponse::Ok().json(candles),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No trades for {}", symbol)
+        })),
+    }
 }
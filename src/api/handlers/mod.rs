@@ -2,3 +2,9 @@ pub mod user;
 pub mod market;
 pub mod order;
 pub mod wallet;
+pub mod derivatives;
+
+// Symbols are addressed as BASE-QUOTE in URLs, e.g. BTC-USDT for BTC/USDT
+pub(crate) fn path_symbol(path: &str) -> String {
+    path.replacen('-', "/", 1).to_uppercase()
+}
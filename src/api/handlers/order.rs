@@ -1,9 +1,54 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::trading_engine::rate_limiter::{self, OrderAction, OrderRateLimiter, RateLimitDecision};
+use super::user::TokenClaims;
+
+// Apply the order-entry rate limit for the authenticated user.
+// Returns a 429 response with a retry-after hint when throttled.
+fn check_rate_limit(
+    http_req: &HttpRequest,
+    rate_limiter: &OrderRateLimiter,
+    action: OrderAction,
+) -> Option<HttpResponse> {
+    let user_id = http_req
+        .extensions()
+        .get::<TokenClaims>()
+        .and_then(|claims| Uuid::parse_str(&claims.sub).ok());
+    
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => {
+            return Some(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Authentication required"
+            })));
+        }
+    };
+    
+    match rate_limiter.check(&user_id, action) {
+        RateLimitDecision::Allowed { .. } => None,
+        RateLimitDecision::Throttled { retry_after } => {
+            Some(HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", rate_limiter::retry_after_secs(retry_after).to_string()))
+                .json(serde_json::json!({
+                    "error": "Order rate limit exceeded",
+                    "retry_after_ms": rate_limiter::retry_after_millis(retry_after)
+                })))
+        }
+    }
+}
 
 // Create order
 pub async fn create_order(
+    http_req: HttpRequest,
     req: web::Json<serde_json::Value>,
+    rate_limiter: web::Data<Arc<OrderRateLimiter>>,
 ) -> impl Responder {
+    if let Some(response) = check_rate_limit(&http_req, &rate_limiter, OrderAction::NewOrder) {
+        return response;
+    }
+    
     // Implementation will go here
     HttpResponse::Ok().json(serde_json::json!({}))
 }
@@ -21,7 +66,15 @@ pub async fn get_order(path: web::Path<String>) -> impl Responder {
 }
 
 // Cancel order
-pub async fn cancel_order(path: web::Path<String>) -> impl Responder {
+pub async fn cancel_order(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    rate_limiter: web::Data<Arc<OrderRateLimiter>>,
+) -> impl Responder {
+    if let Some(response) = check_rate_limit(&http_req, &rate_limiter, OrderAction::CancelOrder) {
+        return response;
+    }
+    
     // Implementation will go here
     HttpResponse::Ok().json(serde_json::json!({
         "success": true
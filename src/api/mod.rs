@@ -5,10 +5,75 @@ pub mod websocket;
 
 use actix_web::{web, App, HttpServer};
 use actix_cors::Cors;
+use std::sync::Arc;
 use middleware::{auth::AuthenticationMiddleware, logging::RequestLogger};
 
-pub async fn start_api_server(config: crate::config::Config) -> std::io::Result<()> {
-    let server_address = format!("{}:{}", config.api.host, config.api.port);
+use crate::admin::{TradingPairStore, UserFeeTierStore};
+use crate::db::{
+    AccountRepository, AssetRepository, DepositRepository, OrderRepository, TradeRepository,
+    TradingPairRepository, UserRepository, WalletRepository, WithdrawalRepository,
+};
+use crate::security::AuthService;
+use crate::kyc::KycManager;
+use crate::trading_engine::rate_limiter::OrderRateLimiter;
+use crate::trading_engine::risk_management::RiskManager;
+use crate::trading_engine::derivatives::DerivativesEngine;
+use crate::trading_engine::derivatives::vol_surface::VolSurfaceService;
+use crate::trading_engine::market_data::MarketDataService;
+use crate::trading_engine::market_data::depth::DepthFeed;
+use crate::trading_engine::market_data::l3::L3Feed;
+use crate::trading_engine::market_data::liquidity::LiquidityAnalytics;
+use crate::trading_engine::market_data::trades::TradeFeed;
+use websocket::channels::ChannelManager;
+
+/// Database repositories, when the engine runs against a database
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<UserRepository>,
+    pub assets: Arc<AssetRepository>,
+    pub trading_pairs: Arc<TradingPairRepository>,
+    pub orders: Arc<OrderRepository>,
+    pub trades: Arc<TradeRepository>,
+    pub wallets: Arc<WalletRepository>,
+    pub accounts: Arc<AccountRepository>,
+    pub deposits: Arc<DepositRepository>,
+    pub withdrawals: Arc<WithdrawalRepository>,
+}
+
+/// Engine services and market data feeds shared by every request handler
+#[derive(Clone)]
+pub struct AppServices {
+    pub auth: Arc<AuthService>,
+    /// Not set for replays, which never touch the database
+    pub repositories: Option<Repositories>,
+    /// Order-entry throttle shared by REST, WebSocket and the risk manager
+    pub rate_limiter: Arc<OrderRateLimiter>,
+    /// Pre-trade checks, charging the same rate limit for internally generated orders
+    pub risk_manager: Arc<RiskManager>,
+    /// Identity verification; moves users between rate limit tiers as they verify
+    pub kyc: Arc<KycManager>,
+    /// VIP fee levels, which also scale users' rate limits
+    pub fee_tiers: Arc<UserFeeTierStore>,
+    pub derivatives: Arc<DerivativesEngine>,
+    pub vol_surfaces: Arc<VolSurfaceService>,
+    pub market_data: Arc<MarketDataService>,
+    pub depth_feed: Arc<DepthFeed>,
+    pub l3_feed: Arc<L3Feed>,
+    pub trading_pairs: Arc<TradingPairStore>,
+    pub liquidity: Arc<LiquidityAnalytics>,
+    pub trade_feed: Arc<TradeFeed>,
+}
+
+pub async fn start_api_server(config: crate::config::Config, services: AppServices) -> std::io::Result<()> {
+    let server_address = format!("{}:{}", config.api_host, config.api_port);
+    
+    // WebSocket subscriptions are shared by every worker so publishers reach all sessions
+    let channel_manager = ChannelManager::new();
+    websocket::spawn_depth_relay(channel_manager.clone(), &services.depth_feed);
+    websocket::spawn_l3_relay(channel_manager.clone(), &services.l3_feed);
+    websocket::spawn_kline_relay(channel_manager.clone(), &services.market_data);
+    websocket::spawn_mini_ticker_publisher(channel_manager.clone(), &services.market_data, &services.trading_pairs);
+    websocket::spawn_trade_relay(channel_manager.clone(), &services.trade_feed);
     
     println!("Starting API server on {}", server_address);
     
@@ -24,6 +89,32 @@ pub async fn start_api_server(config: crate::config::Config) -> std::io::Result<
             .wrap(cors)
             .wrap(RequestLogger::new())
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(Arc::clone(&services.auth)))
+            .app_data(web::Data::new(Arc::clone(&services.rate_limiter)))
+            .app_data(web::Data::new(Arc::clone(&services.risk_manager)))
+            .app_data(web::Data::new(Arc::clone(&services.kyc)))
+            .app_data(web::Data::new(Arc::clone(&services.fee_tiers)))
+            .app_data(web::Data::new(Arc::clone(&services.derivatives)))
+            .app_data(web::Data::new(Arc::clone(&services.vol_surfaces)))
+            .app_data(web::Data::new(Arc::clone(&services.market_data)))
+            .app_data(web::Data::new(Arc::clone(&services.depth_feed)))
+            .app_data(web::Data::new(Arc::clone(&services.l3_feed)))
+            .app_data(web::Data::new(Arc::clone(&services.trading_pairs)))
+            .app_data(web::Data::new(Arc::clone(&services.liquidity)))
+            .app_data(web::Data::new(channel_manager.clone()))
+            .configure(|cfg| {
+                if let Some(repositories) = &services.repositories {
+                    cfg.app_data(web::Data::new(Arc::clone(&repositories.users)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.assets)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.trading_pairs)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.orders)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.trades)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.wallets)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.accounts)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.deposits)))
+                        .app_data(web::Data::new(Arc::clone(&repositories.withdrawals)));
+                }
+            })
             // Register API routes
             .configure(routes::register_routes)
     })
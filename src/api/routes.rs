@@ -1,5 +1,5 @@
 use actix_web::web;
-use super::handlers::{user, market, order, wallet};
+use super::handlers::{user, market, order, wallet, derivatives};
 
 pub fn register_routes(cfg: &mut web::ServiceConfig) {
     // API version prefix - all routes will be under /api/v1
@@ -32,6 +32,8 @@ pub fn register_routes(cfg: &mut web::ServiceConfig) {
                     .route("/{symbol}", web::get().to(market::get_market_details))
                     .route("/{symbol}/ticker", web::get().to(market::get_ticker))
                     .route("/{symbol}/orderbook", web::get().to(market::get_orderbook))
+                    .route("/{symbol}/orderbook/l3", web::get().to(market::get_orderbook_l3))
+                    .route("/{symbol}/liquidity", web::get().to(market::get_liquidity))
                     .route("/{symbol}/trades", web::get().to(market::get_trades))
                     .route("/{symbol}/candles", web::get().to(market::get_candles))
             )
@@ -53,6 +55,13 @@ pub fn register_routes(cfg: &mut web::ServiceConfig) {
                     .route("/withdraw", web::post().to(wallet::create_withdrawal))
                     .route("/estimate-withdrawal-fee", web::post().to(wallet::estimate_withdrawal_fee))
             )
+            // Derivatives routes
+            .service(
+                web::scope("/derivatives")
+                    .route("/vol-surface/{underlying}", web::get().to(derivatives::get_vol_surface))
+                    .route("/vol-surface/{underlying}/iv", web::get().to(derivatives::get_implied_vol))
+                    .route("/risk-limits/{contract_id}", web::get().to(derivatives::get_risk_limits))
+            )
             // WebSocket endpoint
             .route("/ws", web::get().to(super::websocket::ws_handler))
     );
@@ -1,16 +1,22 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use actix::prelude::SendError;
 use actix::Addr;
+use log::warn;
+use uuid::Uuid;
 
-use super::handlers::WebSocketConnection;
+use super::codec::WireFormat;
+use super::handlers::{WebSocketConnection, WebSocketResponse, WebSocketSession};
 
 // Channel types
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ChannelType {
     OrderBook(String),  // Symbol
+    OrderBookL3(String), // Symbol
     Trades(String),     // Symbol
     Ticker(String),     // Symbol
     Kline(String, String), // Symbol, Interval
+    MiniTickers,        // Every market
     UserOrders,
     UserTrades,
     UserWallet,
@@ -28,6 +34,13 @@ impl ChannelType {
                     None
                 }
             },
+            "orderbook_l3" => {
+                if parts.len() >= 2 {
+                    Some(ChannelType::OrderBookL3(parts[1].to_string()))
+                } else {
+                    None
+                }
+            },
             "trades" => {
                 if parts.len() >= 2 {
                     Some(ChannelType::Trades(parts[1].to_string()))
@@ -49,6 +62,7 @@ impl ChannelType {
                     None
                 }
             },
+            "mini_tickers" => Some(ChannelType::MiniTickers),
             "user_orders" => Some(ChannelType::UserOrders),
             "user_trades" => Some(ChannelType::UserTrades),
             "user_wallet" => Some(ChannelType::UserWallet),
@@ -59,9 +73,11 @@ impl ChannelType {
     pub fn to_string(&self) -> String {
         match self {
             ChannelType::OrderBook(symbol) => format!("orderbook:{}", symbol),
+            ChannelType::OrderBookL3(symbol) => format!("orderbook_l3:{}", symbol),
             ChannelType::Trades(symbol) => format!("trades:{}", symbol),
             ChannelType::Ticker(symbol) => format!("ticker:{}", symbol),
             ChannelType::Kline(symbol, interval) => format!("kline:{}:{}", symbol, interval),
+            ChannelType::MiniTickers => "mini_tickers".to_string(),
             ChannelType::UserOrders => "user_orders".to_string(),
             ChannelType::UserTrades => "user_trades".to_string(),
             ChannelType::UserWallet => "user_wallet".to_string(),
@@ -69,9 +85,16 @@ impl ChannelType {
     }
 }
 
+// A subscribed session, the encoding it negotiated and its user, if authenticated
+struct Subscriber {
+    addr: Addr<WebSocketSession>,
+    wire_format: WireFormat,
+    user_id: Option<Uuid>,
+}
+
 // Manages channel subscriptions
 pub struct ChannelManager {
-    channels: Arc<Mutex<HashMap<ChannelType, Vec<Addr<WebSocketConnection>>>>>,
+    channels: Arc<Mutex<HashMap<ChannelType, Vec<Subscriber>>>>,
 }
 
 impl ChannelManager {
@@ -81,18 +104,18 @@ impl ChannelManager {
         }
     }
     
-    pub fn subscribe(&self, channel: ChannelType, addr: Addr<WebSocketConnection>) {
+    pub fn subscribe(&self, channel: ChannelType, addr: Addr<WebSocketSession>, wire_format: WireFormat, user_id: Option<Uuid>) {
         let mut channels = self.channels.lock().unwrap();
         
         let subscribers = channels.entry(channel).or_insert_with(Vec::new);
-        subscribers.push(addr);
+        subscribers.push(Subscriber { addr, wire_format, user_id });
     }
     
-    pub fn unsubscribe(&self, channel: &ChannelType, addr: &Addr<WebSocketConnection>) {
+    pub fn unsubscribe(&self, channel: &ChannelType, addr: &Addr<WebSocketSession>) {
         let mut channels = self.channels.lock().unwrap();
         
         if let Some(subscribers) = channels.get_mut(channel) {
-            subscribers.retain(|subscriber| subscriber != addr);
+            subscribers.retain(|subscriber| subscriber.addr != *addr);
             
             // Remove channel if no subscribers left
             if subscribers.is_empty() {
@@ -101,14 +124,56 @@ impl ChannelManager {
         }
     }
     
-    pub fn broadcast(&self, channel: &ChannelType, message: &str) {
-        let channels = self.channels.lock().unwrap();
+    pub fn has_subscribers(&self, channel: &ChannelType) -> bool {
+        self.channels.lock().unwrap().contains_key(channel)
+    }
+    
+    // Send without waiting on any subscriber, encoding the response once per wire format.
+    // A subscriber whose send buffer is full is dropped from every channel and
+    // disconnected instead of stalling the rest.
+    pub fn broadcast(&self, channel: &ChannelType, response: &WebSocketResponse) {
+        self.send_to(channel, response, None);
+    }
+    
+    // Send only to one user's sessions on a channel, as `broadcast` does
+    pub fn send_to_user(&self, channel: &ChannelType, user_id: Uuid, response: &WebSocketResponse) {
+        self.send_to(channel, response, Some(user_id));
+    }
+    
+    fn send_to(&self, channel: &ChannelType, response: &WebSocketResponse, user_id: Option<Uuid>) {
+        let mut channels = self.channels.lock().unwrap();
         
+        let mut frames: Vec<(WireFormat, WebSocketConnection)> = Vec::new();
+        let mut dropped = Vec::new();
         if let Some(subscribers) = channels.get(channel) {
-            for subscriber in subscribers {
-                let message = message.to_string();
-                subscriber.do_send(WebSocketConnection::Message(message));
+            let recipients = subscribers.iter()
+                .filter(|subscriber| user_id.map_or(true, |user_id| subscriber.user_id == Some(user_id)));
+            for subscriber in recipients {
+                let frame = match frames.iter().find(|(wire_format, _)| *wire_format == subscriber.wire_format) {
+                    Some((_, frame)) => frame.clone(),
+                    None => {
+                        let frame = response.to_frame(subscriber.wire_format);
+                        frames.push((subscriber.wire_format, frame.clone()));
+                        frame
+                    }
+                };
+                match subscriber.addr.try_send(frame) {
+                    Ok(()) => {}
+                    Err(SendError::Full(_)) => {
+                        warn!("Disconnecting WebSocket subscriber of {}: send buffer full", channel.to_string());
+                        subscriber.addr.do_send(WebSocketConnection::Disconnect);
+                        dropped.push(subscriber.addr.clone());
+                    }
+                    Err(SendError::Closed(_)) => dropped.push(subscriber.addr.clone()),
+                }
+            }
+        }
+        
+        if !dropped.is_empty() {
+            for subscribers in channels.values_mut() {
+                subscribers.retain(|subscriber| !dropped.contains(&subscriber.addr));
             }
+            channels.retain(|_, subscribers| !subscribers.is_empty());
         }
     }
 }
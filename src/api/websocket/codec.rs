@@ -0,0 +1,985 @@
+// Compact binary encoding of market data for WebSocket clients.
+//
+// Clients opt in at connect time with `encoding=binary`, optionally adding
+// `compression=deflate`. Market data then arrives as binary frames; control
+// messages (subscription acks, errors, pongs) stay JSON text.
+//
+// Frame layout, little-endian throughout:
+//   u16 template id | u16 schema version | u8 flags | body
+// Bit 0 of the flags marks a deflated body. Body fields use fixed layouts:
+//   decimal   i64 mantissa, u8 scale (value = mantissa / 10^scale)
+//   string    u16 length, UTF-8 bytes
+//   uuid      16 bytes
+//   time      i64 nanoseconds since the Unix epoch
+//   levels    u32 count, then (decimal price, decimal quantity) pairs
+//   interval  u8 index into 1m, 5m, 15m, 1h, 4h, 1d
+// A mini ticker frame carries a u32 count followed by that many tickers. L3
+// frames carry u32-counted lists of levels and events, each event led by a u8
+// tag (0 add, 1 modify, 2 delete, 3 execute). A user trade is the public trade
+// body followed by the party's order ids and a u8 maker flag.
+
+use std::io::{Read, Write};
+use chrono::{DateTime, TimeZone, Utc};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::db::models::Ticker;
+use crate::models::{Price, Quantity, Side, Trade};
+use crate::trading_engine::market_data::candles::{Candle, CandleInterval, CandleUpdate};
+use crate::trading_engine::market_data::depth::{DepthSnapshot, DepthUpdate};
+use crate::trading_engine::market_data::l3::{L3Event, L3Level, L3Order, L3Snapshot, L3Update};
+use crate::trading_engine::market_data::summary::MiniTicker;
+use crate::trading_engine::market_data::trades::{PublicTrade, UserTrade};
+
+// Version of the layouts below; bumped on any incompatible change
+pub const SCHEMA_VERSION: u16 = 1;
+
+// Smaller bodies are sent uncompressed even when compression was negotiated
+const COMPRESSION_THRESHOLD: usize = 256;
+
+const FLAG_DEFLATED: u8 = 0x01;
+
+// Encoding of market data negotiated for a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WireFormat {
+    pub encoding: Encoding,
+    // Deflate large binary bodies
+    pub compress: bool,
+}
+
+impl WireFormat {
+    pub const JSON: WireFormat = WireFormat { encoding: Encoding::Json, compress: false };
+
+    // Resolve the `encoding` and `compression` connect parameters
+    pub fn negotiate(encoding: Option<&str>, compression: Option<&str>) -> Result<Self, String> {
+        let encoding = match encoding.unwrap_or("json") {
+            "json" => Encoding::Json,
+            "binary" => Encoding::Binary,
+            other => return Err(format!("Unsupported encoding {}; expected json or binary", other)),
+        };
+        let compress = match compression.unwrap_or("none") {
+            "none" => false,
+            "deflate" if encoding == Encoding::Binary => true,
+            "deflate" => return Err("Compression requires encoding=binary".to_string()),
+            other => return Err(format!("Unsupported compression {}; expected none or deflate", other)),
+        };
+        Ok(WireFormat { encoding, compress })
+    }
+}
+
+// A message with a binary layout
+pub trait BinaryMessage: Sized {
+    const TEMPLATE_ID: u16;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String>;
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String>;
+}
+
+// A decoded market data frame
+#[derive(Debug, Clone)]
+pub enum MarketDataMessage {
+    DepthSnapshot(DepthSnapshot),
+    DepthUpdate(DepthUpdate),
+    Trade(Trade),
+    Ticker(Ticker),
+    PublicTrade(PublicTrade),
+    MiniTickers(Vec<MiniTicker>),
+    Kline(CandleUpdate),
+    L3Snapshot(L3Snapshot),
+    L3Update(L3Update),
+    UserTrade(UserTrade),
+}
+
+// Encode a message as a complete frame
+pub fn encode<M: BinaryMessage>(message: &M, compress: bool) -> Result<Vec<u8>, String> {
+    let mut body = Writer::default();
+    message.write_body(&mut body)?;
+    let mut body = body.into_bytes();
+
+    let mut flags = 0;
+    if compress && body.len() >= COMPRESSION_THRESHOLD {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&body).map_err(|e| format!("Failed to compress frame: {}", e))?;
+        body = encoder.finish().map_err(|e| format!("Failed to compress frame: {}", e))?;
+        flags |= FLAG_DEFLATED;
+    }
+
+    let mut frame = Writer::default();
+    frame.put_u16(M::TEMPLATE_ID);
+    frame.put_u16(SCHEMA_VERSION);
+    frame.put_u8(flags);
+    let mut frame = frame.into_bytes();
+    frame.extend_from_slice(&body);
+    Ok(frame)
+}
+
+// Decode a complete frame
+pub fn decode(frame: &[u8]) -> Result<MarketDataMessage, String> {
+    let mut header = Reader::new(frame);
+    let template_id = header.get_u16()?;
+    let version = header.get_u16()?;
+    if version != SCHEMA_VERSION {
+        return Err(format!("Unsupported schema version {}", version));
+    }
+    let flags = header.get_u8()?;
+
+    let inflated;
+    let body = if flags & FLAG_DEFLATED != 0 {
+        let mut buf = Vec::new();
+        DeflateDecoder::new(header.remaining())
+            .read_to_end(&mut buf)
+            .map_err(|e| format!("Failed to decompress frame: {}", e))?;
+        inflated = buf;
+        &inflated[..]
+    } else {
+        header.remaining()
+    };
+
+    let mut reader = Reader::new(body);
+    let message = match template_id {
+        DepthSnapshot::TEMPLATE_ID => MarketDataMessage::DepthSnapshot(DepthSnapshot::read_body(&mut reader)?),
+        DepthUpdate::TEMPLATE_ID => MarketDataMessage::DepthUpdate(DepthUpdate::read_body(&mut reader)?),
+        Trade::TEMPLATE_ID => MarketDataMessage::Trade(Trade::read_body(&mut reader)?),
+        Ticker::TEMPLATE_ID => MarketDataMessage::Ticker(Ticker::read_body(&mut reader)?),
+        PublicTrade::TEMPLATE_ID => MarketDataMessage::PublicTrade(PublicTrade::read_body(&mut reader)?),
+        <Vec<MiniTicker>>::TEMPLATE_ID => MarketDataMessage::MiniTickers(<Vec<MiniTicker>>::read_body(&mut reader)?),
+        CandleUpdate::TEMPLATE_ID => MarketDataMessage::Kline(CandleUpdate::read_body(&mut reader)?),
+        L3Snapshot::TEMPLATE_ID => MarketDataMessage::L3Snapshot(L3Snapshot::read_body(&mut reader)?),
+        L3Update::TEMPLATE_ID => MarketDataMessage::L3Update(L3Update::read_body(&mut reader)?),
+        UserTrade::TEMPLATE_ID => MarketDataMessage::UserTrade(UserTrade::read_body(&mut reader)?),
+        other => return Err(format!("Unknown template id {}", other)),
+    };
+    if !reader.remaining().is_empty() {
+        return Err(format!("{} trailing bytes after template {}", reader.remaining().len(), template_id));
+    }
+    Ok(message)
+}
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_i64(&mut self, value: i64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_f64(&mut self, value: f64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Fixed-point: the exact mantissa and scale, so trailing zeros survive too
+    pub fn put_decimal(&mut self, value: Decimal) -> Result<(), String> {
+        let mantissa = i64::try_from(value.mantissa())
+            .map_err(|_| format!("Decimal {} does not fit a 64-bit mantissa", value))?;
+        self.put_i64(mantissa);
+        self.put_u8(value.scale() as u8);
+        Ok(())
+    }
+
+    pub fn put_str(&mut self, value: &str) -> Result<(), String> {
+        let len = u16::try_from(value.len()).map_err(|_| format!("String of {} bytes is too long", value.len()))?;
+        self.put_u16(len);
+        self.buf.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    pub fn put_uuid(&mut self, value: &Uuid) {
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn put_time(&mut self, value: &DateTime<Utc>) -> Result<(), String> {
+        let nanos = value.timestamp_nanos_opt().ok_or_else(|| format!("Time {} is out of range", value))?;
+        self.put_i64(nanos);
+        Ok(())
+    }
+
+    pub fn put_side(&mut self, value: Side) {
+        self.put_u8(match value {
+            Side::Buy => 0,
+            Side::Sell => 1,
+        });
+    }
+
+    pub fn put_bool(&mut self, value: bool) {
+        self.put_u8(value as u8);
+    }
+
+    pub fn put_interval(&mut self, value: CandleInterval) {
+        let index = CandleInterval::ALL.iter().position(|interval| *interval == value).unwrap_or_default();
+        self.put_u8(index as u8);
+    }
+
+    pub fn put_count(&mut self, count: usize) -> Result<(), String> {
+        let count = u32::try_from(count).map_err(|_| format!("Too many entries: {}", count))?;
+        self.put_u32(count);
+        Ok(())
+    }
+
+    pub fn put_levels(&mut self, levels: &[(Price, Quantity)]) -> Result<(), String> {
+        let count = u32::try_from(levels.len()).map_err(|_| "Too many levels".to_string())?;
+        self.put_u32(count);
+        for (price, quantity) in levels {
+            self.put_decimal(*price)?;
+            self.put_decimal(*quantity)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.buf.len() - self.pos < len {
+            return Err(format!("Frame truncated at byte {}", self.pos));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn get_i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn get_f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn get_decimal(&mut self) -> Result<Decimal, String> {
+        let mantissa = self.get_i64()?;
+        let scale = self.get_u8()? as u32;
+        Decimal::try_from_i128_with_scale(mantissa as i128, scale)
+            .map_err(|e| format!("Invalid decimal: {}", e))
+    }
+
+    pub fn get_str(&mut self) -> Result<String, String> {
+        let len = self.get_u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| format!("Invalid string: {}", e))
+    }
+
+    pub fn get_uuid(&mut self) -> Result<Uuid, String> {
+        Ok(Uuid::from_bytes(self.take_array()?))
+    }
+
+    pub fn get_time(&mut self) -> Result<DateTime<Utc>, String> {
+        Ok(Utc.timestamp_nanos(self.get_i64()?))
+    }
+
+    pub fn get_side(&mut self) -> Result<Side, String> {
+        match self.get_u8()? {
+            0 => Ok(Side::Buy),
+            1 => Ok(Side::Sell),
+            other => Err(format!("Invalid side {}", other)),
+        }
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool, String> {
+        match self.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("Invalid flag {}", other)),
+        }
+    }
+
+    pub fn get_interval(&mut self) -> Result<CandleInterval, String> {
+        let index = self.get_u8()?;
+        CandleInterval::ALL
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| format!("Invalid interval {}", index))
+    }
+
+    // A u32 count of entries taking at least `min_size` bytes each, checked against
+    // the bytes left before anything is allocated for them
+    pub fn get_count(&mut self, min_size: usize) -> Result<usize, String> {
+        let count = self.get_u32()? as usize;
+        if count > self.remaining().len() / min_size {
+            return Err(format!("Count {} exceeds frame size", count));
+        }
+        Ok(count)
+    }
+
+    pub fn get_levels(&mut self) -> Result<Vec<(Price, Quantity)>, String> {
+        let count = self.get_u32()? as usize;
+        // Each level takes 18 bytes; reject counts the frame cannot hold before allocating
+        if count > self.remaining().len() / 18 {
+            return Err(format!("Level count {} exceeds frame size", count));
+        }
+        let mut levels = Vec::with_capacity(count);
+        for _ in 0..count {
+            levels.push((self.get_decimal()?, self.get_decimal()?));
+        }
+        Ok(levels)
+    }
+}
+
+impl BinaryMessage for DepthSnapshot {
+    const TEMPLATE_ID: u16 = 1;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_str(&self.symbol)?;
+        writer.put_u64(self.last_update_id);
+        writer.put_time(&self.timestamp)?;
+        writer.put_levels(&self.bids)?;
+        writer.put_levels(&self.asks)
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(DepthSnapshot {
+            symbol: reader.get_str()?,
+            last_update_id: reader.get_u64()?,
+            timestamp: reader.get_time()?,
+            bids: reader.get_levels()?,
+            asks: reader.get_levels()?,
+        })
+    }
+}
+
+impl BinaryMessage for DepthUpdate {
+    const TEMPLATE_ID: u16 = 2;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_str(&self.symbol)?;
+        writer.put_u64(self.first_update_id);
+        writer.put_u64(self.last_update_id);
+        writer.put_time(&self.timestamp)?;
+        writer.put_levels(&self.bids)?;
+        writer.put_levels(&self.asks)
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(DepthUpdate {
+            symbol: reader.get_str()?,
+            first_update_id: reader.get_u64()?,
+            last_update_id: reader.get_u64()?,
+            timestamp: reader.get_time()?,
+            bids: reader.get_levels()?,
+            asks: reader.get_levels()?,
+        })
+    }
+}
+
+impl BinaryMessage for Trade {
+    const TEMPLATE_ID: u16 = 3;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_uuid(&self.id);
+        writer.put_str(&self.symbol)?;
+        writer.put_uuid(&self.taker_order_id);
+        writer.put_uuid(&self.maker_order_id);
+        writer.put_decimal(self.price)?;
+        writer.put_decimal(self.quantity)?;
+        writer.put_side(self.side);
+        writer.put_u64(self.timestamp);
+        Ok(())
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(Trade {
+            id: reader.get_uuid()?,
+            symbol: reader.get_str()?,
+            taker_order_id: reader.get_uuid()?,
+            maker_order_id: reader.get_uuid()?,
+            price: reader.get_decimal()?,
+            quantity: reader.get_decimal()?,
+            side: reader.get_side()?,
+            timestamp: reader.get_u64()?,
+        })
+    }
+}
+
+impl BinaryMessage for Ticker {
+    const TEMPLATE_ID: u16 = 4;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_str(&self.symbol)?;
+        for value in [
+            self.last_price,
+            self.bid_price,
+            self.ask_price,
+            self.high_24h,
+            self.low_24h,
+            self.volume_24h,
+            self.price_change_24h,
+        ] {
+            writer.put_decimal(value)?;
+        }
+        writer.put_f64(self.price_change_percent_24h);
+        writer.put_time(&self.timestamp)
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(Ticker {
+            symbol: reader.get_str()?,
+            last_price: reader.get_decimal()?,
+            bid_price: reader.get_decimal()?,
+            ask_price: reader.get_decimal()?,
+            high_24h: reader.get_decimal()?,
+            low_24h: reader.get_decimal()?,
+            volume_24h: reader.get_decimal()?,
+            price_change_24h: reader.get_decimal()?,
+            price_change_percent_24h: reader.get_f64()?,
+            timestamp: reader.get_time()?,
+        })
+    }
+}
+
+impl BinaryMessage for PublicTrade {
+    const TEMPLATE_ID: u16 = 5;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_str(&self.symbol)?;
+        writer.put_u64(self.trade_id);
+        writer.put_uuid(&self.id);
+        writer.put_decimal(self.price)?;
+        writer.put_decimal(self.quantity)?;
+        writer.put_side(self.aggressor_side);
+        writer.put_time(&self.timestamp)
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(PublicTrade {
+            symbol: reader.get_str()?,
+            trade_id: reader.get_u64()?,
+            id: reader.get_uuid()?,
+            price: reader.get_decimal()?,
+            quantity: reader.get_decimal()?,
+            aggressor_side: reader.get_side()?,
+            timestamp: reader.get_time()?,
+        })
+    }
+}
+
+// The mini tickers channel sends every market in one message
+impl BinaryMessage for Vec<MiniTicker> {
+    const TEMPLATE_ID: u16 = 6;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        let count = u32::try_from(self.len()).map_err(|_| format!("Too many tickers: {}", self.len()))?;
+        writer.put_u32(count);
+        for ticker in self {
+            writer.put_str(&ticker.symbol)?;
+            for value in [
+                ticker.last_price,
+                ticker.price_change_24h,
+                ticker.volume_24h,
+                ticker.bid_price,
+                ticker.ask_price,
+            ] {
+                writer.put_decimal(value)?;
+            }
+            writer.put_f64(ticker.price_change_percent_24h);
+            writer.put_time(&ticker.timestamp)?;
+        }
+        Ok(())
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        let count = reader.get_u32()?;
+        (0..count)
+            .map(|_| {
+                Ok(MiniTicker {
+                    symbol: reader.get_str()?,
+                    last_price: reader.get_decimal()?,
+                    price_change_24h: reader.get_decimal()?,
+                    volume_24h: reader.get_decimal()?,
+                    bid_price: reader.get_decimal()?,
+                    ask_price: reader.get_decimal()?,
+                    price_change_percent_24h: reader.get_f64()?,
+                    timestamp: reader.get_time()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl BinaryMessage for CandleUpdate {
+    const TEMPLATE_ID: u16 = 7;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        let candle = &self.candle;
+        writer.put_str(&candle.symbol)?;
+        writer.put_interval(candle.interval);
+        writer.put_time(&candle.open_time)?;
+        writer.put_time(&candle.close_time)?;
+        for value in [candle.open, candle.high, candle.low, candle.close, candle.volume, candle.quote_volume] {
+            writer.put_decimal(value)?;
+        }
+        writer.put_u64(candle.trade_count);
+        writer.put_bool(self.is_closed);
+        Ok(())
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(CandleUpdate {
+            candle: Candle {
+                symbol: reader.get_str()?,
+                interval: reader.get_interval()?,
+                open_time: reader.get_time()?,
+                close_time: reader.get_time()?,
+                open: reader.get_decimal()?,
+                high: reader.get_decimal()?,
+                low: reader.get_decimal()?,
+                close: reader.get_decimal()?,
+                volume: reader.get_decimal()?,
+                quote_volume: reader.get_decimal()?,
+                trade_count: reader.get_u64()?,
+                last_trade_at: None,
+            },
+            is_closed: reader.get_bool()?,
+        })
+    }
+}
+
+fn put_l3_levels(writer: &mut Writer, levels: &[L3Level]) -> Result<(), String> {
+    writer.put_count(levels.len())?;
+    for level in levels {
+        writer.put_decimal(level.price)?;
+        writer.put_count(level.orders.len())?;
+        for order in &level.orders {
+            writer.put_uuid(&order.order_id);
+            writer.put_uuid(&order.owner);
+            writer.put_decimal(order.quantity)?;
+        }
+    }
+    Ok(())
+}
+
+fn get_l3_levels(reader: &mut Reader) -> Result<Vec<L3Level>, String> {
+    // A level takes at least its price and order count, an order its ids and quantity
+    let count = reader.get_count(13)?;
+    let mut levels = Vec::with_capacity(count);
+    for _ in 0..count {
+        let price = reader.get_decimal()?;
+        let order_count = reader.get_count(41)?;
+        let mut orders = Vec::with_capacity(order_count);
+        for _ in 0..order_count {
+            orders.push(L3Order {
+                order_id: reader.get_uuid()?,
+                owner: reader.get_uuid()?,
+                quantity: reader.get_decimal()?,
+            });
+        }
+        levels.push(L3Level { price, orders });
+    }
+    Ok(levels)
+}
+
+impl BinaryMessage for L3Snapshot {
+    const TEMPLATE_ID: u16 = 8;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_str(&self.symbol)?;
+        writer.put_u64(self.sequence);
+        writer.put_time(&self.timestamp)?;
+        put_l3_levels(writer, &self.bids)?;
+        put_l3_levels(writer, &self.asks)
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(L3Snapshot {
+            symbol: reader.get_str()?,
+            sequence: reader.get_u64()?,
+            timestamp: reader.get_time()?,
+            bids: get_l3_levels(reader)?,
+            asks: get_l3_levels(reader)?,
+        })
+    }
+}
+
+impl BinaryMessage for L3Update {
+    const TEMPLATE_ID: u16 = 9;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        writer.put_str(&self.symbol)?;
+        writer.put_u64(self.first_sequence);
+        writer.put_u64(self.last_sequence);
+        writer.put_time(&self.timestamp)?;
+        writer.put_count(self.events.len())?;
+        for event in &self.events {
+            match event {
+                L3Event::Add { order_id, owner, side, price, quantity } => {
+                    writer.put_u8(0);
+                    writer.put_uuid(order_id);
+                    writer.put_uuid(owner);
+                    writer.put_side(*side);
+                    writer.put_decimal(*price)?;
+                    writer.put_decimal(*quantity)?;
+                }
+                L3Event::Modify { order_id, quantity } => {
+                    writer.put_u8(1);
+                    writer.put_uuid(order_id);
+                    writer.put_decimal(*quantity)?;
+                }
+                L3Event::Delete { order_id } => {
+                    writer.put_u8(2);
+                    writer.put_uuid(order_id);
+                }
+                L3Event::Execute { order_id, trade_id, price, quantity, remaining } => {
+                    writer.put_u8(3);
+                    writer.put_uuid(order_id);
+                    writer.put_uuid(trade_id);
+                    writer.put_decimal(*price)?;
+                    writer.put_decimal(*quantity)?;
+                    writer.put_decimal(*remaining)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        let symbol = reader.get_str()?;
+        let first_sequence = reader.get_u64()?;
+        let last_sequence = reader.get_u64()?;
+        let timestamp = reader.get_time()?;
+        // The smallest event is a delete: its tag and order id
+        let count = reader.get_count(17)?;
+        let mut events = Vec::with_capacity(count);
+        for _ in 0..count {
+            events.push(match reader.get_u8()? {
+                0 => L3Event::Add {
+                    order_id: reader.get_uuid()?,
+                    owner: reader.get_uuid()?,
+                    side: reader.get_side()?,
+                    price: reader.get_decimal()?,
+                    quantity: reader.get_decimal()?,
+                },
+                1 => L3Event::Modify {
+                    order_id: reader.get_uuid()?,
+                    quantity: reader.get_decimal()?,
+                },
+                2 => L3Event::Delete {
+                    order_id: reader.get_uuid()?,
+                },
+                3 => L3Event::Execute {
+                    order_id: reader.get_uuid()?,
+                    trade_id: reader.get_uuid()?,
+                    price: reader.get_decimal()?,
+                    quantity: reader.get_decimal()?,
+                    remaining: reader.get_decimal()?,
+                },
+                other => return Err(format!("Invalid L3 event tag {}", other)),
+            });
+        }
+        Ok(L3Update { symbol, first_sequence, last_sequence, events, timestamp })
+    }
+}
+
+impl BinaryMessage for UserTrade {
+    const TEMPLATE_ID: u16 = 10;
+
+    fn write_body(&self, writer: &mut Writer) -> Result<(), String> {
+        self.trade.write_body(writer)?;
+        writer.put_uuid(&self.order_id);
+        writer.put_uuid(&self.taker_order_id);
+        writer.put_uuid(&self.maker_order_id);
+        writer.put_bool(self.is_maker);
+        Ok(())
+    }
+
+    fn read_body(reader: &mut Reader) -> Result<Self, String> {
+        Ok(UserTrade {
+            trade: PublicTrade::read_body(reader)?,
+            order_id: reader.get_uuid()?,
+            taker_order_id: reader.get_uuid()?,
+            maker_order_id: reader.get_uuid()?,
+            is_maker: reader.get_bool()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use serde::Serialize;
+
+    fn json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn time() -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_709_287_200_123_456_789)
+    }
+
+    fn depth_update(levels: usize) -> DepthUpdate {
+        DepthUpdate {
+            symbol: "BTC/USDT".to_string(),
+            first_update_id: 41,
+            last_update_id: 97,
+            bids: (0..levels).map(|i| (dec!(50000.10) - Decimal::from(i), dec!(0.00100))).collect(),
+            asks: vec![(dec!(50001), Decimal::ZERO)],
+            timestamp: time(),
+        }
+    }
+
+    #[test]
+    fn test_depth_round_trips_to_the_same_json() {
+        let snapshot = DepthSnapshot {
+            symbol: "ETH/USDT".to_string(),
+            last_update_id: 7,
+            bids: vec![(dec!(3000.5), dec!(12.25))],
+            asks: vec![],
+            timestamp: time(),
+        };
+        match decode(&encode(&snapshot, false).unwrap()).unwrap() {
+            MarketDataMessage::DepthSnapshot(decoded) => assert_eq!(json(&decoded), json(&snapshot)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        // Trailing zeros are part of the JSON representation and survive the fixed-point encoding
+        let update = depth_update(3);
+        match decode(&encode(&update, false).unwrap()).unwrap() {
+            MarketDataMessage::DepthUpdate(decoded) => {
+                assert_eq!(json(&decoded), json(&update));
+                assert_eq!(decoded.bids[0].1.to_string(), "0.00100");
+            }
+            other => panic!("Decoded {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_trade_and_ticker_round_trip_to_the_same_json() {
+        let trade = Trade::new("BTC/USDT".to_string(), Uuid::new_v4(), Uuid::new_v4(), dec!(50000.5), dec!(0.25), Side::Sell);
+        match decode(&encode(&trade, false).unwrap()).unwrap() {
+            MarketDataMessage::Trade(decoded) => assert_eq!(json(&decoded), json(&trade)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        let ticker = Ticker {
+            symbol: "BTC/USDT".to_string(),
+            last_price: dec!(50000.5),
+            bid_price: dec!(50000),
+            ask_price: dec!(50001),
+            high_24h: dec!(51000),
+            low_24h: dec!(48000),
+            volume_24h: dec!(1234.5678),
+            price_change_24h: dec!(-250.5),
+            price_change_percent_24h: -0.4985,
+            timestamp: time(),
+        };
+        match decode(&encode(&ticker, false).unwrap()).unwrap() {
+            MarketDataMessage::Ticker(decoded) => assert_eq!(json(&decoded), json(&ticker)),
+            other => panic!("Decoded {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_public_trade_and_mini_tickers_round_trip_to_the_same_json() {
+        let trade = PublicTrade {
+            symbol: "BTC/USDT".to_string(),
+            trade_id: 1042,
+            id: Uuid::new_v4(),
+            price: dec!(50000.50),
+            quantity: dec!(0.250),
+            aggressor_side: Side::Buy,
+            timestamp: time(),
+        };
+        match decode(&encode(&trade, false).unwrap()).unwrap() {
+            MarketDataMessage::PublicTrade(decoded) => assert_eq!(json(&decoded), json(&trade)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        let tickers = vec![
+            MiniTicker {
+                symbol: "BTC/USDT".to_string(),
+                last_price: dec!(50000.5),
+                price_change_24h: dec!(-250.5),
+                price_change_percent_24h: -0.4985,
+                volume_24h: dec!(1234.5678),
+                bid_price: dec!(50000),
+                ask_price: dec!(50001),
+                timestamp: time(),
+            },
+            MiniTicker {
+                symbol: "ETH/USDT".to_string(),
+                last_price: dec!(3000),
+                price_change_24h: Decimal::ZERO,
+                price_change_percent_24h: 0.0,
+                volume_24h: Decimal::ZERO,
+                bid_price: Decimal::ZERO,
+                ask_price: Decimal::ZERO,
+                timestamp: time(),
+            },
+        ];
+        match decode(&encode(&tickers, false).unwrap()).unwrap() {
+            MarketDataMessage::MiniTickers(decoded) => assert_eq!(json(&decoded), json(&tickers)),
+            other => panic!("Decoded {:?}", other),
+        }
+        match decode(&encode(&Vec::<MiniTicker>::new(), false).unwrap()).unwrap() {
+            MarketDataMessage::MiniTickers(decoded) => assert!(decoded.is_empty()),
+            other => panic!("Decoded {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compression_and_malformed_frames() {
+        let update = depth_update(200);
+        let plain = encode(&update, false).unwrap();
+        let compressed = encode(&update, true).unwrap();
+        assert_eq!(compressed[4] & FLAG_DEFLATED, FLAG_DEFLATED);
+        assert!(compressed.len() < plain.len());
+        match decode(&compressed).unwrap() {
+            MarketDataMessage::DepthUpdate(decoded) => assert_eq!(json(&decoded), json(&update)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        // Small bodies are not worth deflating
+        assert_eq!(encode(&depth_update(1), true).unwrap()[4], 0);
+
+        assert!(decode(&plain[..plain.len() - 1]).is_err());
+        let mut unknown = plain.clone();
+        unknown[0] = 99;
+        assert!(decode(&unknown).is_err());
+
+        assert_eq!(WireFormat::negotiate(None, None).unwrap(), WireFormat::JSON);
+        assert!(WireFormat::negotiate(Some("binary"), Some("deflate")).unwrap().compress);
+        assert!(WireFormat::negotiate(None, Some("deflate")).is_err());
+        assert!(WireFormat::negotiate(Some("msgpack"), None).is_err());
+    }
+
+    #[test]
+    fn test_kline_l3_and_user_trade_round_trip_to_the_same_json() {
+        let kline = CandleUpdate {
+            candle: Candle {
+                symbol: "BTC/USDT".to_string(),
+                interval: CandleInterval::FifteenMinutes,
+                open_time: time(),
+                close_time: time(),
+                open: dec!(50000),
+                high: dec!(50100.5),
+                low: dec!(49900),
+                close: dec!(50050.25),
+                volume: dec!(12.5),
+                quote_volume: dec!(625312.5),
+                trade_count: 42,
+                last_trade_at: None,
+            },
+            is_closed: true,
+        };
+        match decode(&encode(&kline, false).unwrap()).unwrap() {
+            MarketDataMessage::Kline(decoded) => assert_eq!(json(&decoded), json(&kline)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        let order_id = Uuid::new_v4();
+        let snapshot = L3Snapshot {
+            symbol: "BTC/USDT".to_string(),
+            sequence: 12,
+            bids: vec![L3Level {
+                price: dec!(50000),
+                orders: vec![L3Order { order_id, owner: Uuid::new_v4(), quantity: dec!(0.500) }],
+            }],
+            asks: vec![],
+            timestamp: time(),
+        };
+        match decode(&encode(&snapshot, false).unwrap()).unwrap() {
+            MarketDataMessage::L3Snapshot(decoded) => assert_eq!(json(&decoded), json(&snapshot)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        let update = L3Update {
+            symbol: "BTC/USDT".to_string(),
+            first_sequence: 13,
+            last_sequence: 16,
+            events: vec![
+                L3Event::Add { order_id, owner: Uuid::new_v4(), side: Side::Sell, price: dec!(50001), quantity: dec!(1) },
+                L3Event::Modify { order_id, quantity: dec!(0.75) },
+                L3Event::Execute { order_id, trade_id: Uuid::new_v4(), price: dec!(50001), quantity: dec!(0.75), remaining: Decimal::ZERO },
+                L3Event::Delete { order_id },
+            ],
+            timestamp: time(),
+        };
+        match decode(&encode(&update, false).unwrap()).unwrap() {
+            MarketDataMessage::L3Update(decoded) => assert_eq!(json(&decoded), json(&update)),
+            other => panic!("Decoded {:?}", other),
+        }
+
+        let user_trade = UserTrade {
+            trade: PublicTrade {
+                symbol: "BTC/USDT".to_string(),
+                trade_id: 1043,
+                id: Uuid::new_v4(),
+                price: dec!(50001),
+                quantity: dec!(0.75),
+                aggressor_side: Side::Buy,
+                timestamp: time(),
+            },
+            order_id,
+            taker_order_id: Uuid::new_v4(),
+            maker_order_id: order_id,
+            is_maker: true,
+        };
+        match decode(&encode(&user_trade, false).unwrap()).unwrap() {
+            MarketDataMessage::UserTrade(decoded) => assert_eq!(json(&decoded), json(&user_trade)),
+            other => panic!("Decoded {:?}", other),
+        }
+    }
+}
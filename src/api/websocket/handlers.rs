@@ -1,19 +1,34 @@
-use actix::{Actor, ActorContext, AsyncContext, Handler, Message, StreamHandler};
+use actix::{Actor, ActorContext, ActorFutureExt, AsyncContext, Handler, Message, StreamHandler, WrapFuture};
+use actix_web::web::Bytes;
 use actix_web_actors::ws;
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Instant;
 use uuid::Uuid;
 
 use crate::config::Config;
+use crate::trading_engine::rate_limiter::{self, OrderAction, OrderRateLimiter, RateLimitDecision};
+use crate::trading_engine::market_data::depth::{DepthFeed, DepthSnapshot, DepthUpdate};
+use crate::trading_engine::market_data::l3::{L3Feed, L3Snapshot, L3Update};
+use crate::trading_engine::market_data::candles::{CandleInterval, CandleUpdate};
+use crate::trading_engine::market_data::summary::MiniTicker;
+use crate::trading_engine::market_data::trades::{PublicTrade, UserTrade};
 use super::channels::{ChannelManager, ChannelType};
-use super::{HEARTBEAT_INTERVAL, CLIENT_TIMEOUT};
+use super::codec::{self, Encoding, WireFormat};
+use super::{HEARTBEAT_INTERVAL, CLIENT_TIMEOUT, SEND_BUFFER_CAPACITY};
+
+// Levels per side in the snapshot sent when subscribing to an order book channel
+const DEPTH_SNAPSHOT_LEVELS: usize = 1000;
 
 // WebSocket messages
-#[derive(Message)]
+#[derive(Message, Clone)]
 #[rtype(result = "()")]
 pub enum WebSocketConnection {
     Message(String),
+    Binary(Bytes),
+    // Sent when the connection falls too far behind its subscriptions
+    Disconnect,
 }
 
 // WebSocket session
@@ -23,6 +38,11 @@ pub struct WebSocketSession {
     pub channel_subscriptions: Vec<ChannelType>,
     pub channel_manager: ChannelManager,
     pub token: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub wire_format: WireFormat,
+    pub rate_limiter: Arc<OrderRateLimiter>,
+    pub depth_feed: Arc<DepthFeed>,
+    pub l3_feed: Arc<L3Feed>,
     pub config: Config,
 }
 
@@ -36,6 +56,12 @@ pub enum WebSocketMessage {
     Unsubscribe {
         channel: String,
     },
+    PlaceOrder {
+        order: serde_json::Value,
+    },
+    CancelOrder {
+        order_id: String,
+    },
     Ping {},
     Ping2 {},
 }
@@ -54,7 +80,46 @@ pub enum WebSocketResponse {
         code: u16,
         message: String,
     },
+    RateLimited {
+        retry_after_ms: u64,
+    },
     Pong {},
+    DepthSnapshot(DepthSnapshot),
+    DepthUpdate(DepthUpdate),
+    L3Snapshot(L3Snapshot),
+    L3Update(L3Update),
+    Kline(CandleUpdate),
+    MiniTickers {
+        tickers: Vec<MiniTicker>,
+    },
+    Trade(PublicTrade),
+    UserTrade(UserTrade),
+}
+
+impl WebSocketResponse {
+    // Frame for a client using `wire_format`; every channel's data has a binary layout,
+    // while control messages stay JSON
+    pub fn to_frame(&self, wire_format: WireFormat) -> WebSocketConnection {
+        if wire_format.encoding == Encoding::Binary {
+            let encoded = match self {
+                WebSocketResponse::DepthSnapshot(snapshot) => Some(codec::encode(snapshot, wire_format.compress)),
+                WebSocketResponse::DepthUpdate(update) => Some(codec::encode(update, wire_format.compress)),
+                WebSocketResponse::L3Snapshot(snapshot) => Some(codec::encode(snapshot, wire_format.compress)),
+                WebSocketResponse::L3Update(update) => Some(codec::encode(update, wire_format.compress)),
+                WebSocketResponse::Kline(kline) => Some(codec::encode(kline, wire_format.compress)),
+                WebSocketResponse::Trade(trade) => Some(codec::encode(trade, wire_format.compress)),
+                WebSocketResponse::UserTrade(trade) => Some(codec::encode(trade, wire_format.compress)),
+                WebSocketResponse::MiniTickers { tickers } => Some(codec::encode(tickers, wire_format.compress)),
+                _ => None,
+            };
+            match encoded {
+                Some(Ok(frame)) => return WebSocketConnection::Binary(Bytes::from(frame)),
+                Some(Err(e)) => warn!("Falling back to JSON for unencodable message: {}", e),
+                None => {}
+            }
+        }
+        WebSocketConnection::Message(serde_json::to_string(self).unwrap())
+    }
 }
 
 impl Actor for WebSocketSession {
@@ -62,13 +127,15 @@ impl Actor for WebSocketSession {
 
     // Start heartbeat process on session start
     fn started(&mut self, ctx: &mut Self::Context) {
+        // Bound the messages queued for a slow client; broadcasts drop it when full
+        ctx.set_mailbox_capacity(SEND_BUFFER_CAPACITY);
         self.heartbeat(ctx);
         
         info!("WebSocket connection established: {}", self.id);
     }
 
     // Clean up on session end
-    fn stopped(&mut self, _: &mut Self::Context) {
+    fn stopped(&mut self, ctx: &mut Self::Context) {
         info!("WebSocket connection closed: {}", self.id);
         
         // Unsubscribe from all channels
@@ -100,6 +167,18 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                         WebSocketMessage::Unsubscribe { channel } => {
                             self.handle_unsubscribe(ctx, &channel);
                         }
+                        // Order entry draws on the same rate limit as REST, but isn't routed
+                        // to the engine over WebSocket yet, so it is refused once charged
+                        WebSocketMessage::PlaceOrder { .. } => {
+                            if self.check_rate_limit(ctx, OrderAction::NewOrder) {
+                                self.refuse_order_entry(ctx);
+                            }
+                        }
+                        WebSocketMessage::CancelOrder { .. } => {
+                            if self.check_rate_limit(ctx, OrderAction::CancelOrder) {
+                                self.refuse_order_entry(ctx);
+                            }
+                        }
                         WebSocketMessage::Ping {} | WebSocketMessage::Ping2 {} => {
                             self.heartbeat = Instant::now();
                             let response = WebSocketResponse::Pong {};
@@ -117,7 +196,13 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                 }
             }
             Ok(ws::Message::Binary(bin)) => {
+                // Binary encoding only applies to market data sent to the client
                 warn!("Unexpected binary message: {:?}", bin);
+                let response = WebSocketResponse::Error {
+                    code: 400,
+                    message: "Requests must be JSON text".to_string(),
+                };
+                ctx.text(serde_json::to_string(&response).unwrap());
             }
             Ok(ws::Message::Close(reason)) => {
                 info!("WebSocket connection closing: {:?}", reason);
@@ -149,7 +234,8 @@ impl WebSocketSession {
             // Check if user-specific channel requires authentication
             match &channel_type {
                 ChannelType::UserOrders | ChannelType::UserTrades | ChannelType::UserWallet => {
-                    if self.token.is_none() {
+                    // Private messages are routed by the user behind the token
+                    if self.user_id.is_none() {
                         let response = WebSocketResponse::Error {
                             code: 401,
                             message: "Authentication required for user channels".to_string(),
@@ -162,11 +248,21 @@ impl WebSocketSession {
                     // In a real implementation, you would verify the token and extract user ID
                     // For now, we'll assume the token is valid
                 }
+                ChannelType::Kline(_, interval) => {
+                    if let Err(e) = interval.parse::<CandleInterval>() {
+                        let response = WebSocketResponse::Error {
+                            code: 400,
+                            message: e,
+                        };
+                        ctx.text(serde_json::to_string(&response).unwrap());
+                        return;
+                    }
+                }
                 _ => {}
             }
             
             // Subscribe to channel
-            self.channel_manager.subscribe(channel_type.clone(), ctx.address());
+            self.channel_manager.subscribe(channel_type.clone(), ctx.address(), self.wire_format, self.user_id);
             self.channel_subscriptions.push(channel_type.clone());
             
             // Send subscription confirmation
@@ -175,6 +271,14 @@ impl WebSocketSession {
             };
             ctx.text(serde_json::to_string(&response).unwrap());
             
+            // Depth updates continue from a snapshot sent after subscribing,
+            // so none are missed between the two
+            match &channel_type {
+                ChannelType::OrderBook(symbol) => self.send_depth_snapshot(ctx, symbol.clone()),
+                ChannelType::OrderBookL3(symbol) => self.send_l3_snapshot(ctx, symbol.clone()),
+                _ => {}
+            }
+            
             info!("Subscribed to channel: {}", channel);
         } else {
             let response = WebSocketResponse::Error {
@@ -185,6 +289,85 @@ impl WebSocketSession {
         }
     }
 
+    // Send the current order book levels for a depth subscription
+    fn send_depth_snapshot(&self, ctx: &mut ws::WebsocketContext<Self>, symbol: String) {
+        let depth_feed = Arc::clone(&self.depth_feed);
+        let snapshot = async move { depth_feed.snapshot(&symbol, DEPTH_SNAPSHOT_LEVELS).await.ok_or(symbol) };
+        
+        ctx.spawn(snapshot.into_actor(self).map(|snapshot, act, ctx| {
+            let response = match snapshot {
+                Ok(snapshot) => WebSocketResponse::DepthSnapshot(snapshot),
+                Err(symbol) => WebSocketResponse::Error {
+                    code: 404,
+                    message: format!("No order book for {}", symbol),
+                },
+            };
+            act.send(ctx, &response);
+        }));
+    }
+
+    // Send every resting order for an L3 subscription
+    fn send_l3_snapshot(&self, ctx: &mut ws::WebsocketContext<Self>, symbol: String) {
+        let l3_feed = Arc::clone(&self.l3_feed);
+        let snapshot = async move { l3_feed.snapshot(&symbol).await.ok_or(symbol) };
+        
+        ctx.spawn(snapshot.into_actor(self).map(|snapshot, act, ctx| {
+            let response = match snapshot {
+                Ok(snapshot) => WebSocketResponse::L3Snapshot(snapshot),
+                Err(symbol) => WebSocketResponse::Error {
+                    code: 404,
+                    message: format!("No order book for {}", symbol),
+                },
+            };
+            act.send(ctx, &response);
+        }));
+    }
+
+    // Send a response in the connection's negotiated encoding
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, response: &WebSocketResponse) {
+        match response.to_frame(self.wire_format) {
+            WebSocketConnection::Message(text) => ctx.text(text),
+            WebSocketConnection::Binary(bytes) => ctx.binary(bytes),
+            WebSocketConnection::Disconnect => {}
+        }
+    }
+
+    // Apply the order-entry rate limit shared with the REST API and trading engine.
+    // Returns false (after notifying the client) if the request must not proceed.
+    fn check_rate_limit(&self, ctx: &mut ws::WebsocketContext<Self>, action: OrderAction) -> bool {
+        let user_id = match self.user_id {
+            Some(user_id) => user_id,
+            None => {
+                let response = WebSocketResponse::Error {
+                    code: 401,
+                    message: "Authentication required for order entry".to_string(),
+                };
+                ctx.text(serde_json::to_string(&response).unwrap());
+                return false;
+            }
+        };
+        
+        match self.rate_limiter.check(&user_id, action) {
+            RateLimitDecision::Allowed { .. } => true,
+            RateLimitDecision::Throttled { retry_after } => {
+                let response = WebSocketResponse::RateLimited {
+                    retry_after_ms: rate_limiter::retry_after_millis(retry_after),
+                };
+                ctx.text(serde_json::to_string(&response).unwrap());
+                false
+            }
+        }
+    }
+
+    // Refuse an order entry request rather than acknowledge an order that is never placed
+    fn refuse_order_entry(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let response = WebSocketResponse::Error {
+            code: 501,
+            message: "Order entry is not available over WebSocket, use the REST API".to_string(),
+        };
+        ctx.text(serde_json::to_string(&response).unwrap());
+    }
+
     // Handle unsubscribe message
     fn handle_unsubscribe(&mut self, ctx: &mut ws::WebsocketContext<Self>, channel: &str) {
         if let Some(channel_type) = ChannelType::from_string(channel) {
@@ -218,6 +401,16 @@ impl Handler<WebSocketConnection> for WebSocketSession {
             WebSocketConnection::Message(message) => {
                 ctx.text(message);
             }
+            WebSocketConnection::Binary(bytes) => {
+                ctx.binary(bytes);
+            }
+            WebSocketConnection::Disconnect => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Policy,
+                    description: Some("Client too slow to keep up with subscriptions".to_string()),
+                }));
+                ctx.stop();
+            }
         }
     }
 }
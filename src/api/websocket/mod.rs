@@ -1,12 +1,29 @@
-mod channels;
+pub mod channels;
+pub mod codec;
 mod handlers;
 
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time;
 
-use channels::ChannelManager;
-use handlers::WebSocketSession;
+use crate::admin::TradingPairStore;
+use crate::api::handlers::user::TokenClaims;
+use crate::trading_engine::rate_limiter::OrderRateLimiter;
+use crate::trading_engine::market_data::depth::DepthFeed;
+use crate::trading_engine::market_data::l3::L3Feed;
+use crate::trading_engine::market_data::summary;
+use crate::trading_engine::market_data::trades::TradeFeed;
+use crate::trading_engine::market_data::MarketDataService;
+
+use channels::{ChannelManager, ChannelType};
+use codec::WireFormat;
+use handlers::{WebSocketResponse, WebSocketSession};
 
 // How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
@@ -14,31 +31,56 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
 // How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
 
+// How often the all-market mini tickers are pushed
+const MINI_TICKER_INTERVAL: Duration = Duration::from_secs(1);
+
+// Messages queued for a connection before it is considered too slow and dropped
+const SEND_BUFFER_CAPACITY: usize = 256;
+
 // WebSocket connection handler
 pub async fn ws_handler(
     req: HttpRequest,
     stream: web::Payload,
     config: web::Data<crate::config::Config>,
+    rate_limiter: web::Data<Arc<OrderRateLimiter>>,
+    channel_manager: web::Data<ChannelManager>,
+    depth_feed: web::Data<Arc<DepthFeed>>,
+    l3_feed: web::Data<Arc<L3Feed>>,
 ) -> Result<HttpResponse, Error> {
-    // Extract token from query parameters
+    // Extract token and market data encoding from query parameters
     let query_params = req.query_string();
-    let token = query_params
-        .split('&')
-        .find_map(|param| {
-            if param.starts_with("token=") {
-                Some(param[6..].to_string())
-            } else {
-                None
-            }
-        });
+    let token = query_param(query_params, "token");
+    let wire_format = match WireFormat::negotiate(
+        query_param(query_params, "encoding").as_deref(),
+        query_param(query_params, "compression").as_deref(),
+    ) {
+        Ok(wire_format) => wire_format,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+
+    // Resolve the user behind the token, if any
+    let user_id = token.as_ref().and_then(|token| {
+        decode::<TokenClaims>(
+            token,
+            &DecodingKey::from_secret(config.jwt.secret.as_bytes()),
+            &Validation::default(),
+        )
+        .ok()
+        .and_then(|data| uuid::Uuid::parse_str(&data.claims.sub).ok())
+    });
 
     // Create WebSocket session
     let session = WebSocketSession {
         id: uuid::Uuid::new_v4(),
         heartbeat: Instant::now(),
         channel_subscriptions: Vec::new(),
-        channel_manager: ChannelManager::new(),
+        channel_manager: channel_manager.get_ref().clone(),
         token,
+        user_id,
+        wire_format,
+        rate_limiter: Arc::clone(rate_limiter.get_ref()),
+        depth_feed: Arc::clone(depth_feed.get_ref()),
+        l3_feed: Arc::clone(l3_feed.get_ref()),
         config: config.get_ref().clone(),
     };
 
@@ -47,3 +89,133 @@ pub async fn ws_handler(
 
     Ok(resp)
 }
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|param| {
+        param.strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+            .map(|value| value.to_string())
+    })
+}
+
+// Forward published depth updates to the subscribers of each symbol's order book channel
+pub fn spawn_depth_relay(channel_manager: ChannelManager, depth_feed: &Arc<DepthFeed>) -> JoinHandle<()> {
+    let mut updates = depth_feed.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(update) => {
+                    let channel = ChannelType::OrderBook(update.symbol.clone());
+                    channel_manager.broadcast(&channel, &WebSocketResponse::DepthUpdate(update));
+                }
+                // Subscribers see the id gap and resync from a snapshot
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Depth relay lagged, {} updates dropped", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+// Forward published order-by-order updates to the subscribers of each symbol's L3 channel
+pub fn spawn_l3_relay(channel_manager: ChannelManager, l3_feed: &Arc<L3Feed>) -> JoinHandle<()> {
+    let mut updates = l3_feed.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(update) => {
+                    let channel = ChannelType::OrderBookL3(update.symbol.clone());
+                    channel_manager.broadcast(&channel, &WebSocketResponse::L3Update(update));
+                }
+                // Subscribers see the sequence gap and resync from a snapshot
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("L3 relay lagged, {} updates dropped", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+// Forward live candle updates to the subscribers of each symbol and interval's kline channel
+pub fn spawn_kline_relay(channel_manager: ChannelManager, market_data: &Arc<MarketDataService>) -> JoinHandle<()> {
+    let mut updates = market_data.subscribe_candles();
+    tokio::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(update) => {
+                    let channel = ChannelType::Kline(update.candle.symbol.clone(), update.candle.interval.to_string());
+                    // Most intervals of most symbols have no subscribers
+                    if !channel_manager.has_subscribers(&channel) {
+                        continue;
+                    }
+                    channel_manager.broadcast(&channel, &WebSocketResponse::Kline(update));
+                }
+                // The next update of each candle carries its full state
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Kline relay lagged, {} updates dropped", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+// Forward published trades to each symbol's trade channel and to both parties' private channels
+pub fn spawn_trade_relay(channel_manager: ChannelManager, trade_feed: &Arc<TradeFeed>) -> JoinHandle<()> {
+    let mut updates = trade_feed.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(event) => {
+                    let channel = ChannelType::Trades(event.trade.symbol.clone());
+                    if channel_manager.has_subscribers(&channel) {
+                        channel_manager.broadcast(&channel, &WebSocketResponse::Trade(event.trade.clone()));
+                    }
+                    if channel_manager.has_subscribers(&ChannelType::UserTrades) {
+                        for (user_id, user_trade) in event.user_trades() {
+                            channel_manager.send_to_user(&ChannelType::UserTrades, user_id, &WebSocketResponse::UserTrade(user_trade));
+                        }
+                    }
+                }
+                // Subscribers see the trade id gap and can fill it from trade history
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Trade relay lagged, {} trades dropped", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+// Push every active market's mini ticker to the all-market channel once per interval
+pub fn spawn_mini_ticker_publisher(
+    channel_manager: ChannelManager,
+    market_data: &Arc<MarketDataService>,
+    trading_pairs: &Arc<TradingPairStore>,
+) -> JoinHandle<()> {
+    let market_data = Arc::clone(market_data);
+    let trading_pairs = Arc::clone(trading_pairs);
+    tokio::spawn(async move {
+        let mut interval = time::interval(MINI_TICKER_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !channel_manager.has_subscribers(&ChannelType::MiniTickers) {
+                continue;
+            }
+            let pairs = match trading_pairs.get_all_pairs().await {
+                Ok(pairs) => pairs,
+                Err(e) => {
+                    warn!("Failed to load trading pairs for mini tickers: {}", e);
+                    continue;
+                }
+            };
+            let tickers = summary::mini_tickers(&pairs, &market_data).await;
+            if tickers.is_empty() {
+                continue;
+            }
+            channel_manager.broadcast(&ChannelType::MiniTickers, &WebSocketResponse::MiniTickers { tickers });
+        }
+    })
+}
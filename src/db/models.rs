@@ -262,6 +262,8 @@ impl Order {
             user_id: self.user_id,
             executed_at: Utc::now(),
             fee: Decimal::from(0), // Fee should be calculated elsewhere
+            sequence: None, // Assigned when the trade is published
+            is_maker: false, // Set by the caller when the order was resting
         };
         
         Ok(trade)
@@ -280,6 +282,10 @@ pub struct Trade {
     pub user_id: Uuid,
     pub executed_at: DateTime<Utc>,
     pub fee: Decimal,
+    /// Per-symbol trade id published to market data clients
+    pub sequence: Option<i64>,
+    /// Each trade is stored once per party; market data reads the taker's row
+    pub is_maker: bool,
 }
 
 /// Match model representing two orders that were matched together
@@ -0,0 +1,150 @@
+// src/db/repositories/candle_repository.rs
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::{PgPool, PgRow}, Error as SqlxError, Row};
+
+use crate::trading_engine::market_data::candles::{Candle, CandleInterval};
+
+#[async_trait]
+pub trait CandleRepositoryTrait: Send + Sync {
+    /// Insert candles, replacing any stored for the same symbol, interval and open time
+    async fn upsert(&self, candles: &[Candle]) -> Result<(), SqlxError>;
+    /// The most recent `limit` candles opening in `[start, end]`, oldest first
+    async fn find_range(
+        &self,
+        symbol: &str,
+        interval: CandleInterval,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Candle>, SqlxError>;
+    async fn first_open_time(&self, symbol: &str, interval: CandleInterval) -> Result<Option<DateTime<Utc>>, SqlxError>;
+    async fn find_symbols(&self) -> Result<Vec<String>, SqlxError>;
+    /// Delete candles opening before `before`, returning how many were removed
+    async fn delete_before(&self, symbol: &str, interval: CandleInterval, before: DateTime<Utc>) -> Result<u64, SqlxError>;
+}
+
+pub struct CandleRepository {
+    pub(crate) pool: PgPool,
+}
+
+impl CandleRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn candle_from_row(row: &PgRow) -> Result<Candle, SqlxError> {
+    let interval: String = row.try_get("interval")?;
+    Ok(Candle {
+        symbol: row.try_get("symbol")?,
+        interval: interval.parse().map_err(|e: String| SqlxError::Decode(e.into()))?,
+        open_time: row.try_get("open_time")?,
+        close_time: row.try_get("close_time")?,
+        open: row.try_get("open")?,
+        high: row.try_get("high")?,
+        low: row.try_get("low")?,
+        close: row.try_get("close")?,
+        volume: row.try_get("volume")?,
+        quote_volume: row.try_get("quote_volume")?,
+        trade_count: row.try_get::<i64, _>("trade_count")? as u64,
+        last_trade_at: None,
+    })
+}
+
+#[async_trait]
+impl CandleRepositoryTrait for CandleRepository {
+    async fn upsert(&self, candles: &[Candle]) -> Result<(), SqlxError> {
+        let mut tx = self.pool.begin().await?;
+        for candle in candles {
+            sqlx::query(
+                r#"
+                INSERT INTO candles (symbol, interval, open_time, close_time, open, high, low, close, volume, quote_volume, trade_count)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT (symbol, interval, open_time) DO UPDATE SET
+                    close_time = EXCLUDED.close_time,
+                    open = EXCLUDED.open,
+                    high = EXCLUDED.high,
+                    low = EXCLUDED.low,
+                    close = EXCLUDED.close,
+                    volume = EXCLUDED.volume,
+                    quote_volume = EXCLUDED.quote_volume,
+                    trade_count = EXCLUDED.trade_count
+                "#,
+            )
+            .bind(&candle.symbol)
+            .bind(candle.interval.as_str())
+            .bind(candle.open_time)
+            .bind(candle.close_time)
+            .bind(candle.open)
+            .bind(candle.high)
+            .bind(candle.low)
+            .bind(candle.close)
+            .bind(candle.volume)
+            .bind(candle.quote_volume)
+            .bind(candle.trade_count as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn find_range(
+        &self,
+        symbol: &str,
+        interval: CandleInterval,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Candle>, SqlxError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM (
+                SELECT * FROM candles
+                WHERE symbol = $1
+                  AND interval = $2
+                  AND ($3::timestamptz IS NULL OR open_time >= $3)
+                  AND ($4::timestamptz IS NULL OR open_time <= $4)
+                ORDER BY open_time DESC
+                LIMIT $5
+            ) recent
+            ORDER BY open_time
+            "#,
+        )
+        .bind(symbol)
+        .bind(interval.as_str())
+        .bind(start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(candle_from_row).collect()
+    }
+
+    async fn first_open_time(&self, symbol: &str, interval: CandleInterval) -> Result<Option<DateTime<Utc>>, SqlxError> {
+        sqlx::query("SELECT MIN(open_time) AS first FROM candles WHERE symbol = $1 AND interval = $2")
+            .bind(symbol)
+            .bind(interval.as_str())
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("first")
+    }
+
+    async fn find_symbols(&self) -> Result<Vec<String>, SqlxError> {
+        let rows = sqlx::query("SELECT DISTINCT symbol FROM candles ORDER BY symbol")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(|row| row.try_get("symbol")).collect()
+    }
+
+    async fn delete_before(&self, symbol: &str, interval: CandleInterval, before: DateTime<Utc>) -> Result<u64, SqlxError> {
+        let result = sqlx::query("DELETE FROM candles WHERE symbol = $1 AND interval = $2 AND open_time < $3")
+            .bind(symbol)
+            .bind(interval.as_str())
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,121 @@
+// src/db/repositories/funding_repository.rs
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::{PgPool, PgRow}, Error as SqlxError, Row};
+
+use crate::trading_engine::derivatives::{FundingPayment, UserId};
+use crate::trading_engine::derivatives::funding::FundingPaymentStore;
+
+/// Funding settlements and payments in Postgres
+pub struct FundingRepository {
+    pub(crate) pool: PgPool,
+}
+
+impl FundingRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn payment_from_row(row: &PgRow) -> Result<FundingPayment, SqlxError> {
+    Ok(FundingPayment {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        contract_id: row.try_get("contract_id")?,
+        position_id: row.try_get("position_id")?,
+        funding_rate: row.try_get("funding_rate")?,
+        payment_amount: row.try_get("payment_amount")?,
+        timestamp: row.try_get("funding_time")?,
+    })
+}
+
+#[async_trait]
+impl FundingPaymentStore for FundingRepository {
+    async fn record_settlement(&self, contract_id: &str, funding_time: DateTime<Utc>, payments: &[FundingPayment]) -> anyhow::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // The primary key makes a second settlement of the same interval a no-op
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO funding_settlements (contract_id, funding_time)
+            VALUES ($1, $2)
+            ON CONFLICT (contract_id, funding_time) DO NOTHING
+            "#,
+        )
+        .bind(contract_id)
+        .bind(funding_time)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() == 1;
+        if !claimed {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        for payment in payments {
+            sqlx::query(
+                r#"
+                INSERT INTO funding_payments (id, user_id, contract_id, position_id, funding_rate, payment_amount, funding_time)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(payment.id)
+            .bind(payment.user_id)
+            .bind(&payment.contract_id)
+            .bind(payment.position_id)
+            .bind(payment.funding_rate)
+            .bind(payment.payment_amount)
+            .bind(payment.timestamp)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn last_settlement(&self, contract_id: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let last = sqlx::query("SELECT MAX(funding_time) AS last FROM funding_settlements WHERE contract_id = $1")
+            .bind(contract_id)
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("last")?;
+        Ok(last)
+    }
+
+    async fn get_user_payments(&self, user_id: &UserId, contract_id: Option<&str>, limit: usize) -> anyhow::Result<Vec<FundingPayment>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM funding_payments
+            WHERE user_id = $1
+              AND ($2::varchar IS NULL OR contract_id = $2)
+            ORDER BY funding_time DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(contract_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(payment_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn get_contract_payments(&self, contract_id: &str, limit: usize) -> anyhow::Result<Vec<FundingPayment>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM funding_payments
+            WHERE contract_id = $1
+            ORDER BY funding_time DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(contract_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(payment_from_row).collect::<Result<_, _>>()?)
+    }
+}
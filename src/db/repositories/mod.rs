@@ -1,5 +1,9 @@
+pub mod candle_repository;
+pub mod funding_repository;
 pub mod order_repository;
 pub mod trade_repository;
 
+pub use candle_repository::CandleRepository;
+pub use funding_repository::FundingRepository;
 pub use order_repository::OrderRepository;
 pub use trade_repository::TradeRepository;
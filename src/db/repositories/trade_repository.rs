@@ -1,16 +1,153 @@
 // src/db/repositories/trade_repository.rs
+use std::fmt;
+use std::str::FromStr;
 use async_trait::async_trait;
-use sqlx::{postgres::PgPool, Error as SqlxError};
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::{postgres::{PgPool, PgRow}, Error as SqlxError, Row};
 use uuid::Uuid;
 
 use crate::db::models::Trade;
 
+/// Position in trade history, just after the trade it names; trades are ordered by
+/// execution time, then id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradeCursor {
+    pub executed_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl TradeCursor {
+    pub fn after(trade: &Trade) -> Self {
+        TradeCursor { executed_at: trade.executed_at, id: trade.id }
+    }
+}
+
+/// Opaque to clients: `<nanoseconds>_<trade id>`
+impl fmt::Display for TradeCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.executed_at.timestamp_nanos_opt().unwrap_or_default(), self.id)
+    }
+}
+
+impl FromStr for TradeCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid trade cursor {}", s);
+        let (nanos, id) = s.split_once('_').ok_or_else(invalid)?;
+        Ok(TradeCursor {
+            executed_at: Utc.timestamp_nanos(nanos.parse().map_err(|_| invalid())?),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A page of a symbol's trade history, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct TradeHistoryQuery {
+    /// Earliest execution time, inclusive
+    pub start: Option<DateTime<Utc>>,
+    /// Latest execution time, inclusive
+    pub end: Option<DateTime<Utc>>,
+    /// Continue after a previous page
+    pub after: Option<TradeCursor>,
+    /// Start from this trade, inclusive
+    pub from_id: Option<Uuid>,
+    pub limit: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TradePage {
+    pub trades: Vec<Trade>,
+    /// Set when more trades match the query
+    pub next_cursor: Option<TradeCursor>,
+}
+
+impl TradePage {
+    /// Build a page from up to `limit + 1` matching trades in history order
+    pub fn from_rows(mut trades: Vec<Trade>, limit: i64) -> Self {
+        let limit = limit.max(0) as usize;
+        let next_cursor = if trades.len() > limit {
+            trades.truncate(limit);
+            trades.last().map(TradeCursor::after)
+        } else {
+            None
+        };
+        TradePage { trades, next_cursor }
+    }
+}
+
 #[async_trait]
 pub trait TradeRepositoryTrait: Send + Sync {
     async fn create(&self, trade: &Trade) -> Result<Trade, SqlxError>;
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<Trade>, SqlxError>;
-    async fn find_by_order_id(&self, order_id: Uuid) -> Result<Vec<Trade>, SqlxError>;
+    /// Store the rows of one trade together, so none is stored without the others. Rows
+    /// already stored are skipped, so a write that may have committed can be retried.
+    async fn create_all(&self, trades: &[Trade]) -> Result<(), SqlxError>;
+    async fn create_all(&self, trades: &[Trade]) -> Result<(), SqlxError> {
+        let mut tx = self.pool.begin().await?;
+
+        for trade in trades {
+            sqlx::query(
+                r#"
+                INSERT INTO trades (id, symbol, price, quantity, side, order_id, user_id, executed_at, fee, sequence, is_maker)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(trade.id)
+            .bind(&trade.symbol)
+            .bind(trade.price)
+            .bind(trade.quantity)
+            .bind(trade.side)
+            .bind(trade.order_id)
+            .bind(trade.user_id)
+            .bind(trade.executed_at)
+            .bind(trade.fee)
+            .bind(trade.sequence)
+            .bind(trade.is_maker)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Trade>, SqlxError> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, symbol, price, quantity, side, order_id, user_id, executed_at, fee, sequence, is_maker
+            FROM trades
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(trade_from_row).transpose()
+    }
+
+    async fn find_by_order_id(&self, order_id: Uuid) -> Result<Vec<Trade>, SqlxError> {
+        // The order's fills in execution order, served by the trades_order_time index
+        let rows = sqlx::query(
+            r#"
+            SELECT id, symbol, price, quantity, side, order_id, user_id, executed_at, fee, sequence, is_maker
+            FROM trades
+            WHERE order_id = $1
+            ORDER BY executed_at, id
+            "#,
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(trade_from_row).collect()
+    }
+
     async fn find_by_symbol(&self, symbol: &str, limit: i64) -> Result<Vec<Trade>, SqlxError>;
+    async fn find_history(&self, symbol: &str, query: &TradeHistoryQuery) -> Result<TradePage, SqlxError>;
+    /// Highest trade id stored for a symbol
+    async fn last_sequence(&self, symbol: &str) -> Result<Option<i64>, SqlxError>;
 }
 
 pub struct TradeRepository {
@@ -23,10 +160,45 @@ impl TradeRepository {
     }
 }
 
+fn trade_from_row(row: &PgRow) -> Result<Trade, SqlxError> {
+    Ok(Trade {
+        id: row.try_get("id")?,
+        symbol: row.try_get("symbol")?,
+        price: row.try_get("price")?,
+        quantity: row.try_get("quantity")?,
+        side: row.try_get("side")?,
+        order_id: row.try_get("order_id")?,
+        user_id: row.try_get("user_id")?,
+        executed_at: row.try_get("executed_at")?,
+        fee: row.try_get("fee")?,
+        sequence: row.try_get("sequence")?,
+        is_maker: row.try_get("is_maker")?,
+    })
+}
+
 #[async_trait]
 impl TradeRepositoryTrait for TradeRepository {
     async fn create(&self, trade: &Trade) -> Result<Trade, SqlxError> {
-        // Simplified implementation
+        sqlx::query(
+            r#"
+            INSERT INTO trades (id, symbol, price, quantity, side, order_id, user_id, executed_at, fee, sequence, is_maker)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(trade.id)
+        .bind(&trade.symbol)
+        .bind(trade.price)
+        .bind(trade.quantity)
+        .bind(trade.side)
+        .bind(trade.order_id)
+        .bind(trade.user_id)
+        .bind(trade.executed_at)
+        .bind(trade.fee)
+        .bind(trade.sequence)
+        .bind(trade.is_maker)
+        .execute(&self.pool)
+        .await?;
+
         Ok(trade.clone())
     }
 
@@ -41,7 +213,60 @@ impl TradeRepositoryTrait for TradeRepository {
     }
 
     async fn find_by_symbol(&self, symbol: &str, limit: i64) -> Result<Vec<Trade>, SqlxError> {
-        // Simplified implementation
-        Ok(Vec::new())
+        // Newest taker rows, one per trade, served by the trades_symbol_time index
+        let rows = sqlx::query(
+            r#"
+            SELECT id, symbol, price, quantity, side, order_id, user_id, executed_at, fee, sequence, is_maker
+            FROM trades
+            WHERE symbol = $1 AND NOT is_maker
+            ORDER BY executed_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(symbol)
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(trade_from_row).collect()
+    }
+
+    async fn find_history(&self, symbol: &str, query: &TradeHistoryQuery) -> Result<TradePage, SqlxError> {
+        // Keyset pagination on (executed_at, id), served by the trades_symbol_time index
+        let rows = sqlx::query(
+            r#"
+            SELECT id, symbol, price, quantity, side, order_id, user_id, executed_at, fee, sequence, is_maker
+            FROM trades
+            WHERE symbol = $1
+              AND NOT is_maker
+              AND ($2::timestamptz IS NULL OR executed_at >= $2)
+              AND ($3::timestamptz IS NULL OR executed_at <= $3)
+              AND ($4::timestamptz IS NULL OR (executed_at, id) > ($4, $5))
+              AND ($6::uuid IS NULL OR (executed_at, id) >= (SELECT executed_at, id FROM trades WHERE id = $6))
+            ORDER BY executed_at, id
+            LIMIT $7
+            "#,
+        )
+        .bind(symbol)
+        .bind(query.start)
+        .bind(query.end)
+        .bind(query.after.map(|cursor| cursor.executed_at))
+        .bind(query.after.map(|cursor| cursor.id))
+        .bind(query.from_id)
+        .bind(query.limit.max(0) + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let trades = rows.iter().map(trade_from_row).collect::<Result<Vec<_>, _>>()?;
+        Ok(TradePage::from_rows(trades, query.limit))
+    }
+
+    async fn last_sequence(&self, symbol: &str) -> Result<Option<i64>, SqlxError> {
+        // Served by the trades_symbol_sequence index
+        let row = sqlx::query("SELECT MAX(sequence) AS sequence FROM trades WHERE symbol = $1 AND NOT is_maker")
+            .bind(symbol)
+            .fetch_one(&self.pool)
+            .await?;
+        row.try_get("sequence")
     }
 }
@@ -11,13 +11,15 @@ use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use rand::rngs::OsRng;
 use rand::RngCore;
 
+use crate::trading_engine::rate_limiter::OrderRateLimiter;
+
 // Type definitions
 pub type UserId = Uuid;
 pub type DocumentId = Uuid;
 pub type VerificationId = Uuid;
 
 // User identity tiers
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum IdentityTier {
     Tier0, // Email only, very limited functionality
     Tier1, // Basic KYC, limited trading
@@ -754,6 +756,8 @@ pub struct KycManager {
     verification_store: Arc<VerificationStore>,
     kyc_provider: Arc<dyn KycProvider>,
     risk_engine: Arc<RiskScoringEngine>,
+    // Order-entry limits follow the identity tier when set
+    rate_limiter: Option<Arc<OrderRateLimiter>>,
 }
 
 impl KycManager {
@@ -770,9 +774,15 @@ impl KycManager {
             verification_store,
             kyc_provider,
             risk_engine,
+            rate_limiter: None,
         }
     }
     
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<OrderRateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+    
     pub async fn register_user(&self, email: String, password: &str) -> Result<UserProfile> {
         // Create a new user profile
         let user = UserProfile::new(email, password)?;
@@ -780,6 +790,10 @@ impl KycManager {
         // Save the user
         self.user_store.add_user(user.clone()).await?;
         
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.set_identity_tier(user.user_id, user.identity_tier);
+        }
+        
         Ok(user)
     }
     
@@ -881,6 +895,10 @@ impl KycManager {
         if verification_status == VerificationStatus::Approved {
             self.user_store.update_user_tier(user_id, tier).await?;
             self.user_store.update_user_status(user_id, UserStatus::Active).await?;
+            
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.set_identity_tier(*user_id, tier);
+            }
         }
         
         Ok(updated_verification)
@@ -1017,13 +1035,15 @@ mod tests {
         let risk_engine = Arc::new(RiskScoringEngine::new(risk_model));
         
         // Create KYC manager
+        let rate_limiter = Arc::new(OrderRateLimiter::default());
         let kyc_manager = KycManager::new(
             user_store.clone(),
             document_store.clone(),
             verification_store.clone(),
             kyc_provider,
             risk_engine,
-        );
+        )
+        .with_rate_limiter(Arc::clone(&rate_limiter));
         
         // Register a new user
         let user = kyc_manager
@@ -1034,6 +1054,7 @@ mod tests {
         assert_eq!(user.email, "test@example.com");
         assert_eq!(user.status, UserStatus::Unverified);
         assert_eq!(user.identity_tier, IdentityTier::Tier0);
+        assert_eq!(rate_limiter.user_tier(&user.user_id).burst, 0.0);
         
         // Upload documents
         let passport_doc = kyc_manager
@@ -1088,6 +1109,7 @@ mod tests {
             let verified_user = user_store.get_user(&user.user_id).await.unwrap();
             assert_eq!(verified_user.identity_tier, IdentityTier::Tier2);
             assert_eq!(verified_user.status, UserStatus::Active);
+            assert_eq!(rate_limiter.user_tier(&user.user_id).burst, 50.0);
         }
         
         // Perform AML check
@@ -5,6 +5,8 @@ pub mod trading_engine;
 pub mod wallet;
 pub mod security;
 pub mod utils;
+pub mod kyc;
+pub mod admin;
 
 // Re-export models
 pub mod models {
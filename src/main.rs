@@ -2,7 +2,10 @@
 use anyhow::{anyhow, Context, Result};
 use clap::{App, Arg, SubCommand};
 use log::{info, error};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+use rust_decimal_macros::dec;
 use tokio::signal;
 
 // Import local modules
@@ -12,6 +15,8 @@ mod db;
 mod security;
 mod trading_engine;
 mod wallet;
+mod admin;
+mod kyc;
 mod core;
 mod utils;
 
@@ -46,6 +51,33 @@ async fn main() -> Result<()> {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Replay a recorded order and trade event log through a fresh matching engine")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("JSON lines event log to replay")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("speed")
+                        .short("s")
+                        .long("speed")
+                        .value_name("SPEED")
+                        .help("Speed-up over recorded time, such as 1 (real time) or 10x, or max")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .short("p")
+                        .long("port")
+                        .value_name("PORT")
+                        .help("Sets the API port")
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
 
     // Initialize logging
@@ -60,6 +92,8 @@ async fn main() -> Result<()> {
         .and_then(|m| m.value_of("port"))
         .or_else(|| matches.subcommand_matches("api-gateway")
                             .and_then(|m| m.value_of("port")))
+        .or_else(|| matches.subcommand_matches("replay")
+                            .and_then(|m| m.value_of("port")))
     {
         if let Ok(port) = port_str.parse::<u16>() {
             config.api_port = port;
@@ -72,68 +106,82 @@ async fn main() -> Result<()> {
             info!("Starting in trading engine mode");
             
             // Initialize metrics
-            let metrics = utils::metrics::init_metrics(&config)?;
+            let metrics = utils::metrics::init_metrics(&config).map_err(|e| anyhow!(e))?;
             
             // Initialize repositories
             let db_pool = db::init_database(&config.database_url).await?;
-            let user_repo = Arc::new(db::UserRepository::new(db_pool.clone()));
-            let asset_repo = Arc::new(db::AssetRepository::new(db_pool.clone()));
-            let trading_pair_repo = Arc::new(db::TradingPairRepository::new(db_pool.clone()));
-            let order_repo = Arc::new(db::OrderRepository::new(db_pool.clone()));
             let trade_repo = Arc::new(db::TradeRepository::new(db_pool.clone()));
-            let wallet_repo = Arc::new(db::WalletRepository::new(db_pool.clone()));
-            let account_repo = Arc::new(db::AccountRepository::new(db_pool.clone()));
-            let deposit_repo = Arc::new(db::DepositRepository::new(db_pool.clone()));
-            let withdrawal_repo = Arc::new(db::WithdrawalRepository::new(db_pool.clone()));
+            let repositories = api::Repositories {
+                users: Arc::new(db::UserRepository::new(db_pool.clone())),
+                assets: Arc::new(db::AssetRepository::new(db_pool.clone())),
+                trading_pairs: Arc::new(db::TradingPairRepository::new(db_pool.clone())),
+                orders: Arc::new(db::OrderRepository::new(db_pool.clone())),
+                trades: trade_repo.clone(),
+                wallets: Arc::new(db::WalletRepository::new(db_pool.clone())),
+                accounts: Arc::new(db::AccountRepository::new(db_pool.clone())),
+                deposits: Arc::new(db::DepositRepository::new(db_pool.clone())),
+                withdrawals: Arc::new(db::WithdrawalRepository::new(db_pool.clone())),
+            };
+            let candle_repo = Arc::new(db::repositories::candle_repository::CandleRepository::new(db_pool.clone()));
             
-            // Initialize security
-            let auth_service = Arc::new(security::AuthService::new(&config));
-            
-            // Initialize trading engine
-            let manager = Arc::new(trading_engine::EngineManager::new(
-                metrics.clone(),
-                order_repo.clone(),
-                trade_repo.clone(),
-            ));
+            // Initialize matching engines
+            let engines = Arc::new(trading_engine::matching_engine::MatchingEngineManager::new());
             
             // Add trading pairs
             for pair in config.trading_pairs.clone() {
                 info!("Adding trading pair: {}", pair);
-                manager.add_symbol(pair).await?;
+                engines.add_symbol(pair).await.map_err(|e| anyhow!(e))?;
             }
             
             // Initialize wallet system
+            let wallet_config = config.clone();
             let wallet_handle = tokio::spawn(async move {
-                if let Err(e) = wallet::run(config.clone(), false).await {
+                if let Err(e) = wallet::run(wallet_config, false).await {
                     error!("Wallet system error: {}", e);
                 }
             });
             
-            // Initialize API service
-            let mut api_service = api::ApiService::new(
-                Arc::clone(&manager),
-                user_repo,
-                asset_repo,
-                trading_pair_repo,
-                order_repo,
-                trade_repo,
-                wallet_repo,
-                account_repo,
-                deposit_repo,
-                withdrawal_repo,
-                Arc::clone(&auth_service),
+            // Initialize market data, backed by stored trades and candles
+            let market_data = Arc::new(
+                trading_engine::market_data::MarketDataService::new(Default::default())
+                    .with_order_books(Arc::clone(&engines))
+                    .with_trade_store(trade_repo.clone())
+                    .with_candle_store(candle_repo.clone()),
             );
+            market_data.spawn_candle_writer();
             
-            // Start API service
-            api_service.start("0.0.0.0", config.api_port).await?;
+            // Stored candles past their retention are rolled into coarser intervals
+            Arc::new(trading_engine::market_data::history::CandleCompactor::new(Default::default(), candle_repo)).spawn();
+            for pair in &config.trading_pairs {
+                if let Err(e) = market_data.backfill(trade_repo.as_ref(), pair, 10_000).await {
+                    error!("Failed to backfill market data for {}: {}", pair, e);
+                }
+            }
+            let trade_feed = Arc::new(
+                trading_engine::market_data::trades::TradeFeed::new(Default::default())
+                    .with_store(trade_repo),
+            );
             
-            info!("All components started successfully");
+            // Candles and the public trade feed follow the trades the engines execute
+            let (candle_trades, candle_trade_rx) = tokio::sync::mpsc::channel(10_000);
+            engines.add_trade_listener(candle_trades).await;
+            market_data.spawn_trade_listener(candle_trade_rx);
+            let (feed_trades, feed_trade_rx) = tokio::sync::mpsc::channel(10_000);
+            engines.add_trade_listener(feed_trades).await;
+            trade_feed.spawn_trade_listener(feed_trade_rx);
             
-            // Wait for shutdown signal
-            wait_for_shutdown().await?;
+            // Start API service, recording funding history alongside trades
+            let funding_store = Arc::new(db::repositories::FundingRepository::new(db_pool.clone()));
+            let services = app_services(&engines, market_data, trade_feed, funding_store, metrics, Some(repositories), &config).await;
+            let server = api::start_api_server(config.clone(), services);
             
-            // Shutdown API service
-            api_service.stop().await?;
+            info!("All components started successfully");
+            
+            // Serve until the shutdown signal
+            tokio::select! {
+                result = server => result.context("API server failed")?,
+                result = wait_for_shutdown() => result?,
+            }
             
             // Wait for wallet system to shut down
             if let Err(e) = wallet_handle.await {
@@ -186,6 +234,55 @@ async fn main() -> Result<()> {
             // Shutdown API service
             api_service.stop().await?;
         },
+        Some("replay") => {
+            info!("Starting in replay mode");
+            
+            let replay_matches = matches.subcommand_matches("replay").unwrap();
+            let speed = replay_matches.value_of("speed").unwrap_or("1")
+                .parse::<trading_engine::market_data::replay::ReplaySpeed>()
+                .map_err(|e| anyhow!(e))?;
+            let path = replay_matches.value_of("file").context("An event log file is required")?;
+            let events = trading_engine::market_data::replay::read_event_log(Path::new(path))?;
+            info!("Loaded {} events from {}", events.len(), path);
+            
+            let metrics = utils::metrics::init_metrics(&config).map_err(|e| anyhow!(e))?;
+            
+            // Fresh engines and market data with nothing persisted, so a replay
+            // never touches production state
+            let engines = Arc::new(trading_engine::matching_engine::MatchingEngineManager::new());
+            let market_data = Arc::new(
+                trading_engine::market_data::MarketDataService::new(Default::default())
+                    .with_order_books(Arc::clone(&engines)),
+            );
+            
+            // Trades are numbered from 1 and not stored
+            let trade_feed = Arc::new(trading_engine::market_data::trades::TradeFeed::new(Default::default()));
+            let replay = trading_engine::market_data::replay::MarketReplay::new(Arc::clone(&engines), Arc::clone(&market_data))
+                .with_trade_feed(Arc::clone(&trade_feed));
+            replay.open_books(&events).await?;
+            
+            // Serve the same REST endpoints and WebSocket channels as a live engine
+            // Funding history is kept in memory like the rest of the replay
+            let funding_store = Arc::new(trading_engine::derivatives::funding::InMemoryFundingPaymentStore::new());
+            let services = app_services(&engines, market_data, trade_feed, funding_store, metrics, None, &config).await;
+            let server = api::start_api_server(config.clone(), services);
+            
+            let session = async {
+                let summary = replay.run(&events, speed).await?;
+                info!(
+                    "Replay finished: {} orders ({} rejected), {} cancels, {} trades",
+                    summary.orders, summary.rejected, summary.cancels, summary.trades
+                );
+                
+                // Keep serving the final state until shut down
+                wait_for_shutdown().await
+            };
+            
+            tokio::select! {
+                result = server => result.context("API server failed during replay")?,
+                result = session => result?,
+            }
+        },
         _ => {
             return Err(anyhow!("Please specify a valid subcommand. Use --help for more information."));
         }
@@ -196,6 +293,218 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Engine services and market data feeds over the matching engines' books, with their
+/// publishers running
+async fn app_services(
+    engines: &Arc<trading_engine::matching_engine::MatchingEngineManager>,
+    market_data: Arc<trading_engine::market_data::MarketDataService>,
+    trade_feed: Arc<trading_engine::market_data::trades::TradeFeed>,
+    funding_store: Arc<dyn trading_engine::derivatives::funding::FundingPaymentStore>,
+    metrics: Arc<utils::metrics::MetricsCollector>,
+    repositories: Option<api::Repositories>,
+    config: &config::Config,
+) -> api::AppServices {
+    // One order-entry throttle for REST, WebSocket and the risk manager, with each user's
+    // tier following their KYC identity tier and VIP fee level
+    let rate_limiter = Arc::new(trading_engine::rate_limiter::OrderRateLimiter::default());
+    let mut risk_manager = trading_engine::risk_management::RiskManager::with_rate_limiter(
+        Default::default(),
+        Arc::clone(&rate_limiter),
+    )
+    .with_metrics(metrics);
+    for (symbol, _) in engines.market_books().await {
+        risk_manager.register_symbol(symbol);
+    }
+    let risk_manager = Arc::new(risk_manager);
+    risk_manager.spawn_latency_reporter(Duration::from_secs(10));
+    
+    // Risk profiles follow the trades the engines execute
+    let (risk_trades, risk_trade_rx) = tokio::sync::mpsc::channel(10_000);
+    engines.add_trade_listener(risk_trades).await;
+    risk_manager.spawn_trade_listener(risk_trade_rx);
+    
+    // Identity checks go through the mock provider until a KYC vendor is integrated
+    let kyc = Arc::new(
+        kyc::KycManager::new(
+            Arc::new(kyc::UserStore::new()),
+            Arc::new(kyc::DocumentStore::new()),
+            Arc::new(kyc::VerificationStore::new()),
+            Arc::new(kyc::MockKycProvider),
+            Arc::new(kyc::RiskScoringEngine::new(kyc::RiskScoringModel {
+                user_factors: Vec::new(),
+                transaction_factors: Vec::new(),
+                behavioral_factors: Vec::new(),
+            })),
+        )
+        .with_rate_limiter(Arc::clone(&rate_limiter)),
+    );
+    let fee_tiers = Arc::new(admin::UserFeeTierStore::new().with_rate_limiter(Arc::clone(&rate_limiter)));
+    
+    let contract_manager = Arc::new(trading_engine::derivatives::ContractManager::new());
+    let position_manager = Arc::new(trading_engine::derivatives::PositionManager::new());
+    
+    // Index prices come from our own spot books plus any configured replay feeds
+    // (`PRICE_FEED_<NAME>=<path>`), and the contracts' books give the basis for the mark
+    let price_service = Arc::new(trading_engine::derivatives::price_index::PriceIndexService::default());
+    let book_feed = Arc::new(trading_engine::derivatives::price_index::OrderBookPriceFeed::new(Arc::clone(engines)));
+    price_service.add_index_feed(book_feed.clone(), dec!(1)).await;
+    price_service.set_contract_feed(book_feed).await;
+    for (key, path) in &config.extra {
+        if let Some(name) = key.strip_prefix("PRICE_FEED_") {
+            match trading_engine::derivatives::price_index::FileReplayFeed::from_file(&name.to_lowercase(), path) {
+                Ok(feed) => price_service.add_index_feed(Arc::new(feed), dec!(1)).await,
+                Err(e) => error!("Failed to load price feed {} from {}: {}", name, path, e),
+            }
+        }
+    }
+    price_service.spawn_updater(Arc::clone(&contract_manager), Duration::from_secs(1));
+    // Derivatives collateral is held in the users' wallets
+    let wallets = Arc::new(wallet::WalletSystem::new());
+    let derivatives = Arc::new(trading_engine::derivatives::DerivativesEngine::new(
+        Arc::clone(&contract_manager),
+        Arc::clone(&position_manager),
+        trading_engine::derivatives::FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+        Arc::clone(&price_service),
+        Arc::clone(engines),
+    ).with_wallet(Arc::clone(&wallets)));
+    // Positions below maintenance margin are liquidated as marks move
+    derivatives.spawn_liquidation_monitor(Duration::from_secs(1));
+    
+    // Margin borrowing draws on lending pools funded from the wallets, with interest charged hourly
+    let lending_pools = Arc::new(trading_engine::derivatives::LendingPoolManager::new(Arc::clone(&wallets)));
+    let margin_accounts = Arc::new(
+        trading_engine::derivatives::MarginAccountManager::new(dec!(1.5), dec!(1.1)).with_lending_pools(lending_pools),
+    );
+    margin_accounts.spawn_interest_accrual();
+    
+    // Portfolio-margined accounts are monitored for liquidation, and their spot balances
+    // cannot be withdrawn from under the portfolio
+    let portfolio_margin = Arc::new(trading_engine::derivatives::portfolio_margin::PortfolioMarginManager::new(
+        Arc::clone(&derivatives),
+        Arc::clone(&wallets) as Arc<dyn trading_engine::derivatives::portfolio_margin::SpotBalanceSource>,
+        margin_accounts,
+        Default::default(),
+    ));
+    wallets.add_withdrawal_guard(Arc::clone(&portfolio_margin) as Arc<dyn wallet::WithdrawalGuard>).await;
+    portfolio_margin.spawn_monitor(Duration::from_secs(5));
+    
+    // Perpetual funding is settled once per interval and recorded in the funding store
+    let funding = Arc::new(trading_engine::derivatives::funding::FundingScheduler::new(
+        contract_manager,
+        position_manager,
+        price_service,
+        derivatives.cross_margin(),
+        trading_engine::derivatives::FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+        funding_store,
+    ));
+    funding.spawn();
+    
+    // Dated futures stop trading ahead of expiry and are cash-settled against the index average
+    let settlement = Arc::new(trading_engine::derivatives::settlement::SettlementScheduler::new(
+        Arc::clone(&derivatives),
+        Default::default(),
+    ));
+    settlement.spawn();
+    
+    let vol_surfaces = Arc::new(trading_engine::derivatives::vol_surface::VolSurfaceService::new(
+        Arc::clone(&derivatives),
+        Default::default(),
+    ));
+    vol_surfaces.spawn_builder(Duration::from_secs(60));
+    
+    // Klines close at their interval boundaries in quiet markets too
+    market_data.spawn_candle_closer();
+    
+    let depth_feed = Arc::new(trading_engine::market_data::depth::DepthFeed::new(Default::default()));
+    depth_feed.spawn_publisher();
+    let l3_feed = Arc::new(trading_engine::market_data::l3::L3Feed::new(Default::default()));
+    l3_feed.spawn_publisher();
+    let liquidity = Arc::new(trading_engine::market_data::liquidity::LiquidityAnalytics::new(Default::default()));
+    liquidity.spawn_resolver();
+    
+    // Every book, including those of contracts listed later, is published and watched
+    // for market abuse, whose alerts become security incidents for the compliance team
+    let incidents = Arc::new(admin::SecurityIncidentStore::new());
+    let account_links = Arc::new(trading_engine::surveillance::AccountLinkRegistry::new());
+    let mut books = engines.subscribe_books().await;
+    {
+        let depth_feed = Arc::clone(&depth_feed);
+        let l3_feed = Arc::clone(&l3_feed);
+        let liquidity = Arc::clone(&liquidity);
+        tokio::spawn(async move {
+            while let Some((symbol, book)) = books.recv().await {
+                depth_feed.register_book(symbol.clone(), Arc::clone(&book)).await;
+                l3_feed.register_book(symbol.clone(), Arc::clone(&book)).await;
+                liquidity.register_book(symbol.clone(), Arc::clone(&book)).await;
+                let events = book.write().subscribe();
+                trading_engine::surveillance::SurveillanceEngine::new(symbol, Default::default(), account_links.clone())
+                    .spawn(events, Arc::clone(&incidents));
+            }
+        });
+    }
+    
+    // The configured pairs are listed as active markets
+    let trading_pairs = Arc::new(admin::TradingPairStore::new());
+    for name in &config.trading_pairs {
+        let listed = match configured_pair(name) {
+            Some(pair) => trading_pairs.add_pair(pair).await,
+            None => Err(anyhow!("expected BASE-QUOTE")),
+        };
+        if let Err(e) = listed {
+            error!("Failed to list trading pair {}: {}", name, e);
+        }
+    }
+    
+    api::AppServices {
+        auth: Arc::new(security::AuthService::new(config)),
+        repositories,
+        rate_limiter,
+        risk_manager,
+        kyc,
+        fee_tiers,
+        derivatives,
+        vol_surfaces,
+        market_data,
+        depth_feed,
+        l3_feed,
+        trading_pairs,
+        liquidity,
+        trade_feed,
+    }
+}
+
+/// An active listing of a configured pair such as `BTC-USDT`, with the default trading rules
+fn configured_pair(name: &str) -> Option<admin::TradingPair> {
+    let (base, quote) = name.split_once('-')?;
+    if base.is_empty() || quote.is_empty() {
+        return None;
+    }
+    let now = chrono::Utc::now();
+    Some(admin::TradingPair {
+        id: name.to_uppercase(),
+        base_asset: base.to_uppercase(),
+        quote_asset: quote.to_uppercase(),
+        min_price: dec!(0.00000001),
+        max_price: dec!(1000000000),
+        price_precision: 8,
+        min_quantity: dec!(0.00000001),
+        max_quantity: dec!(1000000000),
+        quantity_precision: 8,
+        min_notional: dec!(0),
+        maker_fee: dec!(0.001),
+        taker_fee: dec!(0.001),
+        status: admin::TradingPairStatus::Active,
+        created_at: now,
+        updated_at: now,
+        listing_date: Some(now),
+        delisting_date: None,
+        description: None,
+        tags: Vec::new(),
+        is_leveraged: false,
+        max_leverage: None,
+    })
+}
+
 /// Wait for shutdown signal (Ctrl+C)
 async fn wait_for_shutdown() -> Result<()> {
     match signal::ctrl_c().await {
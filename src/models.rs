@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use rust_decimal::Decimal;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
 
 // Define core types
 pub type OrderId = Uuid;
@@ -15,7 +16,8 @@ pub type Quantity = Decimal;
 pub type Timestamp = u64;
 
 /// Side of the order (buy or sell)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Side {
     Buy,
     Sell,
@@ -115,7 +117,7 @@ impl Order {
 }
 
 /// Representation of a trade in the system
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
     pub id: TradeId,
     pub symbol: Symbol,
@@ -0,0 +1,336 @@
+// src/trading_engine/derivatives/cross_margin.rs
+
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+
+use super::{ContractId, MarginType, PayoffType, Position, PositionDirection, PositionId, PositionStatus, UserId};
+
+/// Collateral account shared by all of a user's cross-margin positions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginAccount {
+    pub user_id: UserId,
+    pub settlement_asset: String,
+    /// Deposited collateral plus realized PnL and funding
+    pub collateral: Decimal,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Margin state of a cross account at a set of mark prices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginStatus {
+    pub user_id: UserId,
+    pub collateral: Decimal,
+    pub unrealized_pnl: Decimal,
+    /// Collateral plus unrealized PnL
+    pub equity: Decimal,
+    /// Sum of initial margin of open positions
+    pub initial_margin: Decimal,
+    /// Sum of maintenance margin and liquidation fees of open positions
+    pub maintenance_margin: Decimal,
+    /// Equity not committed as initial margin
+    pub available_margin: Decimal,
+    /// Maintenance margin over equity; the account is liquidated at 1
+    pub margin_ratio: Decimal,
+    pub liquidatable: bool,
+    /// Estimated liquidation price of each position, holding the others' marks fixed
+    pub liquidation_prices: HashMap<PositionId, Decimal>,
+}
+
+/// Maintenance margin ratio of each contract, keyed by contract id
+pub type MaintenanceRatios = HashMap<ContractId, Decimal>;
+
+/// Manager for cross-margin accounts
+pub struct CrossMarginManager {
+    accounts: RwLock<HashMap<UserId, CrossMarginAccount>>,
+}
+
+impl CrossMarginManager {
+    pub fn new() -> Self {
+        CrossMarginManager {
+            accounts: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Open a cross-margin account for a user
+    pub async fn create_account(&self, user_id: UserId, settlement_asset: &str) -> Result<CrossMarginAccount> {
+        let mut accounts = self.accounts.write().await;
+        if accounts.contains_key(&user_id) {
+            return Err(anyhow!("Cross-margin account already exists"));
+        }
+
+        let now = Utc::now();
+        let account = CrossMarginAccount {
+            user_id,
+            settlement_asset: settlement_asset.to_string(),
+            collateral: Decimal::ZERO,
+            created_at: now,
+            updated_at: now,
+        };
+        accounts.insert(user_id, account.clone());
+        Ok(account)
+    }
+
+    pub async fn get_account(&self, user_id: &UserId) -> Option<CrossMarginAccount> {
+        self.accounts.read().await.get(user_id).cloned()
+    }
+
+    /// Add collateral to an account
+    pub async fn deposit(&self, user_id: &UserId, amount: Decimal) -> Result<Decimal> {
+        if amount <= Decimal::ZERO {
+            return Err(anyhow!("Deposit amount must be positive"));
+        }
+        self.adjust_collateral(user_id, amount).await
+    }
+
+    /// Withdraw collateral not needed as initial margin for the open positions
+    pub async fn withdraw(
+        &self,
+        user_id: &UserId,
+        amount: Decimal,
+        positions: &[Position],
+        marks: &HashMap<ContractId, Decimal>,
+        maintenance_ratios: &MaintenanceRatios,
+    ) -> Result<Decimal> {
+        if amount <= Decimal::ZERO {
+            return Err(anyhow!("Withdrawal amount must be positive"));
+        }
+
+        // Hold the account for both the check and the debit so concurrent withdrawals
+        // cannot each pass against the same collateral
+        let mut accounts = self.accounts.write().await;
+        let account = accounts.get_mut(user_id)
+            .ok_or_else(|| anyhow!("Cross-margin account not found"))?;
+
+        let status = Self::evaluate_account(account, positions, marks, maintenance_ratios)?;
+        if amount > status.available_margin.min(status.collateral) {
+            return Err(anyhow!("Insufficient available margin"));
+        }
+
+        account.collateral -= amount;
+        account.updated_at = Utc::now();
+        Ok(account.collateral)
+    }
+
+    /// Credit (positive) or debit (negative) collateral, e.g. for realized PnL or funding
+    pub async fn adjust_collateral(&self, user_id: &UserId, amount: Decimal) -> Result<Decimal> {
+        let mut accounts = self.accounts.write().await;
+        let account = accounts.get_mut(user_id)
+            .ok_or_else(|| anyhow!("Cross-margin account not found"))?;
+
+        account.collateral += amount;
+        account.updated_at = Utc::now();
+        Ok(account.collateral)
+    }
+
+    /// Evaluate an account against the user's open cross positions at the given marks
+    pub async fn evaluate(
+        &self,
+        user_id: &UserId,
+        positions: &[Position],
+        marks: &HashMap<ContractId, Decimal>,
+        maintenance_ratios: &MaintenanceRatios,
+    ) -> Result<CrossMarginStatus> {
+        let account = self.get_account(user_id).await
+            .ok_or_else(|| anyhow!("Cross-margin account not found"))?;
+        Self::evaluate_account(&account, positions, marks, maintenance_ratios)
+    }
+
+    fn evaluate_account(
+        account: &CrossMarginAccount,
+        positions: &[Position],
+        marks: &HashMap<ContractId, Decimal>,
+        maintenance_ratios: &MaintenanceRatios,
+    ) -> Result<CrossMarginStatus> {
+        let user_id = &account.user_id;
+        let open: Vec<&Position> = positions.iter()
+            .filter(|p| p.user_id == *user_id && p.margin_type == MarginType::Cross && p.status == PositionStatus::Open)
+            .collect();
+
+        let mut unrealized_pnl = Decimal::ZERO;
+        let mut initial_margin = Decimal::ZERO;
+        let mut maintenance_margin = Decimal::ZERO;
+        for position in &open {
+            let mark = *marks.get(&position.contract_id)
+                .ok_or_else(|| anyhow!("No mark price for {}", position.contract_id))?;
+            let ratio = maintenance_ratios.get(&position.contract_id)
+                .copied()
+                .unwrap_or(position.maintenance_margin_ratio);
+
+            unrealized_pnl += position.pnl_at(mark);
+            if position.leverage > Decimal::ZERO {
+                initial_margin += position.notional(mark) / position.leverage;
+            }
+            maintenance_margin += position.maintenance_requirement(mark, ratio);
+        }
+
+        let equity = account.collateral + unrealized_pnl;
+        let margin_ratio = if equity > Decimal::ZERO {
+            maintenance_margin / equity
+        } else if maintenance_margin > Decimal::ZERO {
+            Decimal::MAX
+        } else {
+            Decimal::ZERO
+        };
+
+        // Solve equity(P) = maintenance(P) for each position with the others fixed:
+        //   C + U_o + s·q(P - entry) = M_o + q·P·r  =>  P = (C + U_o - M_o - s·q·entry) / (q(r - s))
+        // and for inverse positions:
+        //   C + U_o + s·q(1/entry - 1/P) = M_o + q·r/P  =>  P = q(r + s) / (C + U_o - M_o + s·q/entry)
+        let mut liquidation_prices = HashMap::new();
+        for position in &open {
+            let mark = marks[&position.contract_id];
+            let ratio = maintenance_ratios.get(&position.contract_id)
+                .copied()
+                .unwrap_or(position.maintenance_margin_ratio);
+            let r = ratio + position.liquidation_fee_ratio;
+            let s = match position.direction {
+                PositionDirection::Long => Decimal::ONE,
+                PositionDirection::Short => -Decimal::ONE,
+            };
+
+            let others_pnl = unrealized_pnl - position.pnl_at(mark);
+            let others_maintenance = maintenance_margin - position.maintenance_requirement(mark, ratio);
+            let others = account.collateral + others_pnl - others_maintenance;
+            let price = match position.payoff {
+                PayoffType::Linear => {
+                    let denominator = position.quantity * (r - s);
+                    if denominator == Decimal::ZERO {
+                        continue;
+                    }
+                    (others - s * position.quantity * position.entry_price) / denominator
+                },
+                PayoffType::Inverse => {
+                    let denominator = others + s * position.notional(position.entry_price);
+                    if denominator == Decimal::ZERO {
+                        continue;
+                    }
+                    position.quantity * (r + s) / denominator
+                },
+            };
+            liquidation_prices.insert(position.id, price.max(Decimal::ZERO));
+        }
+
+        Ok(CrossMarginStatus {
+            user_id: *user_id,
+            collateral: account.collateral,
+            unrealized_pnl,
+            equity,
+            initial_margin,
+            maintenance_margin,
+            available_margin: equity - initial_margin,
+            margin_ratio,
+            liquidatable: !open.is_empty() && equity <= maintenance_margin,
+            liquidation_prices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn cross_position(user_id: UserId, contract: &str, direction: PositionDirection, quantity: Decimal, entry: Decimal) -> Position {
+        Position::new(
+            user_id,
+            contract.to_string(),
+            direction,
+            quantity,
+            entry,
+            dec!(10),
+            MarginType::Cross,
+            quantity * entry / dec!(10),
+            dec!(0.01),
+            dec!(0),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_positions_share_collateral() {
+        let manager = CrossMarginManager::new();
+        let user_id = Uuid::new_v4();
+        manager.create_account(user_id, "USDT").await.unwrap();
+        manager.deposit(&user_id, dec!(10000)).await.unwrap();
+
+        // Long BTC and long ETH on the same collateral
+        let positions = vec![
+            cross_position(user_id, "BTC-PERP", PositionDirection::Long, dec!(1), dec!(50000)),
+            cross_position(user_id, "ETH-PERP", PositionDirection::Long, dec!(10), dec!(3000)),
+        ];
+        let ratios: MaintenanceRatios = [("BTC-PERP".to_string(), dec!(0.01)), ("ETH-PERP".to_string(), dec!(0.01))]
+            .into_iter().collect();
+
+        // ETH gains offset BTC losses that would have liquidated an isolated position
+        let marks: HashMap<ContractId, Decimal> = [("BTC-PERP".to_string(), dec!(44000)), ("ETH-PERP".to_string(), dec!(3500))]
+            .into_iter().collect();
+        let status = manager.evaluate(&user_id, &positions, &marks, &ratios).await.unwrap();
+        assert_eq!(status.unrealized_pnl, dec!(-1000));
+        assert_eq!(status.equity, dec!(9000));
+        assert_eq!(status.maintenance_margin, dec!(790));
+        assert!(!status.liquidatable);
+        assert!(positions[0].equity(dec!(44000)) < positions[0].maintenance_requirement(dec!(44000), dec!(0.01)));
+
+        // Both legs falling together exhausts the shared equity
+        let marks: HashMap<ContractId, Decimal> = [("BTC-PERP".to_string(), dec!(45000)), ("ETH-PERP".to_string(), dec!(2500))]
+            .into_iter().collect();
+        let status = manager.evaluate(&user_id, &positions, &marks, &ratios).await.unwrap();
+        assert_eq!(status.equity, dec!(0));
+        assert!(status.liquidatable);
+    }
+
+    #[tokio::test]
+    async fn test_cross_liquidation_price_matches_isolated_for_single_position() {
+        let manager = CrossMarginManager::new();
+        let user_id = Uuid::new_v4();
+        manager.create_account(user_id, "USDT").await.unwrap();
+        manager.deposit(&user_id, dec!(5000)).await.unwrap();
+
+        let position = cross_position(user_id, "BTC-PERP", PositionDirection::Long, dec!(1), dec!(50000));
+        let marks: HashMap<ContractId, Decimal> = [("BTC-PERP".to_string(), dec!(50000))].into_iter().collect();
+        let status = manager.evaluate(&user_id, &[position.clone()], &marks, &MaintenanceRatios::new()).await.unwrap();
+
+        // With collateral equal to the isolated margin both give the same price
+        assert_eq!(status.liquidation_prices[&position.id], position.calculate_liquidation_price());
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_limited_to_available_margin() {
+        let manager = CrossMarginManager::new();
+        let user_id = Uuid::new_v4();
+        manager.create_account(user_id, "USDT").await.unwrap();
+        manager.deposit(&user_id, dec!(10000)).await.unwrap();
+
+        let positions = vec![cross_position(user_id, "BTC-PERP", PositionDirection::Long, dec!(1), dec!(50000))];
+        let marks: HashMap<ContractId, Decimal> = [("BTC-PERP".to_string(), dec!(50000))].into_iter().collect();
+
+        // 5,000 is committed as initial margin at 10x
+        assert!(manager.withdraw(&user_id, dec!(6000), &positions, &marks, &MaintenanceRatios::new()).await.is_err());
+        assert_eq!(manager.withdraw(&user_id, dec!(5000), &positions, &marks, &MaintenanceRatios::new()).await.unwrap(), dec!(5000));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_withdrawals_cannot_overdraw() {
+        let manager = CrossMarginManager::new();
+        let user_id = Uuid::new_v4();
+        manager.create_account(user_id, "USDT").await.unwrap();
+        manager.deposit(&user_id, dec!(10000)).await.unwrap();
+
+        let positions = vec![cross_position(user_id, "BTC-PERP", PositionDirection::Long, dec!(1), dec!(50000))];
+        let marks: HashMap<ContractId, Decimal> = [("BTC-PERP".to_string(), dec!(50000))].into_iter().collect();
+        let ratios = MaintenanceRatios::new();
+
+        // Only 5,000 is free, so only one of two 5,000 withdrawals goes through
+        let (first, second) = tokio::join!(
+            manager.withdraw(&user_id, dec!(5000), &positions, &marks, &ratios),
+            manager.withdraw(&user_id, dec!(5000), &positions, &marks, &ratios),
+        );
+        assert!(first.is_ok() != second.is_ok());
+        assert_eq!(manager.get_account(&user_id).await.unwrap().collateral, dec!(5000));
+    }
+}
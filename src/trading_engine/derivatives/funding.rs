@@ -0,0 +1,543 @@
+// src/trading_engine/derivatives/funding.rs
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use rust_decimal::Decimal;
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{error, info, warn};
+
+use super::price_index::PriceIndexService;
+use super::cross_margin::CrossMarginManager;
+use super::{
+    Contract, ContractId, ContractManager, ContractType, FundingPayment, FundingRate,
+    FundingRateCalculator, MarginType, PositionManager, UserId,
+};
+
+/// Seconds between premium samples
+pub const PREMIUM_SAMPLE_INTERVAL_SECS: i64 = 60;
+
+/// Persistence for settled funding intervals and their payments
+#[async_trait]
+pub trait FundingPaymentStore: Send + Sync {
+    /// Record a contract's settlement at `funding_time` together with its payments.
+    /// Returns false, saving nothing, if that interval was already recorded.
+    async fn record_settlement(&self, contract_id: &str, funding_time: DateTime<Utc>, payments: &[FundingPayment]) -> Result<bool>;
+    /// Latest funding time recorded for a contract
+    async fn last_settlement(&self, contract_id: &str) -> Result<Option<DateTime<Utc>>>;
+    /// Payments for a user, optionally restricted to a contract, newest first
+    async fn get_user_payments(&self, user_id: &UserId, contract_id: Option<&str>, limit: usize) -> Result<Vec<FundingPayment>>;
+    /// Payments for a contract, newest first
+    async fn get_contract_payments(&self, contract_id: &str, limit: usize) -> Result<Vec<FundingPayment>>;
+}
+
+/// In-memory funding payment history
+pub struct InMemoryFundingPaymentStore {
+    settlements: RwLock<HashSet<(ContractId, DateTime<Utc>)>>,
+    payments: RwLock<Vec<FundingPayment>>,
+}
+
+impl InMemoryFundingPaymentStore {
+    pub fn new() -> Self {
+        InMemoryFundingPaymentStore {
+            settlements: RwLock::new(HashSet::new()),
+            payments: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl FundingPaymentStore for InMemoryFundingPaymentStore {
+    async fn record_settlement(&self, contract_id: &str, funding_time: DateTime<Utc>, payments: &[FundingPayment]) -> Result<bool> {
+        if !self.settlements.write().await.insert((contract_id.to_string(), funding_time)) {
+            return Ok(false);
+        }
+        self.payments.write().await.extend_from_slice(payments);
+        Ok(true)
+    }
+
+    async fn last_settlement(&self, contract_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let settlements = self.settlements.read().await;
+        Ok(settlements.iter()
+            .filter(|(contract, _)| contract == contract_id)
+            .map(|(_, funding_time)| *funding_time)
+            .max())
+    }
+
+    async fn get_user_payments(&self, user_id: &UserId, contract_id: Option<&str>, limit: usize) -> Result<Vec<FundingPayment>> {
+        let payments = self.payments.read().await;
+        Ok(payments.iter()
+            .rev()
+            .filter(|p| p.user_id == *user_id && contract_id.map_or(true, |c| p.contract_id == c))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_contract_payments(&self, contract_id: &str, limit: usize) -> Result<Vec<FundingPayment>> {
+        let payments = self.payments.read().await;
+        Ok(payments.iter()
+            .rev()
+            .filter(|p| p.contract_id == contract_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Premium index observation for one minute
+#[derive(Debug, Clone)]
+struct PremiumSample {
+    /// Start of the minute the sample covers
+    minute: DateTime<Utc>,
+    premium: Decimal,
+}
+
+/// Result of settling one funding interval for a contract
+#[derive(Debug, Clone)]
+pub struct FundingSettlement {
+    pub contract_id: ContractId,
+    pub funding_time: DateTime<Utc>,
+    /// Time-weighted premium index over the interval
+    pub premium_index: Decimal,
+    pub funding_rate: FundingRate,
+    pub mark_price: Decimal,
+    pub payments: Vec<FundingPayment>,
+}
+
+/// Samples the premium index every minute and settles perpetual funding every
+/// `funding_interval_hours` into isolated positions' margin or the user's
+/// cross-margin collateral. Each (contract, funding time) is recorded in the store
+/// before any balance moves, so an interval is paid at most once across restarts.
+/// Recorded payments that fail to apply are kept and retried on every run until
+/// they land, so the balances catch up with the recorded history.
+pub struct FundingScheduler {
+    contract_manager: Arc<ContractManager>,
+    position_manager: Arc<PositionManager>,
+    price_service: Arc<PriceIndexService>,
+    cross_margin: Arc<CrossMarginManager>,
+    calculator: FundingRateCalculator,
+    store: Arc<dyn FundingPaymentStore>,
+    /// Premium samples for the current interval by contract
+    samples: RwLock<HashMap<ContractId, Vec<PremiumSample>>>,
+    /// Last settled funding time by contract
+    last_settlement: RwLock<HashMap<ContractId, DateTime<Utc>>>,
+    /// Recorded payments not yet applied to a balance
+    pending: RwLock<Vec<(FundingPayment, MarginType)>>,
+}
+
+impl FundingScheduler {
+    pub fn new(
+        contract_manager: Arc<ContractManager>,
+        position_manager: Arc<PositionManager>,
+        price_service: Arc<PriceIndexService>,
+        cross_margin: Arc<CrossMarginManager>,
+        calculator: FundingRateCalculator,
+        store: Arc<dyn FundingPaymentStore>,
+    ) -> Self {
+        FundingScheduler {
+            contract_manager,
+            position_manager,
+            price_service,
+            cross_margin,
+            calculator,
+            store,
+            samples: RwLock::new(HashMap::new()),
+            last_settlement: RwLock::new(HashMap::new()),
+            pending: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Funding history store
+    pub fn store(&self) -> Arc<dyn FundingPaymentStore> {
+        self.store.clone()
+    }
+
+    /// Recorded payments still waiting to be applied
+    pub async fn pending_payments(&self) -> Vec<FundingPayment> {
+        self.pending.read().await.iter().map(|(payment, _)| payment.clone()).collect()
+    }
+
+    /// Length of a funding interval in seconds
+    fn interval_secs(&self) -> i64 {
+        i64::from(self.calculator.funding_interval_hours().max(1)) * 3600
+    }
+
+    /// Most recent funding time at or before `now`; funding times are aligned to
+    /// multiples of the interval since midnight UTC
+    pub fn funding_time_at_or_before(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let interval = self.interval_secs();
+        let seconds = now.timestamp().div_euclid(interval) * interval;
+        Utc.timestamp_opt(seconds, 0).unwrap()
+    }
+
+    /// Next funding time after `now`
+    pub fn next_funding_time(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.funding_time_at_or_before(now) + chrono::Duration::seconds(self.interval_secs())
+    }
+
+    async fn perpetual_contracts(&self) -> Vec<Contract> {
+        self.contract_manager.list_active_contracts().await
+            .into_iter()
+            .filter(|c| c.contract_type == ContractType::Perpetual)
+            .collect()
+    }
+
+    /// Record the current premium of every perpetual contract
+    pub async fn sample_premiums(&self, now: DateTime<Utc>) {
+        let minute = Utc.timestamp_opt(
+            now.timestamp().div_euclid(PREMIUM_SAMPLE_INTERVAL_SECS) * PREMIUM_SAMPLE_INTERVAL_SECS,
+            0,
+        ).unwrap();
+
+        for contract in self.perpetual_contracts().await {
+            let prices = match self.price_service.get_prices(&contract.id).await {
+                Some(prices) if prices.index_price > Decimal::ZERO => prices,
+                _ => {
+                    warn!("No prices to sample premium for {}", contract.id);
+                    continue;
+                },
+            };
+            let premium = (prices.mark_price - prices.index_price) / prices.index_price;
+
+            let mut samples = self.samples.write().await;
+            let contract_samples = samples.entry(contract.id.clone()).or_insert_with(Vec::new);
+
+            // One sample per minute; a repeated sample replaces the earlier one
+            match contract_samples.last_mut() {
+                Some(last) if last.minute == minute => last.premium = premium,
+                _ => contract_samples.push(PremiumSample { minute, premium }),
+            }
+        }
+    }
+
+    /// Time-weighted premium index of the samples taken before `funding_time`
+    pub async fn premium_index(&self, contract_id: &str, funding_time: DateTime<Utc>) -> Option<Decimal> {
+        let samples = self.samples.read().await;
+        let interval_start = funding_time - chrono::Duration::seconds(self.interval_secs());
+        let window: Vec<&PremiumSample> = samples.get(contract_id)?
+            .iter()
+            .filter(|s| s.minute >= interval_start && s.minute < funding_time)
+            .collect();
+
+        if window.is_empty() {
+            return None;
+        }
+
+        // Each sample covers one minute, so the mean is time-weighted
+        let total: Decimal = window.iter().map(|s| s.premium).sum();
+        Some(total / Decimal::from(window.len()))
+    }
+
+    /// Settle every perpetual contract whose funding time has passed
+    pub async fn settle_due(&self, now: DateTime<Utc>) -> Result<Vec<FundingSettlement>> {
+        self.retry_pending().await;
+
+        let funding_time = self.funding_time_at_or_before(now);
+        let mut settlements = Vec::new();
+
+        for contract in self.perpetual_contracts().await {
+            let last = self.last_settlement.read().await.get(&contract.id).copied();
+            let last = match last {
+                Some(last) => Some(last),
+                // Pick up where the store left off after a restart
+                None => self.store.last_settlement(&contract.id).await?,
+            };
+            if let Some(last) = last.filter(|last| *last >= funding_time) {
+                self.last_settlement.write().await.insert(contract.id.clone(), last);
+                continue;
+            }
+
+            match self.settle_contract(&contract, funding_time).await {
+                Ok(Some(settlement)) => settlements.push(settlement),
+                Ok(None) => {},
+                Err(e) => warn!("Funding settlement failed for {}: {}", contract.id, e),
+            }
+        }
+
+        Ok(settlements)
+    }
+
+    /// Settle one funding interval for a contract
+    async fn settle_contract(&self, contract: &Contract, funding_time: DateTime<Utc>) -> Result<Option<FundingSettlement>> {
+        let premium_index = match self.premium_index(&contract.id, funding_time).await {
+            Some(premium) => premium,
+            None => {
+                // Nothing sampled yet (e.g. the contract was just listed); start from here
+                self.last_settlement.write().await.insert(contract.id.clone(), funding_time);
+                return Ok(None);
+            },
+        };
+
+        let mark_price = self.price_service.mark_price(&contract.id).await?;
+        let funding_rate = self.calculator.calculate_funding_rate(premium_index, contract.funding_rate_cap);
+
+        let mut payments = Vec::new();
+        let mut margin_types = Vec::new();
+        for position in self.position_manager.get_contract_open_positions(&contract.id).await {
+            let payment_amount = self.calculator.calculate_funding_payment(&position, funding_rate, mark_price);
+            if payment_amount == Decimal::ZERO {
+                continue;
+            }
+
+            margin_types.push(position.margin_type);
+            payments.push(FundingPayment {
+                id: Uuid::new_v4(),
+                user_id: position.user_id,
+                contract_id: contract.id.clone(),
+                position_id: position.id,
+                funding_rate,
+                payment_amount,
+                timestamp: funding_time,
+            });
+        }
+
+        // Claim the interval before moving any balance
+        let recorded = self.store.record_settlement(&contract.id, funding_time, &payments).await?;
+
+        // Start the next interval with a clean sample set
+        if let Some(samples) = self.samples.write().await.get_mut(&contract.id) {
+            samples.retain(|s| s.minute >= funding_time);
+        }
+        self.last_settlement.write().await.insert(contract.id.clone(), funding_time);
+
+        if !recorded {
+            info!("Funding for {} at {} was already settled", contract.id, funding_time);
+            return Ok(None);
+        }
+
+        for (payment, margin_type) in payments.iter().zip(margin_types) {
+            if let Err(e) = self.apply_payment(payment, margin_type).await {
+                error!(
+                    "Recorded funding payment {} of {} for position {} was not applied, will retry: {}",
+                    payment.id, payment.payment_amount, payment.position_id, e
+                );
+                self.pending.write().await.push((payment.clone(), margin_type));
+            }
+        }
+
+        info!(
+            "Settled funding for {} at {}: rate {}, {} payments",
+            contract.id, funding_time, funding_rate, payments.len()
+        );
+
+        Ok(Some(FundingSettlement {
+            contract_id: contract.id.clone(),
+            funding_time,
+            premium_index,
+            funding_rate,
+            mark_price,
+            payments,
+        }))
+    }
+
+    /// Move a recorded payment into the balance it settles against: isolated funding
+    /// moves the position's margin, cross funding the shared collateral
+    async fn apply_payment(&self, payment: &FundingPayment, margin_type: MarginType) -> Result<Decimal> {
+        match margin_type {
+            MarginType::Isolated => self.position_manager.adjust_margin(&payment.position_id, payment.payment_amount).await,
+            MarginType::Cross => self.cross_margin.adjust_collateral(&payment.user_id, payment.payment_amount).await,
+        }
+    }
+
+    /// Apply the recorded payments that failed earlier, keeping those that fail again
+    async fn retry_pending(&self) {
+        let pending = std::mem::take(&mut *self.pending.write().await);
+        let mut still_pending = Vec::new();
+        for (payment, margin_type) in pending {
+            match self.apply_payment(&payment, margin_type).await {
+                Ok(_) => info!("Applied pending funding payment {} for position {}", payment.id, payment.position_id),
+                Err(e) => {
+                    warn!("Pending funding payment {} for position {} still not applied: {}", payment.id, payment.position_id, e);
+                    still_pending.push((payment, margin_type));
+                },
+            }
+        }
+        self.pending.write().await.extend(still_pending);
+    }
+
+    /// Sample premiums every minute and settle funding when due
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let scheduler = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = time::interval(Duration::from_secs(PREMIUM_SAMPLE_INTERVAL_SECS as u64));
+            loop {
+                ticker.tick().await;
+                let now = Utc::now();
+                if let Err(e) = scheduler.settle_due(now).await {
+                    warn!("Funding settlement failed: {}", e);
+                }
+                scheduler.sample_premiums(now).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::price_index::{FileReplayFeed, PriceRecord};
+    use super::super::{MarginType, PayoffType, Position, PositionDirection};
+    use rust_decimal_macros::dec;
+
+    fn contract() -> Contract {
+        Contract {
+            id: "BTC-PERP".to_string(),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Perpetual,
+            tick_size: dec!(0.5),
+            lot_size: dec!(0.001),
+            leverage_max: dec!(100),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: None,
+            settlement_asset: "USDT".to_string(),
+            option_type: None,
+            strike_price: None,
+            funding_rate_cap: Some(dec!(0.001)),
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_funding_settles_into_margin() {
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        let price_service = Arc::new(PriceIndexService::default());
+        let store = Arc::new(InMemoryFundingPaymentStore::new());
+
+        // Perp trades 1% over spot for the whole interval
+        let start = Utc.timestamp_opt(1_700_006_400, 0).unwrap(); // 00:00 UTC
+        let minutes = || (0..8 * 60).map(move |m| start + chrono::Duration::minutes(m));
+        let spot = FileReplayFeed::from_records("spot", minutes()
+            .map(|t| PriceRecord { timestamp: t, symbol: "BTC/USDT".to_string(), price: dec!(50000) })
+            .collect());
+        let perp = FileReplayFeed::from_records("perp", minutes()
+            .map(|t| PriceRecord { timestamp: t, symbol: "BTC-PERP".to_string(), price: dec!(50500) })
+            .collect());
+        price_service.add_index_feed(Arc::new(spot), dec!(1)).await;
+        price_service.set_contract_feed(Arc::new(perp)).await;
+
+        let contract = contract();
+        contract_manager.add_contract(contract.clone()).await.unwrap();
+
+        let long = Position::new(Uuid::new_v4(), contract.id.clone(), PositionDirection::Long,
+                                 dec!(1), dec!(50000), dec!(10), MarginType::Isolated, dec!(5000), dec!(0.01), dec!(0));
+        let short = Position::new(Uuid::new_v4(), contract.id.clone(), PositionDirection::Short,
+                                  dec!(1), dec!(50000), dec!(10), MarginType::Isolated, dec!(5000), dec!(0.01), dec!(0));
+        position_manager.add_position(long.clone()).await.unwrap();
+        position_manager.add_position(short.clone()).await.unwrap();
+
+        let new_scheduler = || FundingScheduler::new(
+            contract_manager.clone(),
+            position_manager.clone(),
+            price_service.clone(),
+            Arc::new(CrossMarginManager::new()),
+            FundingRateCalculator::new(dec!(0), dec!(1), 8),
+            store.clone(),
+        );
+        let scheduler = new_scheduler();
+
+        // Sample every minute of the 8 hour interval
+        for now in minutes() {
+            price_service.update_at(&contract, now).await.unwrap();
+            scheduler.sample_premiums(now).await;
+        }
+
+        let funding_time = start + chrono::Duration::hours(8);
+        let settlements = scheduler.settle_due(funding_time).await.unwrap();
+        assert_eq!(settlements.len(), 1);
+
+        // 1% premium is clamped to the 0.1% cap
+        let settlement = &settlements[0];
+        assert_eq!(settlement.premium_index, dec!(0.01));
+        assert_eq!(settlement.funding_rate, dec!(0.001));
+
+        // Long pays the short 0.1% of 50,500 notional
+        let long_after = position_manager.get_position(&long.id).await.unwrap();
+        let short_after = position_manager.get_position(&short.id).await.unwrap();
+        assert_eq!(long_after.margin_amount, dec!(5000) - dec!(50.5));
+        assert_eq!(short_after.margin_amount, dec!(5000) + dec!(50.5));
+
+        // Settling again for the same interval is a no-op
+        assert!(scheduler.settle_due(funding_time + chrono::Duration::minutes(1)).await.unwrap().is_empty());
+
+        // So is settling it from a restarted scheduler sharing the store
+        let restarted = new_scheduler();
+        assert!(restarted.settle_due(funding_time + chrono::Duration::minutes(2)).await.unwrap().is_empty());
+        assert_eq!(position_manager.get_position(&long.id).await.unwrap().margin_amount, dec!(5000) - dec!(50.5));
+
+        // History is queryable by user and contract
+        let history = store.get_user_payments(&long.user_id, Some("BTC-PERP"), 10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].payment_amount, dec!(-50.5));
+        assert_eq!(store.get_contract_payments("BTC-PERP", 10).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unapplied_funding_is_retried() {
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        let price_service = Arc::new(PriceIndexService::default());
+        let cross_margin = Arc::new(CrossMarginManager::new());
+
+        // Perp trades 1% over spot for the last minutes of the interval
+        let start = Utc.timestamp_opt(1_700_006_400 + 8 * 3600 - 180, 0).unwrap();
+        let minutes = || (0..3).map(move |m| start + chrono::Duration::minutes(m));
+        let spot = FileReplayFeed::from_records("spot", minutes()
+            .map(|t| PriceRecord { timestamp: t, symbol: "BTC/USDT".to_string(), price: dec!(50000) })
+            .collect());
+        let perp = FileReplayFeed::from_records("perp", minutes()
+            .map(|t| PriceRecord { timestamp: t, symbol: "BTC-PERP".to_string(), price: dec!(50500) })
+            .collect());
+        price_service.add_index_feed(Arc::new(spot), dec!(1)).await;
+        price_service.set_contract_feed(Arc::new(perp)).await;
+
+        let contract = contract();
+        contract_manager.add_contract(contract.clone()).await.unwrap();
+
+        // Cross position whose user has no cross-margin account yet
+        let long = Position::new(Uuid::new_v4(), contract.id.clone(), PositionDirection::Long,
+                                 dec!(1), dec!(50000), dec!(10), MarginType::Cross, dec!(5000), dec!(0.01), dec!(0));
+        position_manager.add_position(long.clone()).await.unwrap();
+
+        let scheduler = FundingScheduler::new(
+            contract_manager,
+            position_manager,
+            price_service.clone(),
+            cross_margin.clone(),
+            FundingRateCalculator::new(dec!(0), dec!(1), 8),
+            Arc::new(InMemoryFundingPaymentStore::new()),
+        );
+        for now in minutes() {
+            price_service.update_at(&contract, now).await.unwrap();
+            scheduler.sample_premiums(now).await;
+        }
+
+        // The interval is recorded but the payment has nowhere to go
+        let funding_time = start + chrono::Duration::minutes(3);
+        assert_eq!(scheduler.settle_due(funding_time).await.unwrap().len(), 1);
+        assert_eq!(scheduler.pending_payments().await.len(), 1);
+
+        // Once the account exists the next run applies it, exactly once
+        cross_margin.create_account(long.user_id, "USDT").await.unwrap();
+        assert!(scheduler.settle_due(funding_time + chrono::Duration::minutes(1)).await.unwrap().is_empty());
+        assert!(scheduler.pending_payments().await.is_empty());
+        assert_eq!(cross_margin.get_account(&long.user_id).await.unwrap().collateral, dec!(-50.5));
+
+        assert!(scheduler.settle_due(funding_time + chrono::Duration::minutes(2)).await.unwrap().is_empty());
+        assert_eq!(cross_margin.get_account(&long.user_id).await.unwrap().collateral, dec!(-50.5));
+    }
+}
@@ -0,0 +1,827 @@
+// src/trading_engine/derivatives/liquidation.rs
+
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::trading_engine::matching_engine::{Side, TimeInForce};
+use super::{
+    Contract, ContractId, DerivativesEngine, MarginType, PayoffType, Position, PositionDirection, PositionFill,
+    PositionId, PositionStatus, UserId,
+};
+use super::orders::{DerivativeOrderRequest, OrderOrigin};
+
+/// Parameters of the tiered liquidation process
+#[derive(Debug, Clone)]
+pub struct LiquidationConfig {
+    /// Maximum notional, at mark, closed by a single liquidation order
+    pub step_notional: Decimal,
+    /// Liquidation orders placed before the remainder goes to the insurance fund
+    pub max_steps: usize,
+    /// Account that holds positions taken over by the insurance fund; never liquidated itself
+    pub backstop_account: UserId,
+}
+
+impl Default for LiquidationConfig {
+    fn default() -> Self {
+        LiquidationConfig {
+            step_notional: Decimal::from(100_000),
+            max_steps: 10,
+            backstop_account: Uuid::nil(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InsuranceFundEntryType {
+    Deposit,
+    LiquidationFee,
+    /// Surplus or deficit of a bankrupt position taken over by the fund
+    Takeover,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceFundEntry {
+    pub id: Uuid,
+    pub asset: String,
+    pub entry_type: InsuranceFundEntryType,
+    pub amount: Decimal,
+    pub position_id: Option<PositionId>,
+    pub balance_after: Decimal,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Insurance fund per settlement asset, fed by liquidation fees and drawn on for bankrupt positions
+pub struct InsuranceFund {
+    balances: RwLock<HashMap<String, Decimal>>,
+    entries: RwLock<Vec<InsuranceFundEntry>>,
+}
+
+impl InsuranceFund {
+    pub fn new() -> Self {
+        InsuranceFund {
+            balances: RwLock::new(HashMap::new()),
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub async fn balance(&self, asset: &str) -> Decimal {
+        self.balances.read().await.get(asset).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    /// Seed or top up the fund
+    pub async fn deposit(&self, asset: &str, amount: Decimal) -> Result<Decimal> {
+        if amount <= Decimal::ZERO {
+            return Err(anyhow!("Deposit amount must be positive"));
+        }
+        Ok(self.record(asset, InsuranceFundEntryType::Deposit, amount, None).await)
+    }
+
+    /// Credit a liquidation fee
+    pub async fn credit_fee(&self, asset: &str, amount: Decimal, position_id: PositionId) -> Decimal {
+        self.record(asset, InsuranceFundEntryType::LiquidationFee, amount, Some(position_id)).await
+    }
+
+    /// Take over positions' remaining equity, each negative for a deficit, with one entry
+    /// per position. Returns false, leaving the fund unchanged, if the fund cannot cover
+    /// the combined deficit.
+    pub async fn absorb(&self, asset: &str, shares: &[(PositionId, Decimal)]) -> bool {
+        let mut balances = self.balances.write().await;
+        let balance = balances.entry(asset.to_string()).or_insert(Decimal::ZERO);
+        let total: Decimal = shares.iter().map(|(_, amount)| *amount).sum();
+        if *balance + total < Decimal::ZERO {
+            return false;
+        }
+
+        let mut recorded = Vec::with_capacity(shares.len());
+        for (position_id, amount) in shares {
+            *balance += *amount;
+            recorded.push((*position_id, *amount, *balance));
+        }
+        drop(balances);
+
+        for (position_id, amount, balance_after) in recorded {
+            self.push_entry(asset, InsuranceFundEntryType::Takeover, amount, Some(position_id), balance_after).await;
+        }
+        true
+    }
+
+    /// Most recent entries for an asset, newest first
+    pub async fn entries(&self, asset: &str, limit: usize) -> Vec<InsuranceFundEntry> {
+        self.entries.read().await.iter()
+            .rev()
+            .filter(|e| e.asset == asset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    async fn record(
+        &self,
+        asset: &str,
+        entry_type: InsuranceFundEntryType,
+        amount: Decimal,
+        position_id: Option<PositionId>,
+    ) -> Decimal {
+        let balance_after = {
+            let mut balances = self.balances.write().await;
+            let balance = balances.entry(asset.to_string()).or_insert(Decimal::ZERO);
+            *balance += amount;
+            *balance
+        };
+        self.push_entry(asset, entry_type, amount, position_id, balance_after).await;
+        balance_after
+    }
+
+    async fn push_entry(
+        &self,
+        asset: &str,
+        entry_type: InsuranceFundEntryType,
+        amount: Decimal,
+        position_id: Option<PositionId>,
+        balance_after: Decimal,
+    ) {
+        self.entries.write().await.push(InsuranceFundEntry {
+            id: Uuid::new_v4(),
+            asset: asset.to_string(),
+            entry_type,
+            amount,
+            position_id,
+            balance_after,
+            timestamp: Utc::now(),
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LiquidationStage {
+    /// A liquidation order filled on the book
+    PartialReduction,
+    /// The insurance fund took over the remaining position
+    InsuranceTakeover,
+    /// An opposite position was closed against the bankrupt one
+    AutoDeleverage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationEvent {
+    pub position_id: PositionId,
+    pub user_id: UserId,
+    pub contract_id: ContractId,
+    pub stage: LiquidationStage,
+    pub quantity: Decimal,
+    /// Average fill price, or the bankruptcy price the user was closed at for a takeover or deleveraging
+    pub price: Decimal,
+    pub fee: Decimal,
+    /// Change to the insurance fund balance
+    pub insurance_delta: Decimal,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Outcome of liquidating an isolated position or a cross account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationReport {
+    pub user_id: UserId,
+    pub margin_type: MarginType,
+    pub cancelled_orders: usize,
+    pub events: Vec<LiquidationEvent>,
+    /// The liquidated positions in their final state
+    pub positions: Vec<Position>,
+}
+
+/// Price at which the position alone would bring the given equity to zero
+pub fn bankruptcy_price(position: &Position, mark_price: Decimal, equity: Decimal) -> Decimal {
+    if position.quantity <= Decimal::ZERO {
+        return mark_price;
+    }
+    if position.payoff == PayoffType::Inverse {
+        // Equity changes by q(1/mark - 1/P) for a long, so q/P moves by the equity
+        let value = position.notional(mark_price);
+        let value_at_bankruptcy = match position.direction {
+            PositionDirection::Long => value + equity,
+            PositionDirection::Short => value - equity,
+        };
+        // A short holding more equity than its value cannot go bankrupt; close it at the mark
+        if value_at_bankruptcy <= Decimal::ZERO {
+            return mark_price;
+        }
+        return position.quantity / value_at_bankruptcy;
+    }
+    let price = match position.direction {
+        PositionDirection::Long => mark_price - equity / position.quantity,
+        PositionDirection::Short => mark_price + equity / position.quantity,
+    };
+    price.max(Decimal::ZERO)
+}
+
+/// Auto-deleveraging rank: PnL as a fraction of entry notional times leverage
+pub fn adl_score(position: &Position, mark_price: Decimal) -> Decimal {
+    let notional = position.notional(position.entry_price);
+    if notional <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    position.pnl_at(mark_price) / notional * position.leverage
+}
+
+/// Round a liquidation price to the tick so it never crosses the bankruptcy price
+fn round_to_tick(price: Decimal, tick_size: Decimal, side: Side) -> Decimal {
+    if tick_size <= Decimal::ZERO {
+        return price;
+    }
+    let ticks = price / tick_size;
+    let ticks = match side {
+        Side::Sell => ticks.ceil(),
+        Side::Buy => ticks.floor(),
+    };
+    ticks * tick_size
+}
+
+fn closing_side(position: &Position) -> Side {
+    match position.direction {
+        PositionDirection::Long => Side::Sell,
+        PositionDirection::Short => Side::Buy,
+    }
+}
+
+impl DerivativesEngine {
+    /// Liquidate an isolated position below maintenance margin: cancel the user's orders
+    /// on the contract, reduce it on the book in steps, then hand any remainder to the
+    /// insurance fund or deleverage opposite positions
+    pub async fn liquidate_isolated(&self, position_id: &PositionId) -> Result<LiquidationReport> {
+        let position = self.position_manager.get_position(position_id).await
+            .ok_or_else(|| anyhow!("Position not found"))?;
+        if position.margin_type != MarginType::Isolated || position.status != PositionStatus::Open {
+            return Err(anyhow!("Position is not an open isolated position"));
+        }
+        let contract = self.contract_manager.get_contract(&position.contract_id).await
+            .ok_or_else(|| anyhow!("Contract not found"))?;
+
+        let mut report = LiquidationReport {
+            user_id: position.user_id,
+            margin_type: MarginType::Isolated,
+            cancelled_orders: self.cancel_user_orders(&position.user_id, Some(&contract.id)).await?,
+            events: Vec::new(),
+            positions: Vec::new(),
+        };
+
+        for _ in 0..self.liquidation_config.max_steps {
+            let position = match self.position_manager.get_position(position_id).await {
+                Some(position) if position.status == PositionStatus::Open => position,
+                _ => break,
+            };
+            let mark_price = self.contract_mark_price(&contract).await?;
+            let ratio = contract.maintenance_margin_ratio_for(position.notional(mark_price));
+            if !position.check_liquidation(mark_price, ratio) {
+                break;
+            }
+
+            let equity = position.equity(mark_price);
+            if equity <= Decimal::ZERO {
+                break;
+            }
+            let bankruptcy = bankruptcy_price(&position, mark_price, equity);
+            match self.place_liquidation_order(&contract, &position, mark_price, bankruptcy).await? {
+                Some(event) => report.events.push(event),
+                // The book cannot absorb the position above its bankruptcy price
+                None => break,
+            }
+        }
+
+        if let Some(position) = self.position_manager.get_position(position_id).await {
+            let mark_price = self.contract_mark_price(&contract).await?;
+            let ratio = contract.maintenance_margin_ratio_for(position.notional(mark_price));
+            if position.check_liquidation(mark_price, ratio) {
+                let equity = position.equity(mark_price);
+                report.events.extend(self.take_over(&contract, vec![(position, mark_price, equity)]).await?);
+            }
+        }
+
+        if let Some(position) = self.position_manager.get_position(position_id).await {
+            report.positions.push(position);
+        }
+        info!("Liquidated isolated position {} in {} steps", position_id, report.events.len());
+        Ok(report)
+    }
+
+    /// Liquidate a cross account below maintenance margin: cancel all of the user's orders,
+    /// reduce the largest position on the book in steps until the account is healthy, then
+    /// hand any remainder to the insurance fund or deleverage opposite positions
+    pub async fn liquidate_cross(&self, user_id: &UserId) -> Result<LiquidationReport> {
+        let mut report = LiquidationReport {
+            user_id: *user_id,
+            margin_type: MarginType::Cross,
+            cancelled_orders: self.cancel_user_orders(user_id, None).await?,
+            events: Vec::new(),
+            positions: Vec::new(),
+        };
+        let mut touched: Vec<PositionId> = Vec::new();
+
+        for _ in 0..self.liquidation_config.max_steps {
+            let status = self.cross_margin_status(user_id).await?;
+            if !status.liquidatable || status.equity <= Decimal::ZERO {
+                break;
+            }
+
+            let mut largest: Option<(Position, Decimal)> = None;
+            for position in self.cross_positions(user_id).await {
+                let mark_price = self.price_service.mark_price(&position.contract_id).await?;
+                let notional = position.notional(mark_price);
+                if largest.as_ref().map_or(true, |(p, m)| p.notional(*m) < notional) {
+                    largest = Some((position, mark_price));
+                }
+            }
+            let (position, mark_price) = match largest {
+                Some(largest) => largest,
+                None => break,
+            };
+            let contract = self.contract_manager.get_contract(&position.contract_id).await
+                .ok_or_else(|| anyhow!("Contract not found"))?;
+
+            let bankruptcy = bankruptcy_price(&position, mark_price, status.equity);
+            match self.place_liquidation_order(&contract, &position, mark_price, bankruptcy).await? {
+                Some(event) => {
+                    touched.push(position.id);
+                    report.events.push(event);
+                }
+                None => break,
+            }
+        }
+
+        let status = self.cross_margin_status(user_id).await?;
+        if status.liquidatable {
+            // Share the account's equity across positions by notional
+            let mut remaining = Vec::new();
+            for position in self.cross_positions(user_id).await {
+                let mark_price = self.price_service.mark_price(&position.contract_id).await?;
+                remaining.push((position, mark_price));
+            }
+            let total_notional: Decimal = remaining.iter().map(|(p, m)| p.notional(*m)).sum();
+
+            let mut by_contract: HashMap<ContractId, Vec<(Position, Decimal, Decimal)>> = HashMap::new();
+            for (position, mark_price) in remaining {
+                let share = if total_notional > Decimal::ZERO {
+                    status.equity * position.notional(mark_price) / total_notional
+                } else {
+                    Decimal::ZERO
+                };
+                touched.push(position.id);
+                by_contract.entry(position.contract_id.clone())
+                    .or_default()
+                    .push((position, mark_price, share));
+            }
+
+            for (contract_id, positions) in by_contract {
+                let contract = self.contract_manager.get_contract(&contract_id).await
+                    .ok_or_else(|| anyhow!("Contract not found"))?;
+                report.events.extend(self.take_over(&contract, positions).await?);
+            }
+        }
+
+        touched.sort();
+        touched.dedup();
+        for position_id in touched {
+            if let Some(position) = self.position_manager.get_position(&position_id).await {
+                report.positions.push(position);
+            }
+        }
+        info!("Liquidated cross account {} with {} events", user_id, report.events.len());
+        Ok(report)
+    }
+
+    async fn cross_positions(&self, user_id: &UserId) -> Vec<Position> {
+        self.position_manager.get_user_open_positions(user_id).await
+            .into_iter()
+            .filter(|p| p.margin_type == MarginType::Cross)
+            .collect()
+    }
+
+    /// Place one immediate-or-cancel, reduce-only liquidation order no worse than the
+    /// bankruptcy price and charge the liquidation fee on what filled
+    async fn place_liquidation_order(
+        &self,
+        contract: &Contract,
+        position: &Position,
+        mark_price: Decimal,
+        bankruptcy_price: Decimal,
+    ) -> Result<Option<LiquidationEvent>> {
+        let side = closing_side(position);
+        let price = round_to_tick(bankruptcy_price, contract.tick_size, side);
+
+        // Inverse contracts are already sized in quote currency
+        let mut quantity = match contract.payoff {
+            PayoffType::Inverse => self.liquidation_config.step_notional,
+            PayoffType::Linear if mark_price > Decimal::ZERO => self.liquidation_config.step_notional / mark_price,
+            PayoffType::Linear => position.quantity,
+        };
+        if contract.lot_size > Decimal::ZERO {
+            quantity = (quantity / contract.lot_size).floor() * contract.lot_size;
+        }
+        if quantity <= Decimal::ZERO || quantity > position.quantity {
+            quantity = position.quantity;
+        }
+
+        let mut request = DerivativeOrderRequest::limit(
+            position.user_id,
+            &contract.id,
+            side,
+            price,
+            quantity,
+            position.leverage,
+            position.margin_type,
+        );
+        request.time_in_force = TimeInForce::ImmediateOrCancel;
+        request.reduce_only = true;
+        let result = self.submit_order(request, OrderOrigin::Liquidation).await?;
+
+        let filled: Decimal = result.trades.iter().map(|t| t.quantity).sum();
+        if filled == Decimal::ZERO {
+            return Ok(None);
+        }
+        let notional: Decimal = result.trades.iter().map(|t| contract.payoff.notional(t.quantity, t.price)).sum();
+
+        // The fee comes out of the margin the position held going into the order, so a
+        // fully closed isolated position releases its margin net of the fee
+        let mut fee = notional * contract.liquidation_fee_ratio;
+        match position.margin_type {
+            MarginType::Isolated => {
+                fee = fee.min(position.margin_amount.max(Decimal::ZERO));
+                self.position_manager.adjust_margin(&position.id, -fee).await?;
+            }
+            MarginType::Cross => {
+                self.cross_margin.adjust_collateral(&position.user_id, -fee).await?;
+            }
+        }
+        self.insurance_fund.credit_fee(&contract.settlement_asset, fee, position.id).await;
+
+        Ok(Some(LiquidationEvent {
+            position_id: position.id,
+            user_id: position.user_id,
+            contract_id: contract.id.clone(),
+            stage: LiquidationStage::PartialReduction,
+            quantity: filled,
+            price: notional / filled,
+            fee,
+            insurance_delta: fee,
+            timestamp: Utc::now(),
+        }))
+    }
+
+    /// Close bankrupt positions of one contract, each given as (position, mark, equity share).
+    /// If the insurance fund can cover the combined deficit, each position is netted into
+    /// the backstop account's position at its entry price and size; otherwise they close
+    /// at their bankruptcy prices against opposite positions.
+    async fn take_over(
+        &self,
+        contract: &Contract,
+        positions: Vec<(Position, Decimal, Decimal)>,
+    ) -> Result<Vec<LiquidationEvent>> {
+        let mut events = Vec::new();
+        if positions.is_empty() {
+            return Ok(events);
+        }
+        let equity: Decimal = positions.iter().map(|(_, _, share)| *share).sum();
+        let shares: Vec<(PositionId, Decimal)> = positions.iter()
+            .map(|(position, _, share)| (position.id, *share))
+            .collect();
+
+        if self.insurance_fund.absorb(&contract.settlement_asset, &shares).await {
+            for (position, mark_price, share) in positions {
+                // The user loses the position's share of equity, leaving cross collateral at zero
+                let bankruptcy = bankruptcy_price(&position, mark_price, share);
+                let pnl = self.position_manager.liquidate_position(&position.id, bankruptcy).await?;
+                if position.margin_type == MarginType::Cross {
+                    self.cross_margin.adjust_collateral(&position.user_id, pnl).await?;
+                }
+
+                // The fund takes the position over at zero equity at mark, netted into the
+                // backstop account's one position per contract with an averaged entry
+                let backstop_account = self.liquidation_config.backstop_account;
+                let equity_before = self.position_manager.get_open_position(&backstop_account, &contract.id).await
+                    .map_or(Decimal::ZERO, |backstop| backstop.equity(mark_price));
+                let outcome = self.position_manager.apply_fill(PositionFill {
+                    user_id: backstop_account,
+                    contract_id: contract.id.clone(),
+                    side: match position.direction {
+                        PositionDirection::Long => Side::Buy,
+                        PositionDirection::Short => Side::Sell,
+                    },
+                    quantity: position.quantity,
+                    price: position.entry_price,
+                    leverage: position.leverage,
+                    margin_type: MarginType::Isolated,
+                    maintenance_margin_ratio: position.maintenance_margin_ratio,
+                    liquidation_fee_ratio: position.liquidation_fee_ratio,
+                    payoff: position.payoff,
+                    reduce_only: false,
+                }).await?;
+
+                // Margin is set so the fund's equity at mark is what it was before the takeover
+                match outcome.position {
+                    Some(mut backstop) => {
+                        backstop.margin_amount = equity_before - backstop.pnl_at(mark_price);
+                        backstop.update_unrealized_pnl(mark_price);
+                        backstop.update_liquidation_price();
+                        self.position_manager.update_position(backstop).await?;
+                    },
+                    None => info!(
+                        "Backstop position in {} netted flat with {} equity at mark {}",
+                        contract.id, equity_before, mark_price
+                    ),
+                }
+
+                events.push(LiquidationEvent {
+                    position_id: position.id,
+                    user_id: position.user_id,
+                    contract_id: contract.id.clone(),
+                    stage: LiquidationStage::InsuranceTakeover,
+                    quantity: position.quantity,
+                    price: bankruptcy,
+                    fee: Decimal::ZERO,
+                    insurance_delta: share,
+                    timestamp: Utc::now(),
+                });
+            }
+            return Ok(events);
+        }
+
+        warn!(
+            "Insurance fund for {} cannot cover deficit of {}; auto-deleveraging",
+            contract.settlement_asset, -equity
+        );
+        for (position, mark_price, share) in positions {
+            let bankruptcy = bankruptcy_price(&position, mark_price, share);
+            let pnl = self.position_manager.liquidate_position(&position.id, bankruptcy).await?;
+            if position.margin_type == MarginType::Cross {
+                self.cross_margin.adjust_collateral(&position.user_id, pnl).await?;
+            }
+            events.extend(self.auto_deleverage(&position, mark_price, bankruptcy).await?);
+        }
+        Ok(events)
+    }
+
+    /// Close the most profitable, most leveraged opposite positions at the bankruptcy price
+    async fn auto_deleverage(
+        &self,
+        bankrupt: &Position,
+        mark_price: Decimal,
+        bankruptcy_price: Decimal,
+    ) -> Result<Vec<LiquidationEvent>> {
+        let mut candidates: Vec<(Decimal, Position)> = self.position_manager
+            .get_contract_open_positions(&bankrupt.contract_id).await
+            .into_iter()
+            .filter(|p| p.direction != bankrupt.direction && p.user_id != bankrupt.user_id)
+            .filter(|p| p.pnl_at(mark_price) > Decimal::ZERO)
+            .map(|p| (adl_score(&p, mark_price), p))
+            .collect();
+        candidates.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut events = Vec::new();
+        let mut remaining = bankrupt.quantity;
+        for (_, counterparty) in candidates {
+            if remaining <= Decimal::ZERO {
+                break;
+            }
+            let quantity = remaining.min(counterparty.quantity);
+            let pnl = self.position_manager.close_position(&counterparty.id, bankruptcy_price, quantity).await?;
+            if counterparty.margin_type == MarginType::Cross {
+                self.cross_margin.adjust_collateral(&counterparty.user_id, pnl).await?;
+            }
+            remaining -= quantity;
+
+            events.push(LiquidationEvent {
+                position_id: counterparty.id,
+                user_id: counterparty.user_id,
+                contract_id: counterparty.contract_id.clone(),
+                stage: LiquidationStage::AutoDeleverage,
+                quantity,
+                price: bankruptcy_price,
+                fee: Decimal::ZERO,
+                insurance_delta: Decimal::ZERO,
+                timestamp: Utc::now(),
+            });
+        }
+
+        if remaining > Decimal::ZERO {
+            warn!(
+                "Auto-deleveraging left {} of position {} unmatched",
+                remaining, bankrupt.id
+            );
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use rust_decimal_macros::dec;
+    use crate::trading_engine::matching_engine::MatchingEngineManager;
+    use super::super::{ContractManager, ContractType, FundingRateCalculator, PayoffType, PositionManager};
+    use super::super::price_index::{FileReplayFeed, PriceIndexService, PriceRecord};
+
+    struct Setup {
+        engine: DerivativesEngine,
+        contract: Contract,
+        price_service: Arc<PriceIndexService>,
+        crashed_at: DateTime<Utc>,
+        maker_id: UserId,
+        user_id: UserId,
+        position_id: PositionId,
+    }
+
+    /// A user 10x long 1 BTC at 50,000 against a maker, with the index falling to `crash_price`
+    async fn setup(crash_price: Decimal) -> Setup {
+        let contract_manager = Arc::new(ContractManager::new());
+        let opened_at = Utc::now();
+        let crashed_at = opened_at + chrono::Duration::seconds(10);
+        let feed = FileReplayFeed::from_records("replay", vec![
+            PriceRecord { timestamp: opened_at, symbol: "BTC/USDT".to_string(), price: dec!(50000) },
+            PriceRecord { timestamp: crashed_at, symbol: "BTC/USDT".to_string(), price: crash_price },
+        ]);
+        let price_service = Arc::new(PriceIndexService::default());
+        price_service.add_index_feed(Arc::new(feed), dec!(1)).await;
+
+        let engine = DerivativesEngine::new(
+            contract_manager.clone(),
+            Arc::new(PositionManager::new()),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            price_service.clone(),
+            Arc::new(MatchingEngineManager::new()),
+        ).with_liquidation_config(LiquidationConfig {
+            step_notional: dec!(22800),
+            max_steps: 10,
+            ..Default::default()
+        });
+
+        let now = Utc::now();
+        let contract = Contract {
+            id: "BTC-PERP".to_string(),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Perpetual,
+            tick_size: dec!(0.5),
+            lot_size: dec!(0.001),
+            leverage_max: dec!(100),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: None,
+            settlement_asset: "USDT".to_string(),
+            option_type: None,
+            strike_price: None,
+            funding_rate_cap: None,
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: now,
+            updated_at: now,
+        };
+        contract_manager.add_contract(contract.clone()).await.unwrap();
+        engine.list_contracts().await.unwrap();
+        price_service.update_at(&contract, opened_at).await.unwrap();
+
+        let maker_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Sell, dec!(50000), dec!(1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        let position = engine.open_position(
+            user_id, "BTC-PERP", PositionDirection::Long, dec!(1), dec!(10), MarginType::Isolated,
+        ).await.unwrap();
+
+        Setup {
+            engine,
+            contract,
+            price_service,
+            crashed_at,
+            maker_id,
+            user_id,
+            position_id: position.id,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_liquidation_on_book_charges_fee_to_fund() {
+        let s = setup(dec!(45600)).await;
+
+        // A resting order of the user's is cancelled before liquidating
+        s.engine.place_order(DerivativeOrderRequest::limit(
+            s.user_id, "BTC-PERP", Side::Buy, dec!(40000), dec!(0.1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+
+        // Bids above the 45,000 bankruptcy price
+        for price in [dec!(45500), dec!(45400)] {
+            s.engine.place_order(DerivativeOrderRequest::limit(
+                s.maker_id, "BTC-PERP", Side::Buy, price, dec!(0.5), dec!(10), MarginType::Isolated,
+            )).await.unwrap();
+        }
+
+        // Equity 600 against a 684 maintenance requirement
+        s.price_service.update_at(&s.contract, s.crashed_at).await.unwrap();
+        let reports = s.engine.check_liquidations("BTC-PERP").await.unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.cancelled_orders, 1);
+
+        // Two 22,800 notional steps, one per bid level
+        assert_eq!(report.events.len(), 2);
+        assert!(report.events.iter().all(|e| e.stage == LiquidationStage::PartialReduction));
+        assert_eq!(report.events[0].quantity, dec!(0.5));
+        assert_eq!(report.events[0].price, dec!(45500));
+        assert_eq!(report.events[1].price, dec!(45400));
+        assert_eq!(report.positions[0].status, PositionStatus::Closed);
+
+        // 0.5% of 22,750 and 22,700
+        let fees = dec!(113.75) + dec!(113.5);
+        assert_eq!(s.engine.insurance_fund().balance("USDT").await, fees);
+    }
+
+    #[tokio::test]
+    async fn test_insurance_fund_takes_over_bankrupt_position() {
+        let s = setup(dec!(44000)).await;
+        s.engine.insurance_fund().deposit("USDT", dec!(2000)).await.unwrap();
+
+        // Equity is -1,000 with an empty book
+        s.price_service.update_at(&s.contract, s.crashed_at).await.unwrap();
+        let report = s.engine.liquidate_isolated(&s.position_id).await.unwrap();
+
+        assert_eq!(report.events.len(), 1);
+        assert_eq!(report.events[0].stage, LiquidationStage::InsuranceTakeover);
+        assert_eq!(report.events[0].insurance_delta, dec!(-1000));
+        assert_eq!(report.positions[0].status, PositionStatus::Liquidated);
+        assert_eq!(report.positions[0].realized_pnl, dec!(-5000));
+        assert_eq!(s.engine.insurance_fund().balance("USDT").await, dec!(1000));
+
+        // The backstop account holds the position as it was, at zero equity at mark
+        let backstop = s.engine.position_manager.get_open_position(&Uuid::nil(), "BTC-PERP").await.unwrap();
+        assert_eq!(backstop.direction, PositionDirection::Long);
+        assert_eq!(backstop.quantity, dec!(1));
+        assert_eq!(backstop.entry_price, dec!(50000));
+        assert_eq!(backstop.margin_amount, dec!(6000));
+        assert_eq!(backstop.equity(dec!(44000)), Decimal::ZERO);
+        let entries = s.engine.insurance_fund().entries("USDT", 10).await;
+        assert_eq!(entries[0].entry_type, InsuranceFundEntryType::Takeover);
+        assert_eq!(entries[0].position_id, Some(s.position_id));
+
+        // The maker's profitable short is untouched
+        assert!(s.engine.position_manager.get_open_position(&s.maker_id, "BTC-PERP").await.is_some());
+
+        // A second takeover is netted into the same backstop position at the averaged entry
+        let second = Position::new(Uuid::new_v4(), "BTC-PERP".to_string(), PositionDirection::Long,
+                                   dec!(1), dec!(48000), dec!(10), MarginType::Isolated, dec!(3000), dec!(0.01), dec!(0.005));
+        s.engine.position_manager.add_position(second.clone()).await.unwrap();
+        s.engine.liquidate_isolated(&second.id).await.unwrap();
+
+        let backstops: Vec<Position> = s.engine.position_manager.get_user_positions(&Uuid::nil()).await
+            .into_iter()
+            .filter(|p| p.status == PositionStatus::Open)
+            .collect();
+        assert_eq!(backstops.len(), 1);
+        assert_eq!(backstops[0].quantity, dec!(2));
+        assert_eq!(backstops[0].entry_price, dec!(49000));
+        assert_eq!(backstops[0].equity(dec!(44000)), Decimal::ZERO);
+        assert_eq!(s.engine.insurance_fund().balance("USDT").await, Decimal::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_auto_deleverage_when_fund_is_exhausted() {
+        let s = setup(dec!(44000)).await;
+
+        // No fund balance to cover the 1,000 deficit
+        s.price_service.update_at(&s.contract, s.crashed_at).await.unwrap();
+        let report = s.engine.liquidate_isolated(&s.position_id).await.unwrap();
+
+        // Closed at the 45,000 bankruptcy price against the maker's short
+        let adl: Vec<&LiquidationEvent> = report.events.iter()
+            .filter(|e| e.stage == LiquidationStage::AutoDeleverage)
+            .collect();
+        assert_eq!(adl.len(), 1);
+        assert_eq!(adl[0].user_id, s.maker_id);
+        assert_eq!(adl[0].price, dec!(45000));
+        assert_eq!(report.positions[0].status, PositionStatus::Liquidated);
+        assert_eq!(report.positions[0].realized_pnl, dec!(-5000));
+
+        let maker = s.engine.position_manager.get_user_positions(&s.maker_id).await;
+        assert_eq!(maker[0].status, PositionStatus::Closed);
+        assert_eq!(maker[0].realized_pnl, dec!(5000));
+        assert_eq!(s.engine.insurance_fund().balance("USDT").await, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_adl_ranks_by_profit_and_leverage() {
+        let short = |leverage: Decimal| Position::new(
+            Uuid::new_v4(), "BTC-PERP".to_string(), PositionDirection::Short,
+            dec!(1), dec!(50000), leverage, MarginType::Isolated, dec!(50000) / leverage,
+            dec!(0.01), dec!(0.005),
+        );
+        assert!(adl_score(&short(dec!(20)), dec!(45000)) > adl_score(&short(dec!(5)), dec!(45000)));
+        assert!(adl_score(&short(dec!(20)), dec!(40000)) > adl_score(&short(dec!(20)), dec!(45000)));
+    }
+}
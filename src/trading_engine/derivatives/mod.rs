@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use rust_decimal::Decimal;
@@ -6,7 +7,26 @@ use uuid::Uuid;
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
 
-use crate::trading_engine::matching_engine::{Side, OrderStatus, OrderType};
+use crate::trading_engine::matching_engine::{Side, OrderId, OrderStatus, OrderType, MatchingEngineManager};
+
+pub mod price_index;
+pub mod funding;
+pub mod cross_margin;
+pub mod orders;
+pub mod liquidation;
+pub mod settlement;
+pub mod options;
+pub mod vol_surface;
+pub mod risk_limits;
+pub mod portfolio_margin;
+
+use price_index::PriceIndexService;
+use cross_margin::{CrossMarginManager, CrossMarginStatus};
+use orders::{DerivativeOrderRequest, OrderMeta, OrderOrigin};
+use liquidation::{InsuranceFund, LiquidationConfig, LiquidationReport};
+use options::OptionPricer;
+use risk_limits::RiskTier;
+use crate::wallet::WalletSystem;
 
 /// Type definitions for derivatives trading
 pub type ContractId = String;
@@ -25,6 +45,47 @@ pub enum ContractType {
     Option,
 }
 
+/// How a contract's size, margin and PnL are denominated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PayoffType {
+    /// Size in the base asset, margin and PnL in the quote asset
+    #[default]
+    Linear,
+    /// Size in quote currency (e.g. USD) contracts, margin and PnL in the base coin
+    Inverse,
+}
+
+impl PayoffType {
+    /// Value of a quantity at a price, in the contract's settlement asset
+    pub fn notional(self, quantity: Decimal, price: Decimal) -> Decimal {
+        match self {
+            PayoffType::Linear => quantity * price,
+            PayoffType::Inverse if price > Decimal::ZERO => quantity / price,
+            PayoffType::Inverse => Decimal::ZERO,
+        }
+    }
+    
+    /// PnL of a long quantity moving from entry to exit, in the settlement asset
+    pub fn long_pnl(self, quantity: Decimal, entry_price: Decimal, exit_price: Decimal) -> Decimal {
+        match self {
+            PayoffType::Linear => quantity * (exit_price - entry_price),
+            PayoffType::Inverse => self.notional(quantity, entry_price) - self.notional(quantity, exit_price),
+        }
+    }
+    
+    /// Entry price after adding to a position: arithmetic mean for linear, harmonic for inverse
+    pub fn average_entry(self, quantity: Decimal, entry_price: Decimal, added: Decimal, price: Decimal) -> Decimal {
+        let total = quantity + added;
+        match self {
+            PayoffType::Linear => (quantity * entry_price + added * price) / total,
+            PayoffType::Inverse => {
+                let value = self.notional(quantity, entry_price) + self.notional(added, price);
+                if value > Decimal::ZERO { total / value } else { price }
+            },
+        }
+    }
+}
+
 /// Option types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OptionType {
@@ -56,6 +117,16 @@ pub enum PositionStatus {
     Liquidated,
 }
 
+/// Why a position stopped being open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CloseReason {
+    /// Closed by trading
+    Trade,
+    Liquidation,
+    /// Cash-settled at the expiry of a dated future
+    Settlement,
+}
+
 /// Representation of a derivatives contract
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Contract {
@@ -74,6 +145,15 @@ pub struct Contract {
     pub settlement_asset: String,
     pub option_type: Option<OptionType>,
     pub strike_price: Option<Decimal>,
+    /// Maximum absolute funding rate per interval for perpetuals
+    #[serde(default)]
+    pub funding_rate_cap: Option<Decimal>,
+    /// Leverage and maintenance margin by position notional; empty means one tier from the fields above
+    #[serde(default)]
+    pub risk_limit_tiers: Vec<RiskTier>,
+    /// Linear or inverse; inverse contracts settle in `settlement_asset`, the base coin
+    #[serde(default)]
+    pub payoff: PayoffType,
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -92,12 +172,23 @@ pub struct Position {
     pub liquidation_price: Decimal,
     pub margin_type: MarginType,
     pub margin_amount: Decimal,
+    /// Maintenance margin ratio of the contract when the position was opened
+    #[serde(default)]
+    pub maintenance_margin_ratio: Decimal,
+    /// Liquidation fee ratio of the contract when the position was opened
+    #[serde(default)]
+    pub liquidation_fee_ratio: Decimal,
+    /// Payoff of the contract; margin and PnL are in its settlement asset
+    #[serde(default)]
+    pub payoff: PayoffType,
     pub unrealized_pnl: Decimal,
     pub realized_pnl: Decimal,
     pub status: PositionStatus,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub closed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub close_reason: Option<CloseReason>,
 }
 
 impl Position {
@@ -110,18 +201,12 @@ impl Position {
         leverage: Decimal,
         margin_type: MarginType,
         margin_amount: Decimal,
+        maintenance_margin_ratio: Decimal,
+        liquidation_fee_ratio: Decimal,
     ) -> Self {
         let now = Utc::now();
         
-        // In a real implementation, this would be calculated based on entry price,
-        // leverage, and maintenance margin ratio
-        let liquidation_price = if direction == PositionDirection::Long {
-            entry_price * Decimal::new(95, 2) // 95% of entry price for long
-        } else {
-            entry_price * Decimal::new(105, 2) // 105% of entry price for short
-        };
-        
-        Position {
+        let mut position = Position {
             id: Uuid::new_v4(),
             user_id,
             contract_id,
@@ -129,25 +214,126 @@ impl Position {
             quantity,
             entry_price,
             leverage,
-            liquidation_price,
+            liquidation_price: Decimal::ZERO,
             margin_type,
             margin_amount,
+            maintenance_margin_ratio,
+            liquidation_fee_ratio,
+            payoff: PayoffType::Linear,
             unrealized_pnl: Decimal::ZERO,
             realized_pnl: Decimal::ZERO,
             status: PositionStatus::Open,
             created_at: now,
             updated_at: now,
             closed_at: None,
+            close_reason: None,
+        };
+        position.update_liquidation_price();
+        position
+    }
+    
+    /// Set the contract's payoff, recomputing the liquidation price
+    pub fn with_payoff(mut self, payoff: PayoffType) -> Self {
+        self.payoff = payoff;
+        self.update_liquidation_price();
+        self
+    }
+    
+    /// Value of the position at a price, in the settlement asset
+    pub fn notional(&self, price: Decimal) -> Decimal {
+        self.payoff.notional(self.quantity, price)
+    }
+    
+    /// PnL on a quantity of the position exited at a price
+    fn pnl_for(&self, quantity: Decimal, exit_price: Decimal) -> Decimal {
+        let long_pnl = self.payoff.long_pnl(quantity, self.entry_price, exit_price);
+        match self.direction {
+            PositionDirection::Long => long_pnl,
+            PositionDirection::Short => -long_pnl,
         }
     }
     
-    pub fn update_unrealized_pnl(&mut self, mark_price: Decimal) -> Decimal {
-        let price_diff = match self.direction {
-            PositionDirection::Long => mark_price - self.entry_price,
-            PositionDirection::Short => self.entry_price - mark_price,
+    /// Price at which the position's margin plus PnL falls to the maintenance
+    /// margin and liquidation fee on the position's value at that price.
+    ///
+    /// Long:  margin + q(P - entry) = qP(mmr + fee)  =>  P = (q·entry - margin) / (q(1 - mmr - fee))
+    /// Short: margin + q(entry - P) = qP(mmr + fee)  =>  P = (q·entry + margin) / (q(1 + mmr + fee))
+    ///
+    /// Inverse, with r = mmr + fee:
+    /// Long:  margin + q(1/entry - 1/P) = q·r/P  =>  P = q(1 + r) / (margin + q/entry)
+    /// Short: margin + q(1/P - 1/entry) = q·r/P  =>  P = q(1 - r) / (q/entry - margin)
+    pub fn calculate_liquidation_price(&self) -> Decimal {
+        if self.quantity <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+        
+        let requirement = self.maintenance_margin_ratio + self.liquidation_fee_ratio;
+        if self.payoff == PayoffType::Inverse {
+            return self.inverse_liquidation_price(requirement);
+        }
+        let cost = self.quantity * self.entry_price;
+        
+        let price = match self.direction {
+            PositionDirection::Long => {
+                let denominator = self.quantity * (Decimal::ONE - requirement);
+                if denominator <= Decimal::ZERO {
+                    // Requirement of 100% or more: liquidatable at entry
+                    return self.entry_price;
+                }
+                (cost - self.margin_amount) / denominator
+            },
+            PositionDirection::Short => {
+                (cost + self.margin_amount) / (self.quantity * (Decimal::ONE + requirement))
+            },
         };
         
-        self.unrealized_pnl = price_diff * self.quantity;
+        price.max(Decimal::ZERO)
+    }
+    
+    /// Zero when no price liquidates the position, as for a short margined at or beyond its value
+    fn inverse_liquidation_price(&self, requirement: Decimal) -> Decimal {
+        let value = self.notional(self.entry_price);
+        let (numerator, denominator) = match self.direction {
+            PositionDirection::Long => (
+                self.quantity * (Decimal::ONE + requirement),
+                self.margin_amount + value,
+            ),
+            PositionDirection::Short => {
+                if requirement >= Decimal::ONE {
+                    return self.entry_price;
+                }
+                (self.quantity * (Decimal::ONE - requirement), value - self.margin_amount)
+            },
+        };
+        if denominator <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+        numerator / denominator
+    }
+    
+    /// Recompute the liquidation price after a change to margin or size
+    pub fn update_liquidation_price(&mut self) -> Decimal {
+        self.liquidation_price = self.calculate_liquidation_price();
+        self.liquidation_price
+    }
+    
+    /// Margin plus unrealized PnL at the given mark price
+    pub fn equity(&self, mark_price: Decimal) -> Decimal {
+        self.margin_amount + self.pnl_at(mark_price)
+    }
+    
+    /// Unrealized PnL at the given mark price
+    pub fn pnl_at(&self, mark_price: Decimal) -> Decimal {
+        self.pnl_for(self.quantity, mark_price)
+    }
+    
+    /// Maintenance margin plus liquidation fee at the given mark price
+    pub fn maintenance_requirement(&self, mark_price: Decimal, maintenance_margin_ratio: Decimal) -> Decimal {
+        self.notional(mark_price) * (maintenance_margin_ratio + self.liquidation_fee_ratio)
+    }
+    
+    pub fn update_unrealized_pnl(&mut self, mark_price: Decimal) -> Decimal {
+        self.unrealized_pnl = self.pnl_at(mark_price);
         self.updated_at = Utc::now();
         
         self.unrealized_pnl
@@ -158,18 +344,20 @@ impl Position {
             return Err(anyhow::anyhow!("Exit quantity exceeds position quantity"));
         }
         
-        let price_diff = match self.direction {
-            PositionDirection::Long => exit_price - self.entry_price,
-            PositionDirection::Short => self.entry_price - exit_price,
-        };
-        
-        let realized_pnl_for_exit = price_diff * exit_quantity;
+        let realized_pnl_for_exit = self.pnl_for(exit_quantity, exit_price);
         self.realized_pnl += realized_pnl_for_exit;
+        
+        // Release margin in proportion to the size closed
+        if self.quantity > Decimal::ZERO {
+            self.margin_amount -= self.margin_amount * exit_quantity / self.quantity;
+        }
         self.quantity -= exit_quantity;
+        self.update_liquidation_price();
         
         if self.quantity == Decimal::ZERO {
             self.status = PositionStatus::Closed;
             self.closed_at = Some(Utc::now());
+            self.close_reason = Some(CloseReason::Trade);
         }
         
         self.updated_at = Utc::now();
@@ -182,25 +370,46 @@ impl Position {
             return Err(anyhow::anyhow!("Cannot liquidate a non-open position"));
         }
         
-        let price_diff = match self.direction {
-            PositionDirection::Long => liquidation_price - self.entry_price,
-            PositionDirection::Short => self.entry_price - liquidation_price,
-        };
-        
-        let realized_pnl = price_diff * self.quantity;
+        let realized_pnl = self.pnl_at(liquidation_price);
         self.realized_pnl += realized_pnl;
         self.status = PositionStatus::Liquidated;
         self.closed_at = Some(Utc::now());
+        self.close_reason = Some(CloseReason::Liquidation);
         self.updated_at = Utc::now();
         
         Ok(realized_pnl)
     }
     
+    /// Cash-settle the whole position at the settlement price. Isolated PnL is
+    /// credited to the position's margin, which becomes its final balance.
+    pub fn settle(&mut self, settlement_price: Decimal) -> Result<Decimal> {
+        if self.status != PositionStatus::Open {
+            return Err(anyhow::anyhow!("Cannot settle a non-open position"));
+        }
+        
+        let pnl = self.pnl_at(settlement_price);
+        self.realized_pnl += pnl;
+        self.unrealized_pnl = Decimal::ZERO;
+        if self.margin_type == MarginType::Isolated {
+            self.margin_amount += pnl;
+        }
+        self.quantity = Decimal::ZERO;
+        self.status = PositionStatus::Closed;
+        self.close_reason = Some(CloseReason::Settlement);
+        self.closed_at = Some(Utc::now());
+        self.updated_at = Utc::now();
+        
+        Ok(pnl)
+    }
+    
+    /// Whether an isolated position's equity has fallen to its maintenance requirement.
+    /// Cross positions are evaluated on the whole account instead.
     pub fn check_liquidation(&self, mark_price: Decimal, maintenance_margin_ratio: Decimal) -> bool {
-        match self.direction {
-            PositionDirection::Long => mark_price <= self.liquidation_price,
-            PositionDirection::Short => mark_price >= self.liquidation_price,
+        if self.margin_type == MarginType::Cross || self.status != PositionStatus::Open {
+            return false;
         }
+        
+        self.equity(mark_price) <= self.maintenance_requirement(mark_price, maintenance_margin_ratio)
     }
 }
 
@@ -236,6 +445,17 @@ impl ContractManager {
             return Err(anyhow::anyhow!("Contract already exists"));
         }
         
+        risk_limits::validate_risk_tiers(&contract.risk_limit_tiers)?;
+        
+        if contract.payoff == PayoffType::Inverse {
+            if contract.contract_type == ContractType::Option {
+                return Err(anyhow::anyhow!("Options cannot be inverse contracts"));
+            }
+            if contract.settlement_asset != contract.base_asset {
+                return Err(anyhow::anyhow!("Inverse contracts must settle in their base asset"));
+            }
+        }
+        
         contracts.push(contract);
         Ok(())
     }
@@ -262,6 +482,35 @@ impl ContractManager {
     }
 }
 
+/// A fill to be netted into a user's position
+#[derive(Debug, Clone)]
+pub struct PositionFill {
+    pub user_id: UserId,
+    pub contract_id: ContractId,
+    pub side: Side,
+    pub quantity: Decimal,
+    pub price: Decimal,
+    /// Leverage and margin type used if the fill opens a position
+    pub leverage: Decimal,
+    pub margin_type: MarginType,
+    pub maintenance_margin_ratio: Decimal,
+    pub liquidation_fee_ratio: Decimal,
+    pub payoff: PayoffType,
+    /// Any size beyond the open position is discarded instead of flipping it
+    pub reduce_only: bool,
+}
+
+/// Result of netting a fill into a position
+#[derive(Debug, Clone)]
+pub struct FillOutcome {
+    /// The user's open position after the fill, if any
+    pub position: Option<Position>,
+    /// The position closed by this fill, if it was reduced to zero
+    pub closed_position: Option<Position>,
+    /// PnL realized on the reduced size
+    pub realized_pnl: Decimal,
+}
+
 /// Manager for trader positions
 pub struct PositionManager {
     positions: RwLock<Vec<Position>>,
@@ -291,6 +540,104 @@ impl PositionManager {
         positions.iter().filter(|p| p.user_id == *user_id).cloned().collect()
     }
     
+    /// The user's net open position in a contract
+    pub async fn get_open_position(&self, user_id: &UserId, contract_id: &str) -> Option<Position> {
+        let positions = self.positions.read().await;
+        positions.iter()
+            .find(|p| p.user_id == *user_id && p.contract_id == contract_id && p.status == PositionStatus::Open)
+            .cloned()
+    }
+    
+    /// Net a fill into the user's position in the contract: increase it, reduce it,
+    /// or close it and open the remainder in the other direction.
+    pub async fn apply_fill(&self, fill: PositionFill) -> Result<FillOutcome> {
+        let mut positions = self.positions.write().await;
+        let direction = match fill.side {
+            Side::Buy => PositionDirection::Long,
+            Side::Sell => PositionDirection::Short,
+        };
+        
+        let existing = positions.iter_mut()
+            .find(|p| p.user_id == fill.user_id && p.contract_id == fill.contract_id && p.status == PositionStatus::Open);
+        
+        let mut realized_pnl = Decimal::ZERO;
+        let mut remaining = fill.quantity;
+        let mut reduced = None;
+        
+        if let Some(position) = existing {
+            if position.direction == direction {
+                // Increase: weighted average entry, margin for the added size
+                if fill.reduce_only {
+                    return Err(anyhow::anyhow!("Reduce-only fill would increase the position"));
+                }
+                position.entry_price = position.payoff.average_entry(
+                    position.quantity, position.entry_price, fill.quantity, fill.price,
+                );
+                position.quantity += fill.quantity;
+                if position.leverage > Decimal::ZERO {
+                    position.margin_amount += position.payoff.notional(fill.quantity, fill.price) / position.leverage;
+                }
+                position.update_liquidation_price();
+                position.updated_at = Utc::now();
+                return Ok(FillOutcome {
+                    position: Some(position.clone()),
+                    closed_position: None,
+                    realized_pnl,
+                });
+            }
+            
+            if fill.reduce_only && fill.quantity > position.quantity {
+                return Err(anyhow::anyhow!(
+                    "Reduce-only fill of {} exceeds the {} position", fill.quantity, position.quantity
+                ));
+            }
+            
+            // Reduce: realize PnL on the closed size at the fill price
+            let reduce_quantity = fill.quantity.min(position.quantity);
+            realized_pnl = position.close(fill.price, reduce_quantity)?;
+            remaining -= reduce_quantity;
+            reduced = Some(position.clone());
+            
+            if fill.reduce_only || remaining == Decimal::ZERO {
+                let open = reduced.clone().filter(|p| p.status == PositionStatus::Open);
+                let closed = reduced.filter(|p| p.status == PositionStatus::Closed);
+                return Ok(FillOutcome {
+                    position: open,
+                    closed_position: closed,
+                    realized_pnl,
+                });
+            }
+        } else if fill.reduce_only {
+            return Err(anyhow::anyhow!("Reduce-only fill with no open position"));
+        }
+        
+        // Open (or flip into) a position with the remaining size
+        let margin_amount = if fill.leverage > Decimal::ZERO {
+            fill.payoff.notional(remaining, fill.price) / fill.leverage
+        } else {
+            Decimal::ZERO
+        };
+        let position = Position::new(
+            fill.user_id,
+            fill.contract_id.clone(),
+            direction,
+            remaining,
+            fill.price,
+            fill.leverage,
+            fill.margin_type,
+            margin_amount,
+            fill.maintenance_margin_ratio,
+            fill.liquidation_fee_ratio,
+        ).with_payoff(fill.payoff);
+        positions.push(position.clone());
+        
+        Ok(FillOutcome {
+            position: Some(position),
+            closed_position: reduced,
+            realized_pnl,
+        })
+    }
+    
     pub async fn get_user_open_positions(&self, user_id: &UserId) -> Vec<Position> {
         let positions = self.positions.read().await;
         positions.iter()
@@ -299,6 +646,14 @@ impl PositionManager {
             .collect()
     }
     
+    pub async fn get_contract_open_positions(&self, contract_id: &str) -> Vec<Position> {
+        let positions = self.positions.read().await;
+        positions.iter()
+            .filter(|p| p.contract_id == contract_id && p.status == PositionStatus::Open)
+            .cloned()
+            .collect()
+    }
+    
     pub async fn update_position(&self, position: Position) -> Result<()> {
         let mut positions = self.positions.write().await;
         
@@ -321,6 +676,20 @@ impl PositionManager {
         }
     }
     
+    /// Credit (positive) or debit (negative) a position's margin, returning the new margin
+    pub async fn adjust_margin(&self, position_id: &PositionId, amount: Decimal) -> Result<Decimal> {
+        let mut positions = self.positions.write().await;
+        
+        if let Some(position) = positions.iter_mut().find(|p| p.id == *position_id) {
+            position.margin_amount += amount;
+            position.update_liquidation_price();
+            position.updated_at = Utc::now();
+            Ok(position.margin_amount)
+        } else {
+            Err(anyhow::anyhow!("Position not found"))
+        }
+    }
+    
     pub async fn close_position(
         &self,
         position_id: &PositionId,
@@ -336,6 +705,18 @@ impl PositionManager {
         }
     }
     
+    /// Cash-settle a position, returning it in its settled state
+    pub async fn settle_position(&self, position_id: &PositionId, settlement_price: Decimal) -> Result<Position> {
+        let mut positions = self.positions.write().await;
+        
+        if let Some(position) = positions.iter_mut().find(|p| p.id == *position_id) {
+            position.settle(settlement_price)?;
+            Ok(position.clone())
+        } else {
+            Err(anyhow::anyhow!("Position not found"))
+        }
+    }
+    
     pub async fn liquidate_position(&self, position_id: &PositionId, liquidation_price: Decimal) -> Result<Decimal> {
         let mut positions = self.positions.write().await;
         
@@ -346,16 +727,12 @@ impl PositionManager {
         }
     }
     
-    pub async fn check_liquidations(
-        &self,
-        contract_id: &ContractId,
-        mark_price: Decimal,
-        maintenance_margin_ratio: Decimal,
-    ) -> Vec<Position> {
+    /// Open positions below the maintenance margin of the risk tier their notional falls in
+    pub async fn check_liquidations(&self, contract: &Contract, mark_price: Decimal) -> Vec<Position> {
         let positions = self.positions.read().await;
         positions.iter()
-            .filter(|p| p.contract_id == *contract_id && p.status == PositionStatus::Open)
-            .filter(|p| p.check_liquidation(mark_price, maintenance_margin_ratio))
+            .filter(|p| p.contract_id == contract.id && p.status == PositionStatus::Open)
+            .filter(|p| p.check_liquidation(mark_price, contract.maintenance_margin_ratio_for(p.notional(mark_price))))
             .cloned()
             .collect()
     }
@@ -378,32 +755,30 @@ impl FundingRateCalculator {
         }
     }
     
-    pub fn calculate_funding_rate(
-        &self,
-        mark_price: Decimal,
-        index_price: Decimal,
-        long_positions_size: Decimal,
-        short_positions_size: Decimal,
-    ) -> FundingRate {
-        // Premium index component (mark price vs index price)
-        let premium_index = if index_price > Decimal::ZERO {
-            (mark_price - index_price) / index_price
-        } else {
-            Decimal::ZERO
-        };
-        
+    pub fn funding_interval_hours(&self) -> u32 {
+        self.funding_interval_hours
+    }
+    
+    /// Funding rate for one interval from the time-weighted premium index,
+    /// clamped to the contract's cap
+    pub fn calculate_funding_rate(&self, premium_index: Decimal, cap: Option<Decimal>) -> FundingRate {
         // Interest rate component (fixed)
         let interest_component = self.interest_rate / Decimal::from(24) * Decimal::from(self.funding_interval_hours);
         
         // Premium index component (weighted)
         let premium_component = premium_index * self.premium_index_weight;
         
-        // Final funding rate
-        interest_component + premium_component
+        let funding_rate = interest_component + premium_component;
+        
+        match cap {
+            Some(cap) => funding_rate.max(-cap).min(cap),
+            None => funding_rate,
+        }
     }
     
-    pub fn calculate_funding_payment(&self, position: &Position, funding_rate: FundingRate) -> Decimal {
-        let notional_value = position.quantity * position.entry_price;
+    /// Payment for a position at the given mark price; negative means the user pays
+    pub fn calculate_funding_payment(&self, position: &Position, funding_rate: FundingRate, mark_price: Decimal) -> Decimal {
+        let notional_value = position.notional(mark_price);
         
         // For long positions, positive funding rate means payment, negative means receipt
         // For short positions, it's the opposite
@@ -419,6 +794,22 @@ pub struct DerivativesEngine {
     contract_manager: Arc<ContractManager>,
     position_manager: Arc<PositionManager>,
     funding_calculator: FundingRateCalculator,
+    price_service: Arc<PriceIndexService>,
+    cross_margin: Arc<CrossMarginManager>,
+    matching_engines: Arc<MatchingEngineManager>,
+    /// Open derivatives orders by id, for attributing fills
+    order_meta: RwLock<HashMap<OrderId, OrderMeta>>,
+    insurance_fund: Arc<InsuranceFund>,
+    liquidation_config: LiquidationConfig,
+    /// Contracts closed to new orders ahead of settlement
+    halted_contracts: RwLock<HashSet<ContractId>>,
+    option_pricer: Arc<OptionPricer>,
+    /// Users whose margin and liquidation are computed on their whole portfolio
+    portfolio_margin_users: RwLock<HashSet<UserId>>,
+    /// Wallets that short option margin is reserved from
+    wallet: Option<Arc<WalletSystem>>,
+    /// Collateral reserved for each short option position
+    option_margin_reserved: RwLock<HashMap<PositionId, Decimal>>,
 }
 
 impl DerivativesEngine {
@@ -426,14 +817,85 @@ impl DerivativesEngine {
         contract_manager: Arc<ContractManager>,
         position_manager: Arc<PositionManager>,
         funding_calculator: FundingRateCalculator,
+        price_service: Arc<PriceIndexService>,
+        matching_engines: Arc<MatchingEngineManager>,
     ) -> Self {
         DerivativesEngine {
             contract_manager,
             position_manager,
             funding_calculator,
+            price_service,
+            cross_margin: Arc::new(CrossMarginManager::new()),
+            matching_engines,
+            order_meta: RwLock::new(HashMap::new()),
+            insurance_fund: Arc::new(InsuranceFund::new()),
+            liquidation_config: LiquidationConfig::default(),
+            halted_contracts: RwLock::new(HashSet::new()),
+            option_pricer: Arc::new(OptionPricer::default()),
+            portfolio_margin_users: RwLock::new(HashSet::new()),
+            wallet: None,
+            option_margin_reserved: RwLock::new(HashMap::new()),
+        }
+    }
+    
+    pub fn with_liquidation_config(mut self, config: LiquidationConfig) -> Self {
+        self.liquidation_config = config;
+        self
+    }
+    
+    pub fn with_option_pricer(mut self, pricer: OptionPricer) -> Self {
+        self.option_pricer = Arc::new(pricer);
+        self
+    }
+    
+    pub fn with_wallet(mut self, wallet: Arc<WalletSystem>) -> Self {
+        self.wallet = Some(wallet);
+        self
+    }
+    
+    pub fn price_service(&self) -> Arc<PriceIndexService> {
+        self.price_service.clone()
+    }
+    
+    pub fn cross_margin(&self) -> Arc<CrossMarginManager> {
+        self.cross_margin.clone()
+    }
+    
+    pub fn insurance_fund(&self) -> Arc<InsuranceFund> {
+        self.insurance_fund.clone()
+    }
+    
+    /// Mark price of a contract: the option model value for options, index plus basis otherwise
+    pub async fn contract_mark_price(&self, contract: &Contract) -> Result<Decimal> {
+        match contract.contract_type {
+            ContractType::Option => self.option_mark_price(contract).await,
+            _ => self.price_service.mark_price(&contract.id).await,
         }
     }
     
+    /// Evaluate a user's cross-margin account at current mark prices
+    pub async fn cross_margin_status(&self, user_id: &UserId) -> Result<CrossMarginStatus> {
+        let positions = self.position_manager.get_user_open_positions(user_id).await;
+        
+        let mut marks = HashMap::new();
+        let mut maintenance_ratios = HashMap::new();
+        for position in positions.iter().filter(|p| p.margin_type == MarginType::Cross) {
+            if marks.contains_key(&position.contract_id) {
+                continue;
+            }
+            let mark_price = self.price_service.mark_price(&position.contract_id).await?;
+            marks.insert(position.contract_id.clone(), mark_price);
+            // A user holds one position per contract, so its tier is the contract's ratio for them
+            if let Some(contract) = self.contract_manager.get_contract(&position.contract_id).await {
+                let ratio = contract.maintenance_margin_ratio_for(position.notional(mark_price));
+                maintenance_ratios.insert(contract.id.clone(), ratio);
+            }
+        }
+        
+        self.cross_margin.evaluate(user_id, &positions, &marks, &maintenance_ratios).await
+    }
+    
+    /// Open or add to a position with a market order against the book
     pub async fn open_position(
         &self,
         user_id: UserId,
@@ -443,47 +905,35 @@ impl DerivativesEngine {
         leverage: Decimal,
         margin_type: MarginType,
     ) -> Result<Position> {
-        // Get contract
-        let contract = match self.contract_manager.get_contract(contract_id).await {
-            Some(contract) => contract,
-            None => return Err(anyhow::anyhow!("Contract not found")),
+        let side = match direction {
+            PositionDirection::Long => Side::Buy,
+            PositionDirection::Short => Side::Sell,
         };
         
-        // Validate leverage
-        if leverage > contract.leverage_max {
-            return Err(anyhow::anyhow!("Leverage exceeds maximum allowed"));
-        }
-        
-        // Get current mark price (in a real implementation, this would come from a price oracle)
-        let mark_price = Decimal::new(5000000, 2); // Example: $50,000.00
-        
-        // Calculate required margin
-        let notional_value = quantity * mark_price;
-        let margin_amount = notional_value / leverage;
-        
-        // Create a new position
-        let position = Position::new(
-            user_id,
-            contract_id.to_string(),
-            direction,
-            quantity,
-            mark_price,
-            leverage,
-            margin_type,
-            margin_amount,
-        );
-        
-        // Add position
-        let position_id = self.position_manager.add_position(position.clone()).await?;
+        let request = DerivativeOrderRequest::market(user_id, contract_id, side, quantity, leverage, margin_type);
+        let result = self.place_order(request).await?;
         
-        Ok(position)
+        result.position_for(&user_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Order did not fill"))
     }
     
+    /// Reduce or close a position with a reduce-only market order, returning the realized PnL
     pub async fn close_position(
         &self,
         user_id: UserId,
         position_id: PositionId,
         quantity: Option<Decimal>,
+    ) -> Result<Decimal> {
+        self.close_position_as(user_id, position_id, quantity, OrderOrigin::User).await
+    }
+    
+    async fn close_position_as(
+        &self,
+        user_id: UserId,
+        position_id: PositionId,
+        quantity: Option<Decimal>,
+        origin: OrderOrigin,
     ) -> Result<Decimal> {
         // Get position
         let position = match self.position_manager.get_position(&position_id).await {
@@ -501,16 +951,24 @@ impl DerivativesEngine {
             return Err(anyhow::anyhow!("Position is not open"));
         }
         
-        // Determine quantity to close
-        let close_quantity = quantity.unwrap_or(position.quantity);
-        
-        // Get current mark price (in a real implementation, this would come from a price oracle)
-        let mark_price = Decimal::new(5200000, 2); // Example: $52,000.00
+        let side = match position.direction {
+            PositionDirection::Long => Side::Sell,
+            PositionDirection::Short => Side::Buy,
+        };
         
-        // Close the position
-        let realized_pnl = self.position_manager.close_position(&position_id, mark_price, close_quantity).await?;
+        let mut request = DerivativeOrderRequest::market(
+            user_id,
+            &position.contract_id,
+            side,
+            quantity.unwrap_or(position.quantity),
+            position.leverage,
+            position.margin_type,
+        );
+        request.reduce_only = true;
+        request.close_position = quantity.is_none();
         
-        Ok(realized_pnl)
+        let result = self.submit_order(request, origin).await?;
+        Ok(result.realized_pnl_for(&user_id))
     }
     
     pub async fn update_positions_pnl(&self, contract_id: &str) -> Result<()> {
@@ -520,53 +978,132 @@ impl DerivativesEngine {
             None => return Err(anyhow::anyhow!("Contract not found")),
         };
         
-        // Get current mark price (in a real implementation, this would come from a price oracle)
-        let mark_price = Decimal::new(5200000, 2); // Example: $52,000.00
+        // Get current mark price
+        let mark_price = self.contract_mark_price(&contract).await?;
         
         // Get all open positions for this contract
-        let positions = self.position_manager.get_user_open_positions(&UserId::nil()).await;
+        let positions = self.position_manager.get_contract_open_positions(&contract.id).await;
         
-        // Update unrealized PnL for each position
-        for position in positions {
-            if position.contract_id == contract_id {
-                let _ = self.position_manager.update_unrealized_pnl(&position.id, mark_price).await;
+        // Update unrealized PnL for each position, and its risk tier as its notional moves
+        for position in &positions {
+            if contract.contract_type != ContractType::Option {
+                if let Err(e) = self.refresh_risk_tier(&contract, position, mark_price).await {
+                    log::warn!("Failed to refresh risk tier of position {}: {}", position.id, e);
+                }
+            }
+            let _ = self.position_manager.update_unrealized_pnl(&position.id, mark_price).await;
+        }
+        
+        // Short option margin follows the underlying
+        if contract.contract_type == ContractType::Option {
+            for position in positions.iter().filter(|p| p.direction == PositionDirection::Short) {
+                if let Err(e) = self.refresh_option_margin(&contract, &position.id).await {
+                    log::warn!("Failed to refresh option margin of position {}: {}", position.id, e);
+                }
             }
         }
         
         Ok(())
     }
     
-    pub async fn check_liquidations(&self, contract_id: &str) -> Result<Vec<Position>> {
+    /// Liquidate the contract's isolated positions and cross accounts below maintenance margin
+    pub async fn check_liquidations(&self, contract_id: &str) -> Result<Vec<LiquidationReport>> {
         // Get contract
         let contract = match self.contract_manager.get_contract(contract_id).await {
             Some(contract) => contract,
             None => return Err(anyhow::anyhow!("Contract not found")),
         };
         
-        // Get current mark price (in a real implementation, this would come from a price oracle)
-        let mark_price = Decimal::new(5200000, 2); // Example: $52,000.00
+        // Get current mark price
+        let mark_price = self.contract_mark_price(&contract).await?;
         
         // Check for positions that need to be liquidated
         let liquidation_candidates = self.position_manager
-            .check_liquidations(&contract.id, mark_price, contract.maintenance_margin_ratio)
+            .check_liquidations(&contract, mark_price)
             .await;
         
-        // Liquidate positions
-        let mut liquidated_positions = Vec::new();
-        for position in liquidation_candidates {
-            match self.position_manager.liquidate_position(&position.id, mark_price).await {
-                Ok(_) => {
-                    liquidated_positions.push(position);
-                }
+        // Liquidate positions; portfolio-margined users are liquidated on their whole account
+        // instead, and positions the insurance fund has taken over are left to it
+        let portfolio_users = self.portfolio_margin_users.read().await.clone();
+        let mut reports = Vec::new();
+        let backstop = self.liquidation_config.backstop_account;
+        for position in liquidation_candidates.into_iter().filter(|p| !portfolio_users.contains(&p.user_id) && p.user_id != backstop) {
+            match self.liquidate_isolated(&position.id).await {
+                Ok(report) => reports.push(report),
                 Err(e) => {
                     eprintln!("Failed to liquidate position {}: {}", position.id, e);
                 }
             }
         }
         
-        Ok(liquidated_positions)
+        // Cross accounts are liquidated on total equity versus total maintenance margin
+        let mut cross_users: Vec<UserId> = self.position_manager.get_contract_open_positions(&contract.id).await
+            .into_iter()
+            .filter(|p| p.margin_type == MarginType::Cross && !portfolio_users.contains(&p.user_id))
+            .map(|p| p.user_id)
+            .collect();
+        cross_users.sort();
+        cross_users.dedup();
+        
+        for user_id in cross_users {
+            if let Some(report) = self.check_cross_liquidation(&user_id).await? {
+                reports.push(report);
+            }
+        }
+        
+        Ok(reports)
+    }
+    
+    /// Refresh PnL and liquidate positions below maintenance in every active contract periodically
+    pub fn spawn_liquidation_monitor(self: &Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let engine = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                for contract in engine.contract_manager.list_active_contracts().await {
+                    if let Err(e) = engine.update_positions_pnl(&contract.id).await {
+                        log::warn!("Failed to update positions of {}: {}", contract.id, e);
+                    }
+                    match engine.check_liquidations(&contract.id).await {
+                        Ok(reports) if !reports.is_empty() => {
+                            log::info!("Liquidated {} accounts in {}", reports.len(), contract.id);
+                        },
+                        Ok(_) => {},
+                        Err(e) => log::warn!("Failed to check liquidations for {}: {}", contract.id, e),
+                    }
+                }
+            }
+        })
+    }
+    
+    /// Liquidate a user's cross account if it is below maintenance,
+    /// otherwise refresh the estimated liquidation prices of its positions
+    pub async fn check_cross_liquidation(&self, user_id: &UserId) -> Result<Option<LiquidationReport>> {
+        let status = match self.cross_margin_status(user_id).await {
+            Ok(status) => status,
+            Err(e) => {
+                log::error!("Failed to evaluate cross margin for {}: {}", user_id, e);
+                return Ok(None);
+            }
+        };
+        
+        if status.liquidatable {
+            return Ok(Some(self.liquidate_cross(user_id).await?));
+        }
+        
+        let positions = self.position_manager.get_user_open_positions(user_id).await;
+        for mut position in positions.into_iter().filter(|p| p.margin_type == MarginType::Cross) {
+            if let Some(&price) = status.liquidation_prices.get(&position.id) {
+                position.liquidation_price = price;
+                self.position_manager.update_position(position).await?;
+            }
+        }
+        Ok(None)
     }
     
+    /// Preview funding payments at the current premium. Settlement uses the
+    /// time-weighted premium and is done by `funding::FundingScheduler`.
     pub async fn calculate_funding_payments(&self, contract_id: &str) -> Result<Vec<FundingPayment>> {
         // Get contract
         let contract = match self.contract_manager.get_contract(contract_id).await {
@@ -580,40 +1117,26 @@ impl DerivativesEngine {
         }
         
         // Get current mark price and index price
-        let mark_price = Decimal::new(5200000, 2); // Example: $52,000.00
-        let index_price = Decimal::new(5190000, 2); // Example: $51,900.00
+        let prices = self.price_service.get_prices(&contract.id).await
+            .ok_or_else(|| anyhow::anyhow!("No prices for {}", contract.id))?;
+        let mark_price = prices.mark_price;
+        let index_price = prices.index_price;
         
         // Get all open positions for this contract
-        let positions = self.position_manager
-            .positions.read().await
-            .iter()
-            .filter(|p| p.contract_id == contract_id && p.status == PositionStatus::Open)
-            .cloned()
-            .collect::<Vec<_>>();
-        
-        // Calculate total long and short positions
-        let (long_positions_size, short_positions_size) = positions.iter().fold(
-            (Decimal::ZERO, Decimal::ZERO),
-            |(long_sum, short_sum), position| {
-                match position.direction {
-                    PositionDirection::Long => (long_sum + position.quantity, short_sum),
-                    PositionDirection::Short => (long_sum, short_sum + position.quantity),
-                }
-            },
-        );
+        let positions = self.position_manager.get_contract_open_positions(&contract.id).await;
         
-        // Calculate funding rate
-        let funding_rate = self.funding_calculator.calculate_funding_rate(
-            mark_price,
-            index_price,
-            long_positions_size,
-            short_positions_size,
-        );
+        // Calculate funding rate from the current premium
+        let premium_index = if index_price > Decimal::ZERO {
+            (mark_price - index_price) / index_price
+        } else {
+            Decimal::ZERO
+        };
+        let funding_rate = self.funding_calculator.calculate_funding_rate(premium_index, contract.funding_rate_cap);
         
         // Calculate funding payments for each position
         let mut funding_payments = Vec::new();
         for position in positions {
-            let payment_amount = self.funding_calculator.calculate_funding_payment(&position, funding_rate);
+            let payment_amount = self.funding_calculator.calculate_funding_payment(&position, funding_rate, mark_price);
             
             if payment_amount != Decimal::ZERO {
                 let funding_payment = FundingPayment {
@@ -639,28 +1162,8 @@ mod tests {
     use super::*;
     use rust_decimal_macros::dec;
     
-    #[tokio::test]
-    async fn test_open_and_close_position() {
-        // Create managers
-        let contract_manager = Arc::new(ContractManager::new());
-        let position_manager = Arc::new(PositionManager::new());
-        
-        // Create funding calculator
-        let funding_calculator = FundingRateCalculator::new(
-            dec!(0.0001), // 0.01% interest rate
-            dec!(0.0005), // 0.05% premium index weight
-            8, // 8-hour funding interval
-        );
-        
-        // Create derivatives engine
-        let derivatives_engine = DerivativesEngine::new(
-            contract_manager.clone(),
-            position_manager.clone(),
-            funding_calculator,
-        );
-        
-        // Create a contract
-        let contract = Contract {
+    fn test_contract() -> Contract {
+        Contract {
             id: "BTC-PERP".to_string(),
             base_asset: "BTC".to_string(),
             quote_asset: "USDT".to_string(),
@@ -676,13 +1179,50 @@ mod tests {
             settlement_asset: "USDT".to_string(),
             option_type: None,
             strike_price: None,
+            funding_rate_cap: Some(dec!(0.0075)),
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
             is_active: true,
             created_at: Utc::now(),
             updated_at: Utc::now(),
-        };
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_open_and_close_position() {
+        // Create managers
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        
+        // Create funding calculator
+        let funding_calculator = FundingRateCalculator::new(
+            dec!(0.0001), // 0.01% interest rate
+            dec!(0.0005), // 0.05% premium index weight
+            8, // 8-hour funding interval
+        );
+        
+        let price_service = Arc::new(PriceIndexService::default());
+        let matching_engines = Arc::new(MatchingEngineManager::new());
+        
+        // Create derivatives engine
+        let derivatives_engine = DerivativesEngine::new(
+            contract_manager.clone(),
+            position_manager.clone(),
+            funding_calculator,
+            price_service,
+            matching_engines,
+        );
+        
+        // Add contract and list its order book
+        let contract = test_contract();
+        contract_manager.add_contract(contract.clone()).await.unwrap();
+        derivatives_engine.list_contracts().await.unwrap();
         
-        // Add contract
-        contract_manager.add_contract(contract).await.unwrap();
+        // A market maker offers 0.1 BTC at 50,000
+        let maker_id = Uuid::new_v4();
+        derivatives_engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Sell, dec!(50000), dec!(0.1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
         
         // Open a position
         let user_id = Uuid::new_v4();
@@ -700,6 +1240,17 @@ mod tests {
         assert_eq!(position.quantity, dec!(0.1));
         assert_eq!(position.leverage, dec!(10));
         assert_eq!(position.status, PositionStatus::Open);
+        assert_eq!(position.entry_price, dec!(50000));
+        
+        // The maker is now short on the other side of the fill
+        let maker_position = position_manager.get_open_position(&maker_id, "BTC-PERP").await.unwrap();
+        assert_eq!(maker_position.direction, PositionDirection::Short);
+        assert_eq!(maker_position.quantity, dec!(0.1));
+        
+        // Price moves up: the maker bids 52,000 to cover
+        derivatives_engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Buy, dec!(52000), dec!(0.1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
         
         // Close the position
         let realized_pnl = derivatives_engine.close_position(
@@ -708,8 +1259,8 @@ mod tests {
             Some(dec!(0.05)), // Close half of the position
         ).await.unwrap();
         
-        // Verify PnL (should be positive as the price increased)
-        assert!(realized_pnl > Decimal::ZERO);
+        // Verify PnL: 0.05 * (52,000 - 50,000)
+        assert_eq!(realized_pnl, dec!(100));
         
         // Verify position update
         let updated_position = position_manager.get_position(&position.id).await.unwrap();
@@ -724,27 +1275,259 @@ mod tests {
         ).await.unwrap();
         
         // Verify PnL
-        assert!(realized_pnl > Decimal::ZERO);
+        assert_eq!(realized_pnl, dec!(100));
         
         // Verify position closure
         let closed_position = position_manager.get_position(&position.id).await.unwrap();
         assert_eq!(closed_position.quantity, dec!(0)); // No quantity left
         assert_eq!(closed_position.status, PositionStatus::Closed); // Now closed
         assert!(closed_position.closed_at.is_some()); // Has a closure timestamp
+        
+        // Both sides are flat again
+        assert!(position_manager.get_open_position(&maker_id, "BTC-PERP").await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_fills_net_into_one_position() {
+        let position_manager = PositionManager::new();
+        let user_id = Uuid::new_v4();
+        let fill = |side, quantity, price, reduce_only| PositionFill {
+            user_id,
+            contract_id: "BTC-PERP".to_string(),
+            side,
+            quantity,
+            price,
+            leverage: dec!(10),
+            margin_type: MarginType::Isolated,
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            payoff: PayoffType::Linear,
+            reduce_only,
+        };
+        
+        // Reduce-only with nothing to reduce is rejected
+        assert!(position_manager.apply_fill(fill(Side::Sell, dec!(1), dec!(50000), true)).await.is_err());
+        
+        // Two buys average the entry price
+        position_manager.apply_fill(fill(Side::Buy, dec!(1), dec!(50000), false)).await.unwrap();
+        let outcome = position_manager.apply_fill(fill(Side::Buy, dec!(1), dec!(52000), false)).await.unwrap();
+        let position = outcome.position.unwrap();
+        assert_eq!(position.quantity, dec!(2));
+        assert_eq!(position.entry_price, dec!(51000));
+        assert_eq!(position.margin_amount, dec!(10200));
+        
+        // Reduce-only on the same side would increase the position
+        assert!(position_manager.apply_fill(fill(Side::Buy, dec!(1), dec!(52000), true)).await.is_err());
+        
+        // A partial sell realizes PnL against the average entry
+        let outcome = position_manager.apply_fill(fill(Side::Sell, dec!(0.5), dec!(53000), false)).await.unwrap();
+        assert_eq!(outcome.realized_pnl, dec!(1000));
+        assert_eq!(outcome.position.unwrap().quantity, dec!(1.5));
+        
+        // Selling through the position flips it short at the fill price
+        let outcome = position_manager.apply_fill(fill(Side::Sell, dec!(2.5), dec!(50000), false)).await.unwrap();
+        assert_eq!(outcome.realized_pnl, dec!(-1500));
+        let closed = outcome.closed_position.unwrap();
+        assert_eq!(closed.status, PositionStatus::Closed);
+        let flipped = outcome.position.unwrap();
+        assert_eq!(flipped.direction, PositionDirection::Short);
+        assert_eq!(flipped.quantity, dec!(1));
+        assert_eq!(flipped.entry_price, dec!(50000));
+        assert_eq!(position_manager.get_open_position(&user_id, "BTC-PERP").await.unwrap().id, flipped.id);
+    }
+    
+    #[tokio::test]
+    async fn test_reduce_only_order_is_clamped_to_position() {
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        let derivatives_engine = DerivativesEngine::new(
+            contract_manager.clone(),
+            position_manager.clone(),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            Arc::new(PriceIndexService::default()),
+            Arc::new(MatchingEngineManager::new()),
+        );
+        contract_manager.add_contract(test_contract()).await.unwrap();
+        derivatives_engine.list_contracts().await.unwrap();
+        
+        let maker_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        derivatives_engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Sell, dec!(50000), dec!(1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        derivatives_engine.open_position(
+            user_id, "BTC-PERP", PositionDirection::Long, dec!(1), dec!(10), MarginType::Isolated,
+        ).await.unwrap();
+        
+        // A reduce-only sell for more than the position only closes it
+        derivatives_engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Buy, dec!(50000), dec!(5), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        let mut request = DerivativeOrderRequest::market(user_id, "BTC-PERP", Side::Sell, dec!(3), dec!(10), MarginType::Isolated);
+        request.reduce_only = true;
+        let result = derivatives_engine.place_order(request).await.unwrap();
+        
+        let filled: Decimal = result.trades.iter().map(|t| t.quantity).sum();
+        assert_eq!(filled, dec!(1));
+        assert!(result.position_for(&user_id).is_none());
+        assert!(position_manager.get_open_position(&user_id, "BTC-PERP").await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_order_meta_pruned_and_reduce_only_limited_before_matching() {
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        let derivatives_engine = DerivativesEngine::new(
+            contract_manager.clone(),
+            position_manager.clone(),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            Arc::new(PriceIndexService::default()),
+            Arc::new(MatchingEngineManager::new()),
+        );
+        contract_manager.add_contract(test_contract()).await.unwrap();
+        derivatives_engine.list_contracts().await.unwrap();
+        
+        let maker_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        derivatives_engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Sell, dec!(50000), dec!(2), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        derivatives_engine.place_order(DerivativeOrderRequest::market(
+            user_id, "BTC-PERP", Side::Buy, dec!(2), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        
+        // Both the filled maker and the market taker are forgotten
+        assert!(derivatives_engine.order_meta.read().await.is_empty());
+        
+        // Resting reduce-only orders may not add up to more than the position
+        let mut request = DerivativeOrderRequest::limit(user_id, "BTC-PERP", Side::Sell, dec!(60000), dec!(1.5), dec!(10), MarginType::Isolated);
+        request.reduce_only = true;
+        derivatives_engine.place_order(request.clone()).await.unwrap();
+        derivatives_engine.place_order(request.clone()).await.unwrap();
+        assert_eq!(derivatives_engine.resting_reduce_only(&user_id, "BTC-PERP").await, dec!(2));
+        assert!(derivatives_engine.place_order(request).await.is_err());
+        
+        // Closing the position elsewhere cancels the reduce-only orders it no longer covers
+        derivatives_engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, "BTC-PERP", Side::Buy, dec!(50000), dec!(2), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        derivatives_engine.place_order(DerivativeOrderRequest::market(
+            user_id, "BTC-PERP", Side::Sell, dec!(2), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        assert!(position_manager.get_open_position(&user_id, "BTC-PERP").await.is_none());
+        assert_eq!(derivatives_engine.resting_reduce_only(&user_id, "BTC-PERP").await, dec!(0));
+        let (_, asks) = derivatives_engine.matching_engines.get_engine(&"BTC-PERP".to_string()).await.unwrap()
+            .get_order_book_snapshot(5).await.unwrap();
+        assert!(asks.is_empty());
+    }
+    
+    #[test]
+    fn test_isolated_liquidation_price_uses_leverage_and_maintenance() {
+        // 10x long: margin 5,000 on 50,000 notional, 1% maintenance + 0.5% fee
+        let long = Position::new(
+            Uuid::new_v4(), "BTC-PERP".to_string(), PositionDirection::Long,
+            dec!(1), dec!(50000), dec!(10), MarginType::Isolated, dec!(5000),
+            dec!(0.01), dec!(0.005),
+        );
+        let expected = dec!(45000) / dec!(0.985);
+        assert_eq!(long.liquidation_price, expected);
+        assert!(!long.check_liquidation(dec!(45700), dec!(0.01)));
+        assert!(long.check_liquidation(dec!(45600), dec!(0.01)));
+        
+        // Higher leverage moves the liquidation price closer to entry
+        let long_50x = Position::new(
+            Uuid::new_v4(), "BTC-PERP".to_string(), PositionDirection::Long,
+            dec!(1), dec!(50000), dec!(50), MarginType::Isolated, dec!(1000),
+            dec!(0.01), dec!(0.005),
+        );
+        assert!(long_50x.liquidation_price > long.liquidation_price);
+        
+        // 10x short
+        let short = Position::new(
+            Uuid::new_v4(), "BTC-PERP".to_string(), PositionDirection::Short,
+            dec!(1), dec!(50000), dec!(10), MarginType::Isolated, dec!(5000),
+            dec!(0.01), dec!(0.005),
+        );
+        assert_eq!(short.liquidation_price, dec!(55000) / dec!(1.015));
+        assert!(!short.check_liquidation(dec!(54100), dec!(0.01)));
+        assert!(short.check_liquidation(dec!(54200), dec!(0.01)));
+    }
+
+    #[tokio::test]
+    async fn test_inverse_position_settles_in_base_coin() {
+        // 50,000 USD contracts at 10x: 1 BTC of exposure on 0.1 BTC of margin
+        let long = Position::new(
+            Uuid::new_v4(), "BTC-USD-PERP".to_string(), PositionDirection::Long,
+            dec!(50000), dec!(50000), dec!(10), MarginType::Isolated, dec!(0.1),
+            dec!(0.01), dec!(0.005),
+        ).with_payoff(PayoffType::Inverse);
+        assert_eq!(long.pnl_at(dec!(40000)), dec!(-0.25));
+        assert_eq!(long.notional(dec!(40000)), dec!(1.25));
+        assert_eq!(long.liquidation_price, dec!(50750) / dec!(1.1));
+        assert!(!long.check_liquidation(dec!(46200), dec!(0.01)));
+        assert!(long.check_liquidation(dec!(46100), dec!(0.01)));
+
+        let mut short = Position { direction: PositionDirection::Short, ..long.clone() };
+        assert_eq!(short.update_liquidation_price(), dec!(49250) / dec!(0.9));
+        assert_eq!(short.close(dec!(40000), dec!(25000)).unwrap(), dec!(0.125));
+        assert_eq!(short.margin_amount, dec!(0.05));
+
+        // Funding is paid on the coin value of the position
+        let calculator = FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8);
+        assert_eq!(calculator.calculate_funding_payment(&long, dec!(0.0001), dec!(50000)), dec!(-0.0001));
+
+        // Adding size averages the entry harmonically and margins it in coin
+        let position_manager = PositionManager::new();
+        let user_id = Uuid::new_v4();
+        let fill = |price| PositionFill {
+            user_id,
+            contract_id: "BTC-USD-PERP".to_string(),
+            side: Side::Buy,
+            quantity: dec!(50000),
+            price,
+            leverage: dec!(10),
+            margin_type: MarginType::Isolated,
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            payoff: PayoffType::Inverse,
+            reduce_only: false,
+        };
+        position_manager.apply_fill(fill(dec!(50000))).await.unwrap();
+        let outcome = position_manager.apply_fill(fill(dec!(40000))).await.unwrap();
+        let position = outcome.position.unwrap();
+        assert_eq!(position.entry_price, dec!(100000) / dec!(2.25));
+        assert_eq!(position.margin_amount, dec!(0.225));
+
+        // Inverse contracts must settle in their base coin
+        let contract_manager = ContractManager::new();
+        let mismatched = Contract { payoff: PayoffType::Inverse, ..test_contract() };
+        assert!(contract_manager.add_contract(mismatched).await.is_err());
+        let inverse = Contract {
+            id: "BTC-USD-PERP".to_string(),
+            settlement_asset: "BTC".to_string(),
+            payoff: PayoffType::Inverse,
+            ..test_contract()
+        };
+        assert!(contract_manager.add_contract(inverse).await.is_ok());
     }
 }
 
 // src/trading_engine/margin/mod.rs
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
 use rust_decimal::Decimal;
 use chrono::{DateTime, Utc};
+use log::warn;
 use uuid::Uuid;
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
 
 use crate::trading_engine::matching_engine::{Side, OrderStatus, OrderType};
+use crate::wallet::WalletSystem;
 
 /// Type definitions for margin trading
 pub type MarginAccountId = Uuid;
@@ -782,6 +1565,7 @@ pub struct MarginAccount {
     pub quote_balance: Decimal,
     pub borrowed_base: Decimal,
     pub borrowed_quote: Decimal,
+    /// Annual borrow rates; fixed unless lending pools are configured, then the pool rate at the last accrual
     pub interest_rate_base: Decimal,
     pub interest_rate_quote: Decimal,
     pub last_interest_time: DateTime<Utc>,
@@ -917,39 +1701,305 @@ impl MarginAccount {
         Ok(())
     }
     
-    /// Apply interest to borrowed assets
+    /// Apply interest to borrowed assets at the account's own rates
     pub fn apply_interest(&mut self) -> (Decimal, Decimal) {
-        let now = Utc::now();
-        let seconds_elapsed = (now - self.last_interest_time).num_seconds() as u64;
-        
-        // Convert annual interest rate to per-second rate
-        // Formula: (1 + annual_rate)^(seconds/seconds_in_year) - 1
-        let seconds_in_year = 31_536_000u64; // 365 days
-        
-        let base_interest_factor = ((Decimal::ONE + self.interest_rate_base).powf(
-            Decimal::from(seconds_elapsed) / Decimal::from(seconds_in_year)
-        )) - Decimal::ONE;
-        
-        let quote_interest_factor = ((Decimal::ONE + self.interest_rate_quote).powf(
-            Decimal::from(seconds_elapsed) / Decimal::from(seconds_in_year)
-        )) - Decimal::ONE;
+        let (base_rate, quote_rate) = (self.interest_rate_base, self.interest_rate_quote);
+        self.apply_interest_at(Utc::now(), base_rate, quote_rate)
+    }
+    
+    /// Accrue interest for every whole hour since the last accrual at the given annual rates
+    pub fn apply_interest_at(&mut self, now: DateTime<Utc>, base_rate: Decimal, quote_rate: Decimal) -> (Decimal, Decimal) {
+        let hours = (now - self.last_interest_time).num_hours();
+        if hours <= 0 {
+            return (Decimal::ZERO, Decimal::ZERO);
+        }
         
-        // Calculate interest amounts
-        let base_interest = self.borrowed_base * base_interest_factor;
-        let quote_interest = self.borrowed_quote * quote_interest_factor;
+        let period = Decimal::from(hours) / Decimal::from(HOURS_PER_YEAR);
+        let base_interest = self.borrowed_base * base_rate * period;
+        let quote_interest = self.borrowed_quote * quote_rate * period;
         
-        // Apply interest
         self.borrowed_base += base_interest;
         self.borrowed_quote += quote_interest;
+        self.interest_rate_base = base_rate;
+        self.interest_rate_quote = quote_rate;
         
-        // Update last interest time
-        self.last_interest_time = now;
+        // Carry the partial hour over to the next accrual
+        self.last_interest_time = self.last_interest_time + chrono::Duration::hours(hours);
         self.updated_at = now;
         
         (base_interest, quote_interest)
     }
 }
 
+const HOURS_PER_YEAR: i64 = 8760;
+
+/// Kinked borrow rate curve: gentle up to the optimal utilization, steep above it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestRateModel {
+    /// Annual rate at zero utilization
+    pub base_rate: Decimal,
+    /// Rate added between zero and optimal utilization
+    pub slope_low: Decimal,
+    /// Rate added between optimal and full utilization
+    pub slope_high: Decimal,
+    pub optimal_utilization: Decimal,
+}
+
+impl InterestRateModel {
+    pub fn borrow_rate(&self, utilization: Decimal) -> Decimal {
+        let utilization = utilization.max(Decimal::ZERO).min(Decimal::ONE);
+        
+        if utilization <= self.optimal_utilization {
+            if self.optimal_utilization.is_zero() {
+                return self.base_rate;
+            }
+            self.base_rate + self.slope_low * utilization / self.optimal_utilization
+        } else {
+            let excess = (utilization - self.optimal_utilization) / (Decimal::ONE - self.optimal_utilization);
+            self.base_rate + self.slope_low + self.slope_high * excess
+        }
+    }
+}
+
+impl Default for InterestRateModel {
+    fn default() -> Self {
+        InterestRateModel {
+            base_rate: Decimal::new(1, 2),         // 1%
+            slope_low: Decimal::new(10, 2),        // +10% up to the kink
+            slope_high: Decimal::ONE,              // +100% above it
+            optimal_utilization: Decimal::new(8, 1), // 80%
+        }
+    }
+}
+
+/// Pool of one asset supplied by lenders and drawn by margin borrowers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LendingPool {
+    pub asset: AssetId,
+    pub rate_model: InterestRateModel,
+    /// Share of borrower interest kept as reserves rather than paid to lenders
+    pub reserve_factor: Decimal,
+    /// Lender deposits including interest credited to them
+    pub total_supplied: Decimal,
+    pub total_borrowed: Decimal,
+    pub reserves: Decimal,
+    pub deposits: HashMap<UserId, Decimal>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl LendingPool {
+    pub fn new(asset: AssetId, rate_model: InterestRateModel, reserve_factor: Decimal) -> Self {
+        LendingPool {
+            asset,
+            rate_model,
+            reserve_factor,
+            total_supplied: Decimal::ZERO,
+            total_borrowed: Decimal::ZERO,
+            reserves: Decimal::ZERO,
+            deposits: HashMap::new(),
+            updated_at: Utc::now(),
+        }
+    }
+    
+    /// Amount that can still be borrowed or withdrawn
+    pub fn available_liquidity(&self) -> Decimal {
+        (self.total_supplied - self.total_borrowed).max(Decimal::ZERO)
+    }
+    
+    pub fn utilization(&self) -> Decimal {
+        if self.total_supplied <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+        (self.total_borrowed / self.total_supplied).min(Decimal::ONE)
+    }
+    
+    pub fn borrow_rate(&self) -> Decimal {
+        self.rate_model.borrow_rate(self.utilization())
+    }
+    
+    /// Annual rate earned by lenders
+    pub fn supply_rate(&self) -> Decimal {
+        self.borrow_rate() * self.utilization() * (Decimal::ONE - self.reserve_factor)
+    }
+    
+    pub fn deposit_of(&self, user_id: &UserId) -> Decimal {
+        self.deposits.get(user_id).copied().unwrap_or(Decimal::ZERO)
+    }
+    
+    pub fn supply(&mut self, user_id: UserId, amount: Decimal) -> Result<()> {
+        if amount <= Decimal::ZERO {
+            return Err(anyhow::anyhow!("Supply amount must be positive"));
+        }
+        
+        *self.deposits.entry(user_id).or_insert(Decimal::ZERO) += amount;
+        self.total_supplied += amount;
+        self.updated_at = Utc::now();
+        
+        Ok(())
+    }
+    
+    pub fn withdraw(&mut self, user_id: &UserId, amount: Decimal) -> Result<()> {
+        if amount <= Decimal::ZERO {
+            return Err(anyhow::anyhow!("Withdraw amount must be positive"));
+        }
+        
+        let deposit = self.deposit_of(user_id);
+        if amount > deposit {
+            return Err(anyhow::anyhow!("Withdraw amount exceeds deposit of {}", deposit));
+        }
+        if amount > self.available_liquidity() {
+            return Err(anyhow::anyhow!(
+                "Insufficient liquidity in {} lending pool: {} available",
+                self.asset, self.available_liquidity()
+            ));
+        }
+        
+        if amount == deposit {
+            self.deposits.remove(user_id);
+        } else {
+            self.deposits.insert(*user_id, deposit - amount);
+        }
+        self.total_supplied -= amount;
+        self.updated_at = Utc::now();
+        
+        Ok(())
+    }
+    
+    pub fn borrow(&mut self, amount: Decimal) -> Result<()> {
+        if amount > self.available_liquidity() {
+            return Err(anyhow::anyhow!(
+                "Insufficient liquidity in {} lending pool: {} available",
+                self.asset, self.available_liquidity()
+            ));
+        }
+        
+        self.total_borrowed += amount;
+        self.updated_at = Utc::now();
+        
+        Ok(())
+    }
+    
+    pub fn repay(&mut self, amount: Decimal) {
+        self.total_borrowed = (self.total_borrowed - amount).max(Decimal::ZERO);
+        self.updated_at = Utc::now();
+    }
+    
+    /// Add interest charged to borrowers, crediting lenders pro rata after reserves
+    pub fn accrue(&mut self, interest: Decimal) {
+        if interest <= Decimal::ZERO {
+            return;
+        }
+        
+        let reserve = interest * self.reserve_factor;
+        let lender_share = interest - reserve;
+        
+        if self.total_supplied > Decimal::ZERO {
+            let total_supplied = self.total_supplied;
+            for deposit in self.deposits.values_mut() {
+                *deposit += lender_share * *deposit / total_supplied;
+            }
+            self.total_supplied = self.deposits.values().copied().sum();
+            self.reserves += interest - (self.total_supplied - total_supplied);
+        } else {
+            self.reserves += interest;
+        }
+        
+        self.total_borrowed += interest;
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Lending pools by asset, funded from users' wallets
+pub struct LendingPoolManager {
+    pools: RwLock<HashMap<AssetId, LendingPool>>,
+    wallets: Arc<WalletSystem>,
+}
+
+impl LendingPoolManager {
+    pub fn new(wallets: Arc<WalletSystem>) -> Self {
+        LendingPoolManager {
+            pools: RwLock::new(HashMap::new()),
+            wallets,
+        }
+    }
+    
+    pub async fn create_pool(&self, asset: AssetId, rate_model: InterestRateModel, reserve_factor: Decimal) -> Result<LendingPool> {
+        let mut pools = self.pools.write().await;
+        
+        if pools.contains_key(&asset) {
+            return Err(anyhow::anyhow!("Lending pool for {} already exists", asset));
+        }
+        
+        let pool = LendingPool::new(asset.clone(), rate_model, reserve_factor);
+        pools.insert(asset, pool.clone());
+        
+        Ok(pool)
+    }
+    
+    pub async fn get_pool(&self, asset: &AssetId) -> Option<LendingPool> {
+        self.pools.read().await.get(asset).cloned()
+    }
+    
+    /// Move funds from a user's wallet into the pool
+    pub async fn supply(&self, user_id: UserId, asset: &AssetId, amount: Decimal) -> Result<LendingPool> {
+        let mut pools = self.pools.write().await;
+        let pool = pools.get_mut(asset)
+            .ok_or_else(|| anyhow::anyhow!("No lending pool for {}", asset))?;
+        
+        if amount <= Decimal::ZERO {
+            return Err(anyhow::anyhow!("Supply amount must be positive"));
+        }
+        self.wallets.update_user_balance(&user_id, asset, |balance| balance.debit(amount)).await?;
+        pool.supply(user_id, amount)?;
+        
+        Ok(pool.clone())
+    }
+    
+    /// Return funds from the pool to a user's wallet
+    pub async fn withdraw(&self, user_id: &UserId, asset: &AssetId, amount: Decimal) -> Result<LendingPool> {
+        let mut pools = self.pools.write().await;
+        let pool = pools.get_mut(asset)
+            .ok_or_else(|| anyhow::anyhow!("No lending pool for {}", asset))?;
+        
+        // The pool only changes once the wallet has been credited
+        let mut updated = pool.clone();
+        updated.withdraw(user_id, amount)?;
+        self.wallets.update_user_balance(user_id, asset, |balance| {
+            balance.credit(amount);
+            Ok(())
+        }).await?;
+        *pool = updated;
+        
+        Ok(pool.clone())
+    }
+    
+    pub async fn borrow(&self, asset: &AssetId, amount: Decimal) -> Result<()> {
+        let mut pools = self.pools.write().await;
+        let pool = pools.get_mut(asset).ok_or_else(|| anyhow::anyhow!("No lending pool for {}", asset))?;
+        pool.borrow(amount)
+    }
+    
+    pub async fn repay(&self, asset: &AssetId, amount: Decimal) -> Result<()> {
+        let mut pools = self.pools.write().await;
+        let pool = pools.get_mut(asset).ok_or_else(|| anyhow::anyhow!("No lending pool for {}", asset))?;
+        pool.repay(amount);
+        Ok(())
+    }
+    
+    pub async fn borrow_rate(&self, asset: &AssetId) -> Result<Decimal> {
+        self.pools.read().await.get(asset)
+            .map(|pool| pool.borrow_rate())
+            .ok_or_else(|| anyhow::anyhow!("No lending pool for {}", asset))
+    }
+    
+    pub async fn accrue(&self, asset: &AssetId, interest: Decimal) -> Result<()> {
+        let mut pools = self.pools.write().await;
+        let pool = pools.get_mut(asset).ok_or_else(|| anyhow::anyhow!("No lending pool for {}", asset))?;
+        pool.accrue(interest);
+        Ok(())
+    }
+}
+
 /// Representation of a margin call event
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarginCall {
@@ -969,6 +2019,8 @@ pub struct MarginAccountManager {
     margin_calls: RwLock<Vec<MarginCall>>,
     maintenance_margin_level: Decimal,
     liquidation_margin_level: Decimal,
+    /// Borrowing draws from these pools when set, otherwise from an unlimited lender at fixed rates
+    lending_pools: Option<Arc<LendingPoolManager>>,
 }
 
 impl MarginAccountManager {
@@ -978,9 +2030,19 @@ impl MarginAccountManager {
             margin_calls: RwLock::new(Vec::new()),
             maintenance_margin_level,
             liquidation_margin_level,
+            lending_pools: None,
         }
     }
     
+    pub fn with_lending_pools(mut self, lending_pools: Arc<LendingPoolManager>) -> Self {
+        self.lending_pools = Some(lending_pools);
+        self
+    }
+    
+    pub fn lending_pools(&self) -> Option<Arc<LendingPoolManager>> {
+        self.lending_pools.clone()
+    }
+    
     pub async fn create_account(
         &self,
         user_id: UserId,
@@ -1032,34 +2094,124 @@ impl MarginAccountManager {
     
     pub async fn borrow(&self, account_id: &MarginAccountId, asset_is_base: bool, amount: Decimal) -> Result<MarginAccount> {
         let mut accounts = self.accounts.write().await;
+        let account = accounts.iter_mut().find(|a| a.id == *account_id)
+            .ok_or_else(|| anyhow::anyhow!("Account not found"))?;
         
-        if let Some(account) = accounts.iter_mut().find(|a| a.id == *account_id) {
-            account.borrow(asset_is_base, amount)?;
-            Ok(account.clone())
-        } else {
-            Err(anyhow::anyhow!("Account not found"))
+        if let Some(pools) = &self.lending_pools {
+            if amount <= Decimal::ZERO {
+                return Err(anyhow::anyhow!("Borrow amount must be positive"));
+            }
+            
+            // Settle interest owed so far before the principal changes
+            let now = Utc::now();
+            Self::accrue_from_pools(pools, account, now).await?;
+            
+            let asset = if asset_is_base { &account.base_asset } else { &account.quote_asset };
+            pools.borrow(asset, amount).await?;
         }
+        
+        account.borrow(asset_is_base, amount)?;
+        Ok(account.clone())
     }
     
     pub async fn repay(&self, account_id: &MarginAccountId, asset_is_base: bool, amount: Decimal) -> Result<MarginAccount> {
         let mut accounts = self.accounts.write().await;
+        let account = accounts.iter_mut().find(|a| a.id == *account_id)
+            .ok_or_else(|| anyhow::anyhow!("Account not found"))?;
         
-        if let Some(account) = accounts.iter_mut().find(|a| a.id == *account_id) {
-            account.repay(asset_is_base, amount)?;
-            Ok(account.clone())
-        } else {
-            Err(anyhow::anyhow!("Account not found"))
+        if let Some(pools) = &self.lending_pools {
+            Self::accrue_from_pools(pools, account, Utc::now()).await?;
+        }
+        
+        account.repay(asset_is_base, amount)?;
+        
+        if let Some(pools) = &self.lending_pools {
+            let asset = if asset_is_base { &account.base_asset } else { &account.quote_asset };
+            pools.repay(asset, amount).await?;
         }
+        
+        Ok(account.clone())
     }
     
     pub async fn apply_interest(&self, account_id: &MarginAccountId) -> Result<(Decimal, Decimal)> {
+        self.apply_interest_at(account_id, Utc::now()).await
+    }
+    
+    /// Charge the account's hourly interest, at pool rates and credited to lenders when pools are configured
+    pub async fn apply_interest_at(&self, account_id: &MarginAccountId, now: DateTime<Utc>) -> Result<(Decimal, Decimal)> {
         let mut accounts = self.accounts.write().await;
+        let account = accounts.iter_mut().find(|a| a.id == *account_id)
+            .ok_or_else(|| anyhow::anyhow!("Account not found"))?;
         
-        if let Some(account) = accounts.iter_mut().find(|a| a.id == *account_id) {
-            Ok(account.apply_interest())
-        } else {
-            Err(anyhow::anyhow!("Account not found"))
+        match &self.lending_pools {
+            Some(pools) => Self::accrue_from_pools(pools, account, now).await,
+            None => {
+                let (base_rate, quote_rate) = (account.interest_rate_base, account.interest_rate_quote);
+                Ok(account.apply_interest_at(now, base_rate, quote_rate))
+            }
+        }
+    }
+    
+    /// Charge interest on every account with borrowings
+    pub async fn apply_interest_all(&self, now: DateTime<Utc>) -> Result<usize> {
+        let account_ids: Vec<MarginAccountId> = self.accounts.read().await.iter()
+            .filter(|a| a.borrowed_base > Decimal::ZERO || a.borrowed_quote > Decimal::ZERO)
+            .map(|a| a.id)
+            .collect();
+        
+        let mut accounts_charged = 0;
+        for account_id in account_ids {
+            // One account failing to accrue must not hold up the rest
+            match self.apply_interest_at(&account_id, now).await {
+                Ok((base_interest, quote_interest)) => {
+                    if base_interest > Decimal::ZERO || quote_interest > Decimal::ZERO {
+                        accounts_charged += 1;
+                    }
+                },
+                Err(e) => warn!("Failed to apply interest to margin account {}: {}", account_id, e),
+            }
+        }
+        
+        Ok(accounts_charged)
+    }
+    
+    /// Accrue interest on all accounts every hour
+    pub fn spawn_interest_accrual(self: &Arc<Self>) -> JoinHandle<()> {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = time::interval(Duration::from_secs(3600));
+            loop {
+                ticker.tick().await;
+                if let Err(e) = manager.apply_interest_all(Utc::now()).await {
+                    warn!("Margin interest accrual failed: {}", e);
+                }
+            }
+        })
+    }
+    
+    async fn accrue_from_pools(pools: &LendingPoolManager, account: &mut MarginAccount, now: DateTime<Utc>) -> Result<(Decimal, Decimal)> {
+        // An asset without a pool is fine as long as nothing of it is borrowed
+        let rate = |asset: &AssetId, borrowed: Decimal| {
+            let asset = asset.clone();
+            async move {
+                match pools.borrow_rate(&asset).await {
+                    Err(_) if borrowed.is_zero() => Ok(Decimal::ZERO),
+                    result => result,
+                }
+            }
+        };
+        let base_rate = rate(&account.base_asset, account.borrowed_base).await?;
+        let quote_rate = rate(&account.quote_asset, account.borrowed_quote).await?;
+        
+        let (base_interest, quote_interest) = account.apply_interest_at(now, base_rate, quote_rate);
+        if base_interest > Decimal::ZERO {
+            pools.accrue(&account.base_asset, base_interest).await?;
         }
+        if quote_interest > Decimal::ZERO {
+            pools.accrue(&account.quote_asset, quote_interest).await?;
+        }
+        
+        Ok((base_interest, quote_interest))
     }
     
     pub async fn update_margin_level(&self, account_id: &MarginAccountId, base_price: Decimal) -> Result<Decimal> {
@@ -1401,4 +2553,87 @@ mod tests {
         assert_eq!(account.borrowed_base, dec!(0));
         assert_eq!(account.borrowed_quote, dec!(0));
     }
+    
+    #[test]
+    fn test_kinked_borrow_rate() {
+        let model = InterestRateModel {
+            base_rate: dec!(0.02),
+            slope_low: dec!(0.1),
+            slope_high: dec!(1),
+            optimal_utilization: dec!(0.8),
+        };
+        
+        assert_eq!(model.borrow_rate(dec!(0)), dec!(0.02));
+        assert_eq!(model.borrow_rate(dec!(0.4)), dec!(0.07));
+        assert_eq!(model.borrow_rate(dec!(0.8)), dec!(0.12));
+        // Above the kink each point of utilization costs ten times more
+        assert_eq!(model.borrow_rate(dec!(0.9)), dec!(0.62));
+        assert_eq!(model.borrow_rate(dec!(1)), dec!(1.12));
+    }
+    
+    #[tokio::test]
+    async fn test_borrowing_from_lending_pool() {
+        let wallets = Arc::new(WalletSystem::new());
+        let pools = Arc::new(LendingPoolManager::new(wallets.clone()));
+        pools.create_pool("USDT".to_string(), InterestRateModel {
+            base_rate: dec!(0),
+            slope_low: dec!(0.1),
+            slope_high: dec!(1),
+            optimal_utilization: dec!(0.8),
+        }, dec!(0.1)).await.unwrap();
+        pools.create_pool("BTC".to_string(), InterestRateModel::default(), dec!(0.1)).await.unwrap();
+        
+        // A lender moves 10,000 USDT of spot balance into the pool
+        let lender = Uuid::new_v4();
+        let usdt = "USDT".to_string();
+        wallets.create_user_wallet(lender).await.unwrap();
+        wallets.update_user_balance(&lender, &usdt, |spot| {
+            spot.credit(dec!(12000));
+            Ok(())
+        }).await.unwrap();
+        pools.supply(lender, &usdt, dec!(10000)).await.unwrap();
+        assert_eq!(wallets.get_user_balance(&lender, &usdt).await.unwrap().available, dec!(2000));
+        // Nothing more can be supplied than the wallet holds
+        assert!(pools.supply(lender, &usdt, dec!(2001)).await.is_err());
+        
+        let margin_manager = MarginAccountManager::new(dec!(1.5), dec!(1.1))
+            .with_lending_pools(pools.clone());
+        let account = margin_manager.create_account(
+            Uuid::new_v4(), true, "BTC".to_string(), "USDT".to_string(),
+            dec!(1), dec!(0), dec!(0), dec!(0),
+        ).await.unwrap();
+        
+        margin_manager.borrow(&account.id, false, dec!(8000)).await.unwrap();
+        let pool = pools.get_pool(&"USDT".to_string()).await.unwrap();
+        assert_eq!(pool.utilization(), dec!(0.8));
+        assert_eq!(pool.borrow_rate(), dec!(0.1));
+        
+        // Only 2,000 left to lend, and the lender cannot pull what is lent out
+        assert!(margin_manager.borrow(&account.id, false, dec!(3000)).await.is_err());
+        assert!(pools.withdraw(&lender, &usdt, dec!(3000)).await.is_err());
+        
+        // 876 hours at 10% a year is 1% of the loan
+        let account = margin_manager.get_account(&account.id).await.unwrap();
+        let later = account.last_interest_time + chrono::Duration::hours(876) + chrono::Duration::minutes(30);
+        let (_, quote_interest) = margin_manager.apply_interest_at(&account.id, later).await.unwrap();
+        assert_eq!(quote_interest, dec!(80));
+        
+        let account = margin_manager.get_account(&account.id).await.unwrap();
+        assert_eq!(account.borrowed_quote, dec!(8080));
+        assert_eq!(account.interest_rate_quote, dec!(0.1));
+        // The partial hour is carried over
+        assert_eq!(later - account.last_interest_time, chrono::Duration::minutes(30));
+        
+        // Lenders receive the interest less the 10% reserve
+        let pool = pools.get_pool(&"USDT".to_string()).await.unwrap();
+        assert_eq!(pool.deposit_of(&lender), dec!(10072));
+        assert_eq!(pool.reserves, dec!(8));
+        assert_eq!(pool.total_borrowed, dec!(8080));
+        
+        margin_manager.repay(&account.id, false, dec!(8000)).await.unwrap();
+        let pool = pools.get_pool(&"USDT".to_string()).await.unwrap();
+        assert_eq!(pool.total_borrowed, dec!(80));
+        pools.withdraw(&lender, &usdt, dec!(3000)).await.unwrap();
+        assert_eq!(wallets.get_user_balance(&lender, &usdt).await.unwrap().available, dec!(5000));
+    }
 }
@@ -0,0 +1,844 @@
+// src/trading_engine/derivatives/options.rs
+
+use std::collections::HashMap;
+use std::f64::consts::{PI, SQRT_2};
+use tokio::sync::RwLock;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use chrono::{DateTime, Utc};
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use super::{
+    Contract, ContractId, ContractType, DerivativesEngine, MarginType, OptionType, Position,
+    PositionDirection, PositionId, PositionStatus, UserId,
+};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 86400.0;
+
+/// Pricing model for European options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PricingModel {
+    /// Underlying is the spot price
+    BlackScholes,
+    /// Underlying is the forward price
+    Black76,
+}
+
+/// Inputs to the option pricer
+#[derive(Debug, Clone, Copy)]
+pub struct OptionInputs {
+    pub option_type: OptionType,
+    /// Spot for Black-Scholes, forward for Black-76
+    pub underlying: f64,
+    pub strike: f64,
+    /// Years to expiry
+    pub time_to_expiry: f64,
+    pub volatility: f64,
+    /// Continuously compounded risk-free rate
+    pub rate: f64,
+}
+
+/// Option sensitivities
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Greeks {
+    /// Change in value per unit change of the underlying
+    pub delta: f64,
+    /// Change in delta per unit change of the underlying
+    pub gamma: f64,
+    /// Change in value per one volatility point (0.01)
+    pub vega: f64,
+    /// Change in value per calendar day
+    pub theta: f64,
+}
+
+impl Greeks {
+    fn scaled(self, factor: f64) -> Greeks {
+        Greeks {
+            delta: self.delta * factor,
+            gamma: self.gamma * factor,
+            vega: self.vega * factor,
+            theta: self.theta * factor,
+        }
+    }
+}
+
+impl std::ops::Add for Greeks {
+    type Output = Greeks;
+
+    fn add(self, other: Greeks) -> Greeks {
+        Greeks {
+            delta: self.delta + other.delta,
+            gamma: self.gamma + other.gamma,
+            vega: self.vega + other.vega,
+            theta: self.theta + other.theta,
+        }
+    }
+}
+
+/// Standard normal density
+fn norm_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * PI).sqrt()
+}
+
+/// Complementary error function (Numerical Recipes erfcc, relative error below 1.2e-7)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223
+        + t * (1.00002368
+        + t * (0.37409196
+        + t * (0.09678418
+        + t * (-0.18628806
+        + t * (0.27886807
+        + t * (-1.13520398
+        + t * (1.48851587
+        + t * (-0.82215223
+        + t * 0.17087277))))))))).exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+/// Standard normal cumulative distribution
+pub fn norm_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+fn intrinsic(option_type: OptionType, underlying: f64, strike: f64) -> f64 {
+    match option_type {
+        OptionType::Call => (underlying - strike).max(0.0),
+        OptionType::Put => (strike - underlying).max(0.0),
+    }
+}
+
+fn d1_d2(inputs: &OptionInputs, model: PricingModel) -> (f64, f64) {
+    let sqrt_t = inputs.time_to_expiry.sqrt();
+    let vol_sqrt_t = inputs.volatility * sqrt_t;
+    let drift = match model {
+        PricingModel::BlackScholes => inputs.rate + 0.5 * inputs.volatility * inputs.volatility,
+        PricingModel::Black76 => 0.5 * inputs.volatility * inputs.volatility,
+    };
+    let d1 = ((inputs.underlying / inputs.strike).ln() + drift * inputs.time_to_expiry) / vol_sqrt_t;
+    (d1, d1 - vol_sqrt_t)
+}
+
+fn is_expired(inputs: &OptionInputs) -> bool {
+    inputs.time_to_expiry <= 0.0 || inputs.volatility <= 0.0
+}
+
+/// Theoretical value of a European option
+pub fn option_price(model: PricingModel, inputs: &OptionInputs) -> f64 {
+    if is_expired(inputs) {
+        return intrinsic(inputs.option_type, inputs.underlying, inputs.strike);
+    }
+
+    let (d1, d2) = d1_d2(inputs, model);
+    let discount = (-inputs.rate * inputs.time_to_expiry).exp();
+    let (s, k) = (inputs.underlying, inputs.strike);
+
+    match (model, inputs.option_type) {
+        (PricingModel::BlackScholes, OptionType::Call) => s * norm_cdf(d1) - k * discount * norm_cdf(d2),
+        (PricingModel::BlackScholes, OptionType::Put) => k * discount * norm_cdf(-d2) - s * norm_cdf(-d1),
+        (PricingModel::Black76, OptionType::Call) => discount * (s * norm_cdf(d1) - k * norm_cdf(d2)),
+        (PricingModel::Black76, OptionType::Put) => discount * (k * norm_cdf(-d2) - s * norm_cdf(-d1)),
+    }
+}
+
+/// Greeks of one European option
+pub fn option_greeks(model: PricingModel, inputs: &OptionInputs) -> Greeks {
+    if is_expired(inputs) {
+        let itm = intrinsic(inputs.option_type, inputs.underlying, inputs.strike) > 0.0;
+        let delta = match (inputs.option_type, itm) {
+            (OptionType::Call, true) => 1.0,
+            (OptionType::Put, true) => -1.0,
+            _ => 0.0,
+        };
+        return Greeks { delta, ..Greeks::default() };
+    }
+
+    let (d1, d2) = d1_d2(inputs, model);
+    let t = inputs.time_to_expiry;
+    let sqrt_t = t.sqrt();
+    let (s, k, r, vol) = (inputs.underlying, inputs.strike, inputs.rate, inputs.volatility);
+    let discount = (-r * t).exp();
+    let pdf = norm_pdf(d1);
+
+    let (delta, gamma, vega, theta) = match model {
+        PricingModel::BlackScholes => {
+            let decay = -s * pdf * vol / (2.0 * sqrt_t);
+            let (delta, theta) = match inputs.option_type {
+                OptionType::Call => (norm_cdf(d1), decay - r * k * discount * norm_cdf(d2)),
+                OptionType::Put => (norm_cdf(d1) - 1.0, decay + r * k * discount * norm_cdf(-d2)),
+            };
+            (delta, pdf / (s * vol * sqrt_t), s * pdf * sqrt_t, theta)
+        },
+        PricingModel::Black76 => {
+            let decay = -s * discount * pdf * vol / (2.0 * sqrt_t);
+            let (delta, theta) = match inputs.option_type {
+                OptionType::Call => (
+                    discount * norm_cdf(d1),
+                    decay - r * k * discount * norm_cdf(d2) + r * s * discount * norm_cdf(d1),
+                ),
+                OptionType::Put => (
+                    -discount * norm_cdf(-d1),
+                    decay + r * k * discount * norm_cdf(-d2) - r * s * discount * norm_cdf(-d1),
+                ),
+            };
+            (delta, discount * pdf / (s * vol * sqrt_t), s * discount * pdf * sqrt_t, theta)
+        },
+    };
+
+    Greeks {
+        delta,
+        gamma,
+        vega: vega / 100.0,
+        theta: theta / 365.0,
+    }
+}
+
+/// Solve for the volatility that reproduces an option price, by Newton's method
+/// with a bisection fallback
+pub fn implied_volatility(model: PricingModel, inputs: &OptionInputs, price: f64) -> Result<f64> {
+    const MIN_VOL: f64 = 1e-4;
+    const MAX_VOL: f64 = 5.0;
+    const TOLERANCE: f64 = 1e-8;
+
+    if inputs.time_to_expiry <= 0.0 {
+        return Err(anyhow!("Option has expired"));
+    }
+    let price_at = |vol: f64| option_price(model, &OptionInputs { volatility: vol, ..*inputs });
+
+    let (lower, upper) = (price_at(MIN_VOL), price_at(MAX_VOL));
+    if price < lower - TOLERANCE || price > upper + TOLERANCE {
+        return Err(anyhow!("Price {} is outside the no-arbitrage bounds [{}, {}]", price, lower, upper));
+    }
+
+    let (mut low, mut high) = (MIN_VOL, MAX_VOL);
+    let mut vol = 0.5;
+    for _ in 0..100 {
+        let diff = price_at(vol) - price;
+        if diff.abs() < TOLERANCE {
+            return Ok(vol);
+        }
+        if diff > 0.0 { high = vol; } else { low = vol; }
+
+        // Vega per unit of volatility
+        let vega = option_greeks(model, &OptionInputs { volatility: vol, ..*inputs }).vega * 100.0;
+        let newton = vol - diff / vega;
+        vol = if vega > 1e-12 && newton > low && newton < high {
+            newton
+        } else {
+            0.5 * (low + high)
+        };
+    }
+
+    Err(anyhow!("Implied volatility did not converge"))
+}
+
+/// Years from `now` to `expiry`
+pub fn years_to_expiry(now: DateTime<Utc>, expiry: DateTime<Utc>) -> f64 {
+    ((expiry - now).num_milliseconds() as f64 / 1000.0 / SECONDS_PER_YEAR).max(0.0)
+}
+
+/// One option position for scenario margining; quantity is negative for shorts
+#[derive(Debug, Clone, Copy)]
+pub struct OptionLeg {
+    pub option_type: OptionType,
+    pub strike: f64,
+    pub time_to_expiry: f64,
+    pub volatility: f64,
+    pub quantity: f64,
+}
+
+/// Scenario grid for margining option portfolios
+#[derive(Debug, Clone)]
+pub struct ScenarioMarginConfig {
+    /// Relative moves of the underlying
+    pub price_moves: Vec<f64>,
+    /// Relative moves of volatility
+    pub vol_moves: Vec<f64>,
+    /// Minimum margin per short contract as a fraction of the underlying
+    pub short_option_minimum: f64,
+}
+
+impl Default for ScenarioMarginConfig {
+    fn default() -> Self {
+        ScenarioMarginConfig {
+            price_moves: vec![-0.15, -0.10, -0.05, 0.0, 0.05, 0.10, 0.15],
+            vol_moves: vec![-0.3, 0.0, 0.3],
+            short_option_minimum: 0.05,
+        }
+    }
+}
+
+/// Margin for a portfolio of options on one underlying: the worst loss over the
+/// scenario grid, floored by a minimum per short contract
+pub fn scenario_margin(
+    model: PricingModel,
+    rate: f64,
+    underlying: f64,
+    legs: &[OptionLeg],
+    config: &ScenarioMarginConfig,
+) -> f64 {
+    let value_at = |price: f64, vol_move: f64| -> f64 {
+        legs.iter()
+            .map(|leg| leg.quantity * option_price(model, &OptionInputs {
+                option_type: leg.option_type,
+                underlying: price,
+                strike: leg.strike,
+                time_to_expiry: leg.time_to_expiry,
+                volatility: (leg.volatility * (1.0 + vol_move)).max(1e-4),
+                rate,
+            }))
+            .sum()
+    };
+
+    let current = value_at(underlying, 0.0);
+    let mut worst_loss: f64 = 0.0;
+    for price_move in &config.price_moves {
+        for vol_move in &config.vol_moves {
+            let loss = current - value_at(underlying * (1.0 + price_move), *vol_move);
+            worst_loss = worst_loss.max(loss);
+        }
+    }
+
+    let short_quantity: f64 = legs.iter().filter(|l| l.quantity < 0.0).map(|l| -l.quantity).sum();
+    worst_loss.max(short_quantity * underlying * config.short_option_minimum)
+}
+
+/// Option pricing parameters and the volatilities used for each option contract
+pub struct OptionPricer {
+    pub model: PricingModel,
+    pub rate: f64,
+    pub default_volatility: f64,
+    pub scenarios: ScenarioMarginConfig,
+    volatilities: RwLock<HashMap<ContractId, f64>>,
+}
+
+impl OptionPricer {
+    pub fn new(model: PricingModel, rate: f64, default_volatility: f64) -> Self {
+        OptionPricer {
+            model,
+            rate,
+            default_volatility,
+            scenarios: ScenarioMarginConfig::default(),
+            volatilities: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn set_volatility(&self, contract_id: &str, volatility: f64) {
+        self.volatilities.write().await.insert(contract_id.to_string(), volatility);
+    }
+
+    pub async fn volatility(&self, contract_id: &str) -> f64 {
+        self.volatilities.read().await.get(contract_id).copied().unwrap_or(self.default_volatility)
+    }
+
+    /// Pricer inputs for an option contract at the given underlying price
+    pub async fn inputs(&self, contract: &Contract, underlying: Decimal, now: DateTime<Utc>) -> Result<OptionInputs> {
+        let (option_type, strike, expiry) = option_terms(contract)?;
+        let spot = underlying.to_f64().ok_or_else(|| anyhow!("Invalid underlying price"))?;
+        let time_to_expiry = years_to_expiry(now, expiry);
+
+        // Black-76 prices off the forward implied by the rate
+        let underlying = match self.model {
+            PricingModel::BlackScholes => spot,
+            PricingModel::Black76 => spot * (self.rate * time_to_expiry).exp(),
+        };
+
+        Ok(OptionInputs {
+            option_type,
+            underlying,
+            strike,
+            time_to_expiry,
+            volatility: self.volatility(&contract.id).await,
+            rate: self.rate,
+        })
+    }
+
+    /// Calibrate a contract's volatility to an observed option price
+    pub async fn calibrate(&self, contract: &Contract, underlying: Decimal, price: Decimal, now: DateTime<Utc>) -> Result<f64> {
+        let inputs = self.inputs(contract, underlying, now).await?;
+        let price = price.to_f64().ok_or_else(|| anyhow!("Invalid option price"))?;
+        let volatility = implied_volatility(self.model, &inputs, price)?;
+        self.set_volatility(&contract.id, volatility).await;
+        Ok(volatility)
+    }
+}
+
+impl Default for OptionPricer {
+    fn default() -> Self {
+        OptionPricer::new(PricingModel::BlackScholes, 0.0, 0.6)
+    }
+}
+
+/// Type, strike and expiry of an option contract
+pub fn option_terms(contract: &Contract) -> Result<(OptionType, f64, DateTime<Utc>)> {
+    if contract.contract_type != ContractType::Option {
+        return Err(anyhow!("{} is not an option", contract.id));
+    }
+    let option_type = contract.option_type.ok_or_else(|| anyhow!("Option {} has no type", contract.id))?;
+    let strike = contract.strike_price
+        .and_then(|s| s.to_f64())
+        .ok_or_else(|| anyhow!("Option {} has no strike", contract.id))?;
+    let expiry = contract.expiry_time.ok_or_else(|| anyhow!("Option {} has no expiry", contract.id))?;
+    Ok((option_type, strike, expiry))
+}
+
+fn to_decimal(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or(Decimal::ZERO)
+}
+
+fn signed_quantity(position: &Position) -> f64 {
+    let quantity = position.quantity.to_f64().unwrap_or(0.0);
+    match position.direction {
+        PositionDirection::Long => quantity,
+        PositionDirection::Short => -quantity,
+    }
+}
+
+/// Cash settlement of an option position at expiry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionExercise {
+    pub position_id: PositionId,
+    pub user_id: UserId,
+    pub contract_id: ContractId,
+    pub direction: PositionDirection,
+    pub quantity: Decimal,
+    pub settlement_price: Decimal,
+    /// Value per contract paid by the short to the long
+    pub intrinsic_value: Decimal,
+    pub pnl: Decimal,
+}
+
+impl DerivativesEngine {
+    pub fn option_pricer(&self) -> std::sync::Arc<OptionPricer> {
+        self.option_pricer.clone()
+    }
+
+    /// Theoretical value of an option contract at the current underlying index
+    pub async fn option_mark_price(&self, contract: &Contract) -> Result<Decimal> {
+        let underlying = self.price_service.index_price(&contract.id).await?;
+        let inputs = self.option_pricer.inputs(contract, underlying, Utc::now()).await?;
+        Ok(to_decimal(option_price(self.option_pricer.model, &inputs)))
+    }
+
+    /// Greeks of a position, scaled by its size and signed by direction
+    pub async fn position_greeks(&self, position_id: &PositionId) -> Result<Greeks> {
+        let position = self.position_manager.get_position(position_id).await
+            .ok_or_else(|| anyhow!("Position not found"))?;
+        let contract = self.contract_manager.get_contract(&position.contract_id).await
+            .ok_or_else(|| anyhow!("Contract not found"))?;
+
+        if contract.contract_type != ContractType::Option {
+            // A linear contract has a delta of one per unit and no other sensitivities
+            return Ok(Greeks { delta: signed_quantity(&position), ..Greeks::default() });
+        }
+
+        let underlying = self.price_service.index_price(&contract.id).await?;
+        let inputs = self.option_pricer.inputs(&contract, underlying, Utc::now()).await?;
+        Ok(option_greeks(self.option_pricer.model, &inputs).scaled(signed_quantity(&position)))
+    }
+
+    /// Net Greeks of all of a user's open positions
+    pub async fn portfolio_greeks(&self, user_id: &UserId) -> Result<Greeks> {
+        let mut total = Greeks::default();
+        for position in self.position_manager.get_user_open_positions(user_id).await {
+            total = total + self.position_greeks(&position.id).await?;
+        }
+        Ok(total)
+    }
+
+    /// Scenario margin for a set of option positions on one contract's underlying
+    async fn option_positions_margin(&self, contract: &Contract, positions: &[(Position, Contract)]) -> Result<Decimal> {
+        let underlying = self.price_service.index_price(&contract.id).await?;
+        let now = Utc::now();
+
+        let mut legs = Vec::new();
+        for (position, contract) in positions {
+            let inputs = self.option_pricer.inputs(contract, underlying, now).await?;
+            legs.push(OptionLeg {
+                option_type: inputs.option_type,
+                strike: inputs.strike,
+                time_to_expiry: inputs.time_to_expiry,
+                volatility: inputs.volatility,
+                quantity: signed_quantity(position),
+            });
+        }
+
+        let underlying = underlying.to_f64().ok_or_else(|| anyhow!("Invalid underlying price"))?;
+        let pricer = &self.option_pricer;
+        Ok(to_decimal(scenario_margin(pricer.model, pricer.rate, underlying, &legs, &pricer.scenarios)))
+    }
+
+    /// Scenario-based margin requirement of a user's option positions, netted per underlying
+    pub async fn option_margin_requirement(&self, user_id: &UserId) -> Result<Decimal> {
+        let mut by_underlying: HashMap<(String, String), Vec<(Position, Contract)>> = HashMap::new();
+        for position in self.position_manager.get_user_open_positions(user_id).await {
+            let contract = match self.contract_manager.get_contract(&position.contract_id).await {
+                Some(contract) if contract.contract_type == ContractType::Option => contract,
+                _ => continue,
+            };
+            by_underlying.entry((contract.base_asset.clone(), contract.quote_asset.clone()))
+                .or_default()
+                .push((position, contract));
+        }
+
+        let mut requirement = Decimal::ZERO;
+        for positions in by_underlying.values() {
+            requirement += self.option_positions_margin(&positions[0].1, positions).await?;
+        }
+        Ok(requirement)
+    }
+
+    /// Set a short option position's margin to its scenario requirement, reserving the
+    /// difference from the holder's wallet; positions no longer short and open release theirs
+    pub(super) async fn refresh_option_margin(&self, contract: &Contract, position_id: &PositionId) -> Result<()> {
+        let mut position = match self.position_manager.get_position(position_id).await {
+            Some(position) => position,
+            None => return Ok(()),
+        };
+        let open_short = position.status == PositionStatus::Open && position.direction == PositionDirection::Short;
+        let requirement = if open_short {
+            self.option_positions_margin(contract, &[(position.clone(), contract.clone())]).await?
+        } else {
+            Decimal::ZERO
+        };
+
+        // Held across the wallet update so concurrent refreshes cannot reserve twice
+        let mut reserved = self.option_margin_reserved.write().await;
+        let held = reserved.get(position_id).copied().unwrap_or(Decimal::ZERO);
+        let margin = match &self.wallet {
+            Some(wallet) => {
+                let delta = requirement - held;
+                let asset = contract.settlement_asset.clone();
+                if delta > Decimal::ZERO {
+                    // A shortfall leaves the position under-margined for liquidation to pick up
+                    let mut taken = Decimal::ZERO;
+                    wallet.update_user_balance(&position.user_id, &asset, |balance| {
+                        taken = delta.min(balance.available).max(Decimal::ZERO);
+                        balance.reserve(taken)
+                    }).await.map_err(|e| anyhow!(e))?;
+                    if taken < delta {
+                        warn!("User {} is short {} of option margin for position {}", position.user_id, delta - taken, position.id);
+                    }
+                    held + taken
+                } else if delta < Decimal::ZERO {
+                    wallet.update_user_balance(&position.user_id, &asset, |balance| {
+                        balance.release_reservation(-delta)
+                    }).await.map_err(|e| anyhow!(e))?;
+                    requirement
+                } else {
+                    held
+                }
+            },
+            None => requirement,
+        };
+        if margin > Decimal::ZERO {
+            reserved.insert(*position_id, margin);
+        } else {
+            reserved.remove(position_id);
+        }
+        drop(reserved);
+
+        if !open_short {
+            return Ok(());
+        }
+        position.margin_amount = margin;
+        position.update_liquidation_price();
+        self.position_manager.update_position(position).await
+    }
+
+    /// Re-margin every open short option position, after the underlying or volatility moves
+    pub async fn refresh_option_margins(&self) -> Result<()> {
+        for contract in self.contract_manager.list_active_contracts().await {
+            if contract.contract_type != ContractType::Option {
+                continue;
+            }
+            for position in self.position_manager.get_contract_open_positions(&contract.id).await {
+                if position.direction != PositionDirection::Short {
+                    continue;
+                }
+                if let Err(e) = self.refresh_option_margin(&contract, &position.id).await {
+                    warn!("Failed to refresh option margin of position {}: {}", position.id, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cash-settle expired European options at their intrinsic value and delist them
+    pub async fn exercise_expired_options(&self, now: DateTime<Utc>) -> Result<Vec<OptionExercise>> {
+        let mut exercises = Vec::new();
+
+        for contract in self.contract_manager.list_active_contracts().await {
+            if contract.contract_type != ContractType::Option {
+                continue;
+            }
+            let (option_type, _, expiry) = match option_terms(&contract) {
+                Ok(terms) => terms,
+                Err(e) => {
+                    warn!("Skipping option {}: {}", contract.id, e);
+                    continue;
+                },
+            };
+            if now < expiry {
+                continue;
+            }
+
+            // Without a settlement price the option stays open to be retried, not halted
+            let settlement_price = match self.price_service.index_price(&contract.id).await {
+                Ok(price) => price,
+                Err(e) => {
+                    warn!("No settlement price for expired option {}: {}", contract.id, e);
+                    continue;
+                },
+            };
+            self.halt_trading(&contract.id).await?;
+            let strike = contract.strike_price.unwrap_or(Decimal::ZERO);
+            let intrinsic_value = match option_type {
+                OptionType::Call => (settlement_price - strike).max(Decimal::ZERO),
+                OptionType::Put => (strike - settlement_price).max(Decimal::ZERO),
+            };
+
+            // Settling at the intrinsic value realizes premium paid or received against it
+            for position in self.position_manager.get_contract_open_positions(&contract.id).await {
+                let settled = self.position_manager.settle_position(&position.id, intrinsic_value).await?;
+                self.refresh_option_margin(&contract, &position.id).await?;
+                exercises.push(OptionExercise {
+                    position_id: position.id,
+                    user_id: position.user_id,
+                    contract_id: contract.id.clone(),
+                    direction: position.direction,
+                    quantity: position.quantity,
+                    settlement_price,
+                    intrinsic_value,
+                    pnl: settled.realized_pnl - position.realized_pnl,
+                });
+            }
+
+            let mut expired = contract.clone();
+            expired.is_active = false;
+            expired.updated_at = now;
+            self.contract_manager.update_contract(expired).await?;
+            info!("Exercised {} at {} (intrinsic {})", contract.id, settlement_price, intrinsic_value);
+        }
+
+        Ok(exercises)
+    }
+
+}
+
+/// Options trade without leverage in isolated margin: longs post the premium,
+/// shorts the scenario margin
+pub(super) fn validate_option_order(contract: &Contract, margin_type: MarginType) -> Result<()> {
+    option_terms(contract)?;
+    if margin_type != MarginType::Isolated {
+        return Err(anyhow!("Options are only traded in isolated margin"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::trading_engine::matching_engine::{MatchingEngineManager, Side};
+    use super::super::{ContractManager, FundingRateCalculator, PositionManager, CloseReason, PayoffType};
+    use super::super::orders::DerivativeOrderRequest;
+    use super::super::price_index::{FileReplayFeed, PriceIndexService, PriceRecord};
+    use crate::wallet::WalletSystem;
+
+    fn inputs(option_type: OptionType) -> OptionInputs {
+        OptionInputs {
+            option_type,
+            underlying: 100.0,
+            strike: 100.0,
+            time_to_expiry: 1.0,
+            volatility: 0.2,
+            rate: 0.05,
+        }
+    }
+
+    #[test]
+    fn test_black_scholes_reference_values() {
+        // Reference values for S = K = 100, T = 1, r = 5%, vol = 20%
+        let call = option_price(PricingModel::BlackScholes, &inputs(OptionType::Call));
+        let put = option_price(PricingModel::BlackScholes, &inputs(OptionType::Put));
+        assert!((call - 10.4506).abs() < 1e-3);
+        assert!((put - 5.5735).abs() < 1e-3);
+
+        // Put-call parity
+        assert!((call - put - (100.0 - 100.0 * (-0.05f64).exp())).abs() < 1e-6);
+
+        let greeks = option_greeks(PricingModel::BlackScholes, &inputs(OptionType::Call));
+        assert!((greeks.delta - 0.6368).abs() < 1e-3);
+        assert!((greeks.gamma - 0.01876).abs() < 1e-4);
+        assert!((greeks.vega - 0.3752).abs() < 1e-3);
+        assert!((greeks.theta - (-6.414 / 365.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_black_76_matches_black_scholes_on_the_forward() {
+        let spot = inputs(OptionType::Call);
+        let forward = OptionInputs { underlying: spot.underlying * (spot.rate * spot.time_to_expiry).exp(), ..spot };
+        let bs = option_price(PricingModel::BlackScholes, &spot);
+        let b76 = option_price(PricingModel::Black76, &forward);
+        assert!((bs - b76).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_implied_volatility_round_trip() {
+        for model in [PricingModel::BlackScholes, PricingModel::Black76] {
+            for (option_type, strike, vol) in [(OptionType::Call, 90.0, 0.35), (OptionType::Put, 120.0, 0.8)] {
+                let inputs = OptionInputs { strike, volatility: vol, ..inputs(option_type) };
+                let price = option_price(model, &inputs);
+                let solved = implied_volatility(model, &inputs, price).unwrap();
+                assert!((solved - vol).abs() < 1e-6);
+            }
+        }
+
+        // Below intrinsic value there is no solution
+        let deep = OptionInputs { strike: 50.0, ..inputs(OptionType::Call) };
+        assert!(implied_volatility(PricingModel::BlackScholes, &deep, 10.0).is_err());
+    }
+
+    #[test]
+    fn test_scenario_margin_offsets_spreads() {
+        let config = ScenarioMarginConfig::default();
+        let leg = |strike: f64, quantity: f64| OptionLeg {
+            option_type: OptionType::Call,
+            strike,
+            time_to_expiry: 0.25,
+            volatility: 0.6,
+            quantity,
+        };
+
+        let naked = scenario_margin(PricingModel::BlackScholes, 0.0, 100.0, &[leg(100.0, -1.0)], &config);
+        let spread = scenario_margin(PricingModel::BlackScholes, 0.0, 100.0, &[leg(100.0, -1.0), leg(110.0, 1.0)], &config);
+        assert!(naked > spread);
+        // A call spread can lose at most the strike width
+        assert!(spread <= 10.0);
+        // A long option only risks its premium and needs no margin beyond it
+        let long = scenario_margin(PricingModel::BlackScholes, 0.0, 100.0, &[leg(100.0, 1.0)], &config);
+        assert!(long < option_price(PricingModel::BlackScholes, &OptionInputs {
+            option_type: OptionType::Call, underlying: 100.0, strike: 100.0,
+            time_to_expiry: 0.25, volatility: 0.6, rate: 0.0,
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_option_positions_margined_and_cash_settled() {
+        let listed_at = Utc::now();
+        let expiry = listed_at + chrono::Duration::days(30);
+
+        let price_service = Arc::new(PriceIndexService::default());
+        price_service.add_index_feed(Arc::new(FileReplayFeed::from_records("replay", vec![
+            PriceRecord { timestamp: listed_at, symbol: "BTC/USDT".to_string(), price: dec!(50000) },
+            PriceRecord { timestamp: listed_at + chrono::Duration::days(1), symbol: "BTC/USDT".to_string(), price: dec!(52000) },
+            PriceRecord { timestamp: expiry, symbol: "BTC/USDT".to_string(), price: dec!(53000) },
+        ])), dec!(1)).await;
+
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        let wallet = Arc::new(WalletSystem::new());
+        let engine = DerivativesEngine::new(
+            contract_manager.clone(),
+            position_manager.clone(),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            price_service.clone(),
+            Arc::new(MatchingEngineManager::new()),
+        ).with_wallet(wallet.clone());
+
+        let contract = Contract {
+            id: "BTC-50000-C".to_string(),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Option,
+            tick_size: dec!(5),
+            lot_size: dec!(0.01),
+            leverage_max: dec!(1),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: Some(expiry),
+            settlement_asset: "USDT".to_string(),
+            option_type: Some(OptionType::Call),
+            strike_price: Some(dec!(50000)),
+            funding_rate_cap: None,
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: listed_at,
+            updated_at: listed_at,
+        };
+        contract_manager.add_contract(contract.clone()).await.unwrap();
+        engine.list_contracts().await.unwrap();
+        price_service.update_at(&contract, listed_at).await.unwrap();
+
+        // The writer sells one call at 3,000 to the buyer
+        let writer_id = Uuid::new_v4();
+        let buyer_id = Uuid::new_v4();
+        let usdt = "USDT".to_string();
+        wallet.create_user_wallet(writer_id).await.unwrap();
+        wallet.update_user_balance(&writer_id, &usdt, |balance| {
+            balance.credit(dec!(20000));
+            Ok(())
+        }).await.unwrap();
+        engine.place_order(DerivativeOrderRequest::limit(
+            writer_id, &contract.id, Side::Sell, dec!(3000), dec!(1), dec!(1), MarginType::Isolated,
+        )).await.unwrap();
+        let long = engine.open_position(
+            buyer_id, &contract.id, PositionDirection::Long, dec!(1), dec!(1), MarginType::Isolated,
+        ).await.unwrap();
+        let short = position_manager.get_open_position(&writer_id, &contract.id).await.unwrap();
+
+        // The long posts the premium, the short its scenario margin
+        assert_eq!(long.margin_amount, dec!(3000));
+        assert_eq!(short.margin_amount, engine.option_margin_requirement(&writer_id).await.unwrap());
+        assert!(short.margin_amount > dec!(2500));
+
+        // The short's margin is reserved from the writer's wallet
+        let balance = wallet.get_user_balance(&writer_id, &usdt).await.unwrap();
+        assert_eq!(balance.reserved, short.margin_amount);
+        assert_eq!(balance.available, dec!(20000) - short.margin_amount);
+
+        // A rally raises the requirement and the reservation with it, without a fill
+        price_service.update_at(&contract, listed_at + chrono::Duration::days(1)).await.unwrap();
+        engine.update_positions_pnl(&contract.id).await.unwrap();
+        let rallied = position_manager.get_position(&short.id).await.unwrap();
+        assert!(rallied.margin_amount > short.margin_amount);
+        assert_eq!(wallet.get_user_balance(&writer_id, &usdt).await.unwrap().reserved, rallied.margin_amount);
+
+        // An at-the-money call has a delta near one half; the writer's is the opposite
+        let long_greeks = engine.position_greeks(&long.id).await.unwrap();
+        let short_greeks = engine.position_greeks(&short.id).await.unwrap();
+        assert!(long_greeks.delta > 0.5 && long_greeks.delta < 0.6);
+        assert!((long_greeks.delta + short_greeks.delta).abs() < 1e-12);
+        assert!(long_greeks.theta < 0.0);
+
+        // Cross margin is not available for options
+        assert!(engine.place_order(DerivativeOrderRequest::limit(
+            writer_id, &contract.id, Side::Sell, dec!(3000), dec!(1), dec!(1), MarginType::Cross,
+        )).await.is_err());
+
+        // Expires 3,000 in the money: the premium paid and received nets to zero
+        price_service.update_at(&contract, expiry).await.unwrap();
+        let exercises = engine.exercise_expired_options(expiry).await.unwrap();
+        assert_eq!(exercises.len(), 2);
+        assert!(exercises.iter().all(|e| e.intrinsic_value == dec!(3000) && e.pnl == Decimal::ZERO));
+
+        let settled = position_manager.get_position(&long.id).await.unwrap();
+        assert_eq!(settled.close_reason, Some(CloseReason::Settlement));
+        assert_eq!(settled.margin_amount, dec!(3000));
+        assert!(!contract_manager.get_contract(&contract.id).await.unwrap().is_active);
+
+        // Settlement releases the writer's margin
+        let balance = wallet.get_user_balance(&writer_id, &usdt).await.unwrap();
+        assert_eq!(balance.reserved, Decimal::ZERO);
+        assert_eq!(balance.available, dec!(20000));
+    }
+}
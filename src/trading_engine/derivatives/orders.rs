@@ -0,0 +1,497 @@
+// src/trading_engine/derivatives/orders.rs
+
+use rust_decimal::Decimal;
+use anyhow::{Result, anyhow};
+use log::{error, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::trading_engine::matching_engine::{
+    Order, OrderId, OrderType, Side, TimeInForce, Trade,
+};
+use super::{
+    Contract, ContractType, DerivativesEngine, MarginType, PayoffType, Position, PositionDirection,
+    PositionFill, UserId,
+};
+use super::options::validate_option_order;
+
+/// An order on a derivatives contract
+#[derive(Debug, Clone)]
+pub struct DerivativeOrderRequest {
+    pub user_id: UserId,
+    pub contract_id: String,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub price: Option<Decimal>,
+    /// Ignored when `close_position` is set
+    pub quantity: Decimal,
+    pub time_in_force: TimeInForce,
+    /// Leverage and margin type used if the order opens a position
+    pub leverage: Decimal,
+    pub margin_type: MarginType,
+    /// The order may only reduce the current position
+    pub reduce_only: bool,
+    /// Close the whole current position; implies reduce-only
+    pub close_position: bool,
+}
+
+impl DerivativeOrderRequest {
+    /// A market order for the given size
+    pub fn market(user_id: UserId, contract_id: &str, side: Side, quantity: Decimal, leverage: Decimal, margin_type: MarginType) -> Self {
+        DerivativeOrderRequest {
+            user_id,
+            contract_id: contract_id.to_string(),
+            side,
+            order_type: OrderType::Market,
+            price: None,
+            quantity,
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            leverage,
+            margin_type,
+            reduce_only: false,
+            close_position: false,
+        }
+    }
+
+    /// A limit order for the given size and price
+    pub fn limit(user_id: UserId, contract_id: &str, side: Side, price: Decimal, quantity: Decimal, leverage: Decimal, margin_type: MarginType) -> Self {
+        DerivativeOrderRequest {
+            order_type: OrderType::Limit,
+            price: Some(price),
+            time_in_force: TimeInForce::GoodTillCancel,
+            ..Self::market(user_id, contract_id, side, quantity, leverage, margin_type)
+        }
+    }
+}
+
+/// Order parameters kept so that maker fills can be attributed to positions
+#[derive(Debug, Clone)]
+pub(super) struct OrderMeta {
+    pub user_id: UserId,
+    pub contract_id: String,
+    pub side: Side,
+    pub leverage: Decimal,
+    pub margin_type: MarginType,
+    pub reduce_only: bool,
+    /// Quantity not yet filled; the entry is dropped when it reaches zero
+    pub remaining: Decimal,
+}
+
+/// Where an order comes from, which decides the checks it is subject to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum OrderOrigin {
+    User,
+    /// Checked against the user's whole portfolio by the portfolio margin manager
+    Portfolio,
+    /// Placed by the engine to close out an account; allowed through a settlement halt
+    Liquidation,
+}
+
+/// A position change caused by a fill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionUpdate {
+    pub user_id: UserId,
+    pub position: Option<Position>,
+    pub closed_position: Option<Position>,
+    pub realized_pnl: Decimal,
+}
+
+/// Result of placing a derivatives order
+#[derive(Debug, Clone)]
+pub struct DerivativeOrderResult {
+    pub order_id: OrderId,
+    pub trades: Vec<Trade>,
+    /// Position changes for the taker and every maker, in fill order
+    pub updates: Vec<PositionUpdate>,
+}
+
+impl DerivativeOrderResult {
+    /// Realized PnL of the given user across all fills of the order
+    pub fn realized_pnl_for(&self, user_id: &UserId) -> Decimal {
+        self.updates.iter()
+            .filter(|u| u.user_id == *user_id)
+            .map(|u| u.realized_pnl)
+            .sum()
+    }
+
+    /// The given user's position after the order
+    pub fn position_for(&self, user_id: &UserId) -> Option<&Position> {
+        self.updates.iter()
+            .rev()
+            .find(|u| u.user_id == *user_id)
+            .and_then(|u| u.position.as_ref())
+    }
+}
+
+fn direction_of(side: Side) -> PositionDirection {
+    match side {
+        Side::Buy => PositionDirection::Long,
+        Side::Sell => PositionDirection::Short,
+    }
+}
+
+impl DerivativesEngine {
+    /// List every active contract as a symbol in the matching engine
+    pub async fn list_contracts(&self) -> Result<()> {
+        for contract in self.contract_manager.list_active_contracts().await {
+            self.list_contract(&contract).await?;
+        }
+        Ok(())
+    }
+
+    /// List a contract's order book in the matching engine
+    pub async fn list_contract(&self, contract: &Contract) -> Result<()> {
+        if self.matching_engines.get_engine(&contract.id).await.is_ok() {
+            return Ok(());
+        }
+        self.matching_engines.add_symbol(contract.id.clone()).await.map_err(|e| anyhow!(e))
+    }
+
+    /// Place an order on a contract's book and net its fills into positions
+    pub async fn place_order(&self, request: DerivativeOrderRequest) -> Result<DerivativeOrderResult> {
+        self.submit_order(request, OrderOrigin::User).await
+    }
+
+    pub(super) async fn submit_order(&self, mut request: DerivativeOrderRequest, origin: OrderOrigin) -> Result<DerivativeOrderResult> {
+        let contract = self.contract_manager.get_contract(&request.contract_id).await
+            .ok_or_else(|| anyhow!("Contract not found"))?;
+        if !contract.is_active {
+            return Err(anyhow!("Contract is not active"));
+        }
+        if origin != OrderOrigin::Liquidation && self.halted_contracts.read().await.contains(&contract.id) {
+            return Err(anyhow!("Trading is halted on {}", contract.id));
+        }
+        if contract.contract_type == ContractType::Option {
+            validate_option_order(&contract, request.margin_type)?;
+            request.leverage = Decimal::ONE;
+        }
+        if request.leverage <= Decimal::ZERO || request.leverage > contract.leverage_max {
+            return Err(anyhow!("Leverage exceeds maximum allowed"));
+        }
+
+        let current = self.position_manager.get_open_position(&request.user_id, &contract.id).await;
+        let reduce_only = request.reduce_only || request.close_position;
+
+        // Only the portfolio margin manager can check orders that add to an enrolled user's risk
+        if origin == OrderOrigin::User && !reduce_only && self.is_portfolio_margined(&request.user_id).await {
+            return Err(anyhow!("Orders of portfolio-margined users must be placed through portfolio margin"));
+        }
+
+        // Reduce-only orders must be on the opposite side and, together with the user's
+        // resting reduce-only orders, no larger than the position
+        let quantity = if reduce_only {
+            let position = current.as_ref()
+                .ok_or_else(|| anyhow!("Reduce-only order with no open position"))?;
+            let closing_side = match position.direction {
+                PositionDirection::Long => Side::Sell,
+                PositionDirection::Short => Side::Buy,
+            };
+            if request.side != closing_side {
+                return Err(anyhow!("Reduce-only order would increase the position"));
+            }
+            let resting = self.resting_reduce_only(&request.user_id, &contract.id).await;
+            let reducible = position.quantity - resting;
+            if reducible <= Decimal::ZERO {
+                return Err(anyhow!("Resting reduce-only orders already cover the position"));
+            }
+            if request.close_position {
+                reducible
+            } else {
+                request.quantity.min(reducible)
+            }
+        } else {
+            request.quantity
+        };
+
+        if quantity <= Decimal::ZERO {
+            return Err(anyhow!("Order quantity must be positive"));
+        }
+        if contract.lot_size > Decimal::ZERO && quantity % contract.lot_size != Decimal::ZERO {
+            return Err(anyhow!("Quantity {} is not a multiple of lot size {}", quantity, contract.lot_size));
+        }
+
+        // Without a tier table the leverage check above is the contract's whole risk limit
+        let tiered = !contract.risk_limit_tiers.is_empty();
+        if !reduce_only && (tiered || request.margin_type == MarginType::Cross) {
+            let price = match request.price {
+                Some(price) => price,
+                None => self.contract_mark_price(&contract).await?,
+            };
+
+            // Size the position would reach if this order and the user's other resting orders on
+            // the same side fill completely, and whether it adds to the current one
+            let resting = self.resting_exposure(&request.user_id, &contract.id, request.side).await;
+            let (projected, adding_to) = match current.as_ref() {
+                Some(position) if position.direction == direction_of(request.side) => (position.quantity + resting + quantity, Some(position)),
+                Some(position) => ((resting + quantity - position.quantity).max(Decimal::ZERO), None),
+                None => (resting + quantity, None),
+            };
+            if tiered && projected > Decimal::ZERO {
+                // Tiers apply to notional at mark, as liquidation does
+                let mark_price = self.contract_mark_price(&contract).await?;
+                Self::check_risk_limit(&contract, adding_to, contract.payoff.notional(projected, mark_price), request.leverage)?;
+            }
+
+            // Cross collateral is held in one asset, so inverse contracts need an account settled in their coin
+            if request.margin_type == MarginType::Cross && contract.payoff == PayoffType::Inverse {
+                let account = self.cross_margin.get_account(&request.user_id).await
+                    .ok_or_else(|| anyhow!("Cross-margin account not found"))?;
+                if account.settlement_asset != contract.settlement_asset {
+                    return Err(anyhow!(
+                        "{} settles in {}, but the cross-margin account holds {}",
+                        contract.id, contract.settlement_asset, account.settlement_asset
+                    ));
+                }
+            }
+
+            // Cross orders that can add exposure need initial margin from the shared account;
+            // portfolio-margined orders are checked by the portfolio margin manager
+            if request.margin_type == MarginType::Cross && !self.is_portfolio_margined(&request.user_id).await {
+                let required = contract.payoff.notional(quantity, price) / request.leverage;
+                let status = self.cross_margin_status(&request.user_id).await?;
+                if status.available_margin < required {
+                    return Err(anyhow!(
+                        "Insufficient cross margin: {} available, {} required",
+                        status.available_margin, required
+                    ));
+                }
+            }
+        }
+
+        let order = Order::new(
+            request.user_id,
+            contract.id.clone(),
+            request.side,
+            request.order_type,
+            request.price,
+            quantity,
+            request.time_in_force,
+            None,
+        );
+        let order_id = order.id;
+
+        // Record before matching so a resting remainder can be attributed when it fills later
+        self.order_meta.write().await.insert(order_id, OrderMeta {
+            user_id: request.user_id,
+            contract_id: contract.id.clone(),
+            side: request.side,
+            leverage: request.leverage,
+            margin_type: request.margin_type,
+            reduce_only,
+            remaining: quantity,
+        });
+
+        let trades = match self.matching_engines.process_order(order).await {
+            Ok(trades) => trades,
+            Err(e) => {
+                self.order_meta.write().await.remove(&order_id);
+                return Err(anyhow!(e));
+            }
+        };
+
+        let mut updates = Vec::new();
+        for trade in &trades {
+            updates.extend(self.apply_trade(&contract, trade).await?);
+        }
+
+        // Only a limit order that can rest keeps an unfilled remainder on the book
+        let rests = request.order_type == OrderType::Limit
+            && !matches!(request.time_in_force, TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill);
+        if !rests {
+            self.forget_order(&order_id).await;
+        }
+
+        // Positions that shrank may no longer cover their resting reduce-only orders
+        let mut users: Vec<UserId> = updates.iter().map(|u| u.user_id).collect();
+        users.sort();
+        users.dedup();
+        for user_id in users {
+            self.trim_reduce_only(&user_id, &contract.id).await?;
+        }
+
+        Ok(DerivativeOrderResult {
+            order_id,
+            trades,
+            updates,
+        })
+    }
+
+    /// Net both sides of a trade into positions
+    pub async fn apply_trade(&self, contract: &Contract, trade: &Trade) -> Result<Vec<PositionUpdate>> {
+        let maker_side = match trade.aggressor_side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+
+        let mut updates = Vec::new();
+        for (order_id, side) in [(trade.taker_order_id, trade.aggressor_side), (trade.maker_order_id, maker_side)] {
+            let meta = {
+                let mut order_meta = self.order_meta.write().await;
+                let meta = match order_meta.get_mut(&order_id) {
+                    Some(meta) => {
+                        meta.remaining -= trade.quantity;
+                        meta.clone()
+                    },
+                    None => {
+                        warn!("Fill for unknown derivatives order {} on {}", order_id, contract.id);
+                        continue;
+                    }
+                };
+                // A fully filled order is off the book
+                if meta.remaining <= Decimal::ZERO {
+                    order_meta.remove(&order_id);
+                }
+                meta
+            };
+
+            let fill = PositionFill {
+                user_id: meta.user_id,
+                contract_id: meta.contract_id.clone(),
+                side,
+                quantity: trade.quantity,
+                price: trade.price,
+                leverage: meta.leverage,
+                margin_type: meta.margin_type,
+                maintenance_margin_ratio: contract.maintenance_margin_ratio,
+                liquidation_fee_ratio: contract.liquidation_fee_ratio,
+                payoff: contract.payoff,
+                reduce_only: meta.reduce_only,
+            };
+
+            let outcome = match self.position_manager.apply_fill(fill.clone()).await {
+                Ok(outcome) => outcome,
+                Err(e) if meta.reduce_only => {
+                    // The counterparty was filled in full, so book the whole fill as a regular
+                    // one to keep both sides of the trade in step
+                    error!(
+                        "Reduce-only order {} of user {} filled {} past its position on {}: {}; booking it as a regular fill",
+                        order_id, meta.user_id, trade.quantity, contract.id, e
+                    );
+                    self.position_manager.apply_fill(PositionFill { reduce_only: false, ..fill }).await?
+                }
+                Err(e) => return Err(e),
+            };
+
+            // Cross PnL settles into the shared collateral
+            if meta.margin_type == MarginType::Cross && outcome.realized_pnl != Decimal::ZERO {
+                self.cross_margin.adjust_collateral(&meta.user_id, outcome.realized_pnl).await?;
+            }
+
+            // A closed short option gives back the margin reserved for it
+            if let (ContractType::Option, Some(closed)) = (contract.contract_type, outcome.closed_position.as_ref()) {
+                self.refresh_option_margin(contract, &closed.id).await?;
+            }
+
+            // Short options are margined on scenarios rather than leverage; others by risk tier
+            let mut position = outcome.position;
+            match (contract.contract_type, position.as_ref()) {
+                (ContractType::Option, Some(open)) => {
+                    self.refresh_option_margin(contract, &open.id).await?;
+                    position = self.position_manager.get_position(&open.id).await;
+                },
+                (_, Some(open)) => {
+                    // A fill can come in before the contract's first mark
+                    let mark_price = self.contract_mark_price(contract).await.unwrap_or(trade.price);
+                    position = Some(self.refresh_risk_tier(contract, open, mark_price).await?);
+                },
+                _ => {},
+            }
+
+            updates.push(PositionUpdate {
+                user_id: meta.user_id,
+                position,
+                closed_position: outcome.closed_position,
+                realized_pnl: outcome.realized_pnl,
+            });
+        }
+
+        Ok(updates)
+    }
+
+    /// Forget a cancelled or fully filled order
+    pub async fn forget_order(&self, order_id: &OrderId) {
+        self.order_meta.write().await.remove(order_id);
+    }
+
+    /// Unfilled size of a user's resting reduce-only orders on a contract
+    async fn resting_reduce_only(&self, user_id: &UserId, contract_id: &str) -> Decimal {
+        self.order_meta.read().await.values()
+            .filter(|meta| meta.reduce_only && meta.user_id == *user_id && meta.contract_id == contract_id)
+            .map(|meta| meta.remaining)
+            .sum()
+    }
+
+    /// Unfilled size of a user's resting orders on one side of a contract that can add exposure
+    async fn resting_exposure(&self, user_id: &UserId, contract_id: &str, side: Side) -> Decimal {
+        self.order_meta.read().await.values()
+            .filter(|meta| !meta.reduce_only && meta.side == side && meta.user_id == *user_id && meta.contract_id == contract_id)
+            .map(|meta| meta.remaining)
+            .sum()
+    }
+
+    /// Cancel a user's resting reduce-only orders that the current position no longer covers
+    async fn trim_reduce_only(&self, user_id: &UserId, contract_id: &str) -> Result<()> {
+        let position = self.position_manager.get_open_position(user_id, contract_id).await;
+        let orders: Vec<(OrderId, OrderMeta)> = self.order_meta.read().await.iter()
+            .filter(|(_, meta)| meta.reduce_only && meta.user_id == *user_id && meta.contract_id == contract_id)
+            .map(|(order_id, meta)| (*order_id, meta.clone()))
+            .collect();
+
+        let mut covered = Decimal::ZERO;
+        for (order_id, meta) in orders {
+            let fits = match position.as_ref() {
+                Some(position) => direction_of(meta.side) != position.direction
+                    && covered + meta.remaining <= position.quantity,
+                None => false,
+            };
+            if fits {
+                covered += meta.remaining;
+                continue;
+            }
+
+            self.matching_engines.cancel_order(&contract_id.to_string(), order_id).await.map_err(|e| anyhow!(e))?;
+            self.forget_order(&order_id).await;
+        }
+        Ok(())
+    }
+
+    /// Stop accepting orders on a contract and cancel everything resting on its book
+    pub async fn halt_trading(&self, contract_id: &str) -> Result<usize> {
+        self.halted_contracts.write().await.insert(contract_id.to_string());
+
+        let orders: Vec<OrderId> = self.order_meta.read().await.iter()
+            .filter(|(_, meta)| meta.contract_id == contract_id)
+            .map(|(order_id, _)| *order_id)
+            .collect();
+
+        let mut cancelled = 0;
+        for order_id in orders {
+            if self.matching_engines.cancel_order(&contract_id.to_string(), order_id).await.map_err(|e| anyhow!(e))?.is_some() {
+                cancelled += 1;
+            }
+            self.forget_order(&order_id).await;
+        }
+        Ok(cancelled)
+    }
+
+    pub async fn is_trading_halted(&self, contract_id: &str) -> bool {
+        self.halted_contracts.read().await.contains(contract_id)
+    }
+
+    /// Cancel a user's resting orders, on one contract or all of them, returning how many were on the book
+    pub async fn cancel_user_orders(&self, user_id: &UserId, contract_id: Option<&str>) -> Result<usize> {
+        let orders: Vec<(OrderId, String)> = self.order_meta.read().await.iter()
+            .filter(|(_, meta)| meta.user_id == *user_id)
+            .filter(|(_, meta)| contract_id.map_or(true, |id| meta.contract_id == id))
+            .map(|(order_id, meta)| (*order_id, meta.contract_id.clone()))
+            .collect();
+
+        let mut cancelled = 0;
+        for (order_id, contract_id) in orders {
+            if self.matching_engines.cancel_order(&contract_id, order_id).await.map_err(|e| anyhow!(e))?.is_some() {
+                cancelled += 1;
+            }
+            self.forget_order(&order_id).await;
+        }
+        Ok(cancelled)
+    }
+}
@@ -0,0 +1,720 @@
+// src/trading_engine/derivatives/portfolio_margin.rs
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use chrono::Utc;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::trading_engine::matching_engine::Side;
+use crate::wallet::{AssetId, WalletError, WalletSystem, WithdrawalGuard};
+use super::options::{option_price, OptionInputs, PricingModel, ScenarioMarginConfig};
+use super::orders::{DerivativeOrderRequest, DerivativeOrderResult, OrderOrigin};
+use super::{
+    Contract, ContractType, DerivativesEngine, MarginAccountId, MarginAccountManager, MarginAccountStatus,
+    MarginType, PayoffType, Position, PositionDirection, PositionId, UserId,
+};
+
+/// Spot holdings counted towards a portfolio-margined account
+#[async_trait]
+pub trait SpotBalanceSource: Send + Sync {
+    /// Total balance of every asset the user holds
+    async fn spot_balances(&self, user_id: &UserId) -> Result<Vec<(String, Decimal)>>;
+}
+
+#[async_trait]
+impl SpotBalanceSource for WalletSystem {
+    async fn spot_balances(&self, user_id: &UserId) -> Result<Vec<(String, Decimal)>> {
+        match self.get_asset_balances(user_id).await {
+            Ok(balances) => Ok(balances.into_iter().map(|(asset, balance)| (asset.id, balance.total)).collect()),
+            // A user without a wallet simply holds nothing
+            Err(WalletError::UserNotFound(_)) => Ok(Vec::new()),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PortfolioMarginConfig {
+    /// Asset that equity and requirements are measured in; other assets are valued at their index
+    pub quote_asset: String,
+    /// Price and volatility shocks applied to each underlying
+    pub scenarios: ScenarioMarginConfig,
+    /// Floor on the requirement as a fraction of gross derivatives notional, for basis and gap risk
+    pub minimum_margin_ratio: Decimal,
+    /// New exposure must leave equity above the requirement times this
+    pub initial_margin_multiplier: Decimal,
+}
+
+impl Default for PortfolioMarginConfig {
+    fn default() -> Self {
+        PortfolioMarginConfig {
+            quote_asset: "USDT".to_string(),
+            scenarios: ScenarioMarginConfig::default(),
+            minimum_margin_ratio: Decimal::new(1, 2),
+            initial_margin_multiplier: Decimal::new(125, 2),
+        }
+    }
+}
+
+/// Risk of everything a user holds on one underlying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnderlyingRisk {
+    pub asset: String,
+    pub index_price: Decimal,
+    /// Spot balance plus net margin account holdings
+    pub spot_quantity: Decimal,
+    /// Net quantity of linear contracts; negative when short
+    pub derivatives_quantity: Decimal,
+    pub option_contracts: usize,
+    pub gross_derivatives_notional: Decimal,
+    /// Largest loss over the scenario grid
+    pub worst_loss: Decimal,
+    pub worst_price_move: f64,
+    pub worst_vol_move: f64,
+}
+
+/// Margin state of a portfolio-margined user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioMarginStatus {
+    pub user_id: UserId,
+    /// Spot, margin account and derivatives value in the quote asset
+    pub equity: Decimal,
+    /// Sum of worst scenario losses across underlyings
+    pub scenario_requirement: Decimal,
+    pub minimum_requirement: Decimal,
+    /// Maintenance requirement: the larger of the two above
+    pub requirement: Decimal,
+    pub excess_margin: Decimal,
+    /// Requirement over equity; the account is liquidated at 1
+    pub margin_ratio: Decimal,
+    pub liquidatable: bool,
+    pub underlyings: Vec<UnderlyingRisk>,
+}
+
+/// Result of liquidating a portfolio-margined account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioLiquidationReport {
+    pub user_id: UserId,
+    pub cancelled_orders: usize,
+    pub closed_positions: Vec<PositionId>,
+    /// Margin accounts handed to margin liquidation once derivatives could not restore the account
+    pub flagged_margin_accounts: Vec<MarginAccountId>,
+    pub status: PortfolioMarginStatus,
+}
+
+/// Exposures on one underlying, gathered from every product
+#[derive(Default)]
+struct Exposure {
+    index_price: Option<f64>,
+    spot: f64,
+    /// (signed quantity, mark price) of linear contracts
+    linear: Vec<(f64, f64)>,
+    /// Signed quantity and pricer inputs of options
+    options: Vec<(f64, OptionInputs)>,
+}
+
+impl Exposure {
+    /// Value change of the underlying's holdings under a price and volatility shock
+    fn pnl(&self, model: PricingModel, price_move: f64, vol_move: f64) -> f64 {
+        let spot_price = self.index_price.unwrap_or(0.0);
+        let mut pnl = self.spot * spot_price * price_move;
+        pnl += self.linear.iter().map(|(quantity, mark)| quantity * mark * price_move).sum::<f64>();
+        for (quantity, inputs) in &self.options {
+            let shocked = OptionInputs {
+                underlying: inputs.underlying * (1.0 + price_move),
+                volatility: (inputs.volatility * (1.0 + vol_move)).max(1e-4),
+                ..*inputs
+            };
+            pnl += quantity * (option_price(model, &shocked) - option_price(model, inputs));
+        }
+        pnl
+    }
+}
+
+fn to_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+fn to_decimal(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or(Decimal::ZERO)
+}
+
+fn signed(position: &Position) -> Decimal {
+    match position.direction {
+        PositionDirection::Long => position.quantity,
+        PositionDirection::Short => -position.quantity,
+    }
+}
+
+/// Unified margin across spot balances, margin accounts and derivatives positions.
+///
+/// Enrolled users are margined on the worst loss of their combined holdings over price and
+/// volatility shocks, so a short perpetual against spot BTC needs little more than the floor.
+pub struct PortfolioMarginManager {
+    engine: Arc<DerivativesEngine>,
+    spot: Arc<dyn SpotBalanceSource>,
+    margin_accounts: Arc<MarginAccountManager>,
+    config: PortfolioMarginConfig,
+}
+
+impl PortfolioMarginManager {
+    pub fn new(
+        engine: Arc<DerivativesEngine>,
+        spot: Arc<dyn SpotBalanceSource>,
+        margin_accounts: Arc<MarginAccountManager>,
+        config: PortfolioMarginConfig,
+    ) -> Self {
+        PortfolioMarginManager {
+            engine,
+            spot,
+            margin_accounts,
+            config,
+        }
+    }
+
+    /// Switch a user to portfolio margin. Their linear positions must be cross-margined.
+    pub async fn enroll(&self, user_id: UserId) -> Result<()> {
+        for position in self.engine.position_manager.get_user_open_positions(&user_id).await {
+            let contract = self.engine.contract_manager.get_contract(&position.contract_id).await
+                .ok_or_else(|| anyhow!("Contract not found"))?;
+            if position.margin_type == MarginType::Isolated && contract.contract_type != ContractType::Option {
+                return Err(anyhow!("Close or convert isolated position {} before enrolling", position.id));
+            }
+            if contract.payoff == PayoffType::Inverse {
+                return Err(anyhow!("Close inverse position {} before enrolling", position.id));
+            }
+        }
+
+        if self.engine.cross_margin.get_account(&user_id).await.is_none() {
+            self.engine.cross_margin.create_account(user_id, &self.config.quote_asset).await?;
+        }
+        self.engine.portfolio_margin_users.write().await.insert(user_id);
+        info!("Enrolled {} in portfolio margin", user_id);
+        Ok(())
+    }
+
+    /// Return a user to per-product margin; only allowed without open derivatives positions
+    pub async fn unenroll(&self, user_id: &UserId) -> Result<()> {
+        if !self.engine.position_manager.get_user_open_positions(user_id).await.is_empty() {
+            return Err(anyhow!("Close all derivatives positions before leaving portfolio margin"));
+        }
+        self.engine.portfolio_margin_users.write().await.remove(user_id);
+        Ok(())
+    }
+
+    pub async fn enrolled_users(&self) -> Vec<UserId> {
+        self.engine.portfolio_margin_users.read().await.iter().copied().collect()
+    }
+
+    pub async fn evaluate(&self, user_id: &UserId) -> Result<PortfolioMarginStatus> {
+        self.evaluate_with(user_id, &HashSet::new(), None, None).await
+    }
+
+    /// Evaluate the account, leaving out some positions and optionally adding a hypothetical fill
+    /// or change to a spot balance
+    async fn evaluate_with(
+        &self,
+        user_id: &UserId,
+        excluded: &HashSet<PositionId>,
+        extra: Option<(&Contract, Decimal, Decimal)>,
+        spot_change: Option<(&str, Decimal)>,
+    ) -> Result<PortfolioMarginStatus> {
+        let quote = &self.config.quote_asset;
+        let pricer = self.engine.option_pricer();
+        let now = Utc::now();
+        let mut equity = Decimal::ZERO;
+        let mut exposures: BTreeMap<String, Exposure> = BTreeMap::new();
+
+        // Derivatives: unrealized PnL, isolated margin and exposure by underlying
+        let mut legs: Vec<(Contract, Decimal, Decimal)> = Vec::new();
+        for position in self.engine.position_manager.get_user_open_positions(user_id).await {
+            if excluded.contains(&position.id) {
+                continue;
+            }
+            let contract = self.engine.contract_manager.get_contract(&position.contract_id).await
+                .ok_or_else(|| anyhow!("Contract not found"))?;
+            let mark = self.engine.contract_mark_price(&contract).await?;
+            equity += position.pnl_at(mark);
+            if position.margin_type == MarginType::Isolated {
+                equity += position.margin_amount;
+            }
+            legs.push((contract, signed(&position), mark));
+        }
+        if let Some((contract, quantity, price)) = extra {
+            legs.push((contract.clone(), quantity, price));
+        }
+
+        for (contract, quantity, mark) in &legs {
+            if contract.quote_asset != *quote {
+                return Err(anyhow!("Portfolio margin only covers {}-quoted contracts, not {}", quote, contract.id));
+            }
+            // Inverse P&L is in the base coin and size in quote units, neither of which adds up here
+            if contract.payoff == PayoffType::Inverse {
+                return Err(anyhow!("Portfolio margin does not cover inverse contract {}", contract.id));
+            }
+            let index = self.engine.price_service.index_price(&contract.id).await?;
+            let exposure = exposures.entry(contract.base_asset.clone()).or_default();
+            exposure.index_price = Some(to_f64(index));
+            match contract.contract_type {
+                ContractType::Option => {
+                    let inputs = pricer.inputs(contract, index, now).await?;
+                    exposure.options.push((to_f64(*quantity), inputs));
+                },
+                _ => exposure.linear.push((to_f64(*quantity), to_f64(*mark))),
+            }
+        }
+
+        if let Some(account) = self.engine.cross_margin.get_account(user_id).await {
+            equity += account.collateral;
+        }
+
+        // Spot balances and margin accounts, netting borrowings against holdings
+        let mut holdings: HashMap<String, Decimal> = HashMap::new();
+        for (asset, amount) in self.spot.spot_balances(user_id).await? {
+            *holdings.entry(asset).or_insert(Decimal::ZERO) += amount;
+        }
+        if let Some((asset, change)) = spot_change {
+            *holdings.entry(asset.to_string()).or_insert(Decimal::ZERO) += change;
+        }
+        for account in self.margin_accounts.get_user_accounts(user_id).await {
+            if account.status == MarginAccountStatus::Closed {
+                continue;
+            }
+            *holdings.entry(account.base_asset.clone()).or_insert(Decimal::ZERO) += account.base_balance - account.borrowed_base;
+            *holdings.entry(account.quote_asset.clone()).or_insert(Decimal::ZERO) += account.quote_balance - account.borrowed_quote;
+        }
+
+        for (asset, amount) in holdings {
+            if asset == *quote {
+                equity += amount;
+                continue;
+            }
+            if amount.is_zero() {
+                continue;
+            }
+            let index = match exposures.get(&asset).and_then(|e| e.index_price) {
+                Some(index) => to_decimal(index),
+                None => match self.spot_index(&asset).await {
+                    Some(index) => index,
+                    // Unpriced holdings count for nothing, but unpriced debt cannot be margined
+                    None if amount > Decimal::ZERO => continue,
+                    None => return Err(anyhow!("No index price for borrowed {}", asset)),
+                },
+            };
+            equity += amount * index;
+            let exposure = exposures.entry(asset).or_default();
+            exposure.index_price = Some(to_f64(index));
+            exposure.spot += to_f64(amount);
+        }
+
+        // Worst loss per underlying over the shock grid, summed without credit for correlation
+        let mut underlyings = Vec::new();
+        let mut scenario_requirement = Decimal::ZERO;
+        let mut gross_notional = Decimal::ZERO;
+        for (asset, exposure) in &exposures {
+            let mut worst = (0.0, 0.0, 0.0);
+            for &price_move in &self.config.scenarios.price_moves {
+                for &vol_move in &self.config.scenarios.vol_moves {
+                    let loss = -exposure.pnl(pricer.model, price_move, vol_move);
+                    if loss > worst.0 {
+                        worst = (loss, price_move, vol_move);
+                    }
+                }
+            }
+
+            let index_price = exposure.index_price.unwrap_or(0.0);
+            let gross = exposure.linear.iter().map(|(q, mark)| q.abs() * mark).sum::<f64>()
+                + exposure.options.iter().map(|(q, _)| q.abs() * index_price).sum::<f64>();
+            gross_notional += to_decimal(gross);
+            scenario_requirement += to_decimal(worst.0);
+
+            underlyings.push(UnderlyingRisk {
+                asset: asset.clone(),
+                index_price: to_decimal(index_price),
+                spot_quantity: to_decimal(exposure.spot),
+                derivatives_quantity: to_decimal(exposure.linear.iter().map(|(q, _)| q).sum()),
+                option_contracts: exposure.options.len(),
+                gross_derivatives_notional: to_decimal(gross),
+                worst_loss: to_decimal(worst.0),
+                worst_price_move: worst.1,
+                worst_vol_move: worst.2,
+            });
+        }
+
+        let minimum_requirement = gross_notional * self.config.minimum_margin_ratio;
+        let requirement = scenario_requirement.max(minimum_requirement);
+        let margin_ratio = if equity > Decimal::ZERO {
+            requirement / equity
+        } else if requirement > Decimal::ZERO {
+            Decimal::MAX
+        } else {
+            Decimal::ZERO
+        };
+
+        Ok(PortfolioMarginStatus {
+            user_id: *user_id,
+            equity,
+            scenario_requirement,
+            minimum_requirement,
+            requirement,
+            excess_margin: equity - requirement,
+            margin_ratio,
+            liquidatable: requirement > Decimal::ZERO && equity <= requirement,
+            underlyings,
+        })
+    }
+
+    /// Index price of an asset against the quote asset, from any contract on it
+    async fn spot_index(&self, asset: &str) -> Option<Decimal> {
+        for contract in self.engine.contract_manager.list_active_contracts().await {
+            if contract.base_asset == asset && contract.quote_asset == self.config.quote_asset {
+                if let Ok(index) = self.engine.price_service.index_price(&contract.id).await {
+                    return Some(index);
+                }
+            }
+        }
+        None
+    }
+
+    /// Place an order for an enrolled user, checking the portfolio after a complete fill
+    pub async fn place_order(&self, mut request: DerivativeOrderRequest) -> Result<DerivativeOrderResult> {
+        if !self.engine.is_portfolio_margined(&request.user_id).await {
+            return Err(anyhow!("User is not enrolled in portfolio margin"));
+        }
+        let contract = self.engine.contract_manager.get_contract(&request.contract_id).await
+            .ok_or_else(|| anyhow!("Contract not found"))?;
+        if contract.contract_type != ContractType::Option {
+            request.margin_type = MarginType::Cross;
+        }
+
+        if !request.reduce_only && !request.close_position {
+            let price = match request.price {
+                Some(price) => price,
+                None => self.engine.contract_mark_price(&contract).await?,
+            };
+            let quantity = match request.side {
+                Side::Buy => request.quantity,
+                Side::Sell => -request.quantity,
+            };
+
+            let before = self.evaluate(&request.user_id).await?;
+            let after = self.evaluate_with(&request.user_id, &HashSet::new(), Some((&contract, quantity, price)), None).await?;
+            // Orders that lower the requirement, such as hedges, are always allowed
+            if after.requirement > before.requirement
+                && after.equity < after.requirement * self.config.initial_margin_multiplier
+            {
+                return Err(anyhow!(
+                    "Insufficient portfolio margin: equity {} below initial requirement {}",
+                    after.equity, after.requirement * self.config.initial_margin_multiplier
+                ));
+            }
+        }
+
+        self.engine.submit_order(request, OrderOrigin::Portfolio).await
+    }
+
+    /// Liquidate an enrolled user whose equity has fallen to the portfolio requirement:
+    /// cancel their orders, close the derivatives positions that free the most requirement
+    /// first, and hand borrowings to margin liquidation if that is not enough
+    pub async fn check_liquidation(&self, user_id: &UserId) -> Result<Option<PortfolioLiquidationReport>> {
+        let mut status = self.evaluate(user_id).await?;
+        if !status.liquidatable {
+            return Ok(None);
+        }
+
+        let cancelled_orders = self.engine.cancel_user_orders(user_id, None).await?;
+        let mut closed_positions = Vec::new();
+
+        for _ in 0..self.engine.liquidation_config.max_steps {
+            if !status.liquidatable {
+                break;
+            }
+            let positions = self.engine.position_manager.get_user_open_positions(user_id).await;
+            if positions.is_empty() {
+                break;
+            }
+
+            // The position whose removal lowers the requirement the most
+            let mut best: Option<(Position, Decimal)> = None;
+            for position in positions {
+                let without = self.evaluate_with(user_id, &HashSet::from([position.id]), None, None).await?;
+                if best.as_ref().map_or(true, |(_, requirement)| without.requirement < *requirement) {
+                    best = Some((position, without.requirement));
+                }
+            }
+            let (position, _) = match best {
+                Some(best) => best,
+                None => break,
+            };
+
+            if let Err(e) = self.engine.close_position_as(*user_id, position.id, None, OrderOrigin::Liquidation).await {
+                warn!("Could not close {} for portfolio liquidation of {}: {}", position.id, user_id, e);
+                break;
+            }
+            closed_positions.push(position.id);
+            status = self.evaluate(user_id).await?;
+        }
+
+        let mut flagged_margin_accounts = Vec::new();
+        if status.liquidatable {
+            for mut account in self.margin_accounts.get_user_accounts(user_id).await {
+                let borrowing = account.borrowed_base > Decimal::ZERO || account.borrowed_quote > Decimal::ZERO;
+                if borrowing && account.status != MarginAccountStatus::Closed {
+                    account.status = MarginAccountStatus::Liquidating;
+                    account.updated_at = Utc::now();
+                    flagged_margin_accounts.push(account.id);
+                    self.margin_accounts.update_account(account).await?;
+                }
+            }
+        }
+
+        info!(
+            "Portfolio liquidation of {}: {} orders cancelled, {} positions closed, {} margin accounts flagged",
+            user_id, cancelled_orders, closed_positions.len(), flagged_margin_accounts.len()
+        );
+        Ok(Some(PortfolioLiquidationReport {
+            user_id: *user_id,
+            cancelled_orders,
+            closed_positions,
+            flagged_margin_accounts,
+            status,
+        }))
+    }
+
+    pub async fn check_liquidations(&self) -> Vec<PortfolioLiquidationReport> {
+        let mut reports = Vec::new();
+        for user_id in self.enrolled_users().await {
+            match self.check_liquidation(&user_id).await {
+                Ok(Some(report)) => reports.push(report),
+                Ok(None) => {},
+                Err(e) => warn!("Failed to evaluate portfolio margin for {}: {}", user_id, e),
+            }
+        }
+        reports
+    }
+
+    /// Check every enrolled account periodically
+    pub fn spawn_monitor(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = time::interval(interval);
+            loop {
+                ticker.tick().await;
+                manager.check_liquidations().await;
+            }
+        })
+    }
+}
+
+/// Spot balances back an enrolled user's portfolio, so they can only leave the wallet
+/// while equity stays above the initial requirement
+#[async_trait]
+impl WithdrawalGuard for PortfolioMarginManager {
+    async fn check_withdrawal(&self, user_id: &UserId, asset_id: &AssetId, amount: Decimal) -> std::result::Result<(), WalletError> {
+        if !self.engine.is_portfolio_margined(user_id).await {
+            return Ok(());
+        }
+        let after = self.evaluate_with(user_id, &HashSet::new(), None, Some((asset_id.as_str(), -amount))).await
+            .map_err(|e| WalletError::OperationNotPermitted(format!("Portfolio margin could not be evaluated: {}", e)))?;
+        let initial = after.requirement * self.config.initial_margin_multiplier;
+        if after.requirement > Decimal::ZERO && after.equity < initial {
+            return Err(WalletError::OperationNotPermitted(format!(
+                "Withdrawal would leave portfolio equity {} below initial requirement {}",
+                after.equity, initial
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl DerivativesEngine {
+    pub async fn is_portfolio_margined(&self, user_id: &UserId) -> bool {
+        self.portfolio_margin_users.read().await.contains(user_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::RwLock;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::trading_engine::matching_engine::MatchingEngineManager;
+    use super::super::{ContractManager, FundingRateCalculator, PositionManager};
+    use super::super::price_index::{FileReplayFeed, PriceIndexService, PriceRecord};
+
+    #[derive(Default)]
+    struct StaticBalances(RwLock<HashMap<UserId, Vec<(String, Decimal)>>>);
+
+    #[async_trait]
+    impl SpotBalanceSource for StaticBalances {
+        async fn spot_balances(&self, user_id: &UserId) -> Result<Vec<(String, Decimal)>> {
+            Ok(self.0.read().await.get(user_id).cloned().unwrap_or_default())
+        }
+    }
+
+    struct Setup {
+        engine: Arc<DerivativesEngine>,
+        balances: Arc<StaticBalances>,
+        margin_accounts: Arc<MarginAccountManager>,
+        manager: PortfolioMarginManager,
+    }
+
+    async fn setup(index: Decimal) -> Setup {
+        let now = Utc::now();
+        let contract = Contract {
+            id: "BTC-PERP".to_string(),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Perpetual,
+            tick_size: dec!(0.5),
+            lot_size: dec!(0.001),
+            leverage_max: dec!(100),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: None,
+            settlement_asset: "USDT".to_string(),
+            option_type: None,
+            strike_price: None,
+            funding_rate_cap: None,
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let price_service = Arc::new(PriceIndexService::default());
+        price_service.add_index_feed(Arc::new(FileReplayFeed::from_records("replay", vec![
+            PriceRecord { timestamp: now, symbol: "BTC/USDT".to_string(), price: index },
+        ])), dec!(1)).await;
+        price_service.update_at(&contract, now).await.unwrap();
+
+        let contract_manager = Arc::new(ContractManager::new());
+        contract_manager.add_contract(contract).await.unwrap();
+        let engine = Arc::new(DerivativesEngine::new(
+            contract_manager,
+            Arc::new(PositionManager::new()),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            price_service,
+            Arc::new(MatchingEngineManager::new()),
+        ));
+        engine.list_contracts().await.unwrap();
+
+        let balances = Arc::new(StaticBalances::default());
+        let margin_accounts = Arc::new(MarginAccountManager::new(dec!(1.5), dec!(1.1)));
+        let manager = PortfolioMarginManager::new(
+            engine.clone(), balances.clone(), margin_accounts.clone(), PortfolioMarginConfig::default(),
+        );
+        Setup { engine, balances, margin_accounts, manager }
+    }
+
+    #[tokio::test]
+    async fn test_spot_hedge_nets_against_short_perpetual() {
+        let s = setup(dec!(50000)).await;
+        let user = Uuid::new_v4();
+        s.balances.0.write().await.insert(user, vec![("BTC".to_string(), dec!(1)), ("USDT".to_string(), dec!(1000))]);
+        s.manager.enroll(user).await.unwrap();
+
+        // 1 BTC spot alone loses 15% in the worst scenario
+        let unhedged = s.manager.evaluate(&user).await.unwrap();
+        assert_eq!(unhedged.equity, dec!(51000));
+        assert_eq!(unhedged.requirement, dec!(7500));
+
+        let maker = Uuid::new_v4();
+        s.engine.place_order(DerivativeOrderRequest::limit(
+            maker, "BTC-PERP", Side::Buy, dec!(50000), dec!(5), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+
+        // Shorting 1 BTC of perpetual against the spot leaves only the notional floor
+        s.manager.place_order(DerivativeOrderRequest::market(
+            user, "BTC-PERP", Side::Sell, dec!(1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        let position = s.engine.position_manager.get_open_position(&user, "BTC-PERP").await.unwrap();
+        assert_eq!(position.margin_type, MarginType::Cross);
+
+        let hedged = s.manager.evaluate(&user).await.unwrap();
+        assert_eq!(hedged.scenario_requirement, dec!(0));
+        assert_eq!(hedged.requirement, dec!(500));
+        assert_eq!(hedged.underlyings[0].spot_quantity, dec!(1));
+        assert_eq!(hedged.underlyings[0].derivatives_quantity, dec!(-1));
+
+        // A further naked 2 BTC short needs 15% of 100,000 times 1.25, within the 51,000 of equity
+        assert!(s.manager.place_order(DerivativeOrderRequest::market(
+            user, "BTC-PERP", Side::Sell, dec!(2), dec!(10), MarginType::Cross,
+        )).await.is_ok());
+        // 6 more would need 75,000
+        assert!(s.manager.place_order(DerivativeOrderRequest::market(
+            user, "BTC-PERP", Side::Sell, dec!(6), dec!(10), MarginType::Cross,
+        )).await.is_err());
+
+        // Going around the portfolio check is refused, closing out is not
+        assert!(s.engine.place_order(DerivativeOrderRequest::market(
+            user, "BTC-PERP", Side::Sell, dec!(6), dec!(10), MarginType::Cross,
+        )).await.is_err());
+        let mut close = DerivativeOrderRequest::market(user, "BTC-PERP", Side::Buy, dec!(0.5), dec!(10), MarginType::Cross);
+        close.reduce_only = true;
+        s.engine.place_order(DerivativeOrderRequest::limit(
+            Uuid::new_v4(), "BTC-PERP", Side::Sell, dec!(50500), dec!(1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        assert!(s.engine.place_order(close).await.is_ok());
+
+        // The spot BTC hedges the short, so it cannot be withdrawn; spare USDT can
+        assert!(s.manager.check_withdrawal(&user, &"BTC".to_string(), dec!(1)).await.is_err());
+        assert!(s.manager.check_withdrawal(&user, &"USDT".to_string(), dec!(1000)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_inverse_contracts_not_portfolio_margined() {
+        let s = setup(dec!(50000)).await;
+        let mut inverse = s.engine.contract_manager.get_contract("BTC-PERP").await.unwrap();
+        inverse.id = "BTC-USD-INV".to_string();
+        inverse.settlement_asset = "BTC".to_string();
+        inverse.payoff = PayoffType::Inverse;
+        s.engine.contract_manager.add_contract(inverse.clone()).await.unwrap();
+        s.engine.price_service.update_at(&inverse, Utc::now()).await.unwrap();
+
+        let user = Uuid::new_v4();
+        s.balances.0.write().await.insert(user, vec![("USDT".to_string(), dec!(100000))]);
+        s.manager.enroll(user).await.unwrap();
+        assert!(s.manager.place_order(DerivativeOrderRequest::market(
+            user, "BTC-USD-INV", Side::Sell, dec!(1000), dec!(10), MarginType::Cross,
+        )).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_combined_account_liquidation() {
+        let s = setup(dec!(50000)).await;
+        let user = Uuid::new_v4();
+        s.balances.0.write().await.insert(user, vec![("USDT".to_string(), dec!(2000))]);
+
+        // A margin account long 1 BTC bought with 48,000 of borrowed USDT
+        let account = s.margin_accounts.create_account(
+            user, false, "BTC".to_string(), "USDT".to_string(), dec!(1), dec!(0), dec!(0), dec!(0.05),
+        ).await.unwrap();
+        let mut account = s.margin_accounts.get_account(&account.id).await.unwrap();
+        account.borrowed_quote = dec!(48000);
+        s.margin_accounts.update_account(account.clone()).await.unwrap();
+
+        s.manager.enroll(user).await.unwrap();
+        let status = s.manager.evaluate(&user).await.unwrap();
+        // 2,000 + 50,000 - 48,000
+        assert_eq!(status.equity, dec!(4000));
+        assert!(status.liquidatable);
+
+        let report = s.manager.check_liquidation(&user).await.unwrap().unwrap();
+        assert!(report.closed_positions.is_empty());
+        assert_eq!(report.flagged_margin_accounts, vec![account.id]);
+        let account = s.margin_accounts.get_account(&account.id).await.unwrap();
+        assert_eq!(account.status, MarginAccountStatus::Liquidating);
+    }
+}
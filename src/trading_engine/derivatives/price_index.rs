@@ -0,0 +1,544 @@
+// src/trading_engine/derivatives/price_index.rs
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use chrono::{DateTime, TimeZone, Utc};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::trading_engine::matching_engine::MatchingEngineManager;
+use super::{Contract, ContractId, ContractManager};
+
+/// A price observed by a feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceQuote {
+    /// Name of the feed that produced the quote
+    pub source: String,
+    pub price: Decimal,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A source of spot prices for index construction
+#[async_trait]
+pub trait PriceFeed: Send + Sync {
+    /// Name of the feed, used in quotes and logs
+    fn name(&self) -> &str;
+
+    /// Latest price for a symbol as of `at`, or None if the feed has no price
+    async fn fetch_price(&self, symbol: &str, at: DateTime<Utc>) -> Result<Option<PriceQuote>>;
+}
+
+/// Price feed reading the mid price of our own order books
+pub struct OrderBookPriceFeed {
+    name: String,
+    engines: Arc<MatchingEngineManager>,
+}
+
+impl OrderBookPriceFeed {
+    pub fn new(engines: Arc<MatchingEngineManager>) -> Self {
+        OrderBookPriceFeed {
+            name: "internal".to_string(),
+            engines,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceFeed for OrderBookPriceFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch_price(&self, symbol: &str, at: DateTime<Utc>) -> Result<Option<PriceQuote>> {
+        // Spot books are keyed by the configured pair name, e.g. `BTC-USDT` for `BTC/USDT`
+        let engine = match self.engines.get_engine(&symbol.to_string()).await {
+            Ok(engine) => engine,
+            Err(_) => match self.engines.get_engine(&symbol.replace('/', "-")).await {
+                Ok(engine) => engine,
+                Err(_) => return Ok(None),
+            },
+        };
+
+        let (bids, asks) = engine.get_order_book_snapshot(1).await.map_err(|e| anyhow!(e))?;
+        let (price, timestamp) = match (bids.first(), asks.first()) {
+            (Some((bid, _)), Some((ask, _))) => ((*bid + *ask) / Decimal::from(2), at),
+            // Fall back to the last trade on a one-sided book, stamped with its own time so
+            // the index can drop it once it ages out
+            _ => match engine.get_recent_trades(1).await.map_err(|e| anyhow!(e))?.first() {
+                Some(trade) => (trade.price, Utc.timestamp_nanos(trade.timestamp as i64)),
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(PriceQuote {
+            source: self.name.clone(),
+            price,
+            timestamp,
+        }))
+    }
+}
+
+/// A recorded price used by the replay feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceRecord {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub price: Decimal,
+}
+
+/// Stand-in for an external exchange feed that replays recorded prices.
+///
+/// Returns the latest record at or before the requested time, so the same file
+/// can drive tests, backtests and local development.
+pub struct FileReplayFeed {
+    name: String,
+    /// Records by symbol, sorted by timestamp
+    records: HashMap<String, Vec<PriceRecord>>,
+}
+
+impl FileReplayFeed {
+    /// Build a feed from in-memory records
+    pub fn from_records(name: &str, records: Vec<PriceRecord>) -> Self {
+        let mut by_symbol: HashMap<String, Vec<PriceRecord>> = HashMap::new();
+        for record in records {
+            by_symbol.entry(record.symbol.clone()).or_insert_with(Vec::new).push(record);
+        }
+        for records in by_symbol.values_mut() {
+            records.sort_by_key(|r| r.timestamp);
+        }
+
+        FileReplayFeed {
+            name: name.to_string(),
+            records: by_symbol,
+        }
+    }
+
+    /// Load a feed from a JSON lines file of `PriceRecord`s
+    pub fn from_file(name: &str, path: impl AsRef<Path>) -> Result<Self> {
+        let contents = std::fs::read_to_string(path.as_ref())?;
+        let mut records = Vec::new();
+        for (line_no, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: PriceRecord = serde_json::from_str(line)
+                .map_err(|e| anyhow!("Invalid price record on line {}: {}", line_no + 1, e))?;
+            records.push(record);
+        }
+        Ok(Self::from_records(name, records))
+    }
+}
+
+#[async_trait]
+impl PriceFeed for FileReplayFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch_price(&self, symbol: &str, at: DateTime<Utc>) -> Result<Option<PriceQuote>> {
+        let records = match self.records.get(symbol) {
+            Some(records) => records,
+            None => return Ok(None),
+        };
+
+        let index = records.partition_point(|r| r.timestamp <= at);
+        if index == 0 {
+            return Ok(None);
+        }
+
+        let record = &records[index - 1];
+        Ok(Some(PriceQuote {
+            source: self.name.clone(),
+            price: record.price,
+            timestamp: record.timestamp,
+        }))
+    }
+}
+
+/// Configuration for index and mark price calculation
+#[derive(Debug, Clone)]
+pub struct PriceIndexConfig {
+    /// Quotes further than this fraction from the median are dropped as outliers
+    pub max_deviation: Decimal,
+    /// Quotes older than this are ignored
+    pub max_staleness: chrono::Duration,
+    /// Minimum number of sources left after outlier removal
+    pub min_sources: usize,
+    /// Weight of the newest basis sample in the exponential moving average
+    pub basis_smoothing: Decimal,
+}
+
+impl Default for PriceIndexConfig {
+    fn default() -> Self {
+        Self {
+            max_deviation: dec!(0.05),
+            max_staleness: chrono::Duration::seconds(30),
+            min_sources: 1,
+            basis_smoothing: dec!(0.1),
+        }
+    }
+}
+
+/// Index and mark prices for a contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractPrices {
+    pub contract_id: ContractId,
+    /// Weighted median of the spot constituents
+    pub index_price: Decimal,
+    /// Index plus smoothed basis
+    pub mark_price: Decimal,
+    /// Smoothed difference between the contract's own book and the index
+    pub basis: Decimal,
+    /// Quotes that made up the index
+    pub constituents: Vec<PriceQuote>,
+    /// Quotes dropped as outliers
+    pub excluded: Vec<PriceQuote>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Weighted median of `(price, weight)` pairs
+pub fn weighted_median(values: &[(Decimal, Decimal)]) -> Option<Decimal> {
+    let mut sorted: Vec<(Decimal, Decimal)> = values.iter()
+        .filter(|(_, weight)| *weight > Decimal::ZERO)
+        .copied()
+        .collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let total: Decimal = sorted.iter().map(|(_, weight)| *weight).sum();
+    let half = total / Decimal::from(2);
+
+    let mut cumulative = Decimal::ZERO;
+    for (i, (price, weight)) in sorted.iter().enumerate() {
+        cumulative += *weight;
+        if cumulative > half {
+            return Some(*price);
+        }
+        if cumulative == half {
+            // Exactly half the weight on each side: average the two middle prices
+            return Some(match sorted.get(i + 1) {
+                Some((next, _)) => (*price + *next) / Decimal::from(2),
+                None => *price,
+            });
+        }
+    }
+
+    sorted.last().map(|(price, _)| *price)
+}
+
+/// Service computing index and mark prices for derivatives contracts.
+///
+/// The index is a weighted median of spot prices from the registered feeds after
+/// outlier removal. The mark is the index plus an exponentially smoothed basis
+/// between the contract's own book and the index, so a thin or manipulated
+/// contract book can't move the mark on its own.
+pub struct PriceIndexService {
+    config: PriceIndexConfig,
+    /// Spot feeds and their weights
+    index_feeds: RwLock<Vec<(Arc<dyn PriceFeed>, Decimal)>>,
+    /// Feed for the contracts' own books, keyed by contract id
+    contract_feed: RwLock<Option<Arc<dyn PriceFeed>>>,
+    /// Latest prices by contract
+    prices: RwLock<HashMap<ContractId, ContractPrices>>,
+}
+
+impl PriceIndexService {
+    pub fn new(config: PriceIndexConfig) -> Self {
+        PriceIndexService {
+            config,
+            index_feeds: RwLock::new(Vec::new()),
+            contract_feed: RwLock::new(None),
+            prices: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Register a spot feed contributing to the index with the given weight
+    pub async fn add_index_feed(&self, feed: Arc<dyn PriceFeed>, weight: Decimal) {
+        self.index_feeds.write().await.push((feed, weight));
+    }
+
+    /// Set the feed used to price the contracts' own books for the basis
+    pub async fn set_contract_feed(&self, feed: Arc<dyn PriceFeed>) {
+        *self.contract_feed.write().await = Some(feed);
+    }
+
+    /// Spot symbol the index of a contract is built from
+    pub fn index_symbol(contract: &Contract) -> String {
+        format!("{}/{}", contract.base_asset, contract.quote_asset)
+    }
+
+    /// Recompute prices for a contract now
+    pub async fn update(&self, contract: &Contract) -> Result<ContractPrices> {
+        self.update_at(contract, Utc::now()).await
+    }
+
+    /// Recompute prices for a contract as of the given time
+    pub async fn update_at(&self, contract: &Contract, now: DateTime<Utc>) -> Result<ContractPrices> {
+        let symbol = Self::index_symbol(contract);
+
+        // Collect fresh quotes from every feed
+        let mut quotes = Vec::new();
+        for (feed, weight) in self.index_feeds.read().await.iter() {
+            match feed.fetch_price(&symbol, now).await {
+                Ok(Some(quote)) if now - quote.timestamp <= self.config.max_staleness => {
+                    quotes.push((quote, *weight));
+                },
+                Ok(Some(quote)) => {
+                    debug!("Ignoring stale {} quote from {} at {}", symbol, quote.source, quote.timestamp);
+                },
+                Ok(None) => {},
+                Err(e) => warn!("Price feed {} failed for {}: {}", feed.name(), symbol, e),
+            }
+        }
+
+        let (index_price, constituents, excluded) = self.compute_index(quotes)?;
+
+        // Smooth the basis between the contract's own book and the index
+        let book_price = match self.contract_feed.read().await.as_ref() {
+            Some(feed) => match feed.fetch_price(&contract.id, now).await {
+                Ok(Some(quote)) if now - quote.timestamp <= self.config.max_staleness => Some(quote),
+                Ok(Some(quote)) => {
+                    debug!("Ignoring stale {} quote from {} at {}", contract.id, quote.source, quote.timestamp);
+                    None
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    warn!("Contract price feed failed for {}: {}", contract.id, e);
+                    None
+                },
+            },
+            None => None,
+        };
+
+        let mut prices = self.prices.write().await;
+        let previous_basis = prices.get(&contract.id).map(|p| p.basis);
+        let basis = match (book_price, previous_basis) {
+            (Some(quote), Some(previous)) => {
+                let sample = quote.price - index_price;
+                previous + self.config.basis_smoothing * (sample - previous)
+            },
+            (Some(quote), None) => quote.price - index_price,
+            (None, Some(previous)) => previous,
+            (None, None) => Decimal::ZERO,
+        };
+
+        let contract_prices = ContractPrices {
+            contract_id: contract.id.clone(),
+            index_price,
+            mark_price: index_price + basis,
+            basis,
+            constituents,
+            excluded,
+            updated_at: now,
+        };
+        prices.insert(contract.id.clone(), contract_prices.clone());
+
+        Ok(contract_prices)
+    }
+
+    /// Weighted median of the quotes with outliers removed
+    fn compute_index(&self, quotes: Vec<(PriceQuote, Decimal)>) -> Result<(Decimal, Vec<PriceQuote>, Vec<PriceQuote>)> {
+        let weighted: Vec<(Decimal, Decimal)> = quotes.iter().map(|(q, w)| (q.price, *w)).collect();
+        let median = weighted_median(&weighted).ok_or_else(|| anyhow!("No prices available for index"))?;
+        if median <= Decimal::ZERO {
+            return Err(anyhow!("Invalid median price {}", median));
+        }
+
+        let mut constituents = Vec::new();
+        let mut kept = Vec::new();
+        let mut excluded = Vec::new();
+        for (quote, weight) in quotes {
+            let deviation = (quote.price - median).abs() / median;
+            if deviation > self.config.max_deviation {
+                warn!("Excluding {} price {} from index: {} from median {}", quote.source, quote.price, deviation, median);
+                excluded.push(quote);
+            } else {
+                kept.push((quote.price, weight));
+                constituents.push(quote);
+            }
+        }
+
+        if constituents.len() < self.config.min_sources {
+            return Err(anyhow!(
+                "Only {} index sources left after outlier removal, need {}",
+                constituents.len(), self.config.min_sources
+            ));
+        }
+
+        let index = weighted_median(&kept).ok_or_else(|| anyhow!("No prices available for index"))?;
+        Ok((index, constituents, excluded))
+    }
+
+    /// Latest prices for a contract
+    pub async fn get_prices(&self, contract_id: &str) -> Option<ContractPrices> {
+        self.prices.read().await.get(contract_id).cloned()
+    }
+
+    /// Latest index price for a contract
+    pub async fn index_price(&self, contract_id: &str) -> Result<Decimal> {
+        self.get_prices(contract_id).await
+            .map(|p| p.index_price)
+            .ok_or_else(|| anyhow!("No index price for {}", contract_id))
+    }
+
+    /// Latest mark price for a contract
+    pub async fn mark_price(&self, contract_id: &str) -> Result<Decimal> {
+        self.get_prices(contract_id).await
+            .map(|p| p.mark_price)
+            .ok_or_else(|| anyhow!("No mark price for {}", contract_id))
+    }
+
+    /// Periodically refresh prices for every active contract
+    pub fn spawn_updater(self: &Arc<Self>, contracts: Arc<ContractManager>, interval: Duration) -> JoinHandle<()> {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = time::interval(interval);
+            loop {
+                ticker.tick().await;
+                for contract in contracts.list_active_contracts().await {
+                    if let Err(e) = service.update(&contract).await {
+                        warn!("Failed to update prices for {}: {}", contract.id, e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Default for PriceIndexService {
+    fn default() -> Self {
+        Self::new(PriceIndexConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(symbol: &str, seconds: i64, price: Decimal) -> PriceRecord {
+        PriceRecord {
+            timestamp: Utc.timestamp_opt(seconds, 0).unwrap(),
+            symbol: symbol.to_string(),
+            price,
+        }
+    }
+
+    fn contract() -> Contract {
+        Contract {
+            id: "BTC-PERP".to_string(),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: super::super::ContractType::Perpetual,
+            tick_size: dec!(0.5),
+            lot_size: dec!(0.001),
+            leverage_max: dec!(100),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: None,
+            settlement_asset: "USDT".to_string(),
+            option_type: None,
+            strike_price: None,
+            funding_rate_cap: None,
+            risk_limit_tiers: Vec::new(),
+            payoff: super::super::PayoffType::Linear,
+            is_active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_weighted_median() {
+        assert_eq!(weighted_median(&[]), None);
+        assert_eq!(weighted_median(&[(dec!(100), dec!(1)), (dec!(102), dec!(1)), (dec!(101), dec!(1))]), Some(dec!(101)));
+        // Heavy weight pulls the median
+        assert_eq!(weighted_median(&[(dec!(100), dec!(3)), (dec!(102), dec!(1)), (dec!(101), dec!(1))]), Some(dec!(100)));
+        // Even split averages the middle
+        assert_eq!(weighted_median(&[(dec!(100), dec!(1)), (dec!(102), dec!(1))]), Some(dec!(101)));
+    }
+
+    #[tokio::test]
+    async fn test_index_excludes_outliers() {
+        let service = PriceIndexService::default();
+        let t = 1_700_000_000;
+        for (name, price) in [("a", dec!(50000)), ("b", dec!(50010)), ("c", dec!(49990)), ("d", dec!(60000))] {
+            let feed = FileReplayFeed::from_records(name, vec![record("BTC/USDT", t, price)]);
+            service.add_index_feed(Arc::new(feed), dec!(1)).await;
+        }
+
+        let prices = service.update_at(&contract(), Utc.timestamp_opt(t + 1, 0).unwrap()).await.unwrap();
+        assert_eq!(prices.excluded.len(), 1);
+        assert_eq!(prices.excluded[0].source, "d");
+        assert_eq!(prices.index_price, dec!(50000));
+        assert_eq!(prices.mark_price, dec!(50000));
+    }
+
+    #[tokio::test]
+    async fn test_mark_uses_smoothed_basis() {
+        let mut config = PriceIndexConfig::default();
+        config.basis_smoothing = dec!(0.5);
+        let service = PriceIndexService::new(config);
+        let t = 1_700_000_000;
+
+        let spot = FileReplayFeed::from_records("spot", vec![record("BTC/USDT", t, dec!(50000))]);
+        let perp = FileReplayFeed::from_records("perp", vec![
+            record("BTC-PERP", t, dec!(50100)),
+            record("BTC-PERP", t + 1, dec!(50300)),
+        ]);
+        service.add_index_feed(Arc::new(spot), dec!(1)).await;
+        service.set_contract_feed(Arc::new(perp)).await;
+
+        let first = service.update_at(&contract(), Utc.timestamp_opt(t, 0).unwrap()).await.unwrap();
+        assert_eq!(first.basis, dec!(100));
+        assert_eq!(first.mark_price, dec!(50100));
+
+        // A 300 basis sample only moves the smoothed basis halfway
+        let second = service.update_at(&contract(), Utc.timestamp_opt(t + 1, 0).unwrap()).await.unwrap();
+        assert_eq!(second.basis, dec!(200));
+        assert_eq!(second.mark_price, dec!(50200));
+    }
+
+    #[tokio::test]
+    async fn test_stale_quotes_ignored() {
+        let service = PriceIndexService::default();
+        let feed = FileReplayFeed::from_records("a", vec![record("BTC/USDT", 0, dec!(50000))]);
+        service.add_index_feed(Arc::new(feed), dec!(1)).await;
+
+        assert!(service.update_at(&contract(), Utc.timestamp_opt(3600, 0).unwrap()).await.is_err());
+        assert!(service.mark_price("BTC-PERP").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stale_contract_quote_keeps_basis() {
+        let service = PriceIndexService::default();
+        let t = 1_700_000_000;
+
+        let spot = FileReplayFeed::from_records("spot", vec![
+            record("BTC/USDT", t, dec!(50000)),
+            record("BTC/USDT", t + 3600, dec!(51000)),
+        ]);
+        let perp = FileReplayFeed::from_records("perp", vec![record("BTC-PERP", t, dec!(50100))]);
+        service.add_index_feed(Arc::new(spot), dec!(1)).await;
+        service.set_contract_feed(Arc::new(perp)).await;
+
+        let first = service.update_at(&contract(), Utc.timestamp_opt(t, 0).unwrap()).await.unwrap();
+        assert_eq!(first.basis, dec!(100));
+
+        // The hour-old contract print no longer feeds the basis
+        let second = service.update_at(&contract(), Utc.timestamp_opt(t + 3600, 0).unwrap()).await.unwrap();
+        assert_eq!(second.basis, dec!(100));
+        assert_eq!(second.mark_price, dec!(51100));
+    }
+}
@@ -0,0 +1,302 @@
+// src/trading_engine/derivatives/risk_limits.rs
+
+use rust_decimal::Decimal;
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+
+use super::{Contract, ContractId, DerivativesEngine, Position, UserId};
+
+/// One band of a contract's risk limit table
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RiskTier {
+    /// Largest position notional allowed in this tier, in the settlement asset
+    pub max_notional: Decimal,
+    pub max_leverage: Decimal,
+    pub maintenance_margin_ratio: Decimal,
+}
+
+/// Check that tiers are ordered by notional with leverage falling and maintenance margin rising
+pub fn validate_risk_tiers(tiers: &[RiskTier]) -> Result<()> {
+    for tier in tiers {
+        if tier.max_notional <= Decimal::ZERO || tier.max_leverage <= Decimal::ZERO {
+            return Err(anyhow!("Risk tier limits must be positive"));
+        }
+        if tier.maintenance_margin_ratio <= Decimal::ZERO || tier.maintenance_margin_ratio >= Decimal::ONE / tier.max_leverage {
+            return Err(anyhow!("Maintenance margin must be positive and below the initial margin at max leverage"));
+        }
+    }
+    for pair in tiers.windows(2) {
+        if pair[1].max_notional <= pair[0].max_notional {
+            return Err(anyhow!("Risk tiers must be ordered by increasing notional"));
+        }
+        if pair[1].max_leverage > pair[0].max_leverage || pair[1].maintenance_margin_ratio < pair[0].maintenance_margin_ratio {
+            return Err(anyhow!("Higher risk tiers cannot allow more leverage or less maintenance margin"));
+        }
+    }
+    Ok(())
+}
+
+impl Contract {
+    /// The contract's risk limit table; without one, a single unlimited tier from its leverage and maintenance margin
+    pub fn risk_tiers(&self) -> Vec<RiskTier> {
+        if !self.risk_limit_tiers.is_empty() {
+            return self.risk_limit_tiers.clone();
+        }
+        vec![RiskTier {
+            max_notional: Decimal::MAX,
+            max_leverage: self.leverage_max,
+            maintenance_margin_ratio: self.maintenance_margin_ratio,
+        }]
+    }
+
+    /// Tier number (from 1) and limits for a position notional, or `None` beyond the last tier
+    pub fn risk_tier_for(&self, notional: Decimal) -> Option<(usize, RiskTier)> {
+        self.risk_tiers().into_iter()
+            .enumerate()
+            .find(|(_, tier)| notional <= tier.max_notional)
+            .map(|(index, tier)| (index + 1, tier))
+    }
+
+    /// Maintenance margin ratio for a position notional; the last tier's beyond the table
+    pub fn maintenance_margin_ratio_for(&self, notional: Decimal) -> Decimal {
+        match self.risk_tier_for(notional) {
+            Some((_, tier)) => tier.maintenance_margin_ratio,
+            None => self.risk_tiers().last().map_or(self.maintenance_margin_ratio, |t| t.maintenance_margin_ratio),
+        }
+    }
+}
+
+/// A user's standing against a contract's risk limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskLimitStatus {
+    pub user_id: UserId,
+    pub contract_id: ContractId,
+    pub position_notional: Decimal,
+    /// Tier number from 1; 0 with no position
+    pub current_tier: usize,
+    pub max_leverage: Decimal,
+    pub maintenance_margin_ratio: Decimal,
+    /// Notional the user can still add before moving up a tier
+    pub headroom: Decimal,
+    pub tiers: Vec<RiskTier>,
+}
+
+impl DerivativesEngine {
+    /// Replace a contract's risk limit table
+    pub async fn set_risk_tiers(&self, contract_id: &str, tiers: Vec<RiskTier>) -> Result<()> {
+        validate_risk_tiers(&tiers)?;
+        let mut contract = self.contract_manager.get_contract(contract_id).await
+            .ok_or_else(|| anyhow!("Contract not found"))?;
+        contract.risk_limit_tiers = tiers;
+        contract.updated_at = chrono::Utc::now();
+        self.contract_manager.update_contract(contract).await
+    }
+
+    /// Reject an order whose full fill would take the position past the tier allowing its leverage
+    pub(super) fn check_risk_limit(
+        contract: &Contract,
+        current: Option<&Position>,
+        projected_notional: Decimal,
+        leverage: Decimal,
+    ) -> Result<()> {
+        let (tier_number, tier) = contract.risk_tier_for(projected_notional).ok_or_else(|| anyhow!(
+            "Position notional {} exceeds the largest risk limit tier of {}",
+            projected_notional, contract.id
+        ))?;
+
+        // Added size takes on the existing position's leverage
+        let leverage = current.map_or(leverage, |p| p.leverage);
+        if leverage > tier.max_leverage {
+            return Err(anyhow!(
+                "Leverage {} exceeds the {}x allowed at risk tier {} for notional {}",
+                leverage, tier.max_leverage, tier_number, projected_notional
+            ));
+        }
+        Ok(())
+    }
+
+    /// Move a position to the maintenance margin of the tier its notional at the mark price falls in,
+    /// the notional liquidation is checked against
+    pub(super) async fn refresh_risk_tier(&self, contract: &Contract, position: &Position, mark_price: Decimal) -> Result<Position> {
+        let ratio = contract.maintenance_margin_ratio_for(position.notional(mark_price));
+        if ratio == position.maintenance_margin_ratio {
+            return Ok(position.clone());
+        }
+
+        let mut position = position.clone();
+        position.maintenance_margin_ratio = ratio;
+        position.update_liquidation_price();
+        self.position_manager.update_position(position.clone()).await?;
+        Ok(position)
+    }
+
+    /// The user's current risk tier on a contract
+    pub async fn risk_limit_status(&self, user_id: &UserId, contract_id: &str) -> Result<RiskLimitStatus> {
+        let contract = self.contract_manager.get_contract(contract_id).await
+            .ok_or_else(|| anyhow!("Contract not found"))?;
+        let tiers = contract.risk_tiers();
+
+        let position_notional = match self.position_manager.get_open_position(user_id, contract_id).await {
+            Some(position) => position.notional(self.contract_mark_price(&contract).await?),
+            None => Decimal::ZERO,
+        };
+
+        let (current_tier, tier) = if position_notional.is_zero() {
+            (0, tiers[0])
+        } else {
+            contract.risk_tier_for(position_notional)
+                .unwrap_or((tiers.len(), tiers[tiers.len() - 1]))
+        };
+
+        Ok(RiskLimitStatus {
+            user_id: *user_id,
+            contract_id: contract.id.clone(),
+            position_notional,
+            current_tier,
+            max_leverage: tier.max_leverage,
+            maintenance_margin_ratio: tier.maintenance_margin_ratio,
+            headroom: (tier.max_notional - position_notional).max(Decimal::ZERO),
+            tiers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::trading_engine::matching_engine::{MatchingEngineManager, Side};
+    use super::super::{
+        ContractManager, ContractType, FundingRateCalculator, MarginType, PayoffType, PositionDirection, PositionManager,
+    };
+    use super::super::orders::DerivativeOrderRequest;
+    use super::super::price_index::{FileReplayFeed, PriceIndexService, PriceRecord};
+
+    fn tiers() -> Vec<RiskTier> {
+        vec![
+            RiskTier { max_notional: dec!(50000), max_leverage: dec!(50), maintenance_margin_ratio: dec!(0.01) },
+            RiskTier { max_notional: dec!(250000), max_leverage: dec!(20), maintenance_margin_ratio: dec!(0.025) },
+            RiskTier { max_notional: dec!(1000000), max_leverage: dec!(10), maintenance_margin_ratio: dec!(0.05) },
+        ]
+    }
+
+    fn contract() -> Contract {
+        Contract {
+            id: "BTC-PERP".to_string(),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Perpetual,
+            tick_size: dec!(0.5),
+            lot_size: dec!(0.001),
+            leverage_max: dec!(50),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: None,
+            settlement_asset: "USDT".to_string(),
+            option_type: None,
+            strike_price: None,
+            funding_rate_cap: None,
+            risk_limit_tiers: tiers(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_tier_lookup_and_validation() {
+        let contract = contract();
+        assert_eq!(contract.risk_tier_for(dec!(50000)).unwrap().0, 1);
+        assert_eq!(contract.risk_tier_for(dec!(50001)).unwrap().0, 2);
+        assert!(contract.risk_tier_for(dec!(2000000)).is_none());
+        assert_eq!(contract.maintenance_margin_ratio_for(dec!(2000000)), dec!(0.05));
+
+        let untiered = Contract { risk_limit_tiers: Vec::new(), ..contract };
+        assert_eq!(untiered.risk_tier_for(dec!(1000000000)).unwrap().1.max_leverage, dec!(50));
+
+        assert!(validate_risk_tiers(&tiers()).is_ok());
+        let mut loosening = tiers();
+        loosening[2].max_leverage = dec!(25);
+        assert!(validate_risk_tiers(&loosening).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_listing_validates_tiers() {
+        let contract_manager = ContractManager::new();
+        let mut loosening = tiers();
+        loosening[2].max_leverage = dec!(25);
+        assert!(contract_manager.add_contract(Contract { risk_limit_tiers: loosening, ..contract() }).await.is_err());
+        assert!(contract_manager.add_contract(contract()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_orders_limited_by_tier_and_margin_follows_it() {
+        let now = Utc::now();
+        let price_service = Arc::new(PriceIndexService::default());
+        price_service.add_index_feed(Arc::new(FileReplayFeed::from_records("replay", vec![
+            PriceRecord { timestamp: now, symbol: "BTC/USDT".to_string(), price: dec!(50000) },
+        ])), dec!(1)).await;
+        price_service.update_at(&contract(), now).await.unwrap();
+
+        let contract_manager = Arc::new(ContractManager::new());
+        let engine = DerivativesEngine::new(
+            contract_manager.clone(),
+            Arc::new(PositionManager::new()),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            price_service,
+            Arc::new(MatchingEngineManager::new()),
+        );
+        contract_manager.add_contract(contract()).await.unwrap();
+        engine.list_contracts().await.unwrap();
+
+        let maker = Uuid::new_v4();
+        let taker = Uuid::new_v4();
+        engine.place_order(DerivativeOrderRequest::limit(
+            maker, "BTC-PERP", Side::Sell, dec!(50000), dec!(20), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+
+        // 2 BTC is 100,000 notional: tier 2 caps leverage at 20x
+        let too_levered = DerivativeOrderRequest::market(taker, "BTC-PERP", Side::Buy, dec!(2), dec!(50), MarginType::Isolated);
+        assert!(engine.place_order(too_levered).await.is_err());
+
+        let position = engine.open_position(
+            taker, "BTC-PERP", PositionDirection::Long, dec!(0.5), dec!(50), MarginType::Isolated,
+        ).await.unwrap();
+        assert_eq!(position.maintenance_margin_ratio, dec!(0.01));
+
+        // Adding to the 50x position would cross into tier 2
+        let add = DerivativeOrderRequest::market(taker, "BTC-PERP", Side::Buy, dec!(0.6), dec!(50), MarginType::Isolated);
+        assert!(engine.place_order(add).await.is_err());
+
+        // Resting orders count towards the tier: two bids that each fit tier 1 do not together
+        let stacker = Uuid::new_v4();
+        engine.place_order(DerivativeOrderRequest::limit(
+            stacker, "BTC-PERP", Side::Buy, dec!(49000), dec!(0.8), dec!(50), MarginType::Isolated,
+        )).await.unwrap();
+        assert!(engine.place_order(DerivativeOrderRequest::limit(
+            stacker, "BTC-PERP", Side::Buy, dec!(49000), dec!(0.8), dec!(50), MarginType::Isolated,
+        )).await.is_err());
+
+        // A separate 20x position moves to tier 2's maintenance margin once it grows past 50,000
+        let other = Uuid::new_v4();
+        engine.open_position(other, "BTC-PERP", PositionDirection::Long, dec!(0.5), dec!(20), MarginType::Isolated).await.unwrap();
+        let grown = engine.open_position(other, "BTC-PERP", PositionDirection::Long, dec!(1.5), dec!(20), MarginType::Isolated).await.unwrap();
+        assert_eq!(grown.maintenance_margin_ratio, dec!(0.025));
+        assert_eq!(grown.liquidation_price, grown.calculate_liquidation_price());
+
+        let status = engine.risk_limit_status(&other, "BTC-PERP").await.unwrap();
+        assert_eq!(status.current_tier, 2);
+        assert_eq!(status.max_leverage, dec!(20));
+        assert_eq!(status.headroom, dec!(150000));
+
+        // More than the last tier is never allowed
+        let huge = DerivativeOrderRequest::market(Uuid::new_v4(), "BTC-PERP", Side::Buy, dec!(25), dec!(1), MarginType::Isolated);
+        assert!(engine.place_order(huge).await.is_err());
+    }
+}
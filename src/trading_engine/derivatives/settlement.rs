@@ -0,0 +1,454 @@
+// src/trading_engine/derivatives/settlement.rs
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use rust_decimal::Decimal;
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc, Weekday};
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use super::{
+    Contract, ContractId, ContractType, DerivativesEngine, MarginType, PositionDirection, PositionId,
+    UserId,
+};
+
+/// Seconds between index samples for the settlement price
+pub const SETTLEMENT_SAMPLE_INTERVAL_SECS: i64 = 60;
+
+/// Hour (UTC) at which quarterly contracts expire
+pub const QUARTERLY_EXPIRY_HOUR: u32 = 8;
+
+#[derive(Debug, Clone)]
+pub struct SettlementConfig {
+    /// How long before expiry the book stops accepting orders
+    pub trading_halt_before: chrono::Duration,
+    /// Window before expiry over which the index is averaged
+    pub twap_window: chrono::Duration,
+    /// List the next quarterly contract when one settles
+    pub auto_list_next: bool,
+}
+
+impl Default for SettlementConfig {
+    fn default() -> Self {
+        SettlementConfig {
+            trading_halt_before: chrono::Duration::minutes(10),
+            twap_window: chrono::Duration::minutes(30),
+            auto_list_next: true,
+        }
+    }
+}
+
+/// Cash settlement of one position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSettlement {
+    pub position_id: PositionId,
+    pub user_id: UserId,
+    pub margin_type: MarginType,
+    pub direction: PositionDirection,
+    pub quantity: Decimal,
+    pub entry_price: Decimal,
+    pub pnl: Decimal,
+    /// Final isolated margin, or the cross collateral after crediting the PnL
+    pub margin_balance: Decimal,
+}
+
+/// Result of settling an expired contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractSettlement {
+    pub contract_id: ContractId,
+    pub expiry_time: DateTime<Utc>,
+    /// Time-weighted index over the settlement window
+    pub settlement_price: Decimal,
+    pub positions: Vec<PositionSettlement>,
+    /// Contract listed to replace the settled one
+    pub next_contract: Option<ContractId>,
+    pub settled_at: DateTime<Utc>,
+}
+
+/// Index observation for one minute of the settlement window
+#[derive(Debug, Clone)]
+struct IndexSample {
+    minute: DateTime<Utc>,
+    price: Decimal,
+}
+
+/// Last Friday of the month, at the quarterly expiry hour
+fn last_friday(year: i32, month: u32) -> DateTime<Utc> {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    let mut day = NaiveDate::from_ymd_opt(next_year, next_month, 1).unwrap().pred_opt().unwrap();
+    while day.weekday() != Weekday::Fri {
+        day = day.pred_opt().unwrap();
+    }
+    Utc.from_utc_datetime(&day.and_hms_opt(QUARTERLY_EXPIRY_HOUR, 0, 0).unwrap())
+}
+
+/// First quarterly expiry (last Friday of March, June, September or December) after `after`
+pub fn next_quarterly_expiry(after: DateTime<Utc>) -> DateTime<Utc> {
+    let mut year = after.year();
+    let mut month = (after.month() + 2) / 3 * 3;
+    loop {
+        let expiry = last_friday(year, month);
+        if expiry > after {
+            return expiry;
+        }
+        month += 3;
+        if month > 12 {
+            month = 3;
+            year += 1;
+        }
+    }
+}
+
+/// Id of a dated future, e.g. `BTC-240628`
+pub fn futures_contract_id(base_asset: &str, expiry: DateTime<Utc>) -> ContractId {
+    format!("{}-{}", base_asset, expiry.format("%y%m%d"))
+}
+
+/// Halts, prices and cash-settles dated futures at expiry
+pub struct SettlementScheduler {
+    engine: Arc<DerivativesEngine>,
+    config: SettlementConfig,
+    samples: RwLock<HashMap<ContractId, Vec<IndexSample>>>,
+    settlements: RwLock<HashMap<ContractId, ContractSettlement>>,
+}
+
+impl SettlementScheduler {
+    pub fn new(engine: Arc<DerivativesEngine>, config: SettlementConfig) -> Self {
+        SettlementScheduler {
+            engine,
+            config,
+            samples: RwLock::new(HashMap::new()),
+            settlements: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get_settlement(&self, contract_id: &str) -> Option<ContractSettlement> {
+        self.settlements.read().await.get(contract_id).cloned()
+    }
+
+    async fn dated_contracts(&self) -> Vec<(Contract, DateTime<Utc>)> {
+        self.engine.contract_manager.list_active_contracts().await
+            .into_iter()
+            .filter(|c| c.contract_type == ContractType::Futures)
+            .filter_map(|c| c.expiry_time.map(|expiry| (c, expiry)))
+            .collect()
+    }
+
+    /// Record the index of every future inside its settlement window
+    pub async fn sample_index_prices(&self, now: DateTime<Utc>) {
+        let minute = Utc.timestamp_opt(
+            now.timestamp().div_euclid(SETTLEMENT_SAMPLE_INTERVAL_SECS) * SETTLEMENT_SAMPLE_INTERVAL_SECS,
+            0,
+        ).unwrap();
+
+        for (contract, expiry) in self.dated_contracts().await {
+            if now < expiry - self.config.twap_window || now >= expiry {
+                continue;
+            }
+            let price = match self.engine.price_service.index_price(&contract.id).await {
+                Ok(price) => price,
+                Err(e) => {
+                    warn!("No index to sample for settlement of {}: {}", contract.id, e);
+                    continue;
+                },
+            };
+
+            let mut samples = self.samples.write().await;
+            let contract_samples = samples.entry(contract.id.clone()).or_insert_with(Vec::new);
+            match contract_samples.last_mut() {
+                Some(last) if last.minute == minute => last.price = price,
+                _ => contract_samples.push(IndexSample { minute, price }),
+            }
+        }
+    }
+
+    /// Time-weighted average of the index samples in the settlement window
+    pub async fn settlement_price(&self, contract_id: &str, expiry: DateTime<Utc>) -> Option<Decimal> {
+        let samples = self.samples.read().await;
+        let window_start = expiry - self.config.twap_window;
+        let window: Vec<Decimal> = samples.get(contract_id)?
+            .iter()
+            .filter(|s| s.minute >= window_start && s.minute < expiry)
+            .map(|s| s.price)
+            .collect();
+
+        if window.is_empty() {
+            return None;
+        }
+        Some(window.iter().copied().sum::<Decimal>() / Decimal::from(window.len()))
+    }
+
+    /// Halt futures close to expiry and settle those that have expired
+    pub async fn settle_due(&self, now: DateTime<Utc>) -> Result<Vec<ContractSettlement>> {
+        let mut settlements = Vec::new();
+
+        for (contract, expiry) in self.dated_contracts().await {
+            if now >= expiry - self.config.trading_halt_before && !self.engine.is_trading_halted(&contract.id).await {
+                let cancelled = self.engine.halt_trading(&contract.id).await?;
+                info!("Halted trading on {} ahead of expiry, cancelled {} orders", contract.id, cancelled);
+            }
+
+            if now < expiry {
+                continue;
+            }
+            match self.settle_contract(&contract, expiry, now).await {
+                Ok(settlement) => settlements.push(settlement),
+                Err(e) => warn!("Settlement failed for {}: {}", contract.id, e),
+            }
+        }
+
+        Ok(settlements)
+    }
+
+    async fn settle_contract(&self, contract: &Contract, expiry: DateTime<Utc>, now: DateTime<Utc>) -> Result<ContractSettlement> {
+        let settlement_price = match self.settlement_price(&contract.id, expiry).await {
+            Some(price) => price,
+            None => {
+                warn!("No index samples in the settlement window of {}; using the current index", contract.id);
+                self.engine.price_service.index_price(&contract.id).await?
+            },
+        };
+
+        let mut positions = Vec::new();
+        for position in self.engine.position_manager.get_contract_open_positions(&contract.id).await {
+            let settled = self.engine.position_manager.settle_position(&position.id, settlement_price).await?;
+            let pnl = settled.realized_pnl - position.realized_pnl;
+
+            let margin_balance = match settled.margin_type {
+                MarginType::Isolated => settled.margin_amount,
+                MarginType::Cross => self.engine.cross_margin.adjust_collateral(&settled.user_id, pnl).await?,
+            };
+
+            positions.push(PositionSettlement {
+                position_id: position.id,
+                user_id: position.user_id,
+                margin_type: position.margin_type,
+                direction: position.direction,
+                quantity: position.quantity,
+                entry_price: position.entry_price,
+                pnl,
+                margin_balance,
+            });
+        }
+
+        let mut expired = contract.clone();
+        expired.is_active = false;
+        expired.updated_at = now;
+        self.engine.contract_manager.update_contract(expired).await?;
+        self.samples.write().await.remove(&contract.id);
+
+        // Record the settlement before listing the successor, so a listing failure
+        // cannot lose it
+        let mut settlement = ContractSettlement {
+            contract_id: contract.id.clone(),
+            expiry_time: expiry,
+            settlement_price,
+            positions,
+            next_contract: None,
+            settled_at: now,
+        };
+        self.settlements.write().await.insert(contract.id.clone(), settlement.clone());
+
+        if self.config.auto_list_next {
+            match self.list_next_quarterly(contract, expiry, now).await {
+                Ok(next) => {
+                    settlement.next_contract = Some(next);
+                    self.settlements.write().await.insert(contract.id.clone(), settlement.clone());
+                },
+                Err(e) => warn!("Could not list the successor of {}: {}", contract.id, e),
+            }
+        }
+
+        info!(
+            "Settled {} at {}: {} positions{}",
+            contract.id,
+            settlement_price,
+            settlement.positions.len(),
+            settlement.next_contract.as_ref().map(|id| format!(", listed {}", id)).unwrap_or_default()
+        );
+
+        Ok(settlement)
+    }
+
+    /// List the quarterly after the furthest-dated future on the same pair
+    async fn list_next_quarterly(&self, settled: &Contract, expiry: DateTime<Utc>, now: DateTime<Utc>) -> Result<ContractId> {
+        let latest = self.dated_contracts().await
+            .into_iter()
+            .filter(|(c, _)| c.base_asset == settled.base_asset && c.quote_asset == settled.quote_asset)
+            .map(|(_, expiry)| expiry)
+            .fold(expiry, |latest, e| latest.max(e));
+        let next_expiry = next_quarterly_expiry(latest);
+
+        let mut next = settled.clone();
+        next.id = futures_contract_id(&settled.base_asset, next_expiry);
+        next.expiry_time = Some(next_expiry);
+        next.is_active = true;
+        next.created_at = now;
+        next.updated_at = now;
+
+        if self.engine.contract_manager.get_contract(&next.id).await.is_some() {
+            return Err(anyhow!("Contract {} is already listed", next.id));
+        }
+        self.engine.contract_manager.add_contract(next.clone()).await?;
+        self.engine.list_contract(&next).await?;
+        Ok(next.id)
+    }
+
+    /// Sample the index every minute and settle expired futures
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let scheduler = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = time::interval(Duration::from_secs(SETTLEMENT_SAMPLE_INTERVAL_SECS as u64));
+            loop {
+                ticker.tick().await;
+                let now = Utc::now();
+                scheduler.sample_index_prices(now).await;
+                if let Err(e) = scheduler.settle_due(now).await {
+                    warn!("Futures settlement failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::trading_engine::matching_engine::{MatchingEngineManager, Side};
+    use super::super::{CloseReason, ContractManager, FundingRateCalculator, PayoffType, PositionManager, PositionStatus};
+    use super::super::orders::{DerivativeOrderRequest, OrderOrigin};
+    use super::super::price_index::{FileReplayFeed, PriceIndexService, PriceRecord};
+
+    #[test]
+    fn test_next_quarterly_expiry() {
+        let after = Utc.with_ymd_and_hms(2024, 4, 10, 0, 0, 0).unwrap();
+        assert_eq!(next_quarterly_expiry(after), Utc.with_ymd_and_hms(2024, 6, 28, 8, 0, 0).unwrap());
+
+        // At expiry the next quarter is returned
+        let expiry = Utc.with_ymd_and_hms(2024, 6, 28, 8, 0, 0).unwrap();
+        assert_eq!(next_quarterly_expiry(expiry), Utc.with_ymd_and_hms(2024, 9, 27, 8, 0, 0).unwrap());
+
+        let december = Utc.with_ymd_and_hms(2024, 12, 28, 0, 0, 0).unwrap();
+        assert_eq!(next_quarterly_expiry(december), Utc.with_ymd_and_hms(2025, 3, 28, 8, 0, 0).unwrap());
+        assert_eq!(futures_contract_id("BTC", expiry), "BTC-240628");
+    }
+
+    #[tokio::test]
+    async fn test_future_settles_at_index_twap() {
+        let expiry = Utc.with_ymd_and_hms(2024, 6, 28, 8, 0, 0).unwrap();
+        let window_start = expiry - chrono::Duration::minutes(30);
+        let opened_at = window_start - chrono::Duration::hours(1);
+
+        // Index at 50,000 until the window, then rising 10 per minute through it
+        let mut records = vec![PriceRecord { timestamp: opened_at, symbol: "BTC/USDT".to_string(), price: dec!(50000) }];
+        for minute in 0..30 {
+            records.push(PriceRecord {
+                timestamp: window_start + chrono::Duration::minutes(minute),
+                symbol: "BTC/USDT".to_string(),
+                price: dec!(50000) + Decimal::from(minute * 10),
+            });
+        }
+        let price_service = Arc::new(PriceIndexService::default());
+        price_service.add_index_feed(Arc::new(FileReplayFeed::from_records("replay", records)), dec!(1)).await;
+
+        let contract_manager = Arc::new(ContractManager::new());
+        let position_manager = Arc::new(PositionManager::new());
+        let engine = Arc::new(DerivativesEngine::new(
+            contract_manager.clone(),
+            position_manager.clone(),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            price_service.clone(),
+            Arc::new(MatchingEngineManager::new()),
+        ));
+
+        let contract = Contract {
+            id: futures_contract_id("BTC", expiry),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Futures,
+            tick_size: dec!(0.5),
+            lot_size: dec!(0.001),
+            leverage_max: dec!(50),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: Some(expiry),
+            settlement_asset: "USDT".to_string(),
+            option_type: None,
+            strike_price: None,
+            funding_rate_cap: None,
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: opened_at,
+            updated_at: opened_at,
+        };
+        contract_manager.add_contract(contract.clone()).await.unwrap();
+        engine.list_contracts().await.unwrap();
+        price_service.update_at(&contract, opened_at).await.unwrap();
+
+        // A short maker and a long taker at 50,000
+        let maker_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, &contract.id, Side::Sell, dec!(50000), dec!(1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+        let long = engine.open_position(
+            user_id, &contract.id, PositionDirection::Long, dec!(1), dec!(10), MarginType::Isolated,
+        ).await.unwrap();
+
+        // A resting order is cancelled by the pre-expiry halt
+        engine.place_order(DerivativeOrderRequest::limit(
+            maker_id, &contract.id, Side::Sell, dec!(51000), dec!(1), dec!(10), MarginType::Isolated,
+        )).await.unwrap();
+
+        let scheduler = SettlementScheduler::new(engine.clone(), SettlementConfig::default());
+        for minute in 0..30 {
+            let now = window_start + chrono::Duration::minutes(minute);
+            price_service.update_at(&contract, now).await.unwrap();
+            scheduler.sample_index_prices(now).await;
+            scheduler.settle_due(now).await.unwrap();
+        }
+        assert!(engine.is_trading_halted(&contract.id).await);
+        assert!(engine.open_position(
+            user_id, &contract.id, PositionDirection::Long, dec!(1), dec!(10), MarginType::Isolated,
+        ).await.is_err());
+
+        // Liquidation orders still reach the book during the halt
+        let mut close = DerivativeOrderRequest::market(user_id, &contract.id, Side::Sell, dec!(1), dec!(10), MarginType::Isolated);
+        close.reduce_only = true;
+        assert!(engine.place_order(close.clone()).await.is_err());
+        assert!(engine.submit_order(close, OrderOrigin::Liquidation).await.is_ok());
+
+        let settlements = scheduler.settle_due(expiry).await.unwrap();
+        assert_eq!(settlements.len(), 1);
+
+        // Mean of 50,000..50,290 in steps of 10
+        let settlement = &settlements[0];
+        assert_eq!(settlement.settlement_price, dec!(50145));
+        assert_eq!(settlement.positions.len(), 2);
+
+        let settled = position_manager.get_position(&long.id).await.unwrap();
+        assert_eq!(settled.status, PositionStatus::Closed);
+        assert_eq!(settled.close_reason, Some(CloseReason::Settlement));
+        assert_eq!(settled.realized_pnl, dec!(145));
+        assert_eq!(settled.margin_amount, dec!(5145));
+
+        // The expired contract is delisted and the next quarterly listed
+        assert!(!contract_manager.get_contract(&contract.id).await.unwrap().is_active);
+        assert_eq!(settlement.next_contract.as_deref(), Some("BTC-240927"));
+        let next = contract_manager.get_contract("BTC-240927").await.unwrap();
+        assert!(next.is_active);
+        assert_eq!(next.expiry_time, Some(Utc.with_ymd_and_hms(2024, 9, 27, 8, 0, 0).unwrap()));
+
+        // Settling again finds nothing to do
+        assert!(scheduler.settle_due(expiry + chrono::Duration::minutes(1)).await.unwrap().is_empty());
+    }
+}
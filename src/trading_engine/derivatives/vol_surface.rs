@@ -0,0 +1,647 @@
+// src/trading_engine/derivatives/vol_surface.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use super::options::{implied_volatility, option_terms, years_to_expiry};
+use super::price_index::PriceIndexService;
+use super::{Contract, ContractId, ContractType, DerivativesEngine, OptionType};
+
+/// Raw SVI parametrisation of total implied variance against log-moneyness:
+/// `w(k) = a + b(ρ(k - m) + sqrt((k - m)² + σ²))`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SviParams {
+    pub a: f64,
+    pub b: f64,
+    pub rho: f64,
+    pub m: f64,
+    pub sigma: f64,
+}
+
+impl SviParams {
+    pub fn total_variance(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        self.a + self.b * (self.rho * x + (x * x + self.sigma * self.sigma).sqrt())
+    }
+
+    fn first_derivative(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        self.b * (self.rho + x / (x * x + self.sigma * self.sigma).sqrt())
+    }
+
+    fn second_derivative(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        let z = (x * x + self.sigma * self.sigma).sqrt();
+        self.b * self.sigma * self.sigma / (z * z * z)
+    }
+
+    /// Gatheral's butterfly density condition; negative values admit butterfly arbitrage
+    pub fn butterfly_density(&self, k: f64) -> f64 {
+        let w = self.total_variance(k);
+        if w <= 0.0 {
+            return -1.0;
+        }
+        let w1 = self.first_derivative(k);
+        let w2 = self.second_derivative(k);
+        (1.0 - k * w1 / (2.0 * w)).powi(2) - w1 * w1 / 4.0 * (1.0 / w + 0.25) + w2 / 2.0
+    }
+}
+
+/// Solve a 3x3 linear system by Cramer's rule
+fn solve3(m: [[f64; 3]; 3], v: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(m);
+    if d.abs() < 1e-14 {
+        return None;
+    }
+    let mut solution = [0.0; 3];
+    for (i, value) in solution.iter_mut().enumerate() {
+        let mut replaced = m;
+        for row in 0..3 {
+            replaced[row][i] = v[row];
+        }
+        *value = det(replaced) / d;
+    }
+    Some(solution)
+}
+
+/// For fixed `m` and `sigma` SVI is linear in (a, bρ, b); fit those by least squares
+fn fit_linear(points: &[(f64, f64)], m: f64, sigma: f64) -> Option<(SviParams, f64)> {
+    let mut normal = [[0.0; 3]; 3];
+    let mut rhs = [0.0; 3];
+    for &(k, w) in points {
+        let x = k - m;
+        let features = [1.0, x, (x * x + sigma * sigma).sqrt()];
+        for i in 0..3 {
+            for j in 0..3 {
+                normal[i][j] += features[i] * features[j];
+            }
+            rhs[i] += features[i] * w;
+        }
+    }
+
+    let [a, d, b] = solve3(normal, rhs)?;
+    if b <= 0.0 || d.abs() >= b {
+        return None;
+    }
+    let params = SviParams { a, b, rho: d / b, m, sigma };
+
+    // Minimum total variance must not be negative
+    if a + b * sigma * (1.0 - params.rho * params.rho).sqrt() < 0.0 {
+        return None;
+    }
+
+    let sse = points.iter()
+        .map(|&(k, w)| (params.total_variance(k) - w).powi(2))
+        .sum();
+    Some((params, sse))
+}
+
+fn grid_search(points: &[(f64, f64)], m_range: (f64, f64), sigma_range: (f64, f64), steps: usize) -> Option<(SviParams, f64)> {
+    let mut best: Option<(SviParams, f64)> = None;
+    for i in 0..=steps {
+        let m = m_range.0 + (m_range.1 - m_range.0) * i as f64 / steps as f64;
+        for j in 0..=steps {
+            // Geometric steps in sigma
+            let sigma = sigma_range.0 * (sigma_range.1 / sigma_range.0).powf(j as f64 / steps as f64);
+            if let Some(candidate) = fit_linear(points, m, sigma) {
+                if best.as_ref().map_or(true, |(_, sse)| candidate.1 < *sse) {
+                    best = Some(candidate);
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Fit SVI to (log-moneyness, total variance) points, returning the parameters and RMSE
+pub fn fit_svi(points: &[(f64, f64)]) -> Option<(SviParams, f64)> {
+    if points.len() < 3 {
+        return None;
+    }
+    let k_min = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let k_max = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let padding = 0.25 * (k_max - k_min).max(0.1);
+
+    const STEPS: usize = 40;
+    let m_range = (k_min - padding, k_max + padding);
+    let sigma_range = (0.005, 2.0);
+    let (coarse, _) = grid_search(points, m_range, sigma_range, STEPS)?;
+
+    // Refine around the best coarse node
+    let m_step = (m_range.1 - m_range.0) / STEPS as f64;
+    let sigma_ratio = (sigma_range.1 / sigma_range.0).powf(1.0 / STEPS as f64);
+    let (params, sse) = grid_search(
+        points,
+        (coarse.m - m_step, coarse.m + m_step),
+        (coarse.sigma / sigma_ratio, coarse.sigma * sigma_ratio),
+        STEPS,
+    )?;
+
+    Some((params, (sse / points.len() as f64).sqrt()))
+}
+
+/// Implied volatility observed from one option book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmilePoint {
+    pub contract_id: ContractId,
+    pub option_type: OptionType,
+    pub strike: f64,
+    pub log_moneyness: f64,
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub implied_volatility: f64,
+}
+
+/// Fitted smile for one expiry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmileSlice {
+    pub expiry: DateTime<Utc>,
+    pub time_to_expiry: f64,
+    pub forward: f64,
+    pub params: SviParams,
+    /// Root-mean-square error of the fit in total variance
+    pub rmse: f64,
+    pub points: Vec<SmilePoint>,
+}
+
+impl SmileSlice {
+    fn log_moneyness(&self, strike: f64) -> f64 {
+        (strike / self.forward).ln()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArbitrageKind {
+    /// Negative implied density within an expiry
+    Butterfly,
+    /// Total variance decreasing with expiry
+    Calendar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArbitrageViolation {
+    pub kind: ArbitrageKind,
+    pub expiry: DateTime<Utc>,
+    pub log_moneyness: f64,
+    pub value: f64,
+}
+
+/// Implied volatility surface for one underlying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolSurface {
+    /// Index symbol, e.g. `BTC/USDT`
+    pub underlying: String,
+    pub index_price: Decimal,
+    pub rate: f64,
+    pub built_at: DateTime<Utc>,
+    /// Slices ordered by expiry
+    pub slices: Vec<SmileSlice>,
+    pub violations: Vec<ArbitrageViolation>,
+}
+
+impl VolSurface {
+    pub fn is_arbitrage_free(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Implied volatility at any strike and expiry, interpolating total variance
+    /// linearly in time between fitted expiries
+    pub fn implied_vol(&self, strike: f64, expiry: DateTime<Utc>) -> Option<f64> {
+        let t = years_to_expiry(self.built_at, expiry);
+        if t <= 0.0 || strike <= 0.0 {
+            return None;
+        }
+        let first = self.slices.first()?;
+        let last = self.slices.last()?;
+        let variance_at = |slice: &SmileSlice| slice.params.total_variance(slice.log_moneyness(strike)).max(0.0);
+
+        let total_variance = if t <= first.time_to_expiry {
+            variance_at(first) * t / first.time_to_expiry
+        } else if t >= last.time_to_expiry {
+            variance_at(last) * t / last.time_to_expiry
+        } else {
+            let i = self.slices.iter().position(|s| s.time_to_expiry >= t)?;
+            let (before, after) = (&self.slices[i - 1], &self.slices[i]);
+            let weight = (t - before.time_to_expiry) / (after.time_to_expiry - before.time_to_expiry);
+            variance_at(before) + weight * (variance_at(after) - variance_at(before))
+        };
+
+        Some((total_variance / t).sqrt())
+    }
+
+    fn check_arbitrage(&mut self) {
+        const TOLERANCE: f64 = 1e-9;
+        let grid: Vec<f64> = (-30..=30).map(|i| i as f64 * 0.05).collect();
+        self.violations.clear();
+
+        for slice in &self.slices {
+            for &k in &grid {
+                let density = slice.params.butterfly_density(k);
+                if density < -TOLERANCE {
+                    self.violations.push(ArbitrageViolation {
+                        kind: ArbitrageKind::Butterfly,
+                        expiry: slice.expiry,
+                        log_moneyness: k,
+                        value: density,
+                    });
+                }
+            }
+        }
+
+        for pair in self.slices.windows(2) {
+            for &k in &grid {
+                let decrease = pair[0].params.total_variance(k) - pair[1].params.total_variance(k);
+                if decrease > TOLERANCE {
+                    self.violations.push(ArbitrageViolation {
+                        kind: ArbitrageKind::Calendar,
+                        expiry: pair[1].expiry,
+                        log_moneyness: k,
+                        value: -decrease,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VolSurfaceConfig {
+    /// Books wider than this fraction of their mid are ignored
+    pub max_relative_spread: f64,
+    /// Push surface volatilities into the option pricer for mark pricing
+    pub update_pricer: bool,
+}
+
+impl Default for VolSurfaceConfig {
+    fn default() -> Self {
+        VolSurfaceConfig {
+            max_relative_spread: 0.5,
+            update_pricer: true,
+        }
+    }
+}
+
+/// Builds implied volatility surfaces from option order books
+pub struct VolSurfaceService {
+    engine: Arc<DerivativesEngine>,
+    config: VolSurfaceConfig,
+    surfaces: RwLock<HashMap<String, VolSurface>>,
+}
+
+impl VolSurfaceService {
+    pub fn new(engine: Arc<DerivativesEngine>, config: VolSurfaceConfig) -> Self {
+        VolSurfaceService {
+            engine,
+            config,
+            surfaces: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Latest surface for an underlying such as `BTC/USDT`
+    pub async fn get_surface(&self, underlying: &str) -> Option<VolSurface> {
+        self.surfaces.read().await.get(underlying).cloned()
+    }
+
+    /// Underlyings with active option contracts
+    pub async fn underlyings(&self) -> Vec<String> {
+        let mut underlyings: Vec<String> = self.option_contracts(None).await
+            .iter()
+            .map(PriceIndexService::index_symbol)
+            .collect();
+        underlyings.sort();
+        underlyings.dedup();
+        underlyings
+    }
+
+    async fn option_contracts(&self, underlying: Option<&str>) -> Vec<Contract> {
+        self.engine.contract_manager.list_active_contracts().await
+            .into_iter()
+            .filter(|c| c.contract_type == ContractType::Option)
+            .filter(|c| underlying.map_or(true, |u| PriceIndexService::index_symbol(c) == u))
+            .collect()
+    }
+
+    /// Best bid and ask of a contract's book
+    async fn top_of_book(&self, contract_id: &ContractId) -> Option<(Decimal, Decimal)> {
+        let engine = self.engine.matching_engines.get_engine(contract_id).await.ok()?;
+        let (bids, asks) = engine.get_order_book_snapshot(1).await.ok()?;
+        Some((bids.first()?.0, asks.first()?.0))
+    }
+
+    pub async fn build(&self, underlying: &str) -> Result<VolSurface> {
+        self.build_at(underlying, Utc::now()).await
+    }
+
+    /// Solve implied volatilities from out-of-the-money option mids, fit a smile per expiry and check for arbitrage
+    pub async fn build_at(&self, underlying: &str, now: DateTime<Utc>) -> Result<VolSurface> {
+        let contracts = self.option_contracts(Some(underlying)).await;
+        let first = contracts.first().ok_or_else(|| anyhow!("No options listed on {}", underlying))?;
+        let index_price = self.engine.price_service.index_price(&first.id).await?;
+        let spot = index_price.to_f64().ok_or_else(|| anyhow!("Invalid index price"))?;
+
+        let pricer = self.engine.option_pricer.clone();
+        let mut by_expiry: BTreeMap<DateTime<Utc>, Vec<SmilePoint>> = BTreeMap::new();
+        for contract in &contracts {
+            let (option_type, strike, expiry) = match option_terms(contract) {
+                Ok(terms) => terms,
+                Err(e) => {
+                    warn!("Skipping option {}: {}", contract.id, e);
+                    continue;
+                },
+            };
+            let time_to_expiry = years_to_expiry(now, expiry);
+            if time_to_expiry <= 0.0 {
+                continue;
+            }
+
+            // Out-of-the-money options only; in-the-money books are mostly intrinsic value
+            let forward = spot * (pricer.rate * time_to_expiry).exp();
+            let otm = match option_type {
+                OptionType::Call => strike >= forward,
+                OptionType::Put => strike < forward,
+            };
+            if !otm {
+                continue;
+            }
+
+            let (bid, ask) = match self.top_of_book(&contract.id).await {
+                Some(quote) => quote,
+                None => continue,
+            };
+            let mid = ((bid + ask) / Decimal::TWO).to_f64().unwrap_or(0.0);
+            let spread = (ask - bid).to_f64().unwrap_or(f64::MAX);
+            if mid <= 0.0 || spread / mid > self.config.max_relative_spread {
+                continue;
+            }
+
+            let mut inputs = pricer.inputs(contract, index_price, now).await?;
+            inputs.time_to_expiry = time_to_expiry;
+            let implied = match implied_volatility(pricer.model, &inputs, mid) {
+                Ok(vol) => vol,
+                Err(e) => {
+                    warn!("No implied volatility for {} at {}: {}", contract.id, mid, e);
+                    continue;
+                },
+            };
+
+            by_expiry.entry(expiry).or_default().push(SmilePoint {
+                contract_id: contract.id.clone(),
+                option_type,
+                strike,
+                log_moneyness: (strike / forward).ln(),
+                bid,
+                ask,
+                implied_volatility: implied,
+            });
+        }
+
+        let mut slices = Vec::new();
+        for (expiry, points) in by_expiry {
+            let time_to_expiry = years_to_expiry(now, expiry);
+            let variances: Vec<(f64, f64)> = points.iter()
+                .map(|p| (p.log_moneyness, p.implied_volatility * p.implied_volatility * time_to_expiry))
+                .collect();
+            match fit_svi(&variances) {
+                Some((params, rmse)) => slices.push(SmileSlice {
+                    expiry,
+                    time_to_expiry,
+                    forward: spot * (pricer.rate * time_to_expiry).exp(),
+                    params,
+                    rmse,
+                    points,
+                }),
+                None => warn!("Not enough quotes to fit the {} smile for {}", underlying, expiry),
+            }
+        }
+        if slices.is_empty() {
+            return Err(anyhow!("No option smiles could be fitted for {}", underlying));
+        }
+
+        let mut surface = VolSurface {
+            underlying: underlying.to_string(),
+            index_price,
+            rate: pricer.rate,
+            built_at: now,
+            slices,
+            violations: Vec::new(),
+        };
+        surface.check_arbitrage();
+        if !surface.is_arbitrage_free() {
+            // Options stay marked off the last arbitrage-free surface
+            return Err(anyhow!(
+                "Volatility surface for {} has {} arbitrage violations; not published",
+                underlying, surface.violations.len()
+            ));
+        }
+
+        // Mark every listed option, quoted or not, off the surface
+        if self.config.update_pricer {
+            for contract in &contracts {
+                let (strike, expiry) = match option_terms(contract) {
+                    Ok((_, strike, expiry)) => (strike, expiry),
+                    Err(_) => continue,
+                };
+                if let Some(vol) = surface.implied_vol(strike, expiry) {
+                    pricer.set_volatility(&contract.id, vol).await;
+                }
+            }
+            // Short option margin follows the new volatilities
+            self.engine.refresh_option_margins().await?;
+        }
+
+        info!("Built volatility surface for {} with {} expiries", underlying, surface.slices.len());
+        self.surfaces.write().await.insert(underlying.to_string(), surface.clone());
+        Ok(surface)
+    }
+
+    /// Rebuild every underlying's surface periodically
+    pub fn spawn_builder(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = time::interval(interval);
+            loop {
+                ticker.tick().await;
+                for underlying in service.underlyings().await {
+                    if let Err(e) = service.build(&underlying).await {
+                        warn!("Failed to build volatility surface for {}: {}", underlying, e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::prelude::FromPrimitive;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::trading_engine::matching_engine::{MatchingEngineManager, Side};
+    use super::super::{ContractManager, FundingRateCalculator, MarginType, PayoffType, PositionManager};
+    use super::super::options::{option_price, OptionInputs, PricingModel};
+    use super::super::orders::DerivativeOrderRequest;
+    use super::super::price_index::{FileReplayFeed, PriceRecord};
+
+    const TRUE_SMILE: SviParams = SviParams { a: 0.01, b: 0.1, rho: -0.4, m: 0.05, sigma: 0.2 };
+
+    #[test]
+    fn test_svi_fit_recovers_smile() {
+        let points: Vec<(f64, f64)> = (-8..=8)
+            .map(|i| {
+                let k = i as f64 * 0.05;
+                (k, TRUE_SMILE.total_variance(k))
+            })
+            .collect();
+
+        let (params, rmse) = fit_svi(&points).unwrap();
+        assert!(rmse < 1e-4);
+        for k in [-0.3, 0.0, 0.25] {
+            assert!((params.total_variance(k) - TRUE_SMILE.total_variance(k)).abs() < 1e-4);
+        }
+        assert!(fit_svi(&points[..2]).is_none());
+    }
+
+    #[test]
+    fn test_arbitrage_checks() {
+        let slice = |expiry_days: i64, params: SviParams| SmileSlice {
+            expiry: Utc::now() + chrono::Duration::days(expiry_days),
+            time_to_expiry: expiry_days as f64 / 365.0,
+            forward: 100.0,
+            params,
+            rmse: 0.0,
+            points: Vec::new(),
+        };
+        let mut surface = VolSurface {
+            underlying: "BTC/USDT".to_string(),
+            index_price: dec!(100),
+            rate: 0.0,
+            built_at: Utc::now(),
+            slices: vec![slice(30, TRUE_SMILE), slice(90, SviParams { a: 0.03, ..TRUE_SMILE })],
+            violations: Vec::new(),
+        };
+        surface.check_arbitrage();
+        assert!(surface.is_arbitrage_free());
+
+        // A later expiry with less variance, and a smile steep enough to imply negative density
+        surface.slices.push(slice(180, SviParams { a: 0.0, b: 3.0, rho: 0.9, m: 0.0, sigma: 0.01 }));
+        surface.check_arbitrage();
+        assert!(surface.violations.iter().any(|v| v.kind == ArbitrageKind::Calendar));
+        assert!(surface.violations.iter().any(|v| v.kind == ArbitrageKind::Butterfly));
+
+        // Rechecking starts from a clean slate
+        surface.slices.pop();
+        surface.check_arbitrage();
+        assert!(surface.is_arbitrage_free());
+    }
+
+    #[tokio::test]
+    async fn test_surface_from_option_books_marks_illiquid_strikes() {
+        let now = Utc::now();
+        let expiry = now + chrono::Duration::days(73); // 0.2 years
+
+        let price_service = Arc::new(PriceIndexService::default());
+        price_service.add_index_feed(Arc::new(FileReplayFeed::from_records("replay", vec![
+            PriceRecord { timestamp: now, symbol: "BTC/USDT".to_string(), price: dec!(50000) },
+        ])), dec!(1)).await;
+        let contract_manager = Arc::new(ContractManager::new());
+        let engine = Arc::new(DerivativesEngine::new(
+            contract_manager.clone(),
+            Arc::new(PositionManager::new()),
+            FundingRateCalculator::new(dec!(0.0001), dec!(0.0005), 8),
+            price_service.clone(),
+            Arc::new(MatchingEngineManager::new()),
+        ));
+
+        let option = |option_type: OptionType, strike: i64| Contract {
+            id: format!("BTC-{}-{}", strike, if option_type == OptionType::Call { "C" } else { "P" }),
+            base_asset: "BTC".to_string(),
+            quote_asset: "USDT".to_string(),
+            contract_type: ContractType::Option,
+            tick_size: dec!(0.01),
+            lot_size: dec!(0.01),
+            leverage_max: dec!(1),
+            maintenance_margin_ratio: dec!(0.01),
+            liquidation_fee_ratio: dec!(0.005),
+            maker_fee_rate: dec!(0.0002),
+            taker_fee_rate: dec!(0.0005),
+            expiry_time: Some(expiry),
+            settlement_asset: "USDT".to_string(),
+            option_type: Some(option_type),
+            strike_price: Some(Decimal::from(strike)),
+            funding_rate_cap: None,
+            risk_limit_tiers: Vec::new(),
+            payoff: PayoffType::Linear,
+            is_active: true,
+            created_at: now,
+            updated_at: now,
+        };
+
+        // Quote OTM options around the model price under the true smile
+        let t = years_to_expiry(now, expiry);
+        let maker_id = Uuid::new_v4();
+        let quoted = [
+            (OptionType::Put, 40000), (OptionType::Put, 42500), (OptionType::Put, 45000), (OptionType::Put, 47500),
+            (OptionType::Call, 50000), (OptionType::Call, 52500), (OptionType::Call, 55000),
+            (OptionType::Call, 57500), (OptionType::Call, 60000),
+        ];
+        for (option_type, strike) in quoted {
+            let contract = option(option_type, strike);
+            contract_manager.add_contract(contract.clone()).await.unwrap();
+            engine.list_contract(&contract).await.unwrap();
+            price_service.update_at(&contract, now).await.unwrap();
+
+            let k = (strike as f64 / 50000.0).ln();
+            let vol = (TRUE_SMILE.total_variance(k) / t).sqrt();
+            let price = option_price(PricingModel::BlackScholes, &OptionInputs {
+                option_type, underlying: 50000.0, strike: strike as f64, time_to_expiry: t, volatility: vol, rate: 0.0,
+            });
+            let mid = Decimal::from_f64(price).unwrap().round_dp(2);
+            for (side, quote) in [(Side::Buy, mid - dec!(1)), (Side::Sell, mid + dec!(1))] {
+                engine.place_order(DerivativeOrderRequest::limit(
+                    maker_id, &contract.id, side, quote, dec!(1), dec!(1), MarginType::Isolated,
+                )).await.unwrap();
+            }
+        }
+
+        // An unquoted strike between the listed ones
+        let illiquid = option(OptionType::Call, 53750);
+        contract_manager.add_contract(illiquid.clone()).await.unwrap();
+        engine.list_contract(&illiquid).await.unwrap();
+        price_service.update_at(&illiquid, now).await.unwrap();
+
+        // A malformed listing without a strike is skipped rather than failing the surface
+        contract_manager.add_contract(Contract {
+            id: "BTC-NOSTRIKE-C".to_string(),
+            strike_price: None,
+            ..option(OptionType::Call, 0)
+        }).await.unwrap();
+
+        let service = VolSurfaceService::new(engine.clone(), VolSurfaceConfig::default());
+        assert_eq!(service.underlyings().await, vec!["BTC/USDT".to_string()]);
+        let surface = service.build_at("BTC/USDT", now).await.unwrap();
+        assert_eq!(surface.slices.len(), 1);
+        assert_eq!(surface.slices[0].points.len(), quoted.len());
+        assert!(surface.is_arbitrage_free());
+
+        // The illiquid strike is marked at the smile's volatility
+        let expected = (TRUE_SMILE.total_variance((53750.0f64 / 50000.0).ln()) / t).sqrt();
+        let marked = engine.option_pricer().volatility(&illiquid.id).await;
+        assert!((marked - expected).abs() < 0.005);
+        assert!(service.get_surface("BTC/USDT").await.is_some());
+    }
+}
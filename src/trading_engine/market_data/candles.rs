@@ -0,0 +1,377 @@
+// src/trading_engine/market_data/candles.rs
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+
+/// Candle interval; candles open on multiples of the interval since the Unix epoch, so they align to UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 6] = [
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::FifteenMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::FourHours,
+        CandleInterval::OneDay,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::FifteenMinutes => "15m",
+            CandleInterval::OneHour => "1h",
+            CandleInterval::FourHours => "4h",
+            CandleInterval::OneDay => "1d",
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::FifteenMinutes => 15 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::FourHours => 4 * 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.seconds())
+    }
+
+    /// Open time of the candle containing a timestamp
+    pub fn open_time(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let seconds = timestamp.timestamp();
+        let aligned = seconds - seconds.rem_euclid(self.seconds());
+        Utc.timestamp_opt(aligned, 0).single().unwrap_or(timestamp)
+    }
+}
+
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for CandleInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CandleInterval::ALL.iter()
+            .find(|interval| interval.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("Unsupported candle interval {}; expected one of 1m, 5m, 15m, 1h, 4h, 1d", s))
+    }
+}
+
+/// OHLCV bar for one interval of a symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub symbol: String,
+    pub interval: CandleInterval,
+    pub open_time: DateTime<Utc>,
+    pub close_time: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    /// Traded quantity in the base asset
+    pub volume: Decimal,
+    /// Traded value in the quote asset
+    pub quote_volume: Decimal,
+    pub trade_count: u64,
+    /// Time of the trade that set the close, when built from trades
+    #[serde(skip)]
+    pub last_trade_at: Option<DateTime<Utc>>,
+}
+
+impl Candle {
+    fn new(symbol: &str, interval: CandleInterval, open_time: DateTime<Utc>, price: Decimal, quantity: Decimal, at: DateTime<Utc>) -> Self {
+        let mut candle = Candle::empty(symbol, interval, open_time, price);
+        candle.add_trade(price, quantity, at);
+        candle
+    }
+
+    /// A candle with no trades, flat at the previous close
+    fn empty(symbol: &str, interval: CandleInterval, open_time: DateTime<Utc>, close: Decimal) -> Self {
+        Candle {
+            symbol: symbol.to_string(),
+            interval,
+            open_time,
+            close_time: open_time + interval.duration(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: Decimal::ZERO,
+            quote_volume: Decimal::ZERO,
+            trade_count: 0,
+            last_trade_at: None,
+        }
+    }
+
+    /// Whether no trades fell in the interval
+    pub fn is_empty(&self) -> bool {
+        self.trade_count == 0
+    }
+
+    /// Add a trade; only a trade at or after the one that set the close moves it,
+    /// whatever order they arrive in
+    fn add_trade(&mut self, price: Decimal, quantity: Decimal, at: DateTime<Utc>) {
+        let latest = self.last_trade_at.map_or(true, |last| at >= last);
+        if self.is_empty() {
+            self.open = price;
+            self.high = price;
+            self.low = price;
+            self.close = price;
+        }
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        if latest {
+            self.close = price;
+            self.last_trade_at = Some(at);
+        }
+        self.volume += quantity;
+        self.quote_volume += price * quantity;
+        self.trade_count += 1;
+    }
+}
+
+/// A live change to a candle, pushed on every trade
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandleUpdate {
+    pub candle: Candle,
+    /// Whether a later interval has started, making the candle final
+    pub is_closed: bool,
+}
+
+/// The most recent candles of one symbol and interval, with no gaps between them
+#[derive(Debug, Clone)]
+pub struct CandleSeries {
+    symbol: String,
+    interval: CandleInterval,
+    capacity: usize,
+    candles: VecDeque<Candle>,
+    /// Open time of the latest candle announced as closed
+    closed_through: Option<DateTime<Utc>>,
+}
+
+impl CandleSeries {
+    pub fn new(symbol: &str, interval: CandleInterval, capacity: usize) -> Self {
+        CandleSeries {
+            symbol: symbol.to_string(),
+            interval,
+            capacity: capacity.max(1),
+            candles: VecDeque::new(),
+            closed_through: None,
+        }
+    }
+
+    /// Note that the candle opening at `open_time` has been announced as closed,
+    /// returning false if it already was
+    pub fn mark_closed(&mut self, open_time: DateTime<Utc>) -> bool {
+        if self.closed_through.map_or(false, |closed| closed >= open_time) {
+            return false;
+        }
+        self.closed_through = Some(open_time);
+        true
+    }
+
+    /// The last candle, if its interval has ended by `now` and it has not been announced as closed
+    pub fn close_due(&mut self, now: DateTime<Utc>) -> Option<Candle> {
+        let last = self.candles.back()?.clone();
+        if last.close_time > now || !self.mark_closed(last.open_time) {
+            return None;
+        }
+        Some(last)
+    }
+
+    pub fn interval(&self) -> CandleInterval {
+        self.interval
+    }
+
+    pub fn last(&self) -> Option<&Candle> {
+        self.candles.back()
+    }
+
+    /// The retained candle opening at `open_time`
+    pub fn get(&self, open_time: DateTime<Utc>) -> Option<&Candle> {
+        let last_open = self.candles.back()?.open_time;
+        if open_time > last_open {
+            return None;
+        }
+        let back = ((last_open - open_time).num_seconds() / self.interval.seconds()) as usize;
+        self.candles.len().checked_sub(back + 1).map(|index| &self.candles[index])
+    }
+
+    /// Add a trade. Intervals skipped since the last candle are filled with empty candles;
+    /// a trade older than the retained candles is dropped, returning false.
+    pub fn record(&mut self, price: Decimal, quantity: Decimal, at: DateTime<Utc>) -> bool {
+        let open_time = self.interval.open_time(at);
+        let last_open = match self.candles.back() {
+            Some(last) => last.open_time,
+            None => {
+                self.candles.push_back(Candle::new(&self.symbol, self.interval, open_time, price, quantity, at));
+                return true;
+            }
+        };
+
+        if open_time > last_open {
+            self.fill_to(open_time);
+            self.candles.push_back(Candle::new(&self.symbol, self.interval, open_time, price, quantity, at));
+            self.trim();
+            return true;
+        }
+
+        let back = ((last_open - open_time).num_seconds() / self.interval.seconds()) as usize;
+        if back >= self.candles.len() {
+            return false;
+        }
+        let index = self.candles.len() - 1 - back;
+        self.candles[index].add_trade(price, quantity, at);
+        true
+    }
+
+    /// Candles opening in `[start, end]`, the most recent `limit` of them in time order.
+    /// Intervals up to `now` with no trades yet are returned flat at the last close.
+    pub fn range(
+        &self,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: usize,
+        now: DateTime<Utc>,
+    ) -> Vec<Candle> {
+        let mut candles: Vec<Candle> = self.candles.iter().cloned().collect();
+        if let Some(last) = self.candles.back() {
+            let current = self.interval.open_time(now);
+            let earliest = current - self.interval.duration() * (self.capacity as i32);
+            let mut open_time = (last.open_time + self.interval.duration()).max(earliest);
+            while open_time <= current {
+                candles.push(Candle::empty(&self.symbol, self.interval, open_time, last.close));
+                open_time = open_time + self.interval.duration();
+            }
+        }
+
+        let selected: Vec<Candle> = candles.into_iter()
+            .filter(|c| start.map_or(true, |start| c.open_time >= start))
+            .filter(|c| end.map_or(true, |end| c.open_time <= end))
+            .collect();
+        let skip = selected.len().saturating_sub(limit);
+        selected.into_iter().skip(skip).collect()
+    }
+
+    fn fill_to(&mut self, open_time: DateTime<Utc>) {
+        let (mut next, close) = match self.candles.back() {
+            Some(last) => (last.open_time + self.interval.duration(), last.close),
+            None => return,
+        };
+        // Only the gap candles that will be retained are worth creating
+        let earliest = open_time - self.interval.duration() * (self.capacity as i32);
+        if next < earliest {
+            self.candles.clear();
+            next = earliest;
+        }
+        while next < open_time {
+            self.candles.push_back(Candle::empty(&self.symbol, self.interval, next, close));
+            next = next + self.interval.duration();
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.candles.len() > self.capacity {
+            self.candles.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn test_intervals_align_to_utc() {
+        assert_eq!(CandleInterval::FifteenMinutes.open_time(at(10, 44, 59)), at(10, 30, 0));
+        assert_eq!(CandleInterval::FourHours.open_time(at(10, 44, 59)), at(8, 0, 0));
+        assert_eq!(CandleInterval::OneDay.open_time(at(23, 59, 59)), at(0, 0, 0));
+        assert_eq!("4h".parse::<CandleInterval>().unwrap(), CandleInterval::FourHours);
+        assert!("2m".parse::<CandleInterval>().is_err());
+    }
+
+    #[test]
+    fn test_series_fills_empty_intervals() {
+        let mut series = CandleSeries::new("BTC/USDT", CandleInterval::OneMinute, 10);
+        series.record(dec!(100), dec!(1), at(10, 0, 5));
+        series.record(dec!(105), dec!(2), at(10, 0, 30));
+        series.record(dec!(95), dec!(1), at(10, 0, 50));
+        series.record(dec!(110), dec!(1), at(10, 3, 10));
+
+        let candles = series.range(None, None, 100, at(10, 4, 0));
+        assert_eq!(candles.len(), 5);
+        let first = &candles[0];
+        assert_eq!((first.open, first.high, first.low, first.close), (dec!(100), dec!(105), dec!(95), dec!(95)));
+        assert_eq!(first.volume, dec!(4));
+        assert_eq!(first.quote_volume, dec!(405));
+
+        // Minutes without trades are flat at the previous close
+        assert!(candles[1].is_empty() && candles[2].is_empty());
+        assert_eq!(candles[2].open, dec!(95));
+        assert_eq!(candles[3].close, dec!(110));
+        assert_eq!(candles[4].open_time, at(10, 4, 0));
+        assert_eq!(candles[4].close, dec!(110));
+
+        // A late trade lands in its own interval without moving the later close
+        assert!(series.record(dec!(90), dec!(1), at(10, 1, 0)));
+        let candles = series.range(Some(at(10, 1, 0)), Some(at(10, 2, 0)), 100, at(10, 4, 0));
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].close, dec!(90));
+        assert_eq!(candles[0].trade_count, 1);
+        assert_eq!(series.range(None, None, 1, at(10, 3, 0))[0].close, dec!(110));
+
+        // A trade arriving late within the current interval leaves the close to the later one
+        assert!(series.record(dec!(120), dec!(1), at(10, 3, 5)));
+        assert_eq!(series.last().unwrap().close, dec!(110));
+        assert_eq!(series.last().unwrap().high, dec!(120));
+
+        // Trades older than the retained window are dropped
+        assert!(!series.record(dec!(90), dec!(1), at(9, 0, 0)));
+    }
+
+    #[test]
+    fn test_close_due_once_interval_ends() {
+        let mut series = CandleSeries::new("BTC/USDT", CandleInterval::OneMinute, 10);
+        series.record(dec!(100), dec!(1), at(10, 0, 5));
+        assert!(series.close_due(at(10, 0, 59)).is_none());
+
+        let closed = series.close_due(at(10, 1, 0)).unwrap();
+        assert_eq!((closed.open_time, closed.close), (at(10, 0, 0), dec!(100)));
+        // Announced once, whether by the timer or the next trade
+        assert!(series.close_due(at(10, 2, 0)).is_none());
+        assert!(!series.mark_closed(at(10, 0, 0)));
+    }
+}
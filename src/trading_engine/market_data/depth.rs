@@ -0,0 +1,234 @@
+// src/trading_engine/market_data/depth.rs
+
+//! Incremental order book depth feed.
+//!
+//! Every change to a price level gets the next update id of its book. Clients
+//! take a [`DepthSnapshot`] (from the REST `/orderbook` endpoint or on
+//! subscribing), drop any [`DepthUpdate`] whose `last_update_id` is not above
+//! the snapshot's, and then expect each update's `first_update_id` to be one
+//! past the previous update's `last_update_id`; anything else is a gap and the
+//! client resyncs from a fresh snapshot.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use crossbeam::queue::SegQueue;
+use parking_lot::RwLock as PLRwLock;
+use serde::{Serialize, Deserialize};
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::models::{Price, Quantity, Side, Symbol};
+use crate::trading_engine::order_book::{LevelUpdate, OrderBook};
+
+/// Parameters of the depth feed
+#[derive(Debug, Clone)]
+pub struct DepthFeedConfig {
+    /// How often pending level changes are published
+    pub publish_interval: Duration,
+    /// Most levels per side in a snapshot
+    pub max_snapshot_levels: usize,
+    /// Updates buffered for each subscriber before it lags
+    pub channel_capacity: usize,
+}
+
+impl Default for DepthFeedConfig {
+    fn default() -> Self {
+        DepthFeedConfig {
+            publish_interval: Duration::from_millis(100),
+            max_snapshot_levels: 1000,
+            channel_capacity: 1024,
+        }
+    }
+}
+
+/// Levels of a book as of an update id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub symbol: Symbol,
+    pub last_update_id: u64,
+    /// (price, quantity), best first
+    pub bids: Vec<(Price, Quantity)>,
+    pub asks: Vec<(Price, Quantity)>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Level changes with update ids `first_update_id..=last_update_id`, as the new
+/// aggregate quantity per price; zero removes the level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepthUpdate {
+    pub symbol: Symbol,
+    pub first_update_id: u64,
+    pub last_update_id: u64,
+    pub bids: Vec<(Price, Quantity)>,
+    pub asks: Vec<(Price, Quantity)>,
+    pub timestamp: DateTime<Utc>,
+}
+
+struct TrackedBook {
+    book: Arc<PLRwLock<OrderBook>>,
+    level_updates: Arc<SegQueue<LevelUpdate>>,
+}
+
+/// Publishes snapshot-plus-diff depth for registered order books
+pub struct DepthFeed {
+    config: DepthFeedConfig,
+    books: RwLock<HashMap<Symbol, TrackedBook>>,
+    updates: broadcast::Sender<DepthUpdate>,
+}
+
+impl DepthFeed {
+    pub fn new(config: DepthFeedConfig) -> Self {
+        let (updates, _) = broadcast::channel(config.channel_capacity.max(1));
+        DepthFeed {
+            config,
+            books: RwLock::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// Start publishing a book's level changes
+    pub async fn register_book(&self, symbol: Symbol, book: Arc<PLRwLock<OrderBook>>) {
+        let level_updates = book.write().subscribe_depth();
+        self.books.write().await.insert(symbol, TrackedBook { book, level_updates });
+    }
+
+    pub async fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.books.read().await.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Receive every published update, for all symbols
+    pub fn subscribe(&self) -> broadcast::Receiver<DepthUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Current levels of a book, up to `levels` per side
+    pub async fn snapshot(&self, symbol: &str, levels: usize) -> Option<DepthSnapshot> {
+        let books = self.books.read().await;
+        let tracked = books.get(symbol)?;
+        let snapshot = tracked.book.read().get_snapshot(levels.min(self.config.max_snapshot_levels));
+        Some(DepthSnapshot {
+            symbol: snapshot.symbol,
+            last_update_id: snapshot.last_update_id,
+            bids: snapshot.bids,
+            asks: snapshot.asks,
+            timestamp: Utc::now(),
+        })
+    }
+
+    /// Drain each book's pending level changes into one update per book and publish them
+    pub async fn publish_pending(&self) -> Vec<DepthUpdate> {
+        let books = self.books.read().await;
+        let mut published = Vec::new();
+        for (symbol, tracked) in books.iter() {
+            if let Some(update) = Self::collect(symbol, &tracked.level_updates) {
+                // No subscribers is not an error
+                let _ = self.updates.send(update.clone());
+                published.push(update);
+            }
+        }
+        published
+    }
+
+    /// Publish on the configured interval in the background
+    pub fn spawn_publisher(self: &Arc<Self>) -> JoinHandle<()> {
+        let feed = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = time::interval(feed.config.publish_interval);
+            loop {
+                interval.tick().await;
+                feed.publish_pending().await;
+            }
+        })
+    }
+
+    /// Coalesce queued changes, keeping the latest quantity per level
+    fn collect(symbol: &str, level_updates: &SegQueue<LevelUpdate>) -> Option<DepthUpdate> {
+        let first = level_updates.pop()?;
+        let mut last_update_id = first.update_id;
+        let mut bids = BTreeMap::new();
+        let mut asks = BTreeMap::new();
+
+        let mut next = Some(first);
+        while let Some(update) = next {
+            last_update_id = update.update_id;
+            match update.side {
+                Side::Buy => bids.insert(update.price, update.quantity),
+                Side::Sell => asks.insert(update.price, update.quantity),
+            };
+            next = level_updates.pop();
+        }
+
+        Some(DepthUpdate {
+            symbol: symbol.to_string(),
+            first_update_id: first.update_id,
+            last_update_id,
+            bids: bids.into_iter().rev().collect(),
+            asks: asks.into_iter().collect(),
+            timestamp: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::models::{Order, OrderType, TimeInForce};
+
+    fn limit(side: Side, price: Decimal, quantity: Decimal) -> Order {
+        Order::new(
+            Uuid::new_v4(),
+            "BTC/USD".to_string(),
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            TimeInForce::GoodTillCancel,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_updates_continue_from_snapshot() {
+        let book = Arc::new(PLRwLock::new(OrderBook::new("BTC/USD".to_string())));
+        let feed = DepthFeed::new(DepthFeedConfig::default());
+        feed.register_book("BTC/USD".to_string(), book.clone()).await;
+        let mut subscriber = feed.subscribe();
+
+        let resting = limit(Side::Buy, dec!(100), dec!(1));
+        book.write().add_order(Arc::new(PLRwLock::new(resting.clone()))).unwrap();
+        book.write().add_order(Arc::new(PLRwLock::new(limit(Side::Buy, dec!(100), dec!(2))))).unwrap();
+        book.write().add_order(Arc::new(PLRwLock::new(limit(Side::Sell, dec!(101), dec!(1))))).unwrap();
+
+        let snapshot = feed.snapshot("BTC/USD", 10).await.unwrap();
+        assert_eq!(snapshot.last_update_id, 3);
+        assert_eq!(snapshot.bids, vec![(dec!(100), dec!(3))]);
+
+        // Changes already in the snapshot are published too; clients skip them by id
+        let first = feed.publish_pending().await.remove(0);
+        assert_eq!((first.first_update_id, first.last_update_id), (1, 3));
+        assert_eq!(first.bids, vec![(dec!(100), dec!(3))]);
+
+        book.write().remove_order(&resting.id);
+        book.write().add_order(Arc::new(PLRwLock::new(limit(Side::Sell, dec!(102), dec!(4))))).unwrap();
+        book.write().clear();
+
+        let second = feed.publish_pending().await.remove(0);
+        assert_eq!(second.first_update_id, first.last_update_id + 1);
+        assert_eq!(second.last_update_id, 8);
+        // Coalesced to the latest quantity per level: everything was cleared
+        assert_eq!(second.bids, vec![(dec!(100), Decimal::ZERO)]);
+        assert_eq!(second.asks, vec![(dec!(101), Decimal::ZERO), (dec!(102), Decimal::ZERO)]);
+
+        assert_eq!(subscriber.recv().await.unwrap().last_update_id, 3);
+        assert_eq!(subscriber.recv().await.unwrap().last_update_id, 8);
+        assert!(feed.publish_pending().await.is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+// src/trading_engine/market_data/history.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use chrono::{DateTime, Duration, Utc};
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::db::repositories::candle_repository::CandleRepositoryTrait;
+use super::candles::{Candle, CandleInterval};
+
+/// How long stored candles are kept before being rolled into coarser intervals
+#[derive(Debug, Clone)]
+pub struct CompactionConfig {
+    /// Retention per interval; intervals not listed are kept forever
+    pub retention: Vec<(CandleInterval, Duration)>,
+    /// How often the background job runs
+    pub run_interval: StdDuration,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        CompactionConfig {
+            retention: vec![
+                (CandleInterval::OneMinute, Duration::days(7)),
+                (CandleInterval::FiveMinutes, Duration::days(30)),
+                (CandleInterval::FifteenMinutes, Duration::days(90)),
+                (CandleInterval::OneHour, Duration::days(730)),
+            ],
+            run_interval: StdDuration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Merge candles into candles of a coarser interval; empty candles contribute nothing
+pub fn rollup(candles: &[Candle], interval: CandleInterval) -> Vec<Candle> {
+    let mut traded: Vec<&Candle> = candles.iter().filter(|c| !c.is_empty()).collect();
+    traded.sort_by_key(|c| c.open_time);
+
+    let mut rolled: BTreeMap<DateTime<Utc>, Candle> = BTreeMap::new();
+    for candle in traded {
+        let open_time = interval.open_time(candle.open_time);
+        match rolled.get_mut(&open_time) {
+            Some(coarse) => {
+                coarse.high = coarse.high.max(candle.high);
+                coarse.low = coarse.low.min(candle.low);
+                coarse.close = candle.close;
+                coarse.volume += candle.volume;
+                coarse.quote_volume += candle.quote_volume;
+                coarse.trade_count += candle.trade_count;
+            }
+            None => {
+                rolled.insert(open_time, Candle {
+                    interval,
+                    open_time,
+                    close_time: open_time + interval.duration(),
+                    ..candle.clone()
+                });
+            }
+        }
+    }
+    rolled.into_values().collect()
+}
+
+/// Rolls stored candles past their retention into coarser intervals and deletes them
+pub struct CandleCompactor {
+    config: CompactionConfig,
+    store: Arc<dyn CandleRepositoryTrait>,
+}
+
+impl CandleCompactor {
+    pub fn new(config: CompactionConfig, store: Arc<dyn CandleRepositoryTrait>) -> Self {
+        CandleCompactor { config, store }
+    }
+
+    /// Compact one symbol as of `now`, returning the number of candles deleted
+    pub async fn compact_symbol(&self, symbol: &str, now: DateTime<Utc>) -> Result<u64> {
+        let mut deleted = 0;
+        for &(interval, retention) in &self.config.retention {
+            // Whole UTC days, so each coarser candle is rolled from a complete day
+            let cutoff = CandleInterval::OneDay.open_time(now - retention);
+            let first = self.store.first_open_time(symbol, interval).await
+                .map_err(|e| anyhow!("Failed to read {} {} candles: {}", symbol, interval, e))?;
+            let mut day = match first {
+                Some(first) if first < cutoff => CandleInterval::OneDay.open_time(first),
+                _ => continue,
+            };
+
+            let per_day = CandleInterval::OneDay.seconds() / interval.seconds();
+            while day < cutoff {
+                let day_end = day + Duration::days(1);
+                let candles = self.store.find_range(symbol, interval, Some(day), Some(day_end - interval.duration()), per_day).await
+                    .map_err(|e| anyhow!("Failed to read {} {} candles: {}", symbol, interval, e))?;
+                for coarser in CandleInterval::ALL.iter().filter(|c| c.seconds() > interval.seconds()) {
+                    self.store_rollup(symbol, &candles, *coarser, day, day_end).await?;
+                }
+                day = day_end;
+            }
+
+            deleted += self.store.delete_before(symbol, interval, cutoff).await
+                .map_err(|e| anyhow!("Failed to delete {} {} candles: {}", symbol, interval, e))?;
+        }
+        Ok(deleted)
+    }
+
+    /// Compact every stored symbol; a failing symbol is logged and skipped
+    pub async fn compact_all(&self, now: DateTime<Utc>) -> Result<u64> {
+        let symbols = self.store.find_symbols().await
+            .map_err(|e| anyhow!("Failed to list candle symbols: {}", e))?;
+        let mut deleted = 0;
+        for symbol in symbols {
+            match self.compact_symbol(&symbol, now).await {
+                Ok(count) => deleted += count,
+                Err(e) => warn!("Candle compaction failed for {}: {}", symbol, e),
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Compact on the configured interval in the background
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.config.run_interval);
+            loop {
+                interval.tick().await;
+                match self.compact_all(Utc::now()).await {
+                    Ok(deleted) => info!("Candle compaction removed {} rolled-up candles", deleted),
+                    Err(e) => warn!("Candle compaction failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Store rolled candles, never replacing a stored candle built from more trades
+    async fn store_rollup(
+        &self,
+        symbol: &str,
+        candles: &[Candle],
+        interval: CandleInterval,
+        day: DateTime<Utc>,
+        day_end: DateTime<Utc>,
+    ) -> Result<()> {
+        let rolled = rollup(candles, interval);
+        if rolled.is_empty() {
+            return Ok(());
+        }
+
+        let per_day = CandleInterval::OneDay.seconds() / interval.seconds();
+        let stored: HashMap<DateTime<Utc>, u64> = self.store
+            .find_range(symbol, interval, Some(day), Some(day_end - interval.duration()), per_day).await
+            .map_err(|e| anyhow!("Failed to read {} {} candles: {}", symbol, interval, e))?
+            .into_iter()
+            .map(|c| (c.open_time, c.trade_count))
+            .collect();
+        let rolled: Vec<Candle> = rolled.into_iter()
+            .filter(|c| stored.get(&c.open_time).map_or(true, |&count| count <= c.trade_count))
+            .collect();
+
+        self.store.upsert(&rolled).await
+            .map_err(|e| anyhow!("Failed to store {} {} candles: {}", symbol, interval, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use sqlx::Error as SqlxError;
+    use tokio::sync::Mutex;
+
+    #[derive(Default)]
+    struct StoredCandles(Mutex<HashMap<(CandleInterval, DateTime<Utc>), Candle>>);
+
+    #[async_trait]
+    impl CandleRepositoryTrait for StoredCandles {
+        async fn upsert(&self, candles: &[Candle]) -> Result<(), SqlxError> {
+            let mut stored = self.0.lock().await;
+            for candle in candles {
+                stored.insert((candle.interval, candle.open_time), candle.clone());
+            }
+            Ok(())
+        }
+
+        async fn find_range(
+            &self,
+            _symbol: &str,
+            interval: CandleInterval,
+            start: Option<DateTime<Utc>>,
+            end: Option<DateTime<Utc>>,
+            limit: i64,
+        ) -> Result<Vec<Candle>, SqlxError> {
+            let stored = self.0.lock().await;
+            let mut candles: Vec<Candle> = stored.values()
+                .filter(|c| c.interval == interval)
+                .filter(|c| start.map_or(true, |start| c.open_time >= start))
+                .filter(|c| end.map_or(true, |end| c.open_time <= end))
+                .cloned()
+                .collect();
+            candles.sort_by_key(|c| c.open_time);
+            let skip = candles.len().saturating_sub(limit as usize);
+            Ok(candles.split_off(skip))
+        }
+
+        async fn first_open_time(&self, _symbol: &str, interval: CandleInterval) -> Result<Option<DateTime<Utc>>, SqlxError> {
+            Ok(self.0.lock().await.keys().filter(|(i, _)| *i == interval).map(|(_, t)| *t).min())
+        }
+
+        async fn find_symbols(&self) -> Result<Vec<String>, SqlxError> {
+            Ok(vec!["BTC/USDT".to_string()])
+        }
+
+        async fn delete_before(&self, _symbol: &str, interval: CandleInterval, before: DateTime<Utc>) -> Result<u64, SqlxError> {
+            let mut stored = self.0.lock().await;
+            let count = stored.len();
+            stored.retain(|(i, t), _| *i != interval || *t >= before);
+            Ok((count - stored.len()) as u64)
+        }
+    }
+
+    fn minute(open_time: DateTime<Utc>, open: Decimal, close: Decimal, volume: Decimal) -> Candle {
+        Candle {
+            symbol: "BTC/USDT".to_string(),
+            interval: CandleInterval::OneMinute,
+            open_time,
+            close_time: open_time + Duration::minutes(1),
+            open,
+            high: open.max(close),
+            low: open.min(close),
+            close,
+            volume,
+            quote_volume: close * volume,
+            trade_count: 1,
+            last_trade_at: None,
+        }
+    }
+
+    #[test]
+    fn test_rollup_merges_into_coarser_candles() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap();
+        let mut empty = minute(start + Duration::minutes(2), dec!(90), dec!(90), Decimal::ZERO);
+        empty.trade_count = 0;
+        let candles = vec![
+            minute(start + Duration::minutes(3), dec!(105), dec!(95), dec!(1)),
+            minute(start, dec!(100), dec!(104), dec!(2)),
+            empty,
+            minute(start + Duration::minutes(16), dec!(95), dec!(110), dec!(1)),
+        ];
+
+        let rolled = rollup(&candles, CandleInterval::FifteenMinutes);
+        assert_eq!(rolled.len(), 2);
+        let first = &rolled[0];
+        assert_eq!((first.open_time, first.close_time), (start, start + Duration::minutes(15)));
+        // The empty minute's flat price does not become the low
+        assert_eq!((first.open, first.high, first.low, first.close), (dec!(100), dec!(105), dec!(95), dec!(95)));
+        assert_eq!((first.volume, first.trade_count), (dec!(3), 2));
+        assert_eq!(rolled[1].open, dec!(95));
+
+        assert_eq!(rollup(&candles, CandleInterval::OneDay).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_rolls_expired_minutes_up_and_deletes_them() {
+        let store = Arc::new(StoredCandles::default());
+        let old_day = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let recent = Utc.with_ymd_and_hms(2024, 3, 20, 12, 0, 0).unwrap();
+        store.upsert(&[
+            minute(old_day + Duration::minutes(61), dec!(100), dec!(101), dec!(1)),
+            minute(old_day + Duration::minutes(62), dec!(101), dec!(99), dec!(2)),
+            minute(recent, dec!(120), dec!(121), dec!(1)),
+        ]).await.unwrap();
+
+        let config = CompactionConfig {
+            retention: vec![(CandleInterval::OneMinute, Duration::days(7))],
+            ..CompactionConfig::default()
+        };
+        let compactor = CandleCompactor::new(config, store.clone());
+        assert_eq!(compactor.compact_all(recent + Duration::hours(1)).await.unwrap(), 2);
+
+        let minutes = store.find_range("BTC/USDT", CandleInterval::OneMinute, None, None, 100).await.unwrap();
+        assert_eq!(minutes.len(), 1);
+        let hours = store.find_range("BTC/USDT", CandleInterval::OneHour, None, None, 100).await.unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].open_time, old_day + Duration::hours(1));
+        assert_eq!((hours[0].open, hours[0].close, hours[0].volume), (dec!(100), dec!(99), dec!(3)));
+        let days = store.find_range("BTC/USDT", CandleInterval::OneDay, None, None, 100).await.unwrap();
+        assert_eq!(days[0].trade_count, 2);
+
+        // A second run has nothing left to do
+        assert_eq!(compactor.compact_all(recent + Duration::hours(2)).await.unwrap(), 0);
+    }
+}
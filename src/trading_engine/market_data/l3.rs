@@ -0,0 +1,441 @@
+// src/trading_engine/market_data/l3.rs
+
+//! Level-3 (order-by-order) market data feed.
+//!
+//! Each event on a book gets the next sequence number of that book. A client
+//! takes an [`L3Snapshot`], drops any [`L3Update`] events numbered at or below
+//! the snapshot's `sequence`, and applies the rest in order; an update whose
+//! `first_sequence` is not one past the last one seen is a gap and the client
+//! resyncs from a fresh snapshot. Orders are listed in queue order, so the
+//! position of any order at its level can be reconstructed exactly.
+//!
+//! Owners appear as random stand-ins drawn per symbol and renewed every
+//! `owner_rotation`, so a user's orders can be grouped within one market for a
+//! while but not linked across markets or over long periods.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use crossbeam::queue::SegQueue;
+use parking_lot::RwLock as PLRwLock;
+use serde::{Serialize, Deserialize};
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time;
+use uuid::Uuid;
+
+use crate::models::{OrderId, Price, Quantity, Side, Symbol, TradeId, UserId};
+use crate::trading_engine::order_book::{OrderBook, OrderBookEvent, TimedEvent};
+
+/// Parameters of the L3 feed
+#[derive(Debug, Clone)]
+pub struct L3FeedConfig {
+    /// How often pending book events are published
+    pub publish_interval: Duration,
+    /// Updates buffered for each subscriber before it lags
+    pub channel_capacity: usize,
+    /// How long a user keeps the same owner stand-in within a symbol
+    pub owner_rotation: Duration,
+}
+
+impl Default for L3FeedConfig {
+    fn default() -> Self {
+        L3FeedConfig {
+            publish_interval: Duration::from_millis(100),
+            channel_capacity: 1024,
+            owner_rotation: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// A change to one resting order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum L3Event {
+    /// An order joined the back of the queue at its price
+    Add {
+        order_id: OrderId,
+        owner: Uuid,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    },
+    /// An order was reduced in place, keeping its queue position
+    Modify {
+        order_id: OrderId,
+        quantity: Quantity,
+    },
+    /// An order left the book, cancelled or fully filled
+    Delete {
+        order_id: OrderId,
+    },
+    /// A resting order traded; a fully filled order is deleted right after
+    Execute {
+        order_id: OrderId,
+        trade_id: TradeId,
+        price: Price,
+        quantity: Quantity,
+        remaining: Quantity,
+    },
+}
+
+/// Consecutive events of a book; `events[i]` has sequence `first_sequence + i`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct L3Update {
+    pub symbol: Symbol,
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    pub events: Vec<L3Event>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A resting order as seen by L3 clients
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct L3Order {
+    pub order_id: OrderId,
+    pub owner: Uuid,
+    pub quantity: Quantity,
+}
+
+/// The orders resting at one price, front of the queue first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct L3Level {
+    pub price: Price,
+    pub orders: Vec<L3Order>,
+}
+
+/// Every resting order of a book as of a sequence number
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct L3Snapshot {
+    pub symbol: Symbol,
+    pub sequence: u64,
+    /// Best price first
+    pub bids: Vec<L3Level>,
+    pub asks: Vec<L3Level>,
+    pub timestamp: DateTime<Utc>,
+}
+
+struct RestingOrder {
+    owner: Uuid,
+    side: Side,
+    price: Price,
+    remaining: Quantity,
+}
+
+/// The feed's copy of a book, in step with the events published so far
+struct BookMirror {
+    events: Arc<SegQueue<TimedEvent>>,
+    sequence: u64,
+    orders: HashMap<OrderId, RestingOrder>,
+    bids: BTreeMap<Price, Vec<OrderId>>,
+    asks: BTreeMap<Price, Vec<OrderId>>,
+    // Stand-ins of this book's users, drawn afresh every rotation
+    owners: HashMap<UserId, Uuid>,
+    owners_since: Instant,
+}
+
+impl BookMirror {
+    fn owner(&mut self, user_id: UserId, rotation: Duration) -> Uuid {
+        if self.owners_since.elapsed() >= rotation {
+            // Resting orders keep the stand-in they were published with
+            self.owners.clear();
+            self.owners_since = Instant::now();
+        }
+        *self.owners.entry(user_id).or_insert_with(Uuid::new_v4)
+    }
+
+    fn rest(&mut self, order_id: OrderId, order: RestingOrder) {
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.entry(order.price).or_insert_with(Vec::new).push(order_id);
+        self.orders.insert(order_id, order);
+    }
+
+    fn remove(&mut self, order_id: &OrderId) -> bool {
+        let order = match self.orders.remove(order_id) {
+            Some(order) => order,
+            None => return false,
+        };
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        if let Some(queue) = levels.get_mut(&order.price) {
+            queue.retain(|id| id != order_id);
+            if queue.is_empty() {
+                levels.remove(&order.price);
+            }
+        }
+        true
+    }
+
+    fn level(&self, price: Price, queue: &[OrderId]) -> L3Level {
+        L3Level {
+            price,
+            orders: queue.iter()
+                .filter_map(|id| self.orders.get(id).map(|order| L3Order {
+                    order_id: *id,
+                    owner: order.owner,
+                    quantity: order.remaining,
+                }))
+                .collect(),
+        }
+    }
+}
+
+/// Publishes order-by-order events and snapshots for registered order books
+pub struct L3Feed {
+    config: L3FeedConfig,
+    books: RwLock<HashMap<Symbol, BookMirror>>,
+    updates: broadcast::Sender<L3Update>,
+}
+
+impl L3Feed {
+    pub fn new(config: L3FeedConfig) -> Self {
+        let (updates, _) = broadcast::channel(config.channel_capacity.max(1));
+        L3Feed {
+            config,
+            books: RwLock::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// Start publishing a book's events, seeded with the orders already resting
+    pub async fn register_book(&self, symbol: Symbol, book: Arc<PLRwLock<OrderBook>>) {
+        let mirror = {
+            // Subscribing and copying under one lock leaves no event unaccounted for
+            let mut book = book.write();
+            let mut mirror = BookMirror {
+                events: book.subscribe(),
+                sequence: 0,
+                orders: HashMap::new(),
+                bids: BTreeMap::new(),
+                asks: BTreeMap::new(),
+                owners: HashMap::new(),
+                owners_since: Instant::now(),
+            };
+            for side in [Side::Buy, Side::Sell] {
+                for (price, orders) in book.get_level_orders(side) {
+                    for order in orders {
+                        let owner = mirror.owner(order.user_id, self.config.owner_rotation);
+                        mirror.rest(order.id, RestingOrder {
+                            owner,
+                            side,
+                            price,
+                            remaining: order.remaining_quantity(),
+                        });
+                    }
+                }
+            }
+            mirror
+        };
+        self.books.write().await.insert(symbol, mirror);
+    }
+
+    pub async fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.books.read().await.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Receive every published update, for all symbols
+    pub fn subscribe(&self) -> broadcast::Receiver<L3Update> {
+        self.updates.subscribe()
+    }
+
+    /// Every resting order of a book. Pending events are published first, so the
+    /// snapshot is current and updates continue right after its sequence.
+    pub async fn snapshot(&self, symbol: &str) -> Option<L3Snapshot> {
+        let mut books = self.books.write().await;
+        let mirror = books.get_mut(symbol)?;
+        if let Some(update) = self.collect(symbol, mirror) {
+            let _ = self.updates.send(update);
+        }
+
+        Some(L3Snapshot {
+            symbol: symbol.to_string(),
+            sequence: mirror.sequence,
+            bids: mirror.bids.iter().rev().map(|(price, queue)| mirror.level(*price, queue)).collect(),
+            asks: mirror.asks.iter().map(|(price, queue)| mirror.level(*price, queue)).collect(),
+            timestamp: Utc::now(),
+        })
+    }
+
+    /// Drain each book's pending events into one update per book and publish them
+    pub async fn publish_pending(&self) -> Vec<L3Update> {
+        let mut books = self.books.write().await;
+        let mut published = Vec::new();
+        for (symbol, mirror) in books.iter_mut() {
+            if let Some(update) = self.collect(symbol, mirror) {
+                // No subscribers is not an error
+                let _ = self.updates.send(update.clone());
+                published.push(update);
+            }
+        }
+        published
+    }
+
+    /// Publish on the configured interval in the background
+    pub fn spawn_publisher(self: &Arc<Self>) -> JoinHandle<()> {
+        let feed = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = time::interval(feed.config.publish_interval);
+            loop {
+                interval.tick().await;
+                feed.publish_pending().await;
+            }
+        })
+    }
+
+    /// Apply queued book events to the mirror, numbering the resulting L3 events
+    fn collect(&self, symbol: &str, mirror: &mut BookMirror) -> Option<L3Update> {
+        let first_sequence = mirror.sequence + 1;
+        let mut events = Vec::new();
+
+        while let Some((_, event)) = mirror.events.pop() {
+            let l3_event = match event {
+                OrderBookEvent::OrderAdded(order, quantity) => {
+                    let (order_id, user_id, side, price) = {
+                        let order = order.read();
+                        match order.price {
+                            Some(price) => (order.id, order.user_id, order.side, price),
+                            None => continue,
+                        }
+                    };
+                    let owner = mirror.owner(user_id, self.config.owner_rotation);
+                    mirror.rest(order_id, RestingOrder { owner, side, price, remaining: quantity });
+                    L3Event::Add { order_id, owner, side, price, quantity }
+                },
+                OrderBookEvent::OrderReduced(order_id, quantity) => {
+                    match mirror.orders.get_mut(&order_id) {
+                        Some(order) => order.remaining = quantity,
+                        None => continue,
+                    }
+                    L3Event::Modify { order_id, quantity }
+                },
+                OrderBookEvent::OrderRemoved(order_id) => {
+                    if !mirror.remove(&order_id) {
+                        continue;
+                    }
+                    L3Event::Delete { order_id }
+                },
+                OrderBookEvent::TradeExecuted(trade) => {
+                    let remaining = match mirror.orders.get_mut(&trade.maker_order_id) {
+                        Some(order) => {
+                            order.remaining -= trade.quantity;
+                            order.remaining
+                        },
+                        None => continue,
+                    };
+                    L3Event::Execute {
+                        order_id: trade.maker_order_id,
+                        trade_id: trade.id,
+                        price: trade.price,
+                        quantity: trade.quantity,
+                        remaining,
+                    }
+                },
+                OrderBookEvent::OrderReceived(..)
+                | OrderBookEvent::BestBidChanged(_)
+                | OrderBookEvent::BestAskChanged(_) => continue,
+            };
+            mirror.sequence += 1;
+            events.push(l3_event);
+        }
+
+        if events.is_empty() {
+            return None;
+        }
+        Some(L3Update {
+            symbol: symbol.to_string(),
+            first_sequence,
+            last_sequence: mirror.sequence,
+            events,
+            timestamp: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use crate::models::{Order, OrderType, TimeInForce};
+
+    fn limit(user_id: UserId, side: Side, price: Decimal, quantity: Decimal) -> Order {
+        Order::new(
+            user_id,
+            "BTC/USD".to_string(),
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            TimeInForce::GoodTillCancel,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_events_keep_queue_order() {
+        let book = Arc::new(PLRwLock::new(OrderBook::new("BTC/USD".to_string())));
+        let maker = Uuid::new_v4();
+        let a = limit(maker, Side::Buy, dec!(100), dec!(1));
+        let b = limit(maker, Side::Buy, dec!(100), dec!(2));
+        let c = limit(Uuid::new_v4(), Side::Buy, dec!(100), dec!(3));
+
+        // Orders resting before registration are part of the first snapshot
+        book.write().add_order(Arc::new(PLRwLock::new(a.clone()))).unwrap();
+        let feed = L3Feed::new(L3FeedConfig::default());
+        feed.register_book("BTC/USD".to_string(), book.clone()).await;
+
+        book.write().add_order(Arc::new(PLRwLock::new(b.clone()))).unwrap();
+        book.write().add_order(Arc::new(PLRwLock::new(c.clone()))).unwrap();
+        let mut taker = limit(Uuid::new_v4(), Side::Sell, dec!(100), dec!(1.5));
+        book.write().match_limit_order(&mut taker);
+        book.write().reduce_order(&c.id, dec!(1)).unwrap();
+
+        let update = feed.publish_pending().await.remove(0);
+        assert_eq!((update.first_sequence, update.last_sequence), (1, 6));
+        assert!(matches!(update.events[0], L3Event::Add { order_id, .. } if order_id == b.id));
+        assert!(matches!(update.events[2], L3Event::Execute { order_id, remaining, .. } if order_id == a.id && remaining == Decimal::ZERO));
+        assert_eq!(update.events[3], L3Event::Delete { order_id: a.id });
+        assert!(matches!(update.events[4], L3Event::Execute { order_id, remaining, .. } if order_id == b.id && remaining == dec!(1.5)));
+        assert_eq!(update.events[5], L3Event::Modify { order_id: c.id, quantity: dec!(1) });
+
+        // The partly filled order keeps its place ahead of the later one
+        let snapshot = feed.snapshot("BTC/USD").await.unwrap();
+        assert_eq!(snapshot.sequence, 6);
+        let queue = &snapshot.bids[0].orders;
+        assert_eq!(queue.iter().map(|o| (o.order_id, o.quantity)).collect::<Vec<_>>(), vec![(b.id, dec!(1.5)), (c.id, dec!(1))]);
+        assert_ne!(queue[0].owner, maker);
+        assert_ne!(queue[0].owner, queue[1].owner);
+    }
+
+    #[tokio::test]
+    async fn test_owners_are_not_linked_across_symbols_or_rotations() {
+        let user = Uuid::new_v4();
+        let btc = Arc::new(PLRwLock::new(OrderBook::new("BTC/USD".to_string())));
+        let eth = Arc::new(PLRwLock::new(OrderBook::new("ETH/USD".to_string())));
+        let feed = L3Feed::new(L3FeedConfig { owner_rotation: Duration::ZERO, ..Default::default() });
+        feed.register_book("BTC/USD".to_string(), btc.clone()).await;
+        feed.register_book("ETH/USD".to_string(), eth.clone()).await;
+
+        let mut eth_order = limit(user, Side::Buy, dec!(10), dec!(1));
+        eth_order.symbol = "ETH/USD".to_string();
+        btc.write().add_order(Arc::new(PLRwLock::new(limit(user, Side::Buy, dec!(100), dec!(1))))).unwrap();
+        btc.write().add_order(Arc::new(PLRwLock::new(limit(user, Side::Buy, dec!(99), dec!(1))))).unwrap();
+        eth.write().add_order(Arc::new(PLRwLock::new(eth_order))).unwrap();
+
+        let btc_snapshot = feed.snapshot("BTC/USD").await.unwrap();
+        let eth_snapshot = feed.snapshot("ETH/USD").await.unwrap();
+        let btc_owners: Vec<Uuid> = btc_snapshot.bids.iter().map(|level| level.orders[0].owner).collect();
+        assert_eq!(btc_owners.len(), 2);
+        // Every order here falls in a new rotation
+        assert_ne!(btc_owners[0], btc_owners[1]);
+        assert!(!btc_owners.contains(&eth_snapshot.bids[0].orders[0].owner));
+    }
+}
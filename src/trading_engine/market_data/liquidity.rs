@@ -0,0 +1,480 @@
+// src/trading_engine/market_data/liquidity.rs
+
+//! Order book liquidity analytics.
+//!
+//! Book measures (depth near the mid, imbalance and the cost of trading a given
+//! size) are computed from a registered [`OrderBook`] on request. The realized
+//! spread needs the mid price some time after each trade, so trades wait in a
+//! queue until [`LiquidityAnalytics::resolve`] sees them past the horizon.
+//! Trades are taken from each book's event stream, against the quotes they hit.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use crossbeam::queue::SegQueue;
+use parking_lot::RwLock as PLRwLock;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal_macros::dec;
+use serde::{Serialize, Deserialize};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time;
+use uuid::Uuid;
+
+use crate::admin::{AdminId, Report, ReportTimePeriod, ReportType};
+use crate::models::{Price, Quantity, Side, Symbol, Trade};
+use crate::trading_engine::order_book::{OrderBook, OrderBookEvent, TimedEvent};
+
+/// Parameters of the liquidity analytics
+#[derive(Debug, Clone)]
+pub struct LiquidityConfig {
+    /// Distances from the mid, in percent, that depth is reported within
+    pub depth_percents: Vec<Decimal>,
+    /// Distance from the mid, in percent, that imbalance is measured within
+    pub imbalance_percent: Decimal,
+    /// How long after a trade the mid is taken for its realized spread
+    pub realized_spread_horizon: Duration,
+    /// Trades averaged into the rolling realized spread
+    pub realized_spread_window: Duration,
+    /// How often pending trades are resolved
+    pub resolve_interval: StdDuration,
+}
+
+impl Default for LiquidityConfig {
+    fn default() -> Self {
+        LiquidityConfig {
+            depth_percents: vec![dec!(0.1), dec!(0.5), dec!(1), dec!(2)],
+            imbalance_percent: dec!(1),
+            realized_spread_horizon: Duration::seconds(60),
+            realized_spread_window: Duration::hours(1),
+            resolve_interval: StdDuration::from_secs(1),
+        }
+    }
+}
+
+/// Resting quantity within a distance of the mid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthBand {
+    pub percent: Decimal,
+    pub bid_quantity: Quantity,
+    pub ask_quantity: Quantity,
+    /// Quantity times price, in the quote asset
+    pub bid_notional: Decimal,
+    pub ask_notional: Decimal,
+}
+
+/// Expected execution of a market order of `quantity` against the current book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeCost {
+    pub side: Side,
+    pub quantity: Quantity,
+    /// Less than `quantity` when the book is too thin
+    pub filled_quantity: Quantity,
+    pub vwap: Option<Price>,
+    /// Price of the last level reached
+    pub worst_price: Option<Price>,
+    /// VWAP away from the mid in the trade's direction, in basis points
+    pub slippage_bps: Option<f64>,
+}
+
+/// Liquidity measures of one book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquiditySnapshot {
+    pub symbol: Symbol,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+    pub mid_price: Option<Price>,
+    pub spread: Option<Decimal>,
+    pub spread_bps: Option<f64>,
+    pub depth: Vec<DepthBand>,
+    /// (bid - ask) / (bid + ask) quantity within `imbalance_percent` of the mid, in [-1, 1]
+    pub imbalance: Option<f64>,
+    pub buy_cost: Option<TradeCost>,
+    pub sell_cost: Option<TradeCost>,
+    /// Volume-weighted over the rolling window; `None` before any trade resolves
+    pub realized_spread_bps: Option<f64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Resting quantity and notional of `levels`, best first, priced no worse than `limit`
+pub fn depth_within(levels: &[(Price, Quantity)], side: Side, limit: Price) -> (Quantity, Decimal) {
+    levels.iter()
+        .take_while(|(price, _)| match side {
+            Side::Buy => *price >= limit,
+            Side::Sell => *price <= limit,
+        })
+        .fold((Decimal::ZERO, Decimal::ZERO), |(quantity, notional), (price, level)| {
+            (quantity + level, notional + price * level)
+        })
+}
+
+/// Walk the levels a `side` order takes, best first, to fill `quantity`
+pub fn cost_to_trade(levels: &[(Price, Quantity)], side: Side, quantity: Quantity, mid: Option<Price>) -> TradeCost {
+    let mut filled = Decimal::ZERO;
+    let mut notional = Decimal::ZERO;
+    let mut worst_price = None;
+    for (price, level) in levels {
+        if filled >= quantity {
+            break;
+        }
+        let take = (*level).min(quantity - filled);
+        filled += take;
+        notional += price * take;
+        worst_price = Some(*price);
+    }
+
+    let vwap = if filled > Decimal::ZERO { Some(notional / filled) } else { None };
+    let slippage_bps = match (vwap, mid) {
+        (Some(vwap), Some(mid)) if mid > Decimal::ZERO => {
+            let away = match side {
+                Side::Buy => vwap - mid,
+                Side::Sell => mid - vwap,
+            };
+            (away / mid * dec!(10000)).to_f64()
+        }
+        _ => None,
+    };
+    TradeCost { side, quantity, filled_quantity: filled, vwap, worst_price, slippage_bps }
+}
+
+struct PendingTrade {
+    at: DateTime<Utc>,
+    price: Price,
+    quantity: Quantity,
+    aggressor: Side,
+    mid: Price,
+}
+
+struct TrackedBook {
+    book: Arc<PLRwLock<OrderBook>>,
+    events: Arc<SegQueue<TimedEvent>>,
+    // Best prices as of the last event taken from the queue
+    best_bid: Option<Price>,
+    best_ask: Option<Price>,
+    pending: VecDeque<PendingTrade>,
+    /// (trade time, realized spread in bps, quantity)
+    resolved: VecDeque<(DateTime<Utc>, f64, Quantity)>,
+}
+
+/// Liquidity analytics for registered order books
+pub struct LiquidityAnalytics {
+    config: LiquidityConfig,
+    books: RwLock<HashMap<Symbol, TrackedBook>>,
+}
+
+impl LiquidityAnalytics {
+    pub fn new(config: LiquidityConfig) -> Self {
+        LiquidityAnalytics {
+            config,
+            books: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &LiquidityConfig {
+        &self.config
+    }
+
+    /// Start measuring a book and taking its trades for the realized spread
+    pub async fn register_book(&self, symbol: Symbol, book: Arc<PLRwLock<OrderBook>>) {
+        let (events, best_bid, best_ask) = {
+            let mut book = book.write();
+            (book.subscribe(), book.get_best_bid(), book.get_best_ask())
+        };
+        self.books.write().await.insert(symbol, TrackedBook {
+            book,
+            events,
+            best_bid,
+            best_ask,
+            pending: VecDeque::new(),
+            resolved: VecDeque::new(),
+        });
+    }
+
+    pub async fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.books.read().await.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Note a trade for the realized spread, against the mid as it is recorded
+    pub async fn record_trade(&self, symbol: &str, price: Price, quantity: Quantity, aggressor: Side, at: DateTime<Utc>) {
+        let mut books = self.books.write().await;
+        let tracked = match books.get_mut(symbol) {
+            Some(tracked) => tracked,
+            None => return,
+        };
+        // A trade that emptied one side leaves no mid to measure from
+        if let Some(mid) = tracked.book.read().get_mid_price() {
+            tracked.pending.push_back(PendingTrade { at, price, quantity, aggressor, mid });
+        }
+    }
+
+    /// Trade sides are the taker's, so they give the aggressor
+    pub async fn on_trade(&self, trade: &Trade) {
+        let at = Utc.timestamp_nanos(trade.timestamp as i64);
+        self.record_trade(&trade.symbol, trade.price, trade.quantity, trade.side, at).await;
+    }
+
+    /// Resolve the realized spread of trades past the horizon and expire old ones
+    pub async fn resolve(&self, now: DateTime<Utc>) {
+        let horizon = now - self.config.realized_spread_horizon;
+        let window_start = now - self.config.realized_spread_window;
+        let mut books = self.books.write().await;
+        for tracked in books.values_mut() {
+            Self::take_trades(tracked);
+            let later_mid = tracked.book.read().get_mid_price();
+            while tracked.pending.front().map_or(false, |trade| trade.at <= horizon) {
+                let trade = tracked.pending.pop_front().unwrap();
+                let later_mid = match later_mid {
+                    Some(later_mid) => later_mid,
+                    None => continue,
+                };
+                // What the liquidity provider kept once the price moved on
+                let direction = match trade.aggressor {
+                    Side::Buy => Decimal::ONE,
+                    Side::Sell => -Decimal::ONE,
+                };
+                let realized = dec!(2) * direction * (trade.price - later_mid) / trade.mid * dec!(10000);
+                if let Some(bps) = realized.to_f64() {
+                    tracked.resolved.push_back((trade.at, bps, trade.quantity));
+                }
+            }
+            while tracked.resolved.front().map_or(false, |(at, _, _)| *at < window_start) {
+                tracked.resolved.pop_front();
+            }
+        }
+    }
+
+    /// Queue the trades a book published, each against the mid of the quotes it hit
+    fn take_trades(tracked: &mut TrackedBook) {
+        while let Some((_, event)) = tracked.events.pop() {
+            match event {
+                OrderBookEvent::BestBidChanged(price) => tracked.best_bid = price,
+                OrderBookEvent::BestAskChanged(price) => tracked.best_ask = price,
+                // Published ahead of the best price changes the fill causes
+                OrderBookEvent::TradeExecuted(trade) => {
+                    if let (Some(bid), Some(ask)) = (tracked.best_bid, tracked.best_ask) {
+                        tracked.pending.push_back(PendingTrade {
+                            at: Utc.timestamp_nanos(trade.timestamp as i64),
+                            price: trade.price,
+                            quantity: trade.quantity,
+                            aggressor: trade.side,
+                            mid: (bid + ask) / dec!(2),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Resolve on the configured interval in the background
+    pub fn spawn_resolver(self: &Arc<Self>) -> JoinHandle<()> {
+        let analytics = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = time::interval(analytics.config.resolve_interval);
+            loop {
+                interval.tick().await;
+                analytics.resolve(Utc::now()).await;
+            }
+        })
+    }
+
+    /// Liquidity of a book, with the cost of trading `trade_size` if given and depth
+    /// within `depth_percents` of the mid (the configured bands when empty)
+    pub async fn snapshot(&self, symbol: &str, trade_size: Option<Quantity>, depth_percents: &[Decimal]) -> Option<LiquiditySnapshot> {
+        let books = self.books.read().await;
+        let tracked = books.get(symbol)?;
+        let (best_bid, best_ask, mid_price, spread, bids, asks) = {
+            let book = tracked.book.read();
+            (
+                book.get_best_bid(),
+                book.get_best_ask(),
+                book.get_mid_price(),
+                book.get_spread(),
+                book.get_bid_depth(usize::MAX),
+                book.get_ask_depth(usize::MAX),
+            )
+        };
+
+        let percents = if depth_percents.is_empty() { &self.config.depth_percents[..] } else { depth_percents };
+        let band = |percent: Decimal| -> Option<DepthBand> {
+            let mid = mid_price?;
+            let offset = mid * percent / dec!(100);
+            let (bid_quantity, bid_notional) = depth_within(&bids, Side::Buy, mid - offset);
+            let (ask_quantity, ask_notional) = depth_within(&asks, Side::Sell, mid + offset);
+            Some(DepthBand { percent, bid_quantity, ask_quantity, bid_notional, ask_notional })
+        };
+        let depth = percents.iter().filter_map(|percent| band(*percent)).collect();
+        let imbalance = band(self.config.imbalance_percent).and_then(|band| {
+            let total = band.bid_quantity + band.ask_quantity;
+            if total > Decimal::ZERO {
+                ((band.bid_quantity - band.ask_quantity) / total).to_f64()
+            } else {
+                None
+            }
+        });
+
+        let spread_bps = match (spread, mid_price) {
+            (Some(spread), Some(mid)) if mid > Decimal::ZERO => (spread / mid * dec!(10000)).to_f64(),
+            _ => None,
+        };
+        let resolved_quantity: Decimal = tracked.resolved.iter().map(|(_, _, quantity)| *quantity).sum();
+        let realized_spread_bps = resolved_quantity.to_f64().filter(|total| *total > 0.0).map(|total| {
+            tracked.resolved.iter()
+                .map(|(_, bps, quantity)| bps * quantity.to_f64().unwrap_or(0.0))
+                .sum::<f64>() / total
+        });
+
+        Some(LiquiditySnapshot {
+            symbol: symbol.to_string(),
+            best_bid,
+            best_ask,
+            mid_price,
+            spread,
+            spread_bps,
+            depth,
+            imbalance,
+            buy_cost: trade_size.map(|size| cost_to_trade(&asks, Side::Buy, size, mid_price)),
+            sell_cost: trade_size.map(|size| cost_to_trade(&bids, Side::Sell, size, mid_price)),
+            realized_spread_bps,
+            timestamp: Utc::now(),
+        })
+    }
+
+    /// A `MarketLiquidity` report of every registered book
+    pub async fn liquidity_report(&self, created_by: AdminId, time_period: ReportTimePeriod, trade_size: Option<Quantity>) -> Report {
+        let mut snapshots = Vec::new();
+        for symbol in self.symbols().await {
+            if let Some(snapshot) = self.snapshot(&symbol, trade_size, &[]).await {
+                snapshots.push(snapshot);
+            }
+        }
+
+        let now = Utc::now();
+        Report {
+            id: Uuid::new_v4(),
+            title: "Market liquidity".to_string(),
+            description: format!("Depth, imbalance, trading cost and realized spread of {} markets", snapshots.len()),
+            report_type: ReportType::MarketLiquidity,
+            created_by,
+            created_at: now,
+            time_period,
+            parameters: serde_json::json!({ "trade_size": trade_size }),
+            data: serde_json::json!({ "markets": snapshots }),
+            file_url: None,
+            file_type: None,
+            is_scheduled: false,
+            schedule_frequency: None,
+            last_generated: now,
+            next_generation: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Order, OrderType, TimeInForce};
+
+    fn rest(book: &Arc<PLRwLock<OrderBook>>, side: Side, price: Decimal, quantity: Decimal) {
+        let order = Order::new(
+            Uuid::new_v4(),
+            "BTC/USD".to_string(),
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            TimeInForce::GoodTillCancel,
+            None,
+        );
+        book.write().add_order(Arc::new(PLRwLock::new(order))).unwrap();
+    }
+
+    #[test]
+    fn test_cost_to_trade_walks_levels() {
+        let asks = vec![(dec!(101), dec!(1)), (dec!(102), dec!(2)), (dec!(110), dec!(5))];
+
+        let cost = cost_to_trade(&asks, Side::Buy, dec!(2), Some(dec!(100)));
+        assert_eq!(cost.vwap, Some(dec!(101.5)));
+        assert_eq!(cost.worst_price, Some(dec!(102)));
+        assert_eq!(cost.slippage_bps, Some(150.0));
+
+        let thin = cost_to_trade(&asks, Side::Buy, dec!(10), Some(dec!(100)));
+        assert_eq!(thin.filled_quantity, dec!(8));
+
+        let empty = cost_to_trade(&[], Side::Sell, dec!(1), Some(dec!(100)));
+        assert_eq!((empty.vwap, empty.slippage_bps), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_reports_depth_imbalance_and_realized_spread() {
+        let book = Arc::new(PLRwLock::new(OrderBook::new("BTC/USD".to_string())));
+        rest(&book, Side::Buy, dec!(99), dec!(3));
+        rest(&book, Side::Buy, dec!(97), dec!(10));
+        rest(&book, Side::Sell, dec!(101), dec!(1));
+        rest(&book, Side::Sell, dec!(102), dec!(2));
+
+        let analytics = LiquidityAnalytics::new(LiquidityConfig::default());
+        analytics.register_book("BTC/USD".to_string(), book.clone()).await;
+
+        let snapshot = analytics.snapshot("BTC/USD", Some(dec!(2)), &[dec!(1), dec!(5)]).await.unwrap();
+        assert_eq!(snapshot.mid_price, Some(dec!(100)));
+        assert_eq!(snapshot.spread_bps, Some(200.0));
+        assert_eq!((snapshot.depth[0].bid_quantity, snapshot.depth[0].ask_quantity), (dec!(3), dec!(1)));
+        assert_eq!((snapshot.depth[1].bid_quantity, snapshot.depth[1].bid_notional), (dec!(13), dec!(1267)));
+        assert_eq!(snapshot.imbalance, Some(0.5));
+        assert_eq!(snapshot.buy_cost.unwrap().vwap, Some(dec!(101.5)));
+        assert_eq!(snapshot.sell_cost.unwrap().vwap, Some(dec!(99)));
+        assert!(snapshot.realized_spread_bps.is_none());
+
+        // A buyer lifts 101 and the mid then rises to 101: the maker kept nothing
+        let at = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        analytics.record_trade("BTC/USD", dec!(101), dec!(1), Side::Buy, at).await;
+        book.write().clear();
+        rest(&book, Side::Buy, dec!(100), dec!(1));
+        rest(&book, Side::Sell, dec!(102), dec!(1));
+
+        analytics.resolve(at + Duration::seconds(30)).await;
+        assert!(analytics.snapshot("BTC/USD", None, &[]).await.unwrap().realized_spread_bps.is_none());
+        analytics.resolve(at + Duration::seconds(60)).await;
+        assert_eq!(analytics.snapshot("BTC/USD", None, &[]).await.unwrap().realized_spread_bps, Some(0.0));
+
+        // Out of the rolling window
+        analytics.resolve(at + Duration::hours(2)).await;
+        assert!(analytics.snapshot("BTC/USD", None, &[]).await.unwrap().realized_spread_bps.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_trades_are_taken_from_the_book() {
+        let book = Arc::new(PLRwLock::new(OrderBook::new("BTC/USD".to_string())));
+        rest(&book, Side::Buy, dec!(99), dec!(1));
+        rest(&book, Side::Sell, dec!(101), dec!(1));
+        let analytics = LiquidityAnalytics::new(LiquidityConfig::default());
+        analytics.register_book("BTC/USD".to_string(), book.clone()).await;
+
+        // A seller hits the only bid, measured against the 100 mid it traded at
+        let mut taker = Order::new(
+            Uuid::new_v4(),
+            "BTC/USD".to_string(),
+            Side::Sell,
+            OrderType::Market,
+            None,
+            dec!(1),
+            TimeInForce::ImmediateOrCancel,
+            None,
+        );
+        let trades = book.write().match_market_order(&mut taker);
+        assert_eq!(trades.len(), 1);
+        rest(&book, Side::Buy, dec!(97), dec!(1));
+        rest(&book, Side::Sell, dec!(99), dec!(1));
+
+        let traded_at = Utc.timestamp_nanos(trades[0].timestamp as i64);
+        analytics.resolve(traded_at).await;
+        analytics.resolve(traded_at + Duration::seconds(60)).await;
+        // 2 * -1 * (99 - 98) / 100: the maker bought at 99 and the mid fell to 98
+        assert_eq!(analytics.snapshot("BTC/USD", None, &[]).await.unwrap().realized_spread_bps, Some(-200.0));
+    }
+}
@@ -0,0 +1,523 @@
+// src/trading_engine/market_data/mod.rs
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::task::JoinHandle;
+use anyhow::{Result, anyhow};
+use log::{debug, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::db::models::Ticker;
+use crate::db::repositories::candle_repository::CandleRepositoryTrait;
+use crate::db::repositories::trade_repository::{TradeHistoryQuery, TradePage, TradeRepositoryTrait};
+use crate::models::{Symbol, Trade};
+use super::matching_engine::{self, MatchingEngineManager};
+
+pub mod candles;
+pub mod depth;
+pub mod history;
+pub mod l3;
+pub mod liquidity;
+pub mod replay;
+pub mod summary;
+#[cfg(test)]
+mod test_support;
+pub mod trades;
+
+use candles::{Candle, CandleInterval, CandleSeries, CandleUpdate};
+
+/// Minutes in the rolling ticker window
+const TICKER_WINDOW_MINUTES: usize = 24 * 60;
+
+/// Parameters of the market data aggregator
+#[derive(Debug, Clone)]
+pub struct MarketDataConfig {
+    /// Candles kept per symbol and interval
+    pub max_candles: usize,
+    /// Most candles returned by one query
+    pub max_query_candles: usize,
+    /// Most trades in one page of history
+    pub max_query_trades: i64,
+    /// Live candle updates buffered for each subscriber before it lags
+    pub update_channel_capacity: usize,
+}
+
+impl Default for MarketDataConfig {
+    fn default() -> Self {
+        MarketDataConfig {
+            max_candles: 1500,
+            max_query_candles: 1000,
+            max_query_trades: 1000,
+            update_channel_capacity: 4096,
+        }
+    }
+}
+
+/// Aggregates trades into rolling 24h tickers and OHLCV candles per symbol
+pub struct MarketDataService {
+    config: MarketDataConfig,
+    candles: RwLock<HashMap<Symbol, HashMap<CandleInterval, CandleSeries>>>,
+    /// Books to read the best bid and ask from, when available
+    order_books: Option<Arc<MatchingEngineManager>>,
+    candle_updates: broadcast::Sender<CandleUpdate>,
+    /// Stored history beyond what is kept in memory, when available
+    trade_store: Option<Arc<dyn TradeRepositoryTrait>>,
+    candle_store: Option<Arc<dyn CandleRepositoryTrait>>,
+}
+
+impl MarketDataService {
+    pub fn new(config: MarketDataConfig) -> Self {
+        let (candle_updates, _) = broadcast::channel(config.update_channel_capacity.max(1));
+        MarketDataService {
+            config,
+            candles: RwLock::new(HashMap::new()),
+            order_books: None,
+            candle_updates,
+            trade_store: None,
+            candle_store: None,
+        }
+    }
+
+    pub fn with_order_books(mut self, order_books: Arc<MatchingEngineManager>) -> Self {
+        self.order_books = Some(order_books);
+        self
+    }
+
+    pub fn with_trade_store(mut self, trade_store: Arc<dyn TradeRepositoryTrait>) -> Self {
+        self.trade_store = Some(trade_store);
+        self
+    }
+
+    pub fn with_candle_store(mut self, candle_store: Arc<dyn CandleRepositoryTrait>) -> Self {
+        self.candle_store = Some(candle_store);
+        self
+    }
+
+    pub fn config(&self) -> &MarketDataConfig {
+        &self.config
+    }
+
+    /// Symbols that have traded
+    pub async fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.candles.read().await.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Receive the candle changed by each trade, for every symbol and interval
+    pub fn subscribe_candles(&self) -> broadcast::Receiver<CandleUpdate> {
+        self.candle_updates.subscribe()
+    }
+
+    /// Add a trade to every candle interval of its symbol
+    pub async fn record_trade(&self, symbol: &str, price: Decimal, quantity: Decimal, at: DateTime<Utc>) {
+        self.record(symbol, price, quantity, at, true).await;
+    }
+
+    /// Add a trade, publishing the candles it changes only if `publish`
+    async fn record(&self, symbol: &str, price: Decimal, quantity: Decimal, at: DateTime<Utc>, publish: bool) {
+        let mut candles = self.candles.write().await;
+        let series = candles.entry(symbol.to_string()).or_insert_with(|| {
+            CandleInterval::ALL.iter()
+                .map(|&interval| (interval, CandleSeries::new(symbol, interval, self.capacity(interval))))
+                .collect()
+        });
+        for candle_series in series.values_mut() {
+            let previous = candle_series.last().cloned();
+            if !candle_series.record(price, quantity, at) || !publish {
+                continue;
+            }
+
+            // No subscribers is not an error
+            let open_time = candle_series.interval().open_time(at);
+            if let Some(previous) = previous.filter(|candle| candle.open_time < open_time) {
+                // Unless the closing timer got to it first
+                if candle_series.mark_closed(previous.open_time) {
+                    let _ = self.candle_updates.send(CandleUpdate { candle: previous, is_closed: true });
+                }
+            }
+            if let Some(candle) = candle_series.get(open_time) {
+                // A late trade amends a candle that is already closed
+                let is_closed = candle_series.last().map_or(false, |last| last.open_time > open_time);
+                let _ = self.candle_updates.send(CandleUpdate { candle: candle.clone(), is_closed });
+            }
+        }
+    }
+
+    /// Add a trade from the matching engine
+    pub async fn on_trade(&self, trade: &Trade) {
+        let at = Utc.timestamp_nanos(trade.timestamp as i64);
+        self.record_trade(&trade.symbol, trade.price, trade.quantity, at).await;
+    }
+
+    /// Aggregate trades from the matching engine in the background
+    pub fn spawn_trade_listener(self: &Arc<Self>, mut trades: mpsc::Receiver<matching_engine::Trade>) -> JoinHandle<()> {
+        let market_data = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(trade) = trades.recv().await {
+                market_data.on_trade(&Trade::from(&trade)).await;
+            }
+            debug!("Market data trade listener stopped");
+        })
+    }
+
+    /// Announce every candle whose interval has ended by `now` without a later trade to close it
+    pub async fn close_due_candles(&self, now: DateTime<Utc>) -> usize {
+        let mut closed = 0;
+        for series in self.candles.write().await.values_mut() {
+            for candle_series in series.values_mut() {
+                if let Some(candle) = candle_series.close_due(now) {
+                    let _ = self.candle_updates.send(CandleUpdate { candle, is_closed: true });
+                    closed += 1;
+                }
+            }
+        }
+        closed
+    }
+
+    /// Close candles at their interval boundaries even when no trade follows
+    pub fn spawn_candle_closer(self: &Arc<Self>) -> JoinHandle<()> {
+        let market_data = Arc::clone(self);
+        tokio::spawn(async move {
+            // Every interval is a whole number of seconds, so a per-second check is at most a second late
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                ticker.tick().await;
+                market_data.close_due_candles(Utc::now()).await;
+            }
+        })
+    }
+
+    /// Persist candles as they close, including late amendments; `None` without candle storage
+    pub fn spawn_candle_writer(&self) -> Option<JoinHandle<()>> {
+        let store = Arc::clone(self.candle_store.as_ref()?);
+        let mut updates = self.subscribe_candles();
+        Some(tokio::spawn(async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) if update.is_closed && !update.candle.is_empty() => {
+                        if let Err(e) = store.upsert(&[update.candle]).await {
+                            warn!("Failed to store candle: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Candle writer lagged, {} updates dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+            debug!("Candle writer stopped");
+        }))
+    }
+
+    /// A page of a symbol's stored trade history
+    pub async fn trade_history(&self, symbol: &str, query: &TradeHistoryQuery) -> Result<TradePage> {
+        let store = self.trade_store.as_ref().ok_or_else(|| anyhow!("Trade history storage is not configured"))?;
+        let query = TradeHistoryQuery {
+            limit: query.limit.clamp(1, self.config.max_query_trades),
+            ..query.clone()
+        };
+        store.find_history(symbol, &query).await
+            .map_err(|e| anyhow!("Failed to load trade history for {}: {}", symbol, e))
+    }
+
+    /// Rebuild a symbol's candles and ticker from its most recent stored trades. History is
+    /// rebuilt without publishing, so subscribers are not flooded with candles long closed.
+    pub async fn backfill(&self, repository: &dyn TradeRepositoryTrait, symbol: &str, limit: i64) -> Result<usize> {
+        let mut trades = repository.find_by_symbol(symbol, limit).await
+            .map_err(|e| anyhow!("Failed to load trades for {}: {}", symbol, e))?;
+        trades.sort_by_key(|t| t.executed_at);
+
+        for trade in &trades {
+            self.record(symbol, trade.price, trade.quantity, trade.executed_at, false).await;
+        }
+        Ok(trades.len())
+    }
+
+    /// Rolling 24h statistics for a symbol
+    pub async fn ticker(&self, symbol: &str) -> Option<Ticker> {
+        self.ticker_at(symbol, Utc::now()).await
+    }
+
+    /// Rolling 24h statistics as of `now`, from the one-minute candles
+    pub async fn ticker_at(&self, symbol: &str, now: DateTime<Utc>) -> Option<Ticker> {
+        let window = {
+            let candles = self.candles.read().await;
+            let series = candles.get(symbol)?.get(&CandleInterval::OneMinute)?;
+            let start = CandleInterval::OneMinute.open_time(now) - Duration::minutes(TICKER_WINDOW_MINUTES as i64 - 1);
+            series.range(Some(start), Some(now), TICKER_WINDOW_MINUTES, now)
+        };
+
+        let last_price = window.last()?.close;
+        let open_price = window.first().map_or(last_price, |c| c.open);
+        let traded: Vec<&Candle> = window.iter().filter(|c| !c.is_empty()).collect();
+        let high_24h = traded.iter().map(|c| c.high).max().unwrap_or(last_price);
+        let low_24h = traded.iter().map(|c| c.low).min().unwrap_or(last_price);
+        let volume_24h = traded.iter().map(|c| c.volume).sum();
+
+        let price_change_24h = last_price - open_price;
+        let price_change_percent_24h = if open_price > Decimal::ZERO {
+            (price_change_24h / open_price * Decimal::from(100)).to_f64().unwrap_or(0.0)
+        } else {
+            0.0
+        };
+
+        let (bid_price, ask_price) = self.best_bid_ask(symbol).await;
+        Some(Ticker {
+            symbol: symbol.to_string(),
+            last_price,
+            bid_price,
+            ask_price,
+            high_24h,
+            low_24h,
+            volume_24h,
+            price_change_24h,
+            price_change_percent_24h,
+            timestamp: now,
+        })
+    }
+
+    /// A symbol's candles opening in `[start, end]`, most recent `limit` in time order;
+    /// `None` if the symbol has never traded. Candles older than those kept in memory
+    /// come from storage.
+    pub async fn candles(
+        &self,
+        symbol: &str,
+        interval: CandleInterval,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Option<Vec<Candle>> {
+        self.candles_at(symbol, interval, start, end, limit, Utc::now()).await
+    }
+
+    pub async fn candles_at(
+        &self,
+        symbol: &str,
+        interval: CandleInterval,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: usize,
+        now: DateTime<Utc>,
+    ) -> Option<Vec<Candle>> {
+        let end = end.map_or(now, |end| end.min(now));
+        let limit = limit.min(self.config.max_query_candles);
+        let live = {
+            let candles = self.candles.read().await;
+            candles.get(symbol)
+                .and_then(|series| series.get(&interval))
+                .map(|series| series.range(start, Some(end), limit, now))
+        };
+
+        let store = match &self.candle_store {
+            Some(store) => store,
+            None => return live,
+        };
+        let mut live_candles = live.clone().unwrap_or_default();
+        let before = live_candles.first().map_or(end, |first| first.open_time - interval.duration());
+        if live_candles.len() >= limit || start.map_or(false, |start| start > before) {
+            return live;
+        }
+        match store.find_range(symbol, interval, start, Some(before), (limit - live_candles.len()) as i64).await {
+            Ok(mut stored) if !stored.is_empty() => {
+                stored.append(&mut live_candles);
+                Some(stored)
+            }
+            Ok(_) => live,
+            Err(e) => {
+                warn!("Failed to load stored {} candles for {}: {}", interval, symbol, e);
+                live
+            }
+        }
+    }
+
+    fn capacity(&self, interval: CandleInterval) -> usize {
+        // One-minute candles also back the 24h ticker
+        match interval {
+            CandleInterval::OneMinute => self.config.max_candles.max(TICKER_WINDOW_MINUTES + 1),
+            _ => self.config.max_candles,
+        }
+    }
+
+    async fn best_bid_ask(&self, symbol: &str) -> (Decimal, Decimal) {
+        let order_books = match &self.order_books {
+            Some(order_books) => order_books,
+            None => return (Decimal::ZERO, Decimal::ZERO),
+        };
+        let engine = match order_books.get_engine(&symbol.to_string()).await {
+            Ok(engine) => engine,
+            Err(_) => return (Decimal::ZERO, Decimal::ZERO),
+        };
+        match engine.get_order_book_snapshot(1).await {
+            Ok((bids, asks)) => (
+                bids.first().map_or(Decimal::ZERO, |(price, _)| *price),
+                asks.first().map_or(Decimal::ZERO, |(price, _)| *price),
+            ),
+            Err(_) => (Decimal::ZERO, Decimal::ZERO),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+    use crate::db::models::{OrderSide, Trade as StoredTrade};
+    use crate::db::repositories::trade_repository::TradeCursor;
+    use super::test_support::StoredTrades;
+
+    fn stored(price: Decimal, quantity: Decimal, executed_at: DateTime<Utc>) -> StoredTrade {
+        StoredTrade {
+            id: Uuid::new_v4(),
+            symbol: "BTC/USDT".to_string(),
+            price,
+            quantity,
+            side: OrderSide::Buy,
+            order_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            executed_at,
+            fee: Decimal::ZERO,
+            sequence: None,
+            is_maker: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ticker_rolls_over_24_hours() {
+        let service = MarketDataService::new(MarketDataConfig::default());
+        let now = Utc.with_ymd_and_hms(2024, 3, 2, 12, 0, 30).unwrap();
+
+        service.record_trade("BTC/USDT", dec!(40000), dec!(5), now - Duration::hours(30)).await;
+        service.record_trade("BTC/USDT", dec!(50000), dec!(1), now - Duration::hours(20)).await;
+        service.record_trade("BTC/USDT", dec!(48000), dec!(2), now - Duration::hours(2)).await;
+        service.record_trade("BTC/USDT", dec!(55000), dec!(1), now).await;
+
+        let ticker = service.ticker_at("BTC/USDT", now).await.unwrap();
+        assert_eq!(ticker.last_price, dec!(55000));
+        // The trade 30 hours ago sets the price at the start of the window but not its range
+        assert_eq!(ticker.high_24h, dec!(55000));
+        assert_eq!(ticker.low_24h, dec!(48000));
+        assert_eq!(ticker.volume_24h, dec!(4));
+        assert_eq!(ticker.price_change_24h, dec!(15000));
+        assert_eq!(ticker.price_change_percent_24h, 37.5);
+
+        assert!(service.ticker_at("ETH/USDT", now).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_candle_updates_close_on_rollover() {
+        let service = MarketDataService::new(MarketDataConfig::default());
+        let mut updates = service.subscribe_candles();
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap();
+
+        service.record_trade("BTC/USDT", dec!(100), dec!(1), start + Duration::seconds(5)).await;
+        service.record_trade("BTC/USDT", dec!(105), dec!(1), start + Duration::seconds(30)).await;
+        service.record_trade("BTC/USDT", dec!(95), dec!(1), start + Duration::seconds(70)).await;
+        service.record_trade("BTC/USDT", dec!(90), dec!(1), start + Duration::seconds(50)).await;
+
+        let mut minutes = Vec::new();
+        while let Ok(update) = updates.try_recv() {
+            if update.candle.interval == CandleInterval::OneMinute {
+                minutes.push((update.candle.open_time, update.candle.close, update.is_closed));
+            }
+        }
+        let next = start + Duration::minutes(1);
+        assert_eq!(minutes, vec![
+            (start, dec!(100), false),
+            (start, dec!(105), false),
+            (start, dec!(105), true),
+            (next, dec!(95), false),
+            // The late trade lands in the closed minute, and as its last trade by time sets the close
+            (start, dec!(90), true),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_quiet_candles_closed_by_timer() {
+        let service = MarketDataService::new(MarketDataConfig::default());
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap();
+        service.record_trade("BTC/USDT", dec!(100), dec!(1), start + Duration::seconds(5)).await;
+
+        let mut updates = service.subscribe_candles();
+        assert_eq!(service.close_due_candles(start + Duration::seconds(59)).await, 0);
+        // The minute and five minute candles end at 10:05
+        assert_eq!(service.close_due_candles(start + Duration::minutes(5)).await, 2);
+        let update = updates.try_recv().unwrap();
+        assert!(update.is_closed && update.candle.close == dec!(100));
+
+        // A later trade does not close them again
+        service.record_trade("BTC/USDT", dec!(101), dec!(1), start + Duration::minutes(6)).await;
+        while let Ok(update) = updates.try_recv() {
+            assert!(!update.is_closed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trade_history_pages_by_cursor() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let trades: Vec<StoredTrade> = (1..=5)
+            .map(|i| stored(Decimal::from(100 + i), dec!(1), start + Duration::minutes(i)))
+            .collect();
+        let third = trades[2].id;
+        let repository = Arc::new(StoredTrades::new(trades));
+        let service = MarketDataService::new(MarketDataConfig::default())
+            .with_trade_store(repository);
+
+        let query = TradeHistoryQuery { start: Some(start + Duration::minutes(2)), limit: 2, ..Default::default() };
+        let first = service.trade_history("BTC/USDT", &query).await.unwrap();
+        assert_eq!(first.trades.iter().map(|t| t.price).collect::<Vec<_>>(), vec![dec!(102), dec!(103)]);
+
+        // Clients get the cursor as an opaque string and send it back
+        let cursor: TradeCursor = first.next_cursor.unwrap().to_string().parse().unwrap();
+        let second = service.trade_history("BTC/USDT", &TradeHistoryQuery { after: Some(cursor), ..query.clone() }).await.unwrap();
+        assert_eq!(second.trades.iter().map(|t| t.price).collect::<Vec<_>>(), vec![dec!(104), dec!(105)]);
+        assert!(second.next_cursor.is_none());
+
+        let from_id = TradeHistoryQuery { from_id: Some(third), limit: 10, ..Default::default() };
+        let page = service.trade_history("BTC/USDT", &from_id).await.unwrap();
+        assert_eq!(page.trades.len(), 3);
+        assert_eq!(page.trades[0].id, third);
+
+        let unstored = MarketDataService::new(MarketDataConfig::default());
+        assert!(unstored.trade_history("BTC/USDT", &query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_builds_candles_from_stored_trades() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let repository = StoredTrades::new(vec![
+            stored(dec!(100), dec!(1), start + Duration::minutes(10)),
+            stored(dec!(120), dec!(1), start + Duration::minutes(70)),
+            stored(dec!(90), dec!(2), start + Duration::minutes(20)),
+            stored(dec!(110), dec!(1), start + Duration::minutes(130)),
+        ]);
+
+        let service = MarketDataService::new(MarketDataConfig::default());
+        let mut updates = service.subscribe_candles();
+        assert_eq!(service.backfill(&repository, "BTC/USDT", 1000).await.unwrap(), 4);
+        // History is not replayed to live subscribers
+        assert!(updates.try_recv().is_err());
+
+        let now = start + Duration::minutes(200);
+        let hourly = service.candles_at("BTC/USDT", CandleInterval::OneHour, None, None, 10, now).await.unwrap();
+        assert_eq!(hourly.len(), 4);
+        assert_eq!((hourly[0].open, hourly[0].close, hourly[0].low), (dec!(100), dec!(90), dec!(90)));
+        assert_eq!(hourly[0].volume, dec!(3));
+        assert_eq!(hourly[2].close, dec!(110));
+        assert!(hourly[3].is_empty());
+
+        let daily = service.candles_at("BTC/USDT", CandleInterval::OneDay, None, None, 10, now).await.unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!((daily[0].high, daily[0].trade_count), (dec!(120), 4));
+
+        let limited = service.candles_at("BTC/USDT", CandleInterval::FiveMinutes, None, None, 3, now).await.unwrap();
+        assert_eq!(limited.len(), 3);
+        assert_eq!(limited[2].open_time, CandleInterval::FiveMinutes.open_time(now));
+    }
+}
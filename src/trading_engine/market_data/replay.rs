@@ -0,0 +1,409 @@
+// src/trading_engine/market_data/replay.rs
+
+//! Replay of recorded order and trade event logs, for backtesting.
+//!
+//! A log is JSON lines, one [`RecordedEvent`] per line. Orders and cancels are
+//! run through a fresh [`MatchingEngineManager`], and the trades they produce
+//! feed the [`MarketDataService`] at the recorded time, so candles, tickers and
+//! the kline and trade WebSocket channels look as they did during the session.
+//! Logs that only hold trades (an export of the trades table, say) feed market
+//! data and the trade feed directly.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use anyhow::{Context, Result, anyhow};
+use log::{debug, warn};
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+use tokio::time::{self, Instant};
+use uuid::Uuid;
+
+use crate::models::Side;
+use crate::trading_engine::matching_engine::{
+    self, MatchingEngineManager, Order, OrderId, OrderType, TimeInForce,
+};
+use super::MarketDataService;
+use super::trades::TradeFeed;
+
+/// Type of a recorded order; the matching engine only matches these two
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedOrderType {
+    Limit,
+    Market,
+}
+
+/// An order as it reached the matching engine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedOrder {
+    pub id: OrderId,
+    pub user_id: Uuid,
+    pub symbol: String,
+    pub side: Side,
+    pub order_type: RecordedOrderType,
+    pub price: Option<Decimal>,
+    pub quantity: Decimal,
+}
+
+/// One line of an event log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Order {
+        timestamp: DateTime<Utc>,
+        order: RecordedOrder,
+    },
+    Cancel {
+        timestamp: DateTime<Utc>,
+        symbol: String,
+        order_id: OrderId,
+    },
+    /// A trade as published; skipped for symbols whose orders are replayed,
+    /// since matching them produces the trades again
+    Trade {
+        timestamp: DateTime<Utc>,
+        symbol: String,
+        price: Decimal,
+        quantity: Decimal,
+        side: Side,
+    },
+}
+
+impl RecordedEvent {
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            RecordedEvent::Order { timestamp, .. }
+            | RecordedEvent::Cancel { timestamp, .. }
+            | RecordedEvent::Trade { timestamp, .. } => *timestamp,
+        }
+    }
+}
+
+/// Read a log, in time order; events with equal timestamps keep their order in the file
+pub fn read_event_log(path: &Path) -> Result<Vec<RecordedEvent>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open event log {}", path.display()))?;
+    let mut events = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read event log {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: RecordedEvent = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid event on line {} of {}", index + 1, path.display()))?;
+        events.push(event);
+    }
+    events.sort_by_key(|event| event.timestamp());
+    Ok(events)
+}
+
+/// How fast recorded time passes during a replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Recorded time divided by this factor; 1 is real time
+    Accelerated(f64),
+    /// No waiting between events
+    Max,
+}
+
+impl ReplaySpeed {
+    /// Wall-clock time from the start of the replay to an event `elapsed` into the session
+    pub fn delay(&self, elapsed: chrono::Duration) -> Option<Duration> {
+        match self {
+            ReplaySpeed::Max => None,
+            ReplaySpeed::Accelerated(factor) => {
+                let elapsed = elapsed.to_std().unwrap_or_default();
+                Some(elapsed.div_f64(*factor))
+            }
+        }
+    }
+}
+
+/// `max`, or a speed-up factor such as `1`, `10` or `10x`
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("max") {
+            return Ok(ReplaySpeed::Max);
+        }
+        match s.trim_end_matches(|c| c == 'x' || c == 'X').parse::<f64>() {
+            Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(ReplaySpeed::Accelerated(factor)),
+            _ => Err(format!("Invalid replay speed {}, expected a positive factor or max", s)),
+        }
+    }
+}
+
+/// Counts of what a replay did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub orders: usize,
+    pub cancels: usize,
+    /// Trades from matching plus recorded trades fed directly
+    pub trades: usize,
+    /// Orders the matching engine refused
+    pub rejected: usize,
+}
+
+/// Plays event logs into a matching engine manager and market data service
+pub struct MarketReplay {
+    engines: Arc<MatchingEngineManager>,
+    market_data: Arc<MarketDataService>,
+    /// Publishes matched trades, when set
+    trade_feed: Option<Arc<TradeFeed>>,
+}
+
+impl MarketReplay {
+    pub fn new(engines: Arc<MatchingEngineManager>, market_data: Arc<MarketDataService>) -> Self {
+        MarketReplay { engines, market_data, trade_feed: None }
+    }
+
+    pub fn with_trade_feed(mut self, trade_feed: Arc<TradeFeed>) -> Self {
+        self.trade_feed = Some(trade_feed);
+        self
+    }
+
+    /// Create the matching engine of every symbol whose orders `events` replay, so
+    /// feeds can register their books before the replay starts
+    pub async fn open_books(&self, events: &[RecordedEvent]) -> Result<()> {
+        for event in events {
+            if let RecordedEvent::Order { order, .. } = event {
+                if self.engines.get_engine(&order.symbol).await.is_err() {
+                    self.engines.add_symbol(order.symbol.clone()).await.map_err(|e| anyhow!(e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay `events`, already in time order, pacing them at `speed`
+    pub async fn run(&self, events: &[RecordedEvent], speed: ReplaySpeed) -> Result<ReplaySummary> {
+        let matched: HashSet<&str> = events.iter()
+            .filter_map(|event| match event {
+                RecordedEvent::Order { order, .. } => Some(order.symbol.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let mut summary = ReplaySummary::default();
+        let session_start = match events.first() {
+            Some(event) => event.timestamp(),
+            None => return Ok(summary),
+        };
+        let replay_start = Instant::now();
+
+        for event in events {
+            if let Some(delay) = speed.delay(event.timestamp() - session_start) {
+                time::sleep_until(replay_start + delay).await;
+            }
+
+            match event {
+                RecordedEvent::Order { timestamp, order } => {
+                    summary.orders += 1;
+                    match self.submit(order).await {
+                        Ok(trades) => {
+                            for trade in &trades {
+                                self.market_data.record_trade(&trade.symbol, trade.price, trade.quantity, *timestamp).await;
+                                self.publish(trade).await;
+                            }
+                            summary.trades += trades.len();
+                        }
+                        Err(e) => {
+                            debug!("Replayed order {} rejected: {}", order.id, e);
+                            summary.rejected += 1;
+                        }
+                    }
+                }
+                RecordedEvent::Cancel { symbol, order_id, .. } => {
+                    summary.cancels += 1;
+                    // Orders that already filled are no longer in the book
+                    if let Err(e) = self.engines.cancel_order(symbol, *order_id).await {
+                        warn!("Failed to replay cancel of {}: {}", order_id, e);
+                    }
+                }
+                RecordedEvent::Trade { timestamp, symbol, price, quantity, side } => {
+                    if matched.contains(symbol.as_str()) {
+                        continue;
+                    }
+                    self.market_data.record_trade(symbol, *price, *quantity, *timestamp).await;
+                    // The log does not say whose orders traded
+                    self.publish(&matching_engine::Trade {
+                        id: Uuid::new_v4(),
+                        symbol: symbol.clone(),
+                        taker_order_id: Uuid::nil(),
+                        maker_order_id: Uuid::nil(),
+                        taker_user_id: Uuid::nil(),
+                        maker_user_id: Uuid::nil(),
+                        price: *price,
+                        quantity: *quantity,
+                        aggressor_side: match side {
+                            Side::Buy => matching_engine::Side::Buy,
+                            Side::Sell => matching_engine::Side::Sell,
+                        },
+                        timestamp: timestamp.timestamp_nanos_opt().unwrap_or_default().max(0) as u64,
+                        taker_fee: Decimal::ZERO,
+                        maker_fee: Decimal::ZERO,
+                    }).await;
+                    summary.trades += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Publish a replayed trade on the trades channel; a failure is logged and the replay goes on
+    async fn publish(&self, trade: &matching_engine::Trade) {
+        if let Some(trade_feed) = &self.trade_feed {
+            trade_feed.publish(trade).await;
+        }
+    }
+
+    async fn submit(&self, recorded: &RecordedOrder) -> Result<Vec<matching_engine::Trade>> {
+        if self.engines.get_engine(&recorded.symbol).await.is_err() {
+            self.engines.add_symbol(recorded.symbol.clone()).await.map_err(|e| anyhow!(e))?;
+        }
+
+        let side = match recorded.side {
+            Side::Buy => matching_engine::Side::Buy,
+            Side::Sell => matching_engine::Side::Sell,
+        };
+        let order_type = match recorded.order_type {
+            RecordedOrderType::Limit => OrderType::Limit,
+            RecordedOrderType::Market => OrderType::Market,
+        };
+        let mut order = Order::new(
+            recorded.user_id,
+            recorded.symbol.clone(),
+            side,
+            order_type,
+            recorded.price,
+            recorded.quantity,
+            TimeInForce::GoodTillCancel,
+            None,
+        );
+        // Later cancels in the log refer to the recorded id
+        order.id = recorded.id;
+
+        self.engines.process_order(order).await.map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use crate::trading_engine::market_data::MarketDataConfig;
+    use crate::trading_engine::market_data::candles::CandleInterval;
+
+    fn order(at: DateTime<Utc>, side: Side, price: Decimal, quantity: Decimal) -> RecordedEvent {
+        RecordedEvent::Order {
+            timestamp: at,
+            order: RecordedOrder {
+                id: Uuid::new_v4(),
+                user_id: Uuid::new_v4(),
+                symbol: "BTC/USDT".to_string(),
+                side,
+                order_type: RecordedOrderType::Limit,
+                price: Some(price),
+                quantity,
+            },
+        }
+    }
+
+    #[test]
+    fn test_speed_parsing_and_pacing() {
+        assert_eq!("max".parse::<ReplaySpeed>().unwrap(), ReplaySpeed::Max);
+        assert_eq!("10x".parse::<ReplaySpeed>().unwrap(), ReplaySpeed::Accelerated(10.0));
+        assert!("0".parse::<ReplaySpeed>().is_err());
+        assert!("fast".parse::<ReplaySpeed>().is_err());
+
+        let ten_times = ReplaySpeed::Accelerated(10.0);
+        assert_eq!(ten_times.delay(chrono::Duration::seconds(60)), Some(Duration::from_secs(6)));
+        assert_eq!(ReplaySpeed::Max.delay(chrono::Duration::seconds(60)), None);
+    }
+
+    #[test]
+    fn test_events_round_trip_as_json_lines() {
+        let at = Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap();
+        let event = order(at, Side::Buy, dec!(100), dec!(1));
+        let line = serde_json::to_string(&event).unwrap();
+        assert!(line.contains("\"type\":\"order\""));
+        assert_eq!(serde_json::from_str::<RecordedEvent>(&line).unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn test_replay_matches_orders_into_historical_candles() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap();
+        let cancelled = order(start, Side::Sell, dec!(101), dec!(1));
+        let cancelled_id = match &cancelled {
+            RecordedEvent::Order { order, .. } => order.id,
+            _ => unreachable!(),
+        };
+        let events = vec![
+            order(start, Side::Sell, dec!(100), dec!(1)),
+            order(start, Side::Sell, dec!(100.5), dec!(1)),
+            cancelled,
+            order(start + chrono::Duration::seconds(30), Side::Buy, dec!(100), dec!(1)),
+            RecordedEvent::Cancel {
+                timestamp: start + chrono::Duration::seconds(40),
+                symbol: "BTC/USDT".to_string(),
+                order_id: cancelled_id,
+            },
+            // Matching already produced this trade
+            RecordedEvent::Trade {
+                timestamp: start + chrono::Duration::seconds(30),
+                symbol: "BTC/USDT".to_string(),
+                price: dec!(100),
+                quantity: dec!(1),
+                side: Side::Buy,
+            },
+            // Another symbol only has its trades recorded
+            RecordedEvent::Trade {
+                timestamp: start + chrono::Duration::minutes(2),
+                symbol: "ETH/USDT".to_string(),
+                price: dec!(10),
+                quantity: dec!(3),
+                side: Side::Sell,
+            },
+            order(start + chrono::Duration::minutes(3), Side::Buy, dec!(101), dec!(5)),
+        ];
+
+        let engines = Arc::new(MatchingEngineManager::new());
+        let market_data = Arc::new(MarketDataService::new(MarketDataConfig::default()));
+        let trade_feed = Arc::new(TradeFeed::new(Default::default()));
+        let mut published = trade_feed.subscribe();
+        let replay = MarketReplay::new(engines.clone(), market_data.clone()).with_trade_feed(trade_feed);
+        let summary = replay.run(&events, ReplaySpeed::Max).await.unwrap();
+
+        assert_eq!(summary, ReplaySummary { orders: 5, cancels: 1, trades: 3, rejected: 0 });
+
+        let now = start + chrono::Duration::minutes(5);
+        let minutes = market_data.candles_at("BTC/USDT", CandleInterval::OneMinute, None, None, 10, now).await.unwrap();
+        assert_eq!(minutes[0].open_time, start);
+        assert_eq!(minutes[0].volume, dec!(1));
+        // The last buy only finds the 100.5 offer; the 101 offer was cancelled
+        assert_eq!((minutes[3].close, minutes[3].volume), (dec!(100.5), dec!(1)));
+
+        let eth = market_data.candles_at("ETH/USDT", CandleInterval::OneMinute, None, None, 10, now).await.unwrap();
+        assert_eq!(eth[0].volume, dec!(3));
+
+        // Matched and recorded trades alike reach the trades channel
+        let mut symbols = Vec::new();
+        while let Ok(event) = published.try_recv() {
+            symbols.push(event.trade.symbol);
+        }
+        assert_eq!(symbols, vec!["BTC/USDT", "ETH/USDT", "BTC/USDT"]);
+
+        let (bids, asks) = engines.get_engine(&"BTC/USDT".to_string()).await.unwrap()
+            .get_order_book_snapshot(10).await.unwrap();
+        assert_eq!(bids, vec![(dec!(101), dec!(4))]);
+        assert!(asks.is_empty());
+    }
+}
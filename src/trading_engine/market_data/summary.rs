@@ -0,0 +1,191 @@
+// src/trading_engine/market_data/summary.rs
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+
+use crate::admin::{TradingPair, TradingPairStatus};
+use crate::db::models::Ticker;
+use super::MarketDataService;
+
+/// The part of a ticker shown in market lists
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MiniTicker {
+    pub symbol: String,
+    pub last_price: Decimal,
+    pub price_change_24h: Decimal,
+    pub price_change_percent_24h: f64,
+    pub volume_24h: Decimal,
+    pub bid_price: Decimal,
+    pub ask_price: Decimal,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl From<Ticker> for MiniTicker {
+    fn from(ticker: Ticker) -> Self {
+        MiniTicker {
+            symbol: ticker.symbol,
+            last_price: ticker.last_price,
+            price_change_24h: ticker.price_change_24h,
+            price_change_percent_24h: ticker.price_change_percent_24h,
+            volume_24h: ticker.volume_24h,
+            bid_price: ticker.bid_price,
+            ask_price: ticker.ask_price,
+            timestamp: ticker.timestamp,
+        }
+    }
+}
+
+/// A listed market with its trading rules and mini ticker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketSummary {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub status: TradingPairStatus,
+    /// Smallest price increment
+    pub tick_size: Decimal,
+    /// Smallest quantity increment
+    pub lot_size: Decimal,
+    pub min_quantity: Decimal,
+    pub max_quantity: Decimal,
+    pub min_notional: Decimal,
+    /// `None` until the market trades
+    pub ticker: Option<MiniTicker>,
+}
+
+/// Market data symbol of a trading pair, e.g. `BTC/USDT`
+pub fn pair_symbol(pair: &TradingPair) -> String {
+    format!("{}/{}", pair.base_asset, pair.quote_asset).to_uppercase()
+}
+
+/// Summaries of every active pair, in symbol order
+pub async fn market_summaries(pairs: &[TradingPair], market_data: &MarketDataService) -> Vec<MarketSummary> {
+    let now = Utc::now();
+    let mut summaries = Vec::with_capacity(pairs.len());
+    for pair in pairs.iter().filter(|p| p.status == TradingPairStatus::Active) {
+        let symbol = pair_symbol(pair);
+        let ticker = market_data.ticker_at(&symbol, now).await.map(MiniTicker::from);
+        summaries.push(MarketSummary {
+            symbol,
+            base_asset: pair.base_asset.clone(),
+            quote_asset: pair.quote_asset.clone(),
+            status: pair.status,
+            tick_size: Decimal::new(1, pair.price_precision),
+            lot_size: Decimal::new(1, pair.quantity_precision),
+            min_quantity: pair.min_quantity,
+            max_quantity: pair.max_quantity,
+            min_notional: pair.min_notional,
+            ticker,
+        });
+    }
+    summaries.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    summaries
+}
+
+/// Mini tickers of every active pair, in symbol order; a pair that has not traded
+/// yet shows zeros
+pub async fn mini_tickers(pairs: &[TradingPair], market_data: &MarketDataService) -> Vec<MiniTicker> {
+    let now = Utc::now();
+    let mut tickers = Vec::with_capacity(pairs.len());
+    for pair in pairs.iter().filter(|p| p.status == TradingPairStatus::Active) {
+        let symbol = pair_symbol(pair);
+        let ticker = match market_data.ticker_at(&symbol, now).await {
+            Some(ticker) => MiniTicker::from(ticker),
+            None => MiniTicker {
+                symbol,
+                last_price: Decimal::ZERO,
+                price_change_24h: Decimal::ZERO,
+                price_change_percent_24h: 0.0,
+                volume_24h: Decimal::ZERO,
+                bid_price: Decimal::ZERO,
+                ask_price: Decimal::ZERO,
+                timestamp: now,
+            },
+        };
+        tickers.push(ticker);
+    }
+    tickers.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    tickers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+    use crate::trading_engine::market_data::MarketDataConfig;
+
+    fn pair(base: &str, quote: &str, status: TradingPairStatus) -> TradingPair {
+        let now = Utc::now();
+        TradingPair {
+            id: format!("{}{}", base, quote),
+            base_asset: base.to_string(),
+            quote_asset: quote.to_string(),
+            min_price: dec!(0.01),
+            max_price: dec!(1000000),
+            price_precision: 2,
+            min_quantity: dec!(0.0001),
+            max_quantity: dec!(1000),
+            quantity_precision: 4,
+            min_notional: dec!(10),
+            maker_fee: dec!(0.001),
+            taker_fee: dec!(0.001),
+            status,
+            created_at: now,
+            updated_at: now,
+            listing_date: None,
+            delisting_date: None,
+            description: None,
+            tags: Vec::new(),
+            is_leveraged: false,
+            max_leverage: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summaries_cover_active_pairs_with_rules_and_tickers() {
+        let market_data = MarketDataService::new(MarketDataConfig::default());
+        let now = Utc::now();
+        market_data.record_trade("BTC/USDT", dec!(50000), dec!(1), now - Duration::hours(2)).await;
+        market_data.record_trade("BTC/USDT", dec!(51000), dec!(2), now).await;
+
+        let pairs = vec![
+            pair("eth", "usdt", TradingPairStatus::Active),
+            pair("BTC", "USDT", TradingPairStatus::Active),
+            pair("SOL", "USDT", TradingPairStatus::MaintenanceMode),
+            pair("LUNA", "USDT", TradingPairStatus::Delisted),
+        ];
+        let summaries = market_summaries(&pairs, &market_data).await;
+
+        assert_eq!(summaries.iter().map(|s| s.symbol.as_str()).collect::<Vec<_>>(), vec!["BTC/USDT", "ETH/USDT"]);
+        let btc = &summaries[0];
+        assert_eq!((btc.tick_size, btc.lot_size), (dec!(0.01), dec!(0.0001)));
+        let ticker = btc.ticker.as_ref().unwrap();
+        assert_eq!((ticker.last_price, ticker.price_change_24h, ticker.volume_24h), (dec!(51000), dec!(1000), dec!(3)));
+
+        // Active but not yet traded
+        assert_eq!(summaries[1].status, TradingPairStatus::Active);
+        assert!(summaries[1].ticker.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mini_tickers_cover_every_active_pair() {
+        let market_data = MarketDataService::new(MarketDataConfig::default());
+        market_data.record_trade("BTC/USDT", dec!(50000), dec!(1), Utc::now()).await;
+        // Traded, but no longer listed as active
+        market_data.record_trade("LUNA/USDT", dec!(1), dec!(1), Utc::now()).await;
+
+        let pairs = vec![
+            pair("SOL", "USDT", TradingPairStatus::Active),
+            pair("BTC", "USDT", TradingPairStatus::Active),
+            pair("ETH", "USDT", TradingPairStatus::MaintenanceMode),
+            pair("LUNA", "USDT", TradingPairStatus::Delisted),
+        ];
+        let tickers = mini_tickers(&pairs, &market_data).await;
+
+        assert_eq!(tickers.iter().map(|t| t.symbol.as_str()).collect::<Vec<_>>(), vec!["BTC/USDT", "SOL/USDT"]);
+        assert_eq!(tickers[0].last_price, dec!(50000));
+        assert_eq!((tickers[1].last_price, tickers[1].volume_24h), (Decimal::ZERO, Decimal::ZERO));
+    }
+}
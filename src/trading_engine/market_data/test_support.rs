@@ -0,0 +1,103 @@
+// src/trading_engine/market_data/test_support.rs
+
+//! In-memory trade store shared by the market data tests.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use async_trait::async_trait;
+use parking_lot::Mutex as PLMutex;
+use sqlx::Error as SqlxError;
+use uuid::Uuid;
+
+use crate::db::models::Trade as StoredTrade;
+use crate::db::repositories::trade_repository::{TradeCursor, TradeHistoryQuery, TradePage, TradeRepositoryTrait};
+
+/// Trade rows held in memory; while `failing`, writes and sequence loads fail as if the
+/// database were down
+#[derive(Default)]
+pub struct StoredTrades {
+    trades: PLMutex<Vec<StoredTrade>>,
+    failing: AtomicBool,
+}
+
+impl StoredTrades {
+    pub fn new(trades: Vec<StoredTrade>) -> Self {
+        StoredTrades {
+            trades: PLMutex::new(trades),
+            failing: AtomicBool::new(false),
+        }
+    }
+
+    /// Rows in the order they were stored
+    pub fn rows(&self) -> Vec<StoredTrade> {
+        self.trades.lock().clone()
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    fn check(&self) -> Result<(), SqlxError> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(SqlxError::PoolTimedOut);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TradeRepositoryTrait for StoredTrades {
+    async fn create(&self, trade: &StoredTrade) -> Result<StoredTrade, SqlxError> {
+        self.check()?;
+        self.trades.lock().push(trade.clone());
+        Ok(trade.clone())
+    }
+
+    async fn create_all(&self, trades: &[StoredTrade]) -> Result<(), SqlxError> {
+        self.check()?;
+        let mut stored = self.trades.lock();
+        for trade in trades {
+            if !stored.iter().any(|t| t.id == trade.id) {
+                stored.push(trade.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<StoredTrade>, SqlxError> {
+        Ok(self.trades.lock().iter().find(|t| t.id == id).cloned())
+    }
+
+    async fn find_by_order_id(&self, order_id: Uuid) -> Result<Vec<StoredTrade>, SqlxError> {
+        Ok(self.trades.lock().iter().filter(|t| t.order_id == order_id).cloned().collect())
+    }
+
+    async fn find_by_symbol(&self, symbol: &str, limit: i64) -> Result<Vec<StoredTrade>, SqlxError> {
+        // Newest first, as the database returns them
+        let mut trades: Vec<StoredTrade> = self.trades.lock().iter().filter(|t| t.symbol == symbol).cloned().collect();
+        trades.sort_by_key(|t| std::cmp::Reverse(t.executed_at));
+        trades.truncate(limit.max(0) as usize);
+        Ok(trades)
+    }
+
+    async fn find_history(&self, symbol: &str, query: &TradeHistoryQuery) -> Result<TradePage, SqlxError> {
+        let stored = self.trades.lock();
+        let from = query.from_id
+            .map(|id| stored.iter().find(|t| t.id == id).map(TradeCursor::after));
+        let mut trades: Vec<StoredTrade> = stored.iter()
+            .filter(|t| t.symbol == symbol)
+            .filter(|t| query.start.map_or(true, |start| t.executed_at >= start))
+            .filter(|t| query.end.map_or(true, |end| t.executed_at <= end))
+            .filter(|t| query.after.map_or(true, |after| (t.executed_at, t.id) > (after.executed_at, after.id)))
+            .filter(|t| from.map_or(true, |from| from.map_or(false, |from| (t.executed_at, t.id) >= (from.executed_at, from.id))))
+            .cloned()
+            .collect();
+        trades.sort_by_key(|t| (t.executed_at, t.id));
+        trades.truncate(query.limit.max(0) as usize + 1);
+        Ok(TradePage::from_rows(trades, query.limit))
+    }
+
+    async fn last_sequence(&self, symbol: &str) -> Result<Option<i64>, SqlxError> {
+        self.check()?;
+        Ok(self.trades.lock().iter().filter(|t| t.symbol == symbol).filter_map(|t| t.sequence).max())
+    }
+}
@@ -0,0 +1,360 @@
+// src/trading_engine/market_data/trades.rs
+
+//! Public and private trade feed.
+//!
+//! Each trade gets the next trade id of its symbol. With storage configured the
+//! trade is persisted with its id, one row per party with the fee the engine
+//! charged it, in a single write before anyone sees it, and after a restart a
+//! symbol's ids continue from the highest stored one. While storage is down a
+//! symbol's trades wait and the write is retried, so no id is published unsaved
+//! or handed out twice.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, TimeZone, Utc};
+use log::{debug, error};
+use parking_lot::Mutex as PLMutex;
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::db::models::{OrderSide, Trade as StoredTrade};
+use crate::db::repositories::trade_repository::TradeRepositoryTrait;
+use crate::models::{OrderId, Price, Quantity, Side, Symbol, TradeId, UserId};
+use crate::trading_engine::matching_engine::{self, Trade};
+
+/// Parameters of the trade feed
+#[derive(Debug, Clone)]
+pub struct TradeFeedConfig {
+    /// Trades buffered for each subscriber before it lags
+    pub channel_capacity: usize,
+    /// Wait before retrying a failed storage call, doubled after each further failure
+    pub retry_delay: Duration,
+    /// Longest wait between retries
+    pub max_retry_delay: Duration,
+}
+
+impl Default for TradeFeedConfig {
+    fn default() -> Self {
+        TradeFeedConfig {
+            channel_capacity: 4096,
+            retry_delay: Duration::from_millis(100),
+            max_retry_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A trade as published on a symbol's trade channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicTrade {
+    pub symbol: Symbol,
+    /// One past the symbol's previous trade id
+    pub trade_id: u64,
+    pub id: TradeId,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub aggressor_side: Side,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A trade as one of its parties sees it on the private trades channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserTrade {
+    #[serde(flatten)]
+    pub trade: PublicTrade,
+    /// The user's own order
+    pub order_id: OrderId,
+    pub taker_order_id: OrderId,
+    pub maker_order_id: OrderId,
+    pub is_maker: bool,
+}
+
+/// A published trade with the parties it is routed to privately
+#[derive(Debug, Clone)]
+pub struct TradeEvent {
+    pub trade: PublicTrade,
+    pub taker_order_id: OrderId,
+    pub maker_order_id: OrderId,
+    pub taker_user_id: UserId,
+    pub maker_user_id: UserId,
+    /// Fees the engine charged each party
+    pub taker_fee: Decimal,
+    pub maker_fee: Decimal,
+}
+
+impl TradeEvent {
+    /// Each party's view of the trade; a self-trade gives the user both
+    pub fn user_trades(&self) -> Vec<(UserId, UserTrade)> {
+        let view = |order_id, is_maker| UserTrade {
+            trade: self.trade.clone(),
+            order_id,
+            taker_order_id: self.taker_order_id,
+            maker_order_id: self.maker_order_id,
+            is_maker,
+        };
+        vec![
+            (self.taker_user_id, view(self.taker_order_id, false)),
+            (self.maker_user_id, view(self.maker_order_id, true)),
+        ]
+    }
+}
+
+/// Numbers, persists and publishes trades from the matching engine
+pub struct TradeFeed {
+    config: TradeFeedConfig,
+    /// Last trade id of each symbol, `None` until loaded from storage. A symbol's lock is
+    /// held while its trade is stored so its ids publish in order; other symbols don't wait.
+    last_ids: PLMutex<HashMap<Symbol, Arc<Mutex<Option<u64>>>>>,
+    store: Option<Arc<dyn TradeRepositoryTrait>>,
+    updates: broadcast::Sender<TradeEvent>,
+}
+
+impl TradeFeed {
+    pub fn new(config: TradeFeedConfig) -> Self {
+        let (updates, _) = broadcast::channel(config.channel_capacity.max(1));
+        TradeFeed {
+            config,
+            last_ids: PLMutex::new(HashMap::new()),
+            store: None,
+            updates,
+        }
+    }
+
+    pub fn with_store(mut self, store: Arc<dyn TradeRepositoryTrait>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Receive every published trade, for all symbols
+    pub fn subscribe(&self) -> broadcast::Receiver<TradeEvent> {
+        self.updates.subscribe()
+    }
+
+    /// Latest trade id published for a symbol, if any since start
+    pub async fn last_trade_id(&self, symbol: &str) -> Option<u64> {
+        let last_id = self.last_ids.lock().get(symbol).cloned()?;
+        let last_id = last_id.lock().await;
+        *last_id
+    }
+
+    fn symbol_last_id(&self, symbol: &str) -> Arc<Mutex<Option<u64>>> {
+        let mut last_ids = self.last_ids.lock();
+        Arc::clone(last_ids.entry(symbol.to_string()).or_default())
+    }
+
+    /// Number, store and publish a trade. The trade's id is only taken once both
+    /// parties' rows are stored with it.
+    pub async fn publish(&self, trade: &Trade) -> TradeEvent {
+        let last_id = self.symbol_last_id(&trade.symbol);
+        let mut last_id = last_id.lock().await;
+        let previous_id = match *last_id {
+            Some(previous_id) => previous_id,
+            None => self.stored_last_id(&trade.symbol).await,
+        };
+
+        let event = TradeEvent {
+            trade: PublicTrade {
+                symbol: trade.symbol.clone(),
+                trade_id: previous_id + 1,
+                id: trade.id,
+                price: trade.price,
+                quantity: trade.quantity,
+                aggressor_side: side_of(trade.aggressor_side),
+                timestamp: Utc.timestamp_nanos(trade.timestamp as i64),
+            },
+            taker_order_id: trade.taker_order_id,
+            maker_order_id: trade.maker_order_id,
+            taker_user_id: trade.taker_user_id,
+            maker_user_id: trade.maker_user_id,
+            taker_fee: trade.taker_fee,
+            maker_fee: trade.maker_fee,
+        };
+
+        if let Some(store) = &self.store {
+            let rows = stored(&event);
+            self.retry(
+                || store.create_all(&rows),
+                |e| format!("Failed to store trade {} on {}: {}", trade.id, trade.symbol, e),
+            ).await;
+        }
+        *last_id = Some(event.trade.trade_id);
+
+        // No subscribers is not an error
+        let _ = self.updates.send(event.clone());
+        event
+    }
+
+    /// Publish trades from the matching engine in the background
+    pub fn spawn_trade_listener(self: &Arc<Self>, mut trades: mpsc::Receiver<Trade>) -> JoinHandle<()> {
+        let feed = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(trade) = trades.recv().await {
+                feed.publish(&trade).await;
+            }
+            debug!("Trade feed listener stopped");
+        })
+    }
+
+    async fn stored_last_id(&self, symbol: &str) -> u64 {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return 0,
+        };
+        let last = self.retry(
+            || store.last_sequence(symbol),
+            |e| format!("Failed to load the last trade id of {}: {}", symbol, e),
+        ).await;
+        last.unwrap_or(0).max(0) as u64
+    }
+
+    /// Run a storage call until it succeeds, logging each failure and backing off
+    async fn retry<T, E, F, Fut>(&self, mut call: F, failure: impl Fn(E) -> String) -> T
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut delay = self.config.retry_delay;
+        loop {
+            match call().await {
+                Ok(value) => return value,
+                Err(e) => {
+                    error!("{}; retrying in {:?}", failure(e), delay);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(self.config.max_retry_delay);
+                }
+            }
+        }
+    }
+}
+
+fn side_of(side: matching_engine::Side) -> Side {
+    match side {
+        matching_engine::Side::Buy => Side::Buy,
+        matching_engine::Side::Sell => Side::Sell,
+    }
+}
+
+/// Trade history rows of both parties, the taker's first under the trade's own id
+fn stored(event: &TradeEvent) -> [StoredTrade; 2] {
+    let (taker_side, maker_side) = match event.trade.aggressor_side {
+        Side::Buy => (OrderSide::Buy, OrderSide::Sell),
+        Side::Sell => (OrderSide::Sell, OrderSide::Buy),
+    };
+    let row = |id, side, order_id, user_id, fee, is_maker| StoredTrade {
+        id,
+        symbol: event.trade.symbol.clone(),
+        price: event.trade.price,
+        quantity: event.trade.quantity,
+        side,
+        order_id,
+        user_id,
+        executed_at: event.trade.timestamp,
+        fee,
+        sequence: Some(event.trade.trade_id as i64),
+        is_maker,
+    };
+    [
+        row(event.trade.id, taker_side, event.taker_order_id, event.taker_user_id, event.taker_fee, false),
+        row(Uuid::new_v4(), maker_side, event.maker_order_id, event.maker_user_id, event.maker_fee, true),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use super::super::test_support::StoredTrades;
+
+    fn config() -> TradeFeedConfig {
+        TradeFeedConfig {
+            retry_delay: Duration::from_millis(1),
+            max_retry_delay: Duration::from_millis(5),
+            ..Default::default()
+        }
+    }
+
+    fn trade(symbol: &str, aggressor_side: matching_engine::Side) -> Trade {
+        Trade::new(
+            symbol.to_string(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            dec!(50000),
+            dec!(0.1),
+            aggressor_side,
+        )
+        .with_fees(dec!(5), dec!(2))
+    }
+
+    #[tokio::test]
+    async fn test_trade_ids_continue_after_restart() {
+        let store = Arc::new(StoredTrades::default());
+        let feed = Arc::new(TradeFeed::new(config()).with_store(store.clone()));
+        let mut subscriber = feed.subscribe();
+
+        for _ in 0..3 {
+            feed.publish(&trade("BTC/USDT", matching_engine::Side::Buy)).await;
+        }
+        feed.publish(&trade("ETH/USDT", matching_engine::Side::Sell)).await;
+        assert_eq!(feed.last_trade_id("BTC/USDT").await, Some(3));
+
+        let first = subscriber.recv().await.unwrap();
+        assert_eq!((first.trade.trade_id, first.trade.aggressor_side), (1, Side::Buy));
+
+        // A trade is held back until it is stored, then published with the next id
+        store.set_failing(true);
+        let pending = tokio::spawn({
+            let feed = Arc::clone(&feed);
+            async move { feed.publish(&trade("BTC/USDT", matching_engine::Side::Buy)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!pending.is_finished());
+        assert_eq!(store.rows().len(), 8);
+        store.set_failing(false);
+        assert_eq!(pending.await.unwrap().trade.trade_id, 4);
+
+        // The last stored id is loaded again until it can be read
+        store.set_failing(true);
+        let restarted = Arc::new(TradeFeed::new(config()).with_store(store.clone()));
+        let next = tokio::spawn({
+            let restarted = Arc::clone(&restarted);
+            async move { restarted.publish(&trade("BTC/USDT", matching_engine::Side::Sell)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        store.set_failing(false);
+        let next = next.await.unwrap();
+        assert_eq!(next.trade.trade_id, 5);
+        let eth = restarted.publish(&trade("ETH/USDT", matching_engine::Side::Sell)).await;
+        assert_eq!(eth.trade.trade_id, 2);
+
+        // Each party has a row with the fee it was charged; the taker's carries the trade's id
+        let stored = store.rows();
+        assert_eq!(stored.len(), 14);
+        let (taker, maker) = (&stored[10], &stored[11]);
+        assert_eq!((taker.id, taker.sequence, taker.side, taker.order_id), (next.trade.id, Some(5), OrderSide::Sell, next.taker_order_id));
+        assert_eq!((maker.sequence, maker.side, maker.user_id, maker.is_maker), (Some(5), OrderSide::Buy, next.maker_user_id, true));
+        assert_eq!((taker.fee, maker.fee), (dec!(5), dec!(2)));
+    }
+
+    #[tokio::test]
+    async fn test_user_trades_show_each_party_its_role() {
+        let feed = TradeFeed::new(TradeFeedConfig::default());
+        let event = feed.publish(&trade("BTC/USDT", matching_engine::Side::Sell)).await;
+
+        let views = event.user_trades();
+        let (taker, taker_view) = &views[0];
+        let (maker, maker_view) = &views[1];
+        assert_eq!((*taker, taker_view.order_id, taker_view.is_maker), (event.taker_user_id, event.taker_order_id, false));
+        assert_eq!((*maker, maker_view.order_id, maker_view.is_maker), (event.maker_user_id, event.maker_order_id, true));
+        assert_eq!(maker_view.taker_order_id, event.taker_order_id);
+
+        let json = serde_json::to_value(maker_view).unwrap();
+        assert_eq!(json["trade_id"], 1);
+        assert_eq!(json["aggressor_side"], "sell");
+    }
+}
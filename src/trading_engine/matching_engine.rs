@@ -1,12 +1,17 @@
 
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use tokio::sync::{RwLock, Mutex};
+use tokio::sync::{mpsc, RwLock, Mutex};
 use uuid::Uuid;
 use rust_decimal::Decimal;
 use chrono::{DateTime, Utc};
+use log::warn;
+use parking_lot::{Mutex as PLMutex, RwLock as PLRwLock};
+
+use crate::models;
+use super::order_book::OrderBook as MarketBook;
 
 // Define core types
 pub type OrderId = Uuid;
@@ -124,10 +129,16 @@ pub struct Trade {
     pub symbol: Symbol,
     pub taker_order_id: OrderId,
     pub maker_order_id: OrderId,
+    pub taker_user_id: UserId,
+    pub maker_user_id: UserId,
     pub price: Price,
     pub quantity: Quantity,
-    pub side: Side,
+    /// Side of the taker, whose order crossed the book
+    pub aggressor_side: Side,
     pub timestamp: Timestamp,
+    /// Fees charged to each party, in the quote asset
+    pub taker_fee: Decimal,
+    pub maker_fee: Decimal,
 }
 
 impl Trade {
@@ -135,9 +146,11 @@ impl Trade {
         symbol: Symbol,
         taker_order_id: OrderId,
         maker_order_id: OrderId,
+        taker_user_id: UserId,
+        maker_user_id: UserId,
         price: Price,
         quantity: Quantity,
-        side: Side,
+        aggressor_side: Side,
     ) -> Self {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -149,544 +162,99 @@ impl Trade {
             symbol,
             taker_order_id,
             maker_order_id,
+            taker_user_id,
+            maker_user_id,
             price,
             quantity,
-            side,
+            aggressor_side,
             timestamp: now,
-        }
-    }
-}
-
-/// A price level in the order book
-#[derive(Debug, Clone)]
-struct PriceLevel {
-    price: Price,
-    orders: Vec<Arc<Order>>,
-    total_quantity: Quantity,
-}
-
-impl PriceLevel {
-    fn new(price: Price) -> Self {
-        PriceLevel {
-            price,
-            orders: Vec::new(),
-            total_quantity: Decimal::ZERO,
+            taker_fee: Decimal::ZERO,
+            maker_fee: Decimal::ZERO,
         }
     }
     
-    fn add_order(&mut self, order: Arc<Order>) {
-        self.total_quantity += order.remaining_quantity();
-        self.orders.push(order);
-    }
-    
-    fn remove_order(&mut self, order_id: &OrderId) -> Option<Arc<Order>> {
-        if let Some(index) = self.orders.iter().position(|o| o.id == *order_id) {
-            let order = self.orders.remove(index);
-            self.total_quantity -= order.remaining_quantity();
-            Some(order)
-        } else {
-            None
-        }
-    }
-    
-    fn is_empty(&self) -> bool {
-        self.orders.is_empty()
+    pub fn with_fees(mut self, taker_fee: Decimal, maker_fee: Decimal) -> Self {
+        self.taker_fee = taker_fee;
+        self.maker_fee = maker_fee;
+        self
     }
 }
 
-/// The order book for a trading pair
-#[derive(Debug)]
-pub struct OrderBook {
-    symbol: Symbol,
-    bids: BTreeMap<Price, PriceLevel>,
-    asks: BTreeMap<Price, PriceLevel>,
-    orders: HashMap<OrderId, (Side, Price)>,
-    last_update_time: Timestamp,
+/// Fees charged on each fill, as fractions of its notional
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeRates {
+    /// Charged to the resting order
+    pub maker: Decimal,
+    /// Charged to the crossing order
+    pub taker: Decimal,
 }
 
-impl OrderBook {
-    pub fn new(symbol: Symbol) -> Self {
-        OrderBook {
-            symbol,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-            orders: HashMap::new(),
-            last_update_time: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_nanos() as u64,
-        }
-    }
-    
-    pub fn add_order(&mut self, order: Arc<Order>) -> Result<(), String> {
-        if order.symbol != self.symbol {
-            return Err(format!("Symbol mismatch: expected {}, got {}", self.symbol, order.symbol));
-        }
-        
-        let price = match order.price {
-            Some(p) => p,
-            None => return Err("Market orders should be handled separately".to_string()),
-        };
-        
-        match order.side {
-            Side::Buy => {
-                let price_level = self.bids
-                    .entry(price)
-                    .or_insert_with(|| PriceLevel::new(price));
-                price_level.add_order(order.clone());
-                self.orders.insert(order.id, (Side::Buy, price));
-            },
-            Side::Sell => {
-                let price_level = self.asks
-                    .entry(price)
-                    .or_insert_with(|| PriceLevel::new(price));
-                price_level.add_order(order.clone());
-                self.orders.insert(order.id, (Side::Sell, price));
-            }
+impl Default for FeeRates {
+    fn default() -> Self {
+        FeeRates {
+            maker: Decimal::new(1, 3),
+            taker: Decimal::new(1, 3),
         }
-        
-        self.last_update_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64;
-        
-        Ok(())
-    }
-    
-    pub fn remove_order(&mut self, order_id: &OrderId) -> Option<Arc<Order>> {
-        if let Some((side, price)) = self.orders.remove(order_id) {
-            match side {
-                Side::Buy => {
-                    if let Some(price_level) = self.bids.get_mut(&price) {
-                        let order = price_level.remove_order(order_id);
-                        if price_level.is_empty() {
-                            self.bids.remove(&price);
-                        }
-                        
-                        self.last_update_time = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_nanos() as u64;
-                        
-                        return order;
-                    }
-                },
-                Side::Sell => {
-                    if let Some(price_level) = self.asks.get_mut(&price) {
-                        let order = price_level.remove_order(order_id);
-                        if price_level.is_empty() {
-                            self.asks.remove(&price);
-                        }
-                        
-                        self.last_update_time = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_nanos() as u64;
-                        
-                        return order;
-                    }
-                }
-            }
-        }
-        None
-    }
-    
-    pub fn get_best_bid(&self) -> Option<Price> {
-        self.bids.keys().next_back().cloned()
-    }
-    
-    pub fn get_best_ask(&self) -> Option<Price> {
-        self.asks.keys().next().cloned()
-    }
-    
-    pub fn get_bid_depth(&self, levels: usize) -> Vec<(Price, Quantity)> {
-        self.bids.iter()
-            .rev()
-            .take(levels)
-            .map(|(price, level)| (*price, level.total_quantity))
-            .collect()
-    }
-    
-    pub fn get_ask_depth(&self, levels: usize) -> Vec<(Price, Quantity)> {
-        self.asks.iter()
-            .take(levels)
-            .map(|(price, level)| (*price, level.total_quantity))
-            .collect()
-    }
-    
-    pub fn match_limit_order(&mut self, order: &mut Order) -> Vec<Trade> {
-        let mut trades = Vec::new();
-        
-        // Cannot match if order has no price
-        let order_price = match order.price {
-            Some(price) => price,
-            None => return trades,
-        };
-        
-        match order.side {
-            Side::Buy => {
-                // Buy orders match against asks (sell orders)
-                let mut ask_prices: Vec<Price> = self.asks.keys()
-                    .take_while(|&&price| price <= order_price)
-                    .cloned()
-                    .collect();
-                
-                for ask_price in ask_prices {
-                    if order.remaining_quantity() <= Decimal::ZERO {
-                        break;
-                    }
-                    
-                    if let Some(price_level) = self.asks.get_mut(&ask_price) {
-                        let mut i = 0;
-                        while i < price_level.orders.len() {
-                            let maker_order = &price_level.orders[i];
-                            
-                            // Calculate trade quantity
-                            let trade_quantity = std::cmp::min(
-                                order.remaining_quantity(),
-                                maker_order.remaining_quantity(),
-                            );
-                            
-                            if trade_quantity > Decimal::ZERO {
-                                // Create a trade
-                                let trade = Trade::new(
-                                    order.symbol.clone(),
-                                    order.id,
-                                    maker_order.id,
-                                    ask_price,
-                                    trade_quantity,
-                                    Side::Buy,
-                                );
-                                
-                                trades.push(trade);
-                                
-                                // Update order quantities
-                                order.filled_quantity += trade_quantity;
-                                
-                                // Note: This is a simplification. In a real implementation,
-                                // we would need to update the maker order as well, which would
-                                // require a mutable reference.
-                                // For now, we just update our tracking of the total quantity.
-                                price_level.total_quantity -= trade_quantity;
-                                
-                                // Check if maker order is filled
-                                if maker_order.remaining_quantity() <= trade_quantity {
-                                    // Remove filled maker order
-                                    price_level.orders.remove(i);
-                                    self.orders.remove(&maker_order.id);
-                                } else {
-                                    i += 1;
-                                }
-                            } else {
-                                i += 1;
-                            }
-                            
-                            if order.remaining_quantity() <= Decimal::ZERO {
-                                break;
-                            }
-                        }
-                        
-                        // Remove empty price level
-                        if price_level.is_empty() {
-                            self.asks.remove(&ask_price);
-                        }
-                    }
-                }
-            },
-            Side::Sell => {
-                // Sell orders match against bids (buy orders)
-                let mut bid_prices: Vec<Price> = self.bids.keys()
-                    .rev()
-                    .take_while(|&&price| price >= order_price)
-                    .cloned()
-                    .collect();
-                
-                for bid_price in bid_prices {
-                    if order.remaining_quantity() <= Decimal::ZERO {
-                        break;
-                    }
-                    
-                    if let Some(price_level) = self.bids.get_mut(&bid_price) {
-                        let mut i = 0;
-                        while i < price_level.orders.len() {
-                            let maker_order = &price_level.orders[i];
-                            
-                            // Calculate trade quantity
-                            let trade_quantity = std::cmp::min(
-                                order.remaining_quantity(),
-                                maker_order.remaining_quantity(),
-                            );
-                            
-                            if trade_quantity > Decimal::ZERO {
-                                // Create a trade
-                                let trade = Trade::new(
-                                    order.symbol.clone(),
-                                    order.id,
-                                    maker_order.id,
-                                    bid_price,
-                                    trade_quantity,
-                                    Side::Sell,
-                                );
-                                
-                                trades.push(trade);
-                                
-                                // Update order quantities
-                                order.filled_quantity += trade_quantity;
-                                
-                                // Note: This is a simplification. In a real implementation,
-                                // we would need to update the maker order as well.
-                                price_level.total_quantity -= trade_quantity;
-                                
-                                // Check if maker order is filled
-                                if maker_order.remaining_quantity() <= trade_quantity {
-                                    // Remove filled maker order
-                                    price_level.orders.remove(i);
-                                    self.orders.remove(&maker_order.id);
-                                } else {
-                                    i += 1;
-                                }
-                            } else {
-                                i += 1;
-                            }
-                            
-                            if order.remaining_quantity() <= Decimal::ZERO {
-                                break;
-                            }
-                        }
-                        
-                        // Remove empty price level
-                        if price_level.is_empty() {
-                            self.bids.remove(&bid_price);
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Update order status
-        if order.remaining_quantity() <= Decimal::ZERO {
-            order.status = OrderStatus::Filled;
-        } else if order.filled_quantity > Decimal::ZERO {
-            order.status = OrderStatus::PartiallyFilled;
-            
-            // Add remainder to order book if not IOC or FOK
-            match order.time_in_force {
-                TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => {
-                    order.status = if order.filled_quantity > Decimal::ZERO {
-                        OrderStatus::PartiallyFilled
-                    } else {
-                        OrderStatus::Canceled
-                    };
-                },
-                _ => {
-                    // Only add to book if there's remaining quantity and it's not IOC/FOK
-                    if order.remaining_quantity() > Decimal::ZERO && order.price.is_some() {
-                        let order_arc = Arc::new(order.clone());
-                        match self.add_order(order_arc) {
-                            Ok(_) => {},
-                            Err(e) => println!("Error adding remainder to book: {}", e),
-                        }
-                    }
-                }
-            }
-        }
-        
-        self.last_update_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64;
-        
-        trades
-    }
-    
-    pub fn match_market_order(&mut self, order: &mut Order) -> Vec<Trade> {
-        let mut trades = Vec::new();
-        
-        match order.side {
-            Side::Buy => {
-                // Buy market orders match against asks (sell orders) at any price
-                let mut ask_prices: Vec<Price> = self.asks.keys().cloned().collect();
-                
-                for ask_price in ask_prices {
-                    if order.remaining_quantity() <= Decimal::ZERO {
-                        break;
-                    }
-                    
-                    if let Some(price_level) = self.asks.get_mut(&ask_price) {
-                        let mut i = 0;
-                        while i < price_level.orders.len() {
-                            let maker_order = &price_level.orders[i];
-                            
-                            // Calculate trade quantity
-                            let trade_quantity = std::cmp::min(
-                                order.remaining_quantity(),
-                                maker_order.remaining_quantity(),
-                            );
-                            
-                            if trade_quantity > Decimal::ZERO {
-                                // Create a trade
-                                let trade = Trade::new(
-                                    order.symbol.clone(),
-                                    order.id,
-                                    maker_order.id,
-                                    ask_price,
-                                    trade_quantity,
-                                    Side::Buy,
-                                );
-                                
-                                trades.push(trade);
-                                
-                                // Update order quantities
-                                order.filled_quantity += trade_quantity;
-                                
-                                // Note: Simplification as mentioned above
-                                price_level.total_quantity -= trade_quantity;
-                                
-                                // Check if maker order is filled
-                                if maker_order.remaining_quantity() <= trade_quantity {
-                                    // Remove filled maker order
-                                    price_level.orders.remove(i);
-                                    self.orders.remove(&maker_order.id);
-                                } else {
-                                    i += 1;
-                                }
-                            } else {
-                                i += 1;
-                            }
-                            
-                            if order.remaining_quantity() <= Decimal::ZERO {
-                                break;
-                            }
-                        }
-                        
-                        // Remove empty price level
-                        if price_level.is_empty() {
-                            self.asks.remove(&ask_price);
-                        }
-                    }
-                }
-            },
-            Side::Sell => {
-                // Sell market orders match against bids (buy orders) at any price
-                let mut bid_prices: Vec<Price> = self.bids.keys().rev().cloned().collect();
-                
-                for bid_price in bid_prices {
-                    if order.remaining_quantity() <= Decimal::ZERO {
-                        break;
-                    }
-                    
-                    if let Some(price_level) = self.bids.get_mut(&bid_price) {
-                        let mut i = 0;
-                        while i < price_level.orders.len() {
-                            let maker_order = &price_level.orders[i];
-                            
-                            // Calculate trade quantity
-                            let trade_quantity = std::cmp::min(
-                                order.remaining_quantity(),
-                                maker_order.remaining_quantity(),
-                            );
-                            
-                            if trade_quantity > Decimal::ZERO {
-                                // Create a trade
-                                let trade = Trade::new(
-                                    order.symbol.clone(),
-                                    order.id,
-                                    maker_order.id,
-                                    bid_price,
-                                    trade_quantity,
-                                    Side::Sell,
-                                );
-                                
-                                trades.push(trade);
-                                
-                                // Update order quantities
-                                order.filled_quantity += trade_quantity;
-                                
-                                // Note: Simplification as mentioned above
-                                price_level.total_quantity -= trade_quantity;
-                                
-                                // Check if maker order is filled
-                                if maker_order.remaining_quantity() <= trade_quantity {
-                                    // Remove filled maker order
-                                    price_level.orders.remove(i);
-                                    self.orders.remove(&maker_order.id);
-                                } else {
-                                    i += 1;
-                                }
-                            } else {
-                                i += 1;
-                            }
-                            
-                            if order.remaining_quantity() <= Decimal::ZERO {
-                                break;
-                            }
-                        }
-                        
-                        // Remove empty price level
-                        if price_level.is_empty() {
-                            self.bids.remove(&bid_price);
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Update order status
-        if order.remaining_quantity() <= Decimal::ZERO {
-            order.status = OrderStatus::Filled;
-        } else if order.filled_quantity > Decimal::ZERO {
-            order.status = OrderStatus::PartiallyFilled;
-        } else {
-            order.status = OrderStatus::Rejected; // Market orders that can't be filled are rejected
-        }
-        
-        self.last_update_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64;
-        
-        trades
     }
 }
 
 /// The matching engine for a single trading pair
 pub struct MatchingEngine {
-    order_book: RwLock<OrderBook>,
+    /// The book orders rest in; it also publishes the events and level changes the
+    /// depth, order-by-order and analytics feeds follow
+    order_book: Arc<PLRwLock<MarketBook>>,
+    /// Owners of the orders resting in the book, to attribute fills to makers
+    resting_users: PLMutex<HashMap<OrderId, UserId>>,
     symbol: Symbol,
+    fee_rates: FeeRates,
     trade_history: Mutex<Vec<Trade>>,
+    /// Receivers of every trade, held for the whole order so they see trades in execution order
+    trade_listeners: Mutex<Vec<mpsc::Sender<Trade>>>,
 }
 
 impl MatchingEngine {
     pub fn new(symbol: Symbol) -> Self {
         MatchingEngine {
-            order_book: RwLock::new(OrderBook::new(symbol.clone())),
+            order_book: Arc::new(PLRwLock::new(MarketBook::new(symbol.clone()))),
+            resting_users: PLMutex::new(HashMap::new()),
             symbol,
+            fee_rates: FeeRates::default(),
             trade_history: Mutex::new(Vec::new()),
+            trade_listeners: Mutex::new(Vec::new()),
         }
     }
     
-    pub async fn process_order(&self, mut order: Order) -> Result<Vec<Trade>, String> {
+    pub fn with_fee_rates(mut self, fee_rates: FeeRates) -> Self {
+        self.fee_rates = fee_rates;
+        self
+    }
+    
+    /// The book market data feeds subscribe to
+    pub fn market_book(&self) -> Arc<PLRwLock<MarketBook>> {
+        Arc::clone(&self.order_book)
+    }
+    
+    /// Send every trade from now on to a listener, until it hangs up
+    pub async fn add_trade_listener(&self, listener: mpsc::Sender<Trade>) {
+        self.trade_listeners.lock().await.push(listener);
+    }
+    
+    pub async fn process_order(&self, order: Order) -> Result<Vec<Trade>, String> {
         // Validate the order symbol
         if order.symbol != self.symbol {
             return Err(format!("Symbol mismatch: expected {}, got {}", self.symbol, order.symbol));
         }
         
-        let mut order_book = self.order_book.write().await;
-        let trades = match order.order_type {
+        let mut book_order = models::Order::from(&order);
+        match order.order_type {
             OrderType::Limit => {
                 if order.price.is_none() {
                     return Err("Limit orders must have a price".to_string());
                 }
-                
-                // Process limit order
-                order_book.match_limit_order(&mut order)
             },
             OrderType::Market => {
                 // Price is ignored for market orders
-                order.price = None;
-                
-                // Process market order
-                order_book.match_market_order(&mut order)
+                book_order.price = None;
             },
             _ => {
                 // Simplification: Only handling basic limit and market orders for now
@@ -694,22 +262,87 @@ impl MatchingEngine {
             }
         };
         
-        // Record trades in history
+        let mut listeners = self.trade_listeners.lock().await;
+        let trades = {
+            let mut order_book = self.order_book.write();
+            let mut resting_users = self.resting_users.lock();
+            let book_trades = match order.order_type {
+                OrderType::Market => order_book.match_market_order(&mut book_order),
+                _ => order_book.match_limit_order(&mut book_order),
+            };
+            
+            let trades: Vec<Trade> = book_trades.into_iter()
+                .map(|trade| {
+                    // Makers that filled have left the book and need no attributing after this
+                    let maker_user_id = if order_book.contains_order(&trade.maker_order_id) {
+                        resting_users.get(&trade.maker_order_id).copied()
+                    } else {
+                        resting_users.remove(&trade.maker_order_id)
+                    };
+                    let maker_user_id = maker_user_id.unwrap_or_else(|| {
+                        warn!("No owner recorded for maker order {} in {}", trade.maker_order_id, self.symbol);
+                        Uuid::nil()
+                    });
+                    let notional = trade.price * trade.quantity;
+                    Trade {
+                        id: trade.id,
+                        symbol: trade.symbol,
+                        taker_order_id: trade.taker_order_id,
+                        maker_order_id: trade.maker_order_id,
+                        taker_user_id: order.user_id,
+                        maker_user_id,
+                        price: trade.price,
+                        quantity: trade.quantity,
+                        aggressor_side: order.side,
+                        timestamp: trade.timestamp,
+                        taker_fee: notional * self.fee_rates.taker,
+                        maker_fee: notional * self.fee_rates.maker,
+                    }
+                })
+                .collect();
+            
+            if order_book.contains_order(&order.id) {
+                resting_users.insert(order.id, order.user_id);
+            }
+            trades
+        };
+        
         if !trades.is_empty() {
-            let mut history = self.trade_history.lock().await;
-            history.extend(trades.clone());
+            // Record trades in history
+            self.trade_history.lock().await.extend(trades.clone());
+            
+            // Listeners that hung up are dropped
+            let mut open = Vec::with_capacity(listeners.len());
+            for listener in listeners.drain(..) {
+                let mut delivered = true;
+                for trade in &trades {
+                    if listener.send(trade.clone()).await.is_err() {
+                        delivered = false;
+                        break;
+                    }
+                }
+                if delivered {
+                    open.push(listener);
+                }
+            }
+            *listeners = open;
         }
         
         Ok(trades)
     }
     
-    pub async fn cancel_order(&self, order_id: OrderId) -> Result<Option<Arc<Order>>, String> {
-        let mut order_book = self.order_book.write().await;
-        Ok(order_book.remove_order(&order_id))
+    /// Cancel a resting order, returning it as it was in the book
+    pub async fn cancel_order(&self, order_id: OrderId) -> Result<Option<models::Order>, String> {
+        let mut order_book = self.order_book.write();
+        let removed = order_book.remove_order(&order_id).map(|order| order.read().clone());
+        if removed.is_some() {
+            self.resting_users.lock().remove(&order_id);
+        }
+        Ok(removed)
     }
     
     pub async fn get_order_book_snapshot(&self, depth: usize) -> Result<(Vec<(Price, Quantity)>, Vec<(Price, Quantity)>), String> {
-        let order_book = self.order_book.read().await;
+        let order_book = self.order_book.read();
         let bids = order_book.get_bid_depth(depth);
         let asks = order_book.get_ask_depth(depth);
         Ok((bids, asks))
@@ -724,22 +357,42 @@ impl MatchingEngine {
 /// Manager for multiple trading pairs
 pub struct MatchingEngineManager {
     engines: RwLock<HashMap<Symbol, Arc<MatchingEngine>>>,
+    /// Charged by every engine
+    fee_rates: FeeRates,
+    /// Given to every engine, including those added later
+    trade_listeners: PLMutex<Vec<mpsc::Sender<Trade>>>,
+    /// Told about every book added from now on
+    book_listeners: PLMutex<Vec<mpsc::UnboundedSender<(Symbol, Arc<PLRwLock<MarketBook>>)>>>,
 }
 
 impl MatchingEngineManager {
     pub fn new() -> Self {
         MatchingEngineManager {
             engines: RwLock::new(HashMap::new()),
+            fee_rates: FeeRates::default(),
+            trade_listeners: PLMutex::new(Vec::new()),
+            book_listeners: PLMutex::new(Vec::new()),
         }
     }
     
+    pub fn with_fee_rates(mut self, fee_rates: FeeRates) -> Self {
+        self.fee_rates = fee_rates;
+        self
+    }
+    
     pub async fn add_symbol(&self, symbol: Symbol) -> Result<(), String> {
         let mut engines = self.engines.write().await;
         if engines.contains_key(&symbol) {
             return Err(format!("Symbol already exists: {}", symbol));
         }
         
-        let engine = Arc::new(MatchingEngine::new(symbol.clone()));
+        let engine = Arc::new(MatchingEngine::new(symbol.clone()).with_fee_rates(self.fee_rates));
+        let trade_listeners = self.trade_listeners.lock().clone();
+        for listener in trade_listeners {
+            engine.add_trade_listener(listener).await;
+        }
+        self.book_listeners.lock()
+            .retain(|listener| listener.send((symbol.clone(), engine.market_book())).is_ok());
         engines.insert(symbol, engine);
         Ok(())
     }
@@ -756,10 +409,129 @@ impl MatchingEngineManager {
         engine.process_order(order).await
     }
     
-    pub async fn cancel_order(&self, symbol: &Symbol, order_id: OrderId) -> Result<Option<Arc<Order>>, String> {
+    pub async fn cancel_order(&self, symbol: &Symbol, order_id: OrderId) -> Result<Option<models::Order>, String> {
         let engine = self.get_engine(symbol).await?;
         engine.cancel_order(order_id).await
     }
+    
+    /// Every symbol's market data book, in symbol order
+    pub async fn market_books(&self) -> Vec<(Symbol, Arc<PLRwLock<MarketBook>>)> {
+        let engines = self.engines.read().await;
+        let mut books: Vec<_> = engines.iter()
+            .map(|(symbol, engine)| (symbol.clone(), engine.market_book()))
+            .collect();
+        books.sort_by(|a, b| a.0.cmp(&b.0));
+        books
+    }
+    
+    /// Send the trades of every engine, including engines added later, to a listener
+    pub async fn add_trade_listener(&self, listener: mpsc::Sender<Trade>) {
+        // Engines are locked so one added meanwhile gets the listener exactly once
+        let engines = self.engines.write().await;
+        self.trade_listeners.lock().push(listener.clone());
+        for engine in engines.values() {
+            engine.add_trade_listener(listener.clone()).await;
+        }
+    }
+    
+    /// Every book, starting with those already open in symbol order, then each one as
+    /// it is added, so feeds can follow books opened after startup
+    pub async fn subscribe_books(&self) -> mpsc::UnboundedReceiver<(Symbol, Arc<PLRwLock<MarketBook>>)> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let engines = self.engines.write().await;
+        let mut books: Vec<_> = engines.iter()
+            .map(|(symbol, engine)| (symbol.clone(), engine.market_book()))
+            .collect();
+        books.sort_by(|a, b| a.0.cmp(&b.0));
+        for book in books {
+            let _ = sender.send(book);
+        }
+        self.book_listeners.lock().push(sender);
+        receiver
+    }
+}
+
+impl From<Side> for models::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => models::Side::Buy,
+            Side::Sell => models::Side::Sell,
+        }
+    }
+}
+
+impl From<OrderType> for models::OrderType {
+    fn from(order_type: OrderType) -> Self {
+        match order_type {
+            OrderType::Limit => models::OrderType::Limit,
+            OrderType::Market => models::OrderType::Market,
+            OrderType::StopLoss => models::OrderType::StopLoss,
+            OrderType::StopLimit => models::OrderType::StopLimit,
+            OrderType::TrailingStop => models::OrderType::TrailingStop,
+            OrderType::FillOrKill => models::OrderType::FillOrKill,
+            OrderType::ImmediateOrCancel => models::OrderType::ImmediateOrCancel,
+            OrderType::PostOnly => models::OrderType::PostOnly,
+        }
+    }
+}
+
+impl From<TimeInForce> for models::TimeInForce {
+    fn from(time_in_force: TimeInForce) -> Self {
+        match time_in_force {
+            TimeInForce::GoodTillCancel => models::TimeInForce::GoodTillCancel,
+            TimeInForce::ImmediateOrCancel => models::TimeInForce::ImmediateOrCancel,
+            TimeInForce::FillOrKill => models::TimeInForce::FillOrKill,
+            TimeInForce::GoodTillDate(at) => models::TimeInForce::GoodTillDate(at),
+        }
+    }
+}
+
+impl From<OrderStatus> for models::OrderStatus {
+    fn from(status: OrderStatus) -> Self {
+        match status {
+            OrderStatus::New => models::OrderStatus::New,
+            OrderStatus::PartiallyFilled => models::OrderStatus::PartiallyFilled,
+            OrderStatus::Filled => models::OrderStatus::Filled,
+            OrderStatus::Canceled => models::OrderStatus::Canceled,
+            OrderStatus::Rejected => models::OrderStatus::Rejected,
+            OrderStatus::Expired => models::OrderStatus::Expired,
+        }
+    }
+}
+
+impl From<&Order> for models::Order {
+    fn from(order: &Order) -> Self {
+        models::Order {
+            id: order.id,
+            user_id: order.user_id,
+            symbol: order.symbol.clone(),
+            side: order.side.into(),
+            order_type: order.order_type.into(),
+            price: order.price,
+            quantity: order.quantity,
+            filled_quantity: order.filled_quantity,
+            status: order.status.into(),
+            time_in_force: order.time_in_force.into(),
+            created_at: order.created_at,
+            updated_at: order.updated_at,
+            stop_price: order.stop_price,
+        }
+    }
+}
+
+impl From<&Trade> for models::Trade {
+    fn from(trade: &Trade) -> Self {
+        models::Trade {
+            id: trade.id,
+            symbol: trade.symbol.clone(),
+            taker_order_id: trade.taker_order_id,
+            maker_order_id: trade.maker_order_id,
+            price: trade.price,
+            quantity: trade.quantity,
+            side: trade.aggressor_side.into(),
+            timestamp: trade.timestamp,
+        }
+    }
 }
 
 // Example of using the matching engine
@@ -767,6 +539,7 @@ impl MatchingEngineManager {
 mod tests {
     use super::*;
     use rust_decimal_macros::dec;
+    use crate::trading_engine::order_book;
     
     #[tokio::test]
     async fn test_limit_order_matching() {
@@ -807,7 +580,7 @@ mod tests {
         let trade = &trades[0];
         assert_eq!(trade.price, dec!(50000));
         assert_eq!(trade.quantity, dec!(0.5));
-        assert_eq!(trade.side, Side::Buy);
+        assert_eq!(trade.aggressor_side, Side::Buy);
         
         // Get order book snapshot
         let (bids, asks) = engine.get_order_book_snapshot(10).await.unwrap();
@@ -1067,6 +840,63 @@ mod tests {
         assert_eq!(trades[0].quantity, dec!(5));
     }
     
+    #[tokio::test]
+    async fn test_engine_book_publishes_fills_and_cancels() {
+        let engine = MatchingEngine::new("BTC-USDT".to_string());
+        let book = engine.market_book();
+        let events = book.write().subscribe();
+        let (sender, mut listener) = mpsc::channel(16);
+        engine.add_trade_listener(sender).await;
+        
+        let sell_order = Order::new(
+            Uuid::new_v4(),
+            "BTC-USDT".to_string(),
+            Side::Sell,
+            OrderType::Limit,
+            Some(dec!(50000)),
+            dec!(1),
+            TimeInForce::GoodTillCancel,
+            None,
+        );
+        let sell_id = sell_order.id;
+        let seller = sell_order.user_id;
+        engine.process_order(sell_order).await.unwrap();
+        assert_eq!(book.read().get_ask_depth(10), vec![(dec!(50000), dec!(1))]);
+        
+        let buy_order = Order::new(
+            Uuid::new_v4(),
+            "BTC-USDT".to_string(),
+            Side::Buy,
+            OrderType::Limit,
+            Some(dec!(50000)),
+            dec!(0.4),
+            TimeInForce::GoodTillCancel,
+            None,
+        );
+        let trades = engine.process_order(buy_order).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].maker_user_id, seller);
+        assert_eq!((trades[0].taker_fee, trades[0].maker_fee), (dec!(20), dec!(20)));
+        assert_eq!(listener.try_recv().unwrap().id, trades[0].id);
+        assert_eq!(book.read().get_ask_depth(10), vec![(dec!(50000), dec!(0.6))]);
+        
+        let cancelled = engine.cancel_order(sell_id).await.unwrap().unwrap();
+        assert_eq!(cancelled.remaining_quantity(), dec!(0.6));
+        assert!(book.read().get_ask_depth(10).is_empty());
+        assert!(engine.cancel_order(sell_id).await.unwrap().is_none());
+        
+        let mut published = Vec::new();
+        while let Some((_, event)) = events.pop() {
+            published.push(event);
+        }
+        assert!(published.iter().any(|event| matches!(event, order_book::OrderBookEvent::OrderAdded(..))));
+        assert!(published.iter().any(|event| matches!(
+            event,
+            order_book::OrderBookEvent::TradeExecuted(trade) if trade.maker_order_id == sell_id && trade.side == models::Side::Buy
+        )));
+        assert!(matches!(published.last(), Some(order_book::OrderBookEvent::OrderRemoved(id)) if *id == sell_id));
+    }
+    
     #[tokio::test]
     async fn test_matching_engine_manager() {
         let manager = MatchingEngineManager::new();
@@ -1131,4 +961,36 @@ mod tests {
         // For this example, we'll just ensure no error was returned
         assert!(cancel_result.is_ok());
     }
+    
+    #[tokio::test]
+    async fn test_manager_reaches_symbols_added_later() {
+        let manager = MatchingEngineManager::new();
+        manager.add_symbol("BTC-USDT".to_string()).await.unwrap();
+        
+        let (sender, mut trades) = mpsc::channel(16);
+        manager.add_trade_listener(sender).await;
+        let mut books = manager.subscribe_books().await;
+        assert_eq!(books.try_recv().unwrap().0, "BTC-USDT");
+        
+        manager.add_symbol("ETH-USDT".to_string()).await.unwrap();
+        let (symbol, book) = books.try_recv().unwrap();
+        assert_eq!(symbol, "ETH-USDT");
+        
+        for side in [Side::Sell, Side::Buy] {
+            let order = Order::new(
+                Uuid::new_v4(),
+                "ETH-USDT".to_string(),
+                side,
+                OrderType::Limit,
+                Some(dec!(3000)),
+                dec!(1),
+                TimeInForce::GoodTillCancel,
+                None,
+            );
+            manager.process_order(order).await.unwrap();
+        }
+        
+        assert_eq!(trades.try_recv().unwrap().symbol, "ETH-USDT");
+        assert!(book.read().get_ask_depth(10).is_empty());
+    }
 }
@@ -12,6 +12,9 @@ pub mod order_book;
 pub mod matching_engine;
 pub mod risk_management;
 pub mod market_data;
+pub mod rate_limiter;
+pub mod surveillance;
+pub mod derivatives;
 
 use matching_engine::MatchingEngine;
 use risk_management::RiskManager;
@@ -1,7 +1,7 @@
 // src/trading_engine/order_book.rs
 
 use std::collections::{BTreeMap, HashMap};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, Weak};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 use rust_decimal::Decimal;
@@ -9,7 +9,7 @@ use parking_lot::{RwLock as PLRwLock, Mutex};
 use crossbeam::queue::SegQueue;
 use rayon::prelude::*;
 
-use crate::models::{Side, OrderId, Symbol, Price, Quantity, Timestamp, Order, Trade, OrderStatus, TimeInForce};
+use crate::models::{Side, OrderId, Symbol, Price, Quantity, Timestamp, Order, Trade, OrderStatus, TimeInForce, UserId};
 
 /// Represents order execution statistics for monitoring and analytics
 #[derive(Debug, Clone, Default)]
@@ -55,7 +55,8 @@ impl PriceLevel {
     
     fn remove_order(&mut self, order_id: &OrderId) -> Option<Arc<PLRwLock<Order>>> {
         if let Some(index) = self.orders.iter().position(|o| o.read().id == *order_id) {
-            let order = self.orders.swap_remove(index); // Use swap_remove for O(1) removal
+            // Shift rather than swap so the remaining orders keep time priority
+            let order = self.orders.remove(index);
             let quantity = order.read().remaining_quantity();
             self.total_quantity -= quantity;
             Some(order)
@@ -82,8 +83,13 @@ impl PriceLevel {
 /// Event type for order book notifications
 #[derive(Debug, Clone)]
 pub enum OrderBookEvent {
-    /// An order was added to the book
-    OrderAdded(Arc<PLRwLock<Order>>),
+    /// An order reached the book, ahead of any trades it makes; takers that never
+    /// rest are only seen here
+    OrderReceived(OrderId, UserId, Side),
+    /// An order was added to the book, with the quantity it rested with
+    OrderAdded(Arc<PLRwLock<Order>>, Quantity),
+    /// A resting order was reduced in place to the given remaining quantity
+    OrderReduced(OrderId, Quantity),
     /// An order was removed from the book
     OrderRemoved(OrderId),
     /// A trade was executed
@@ -94,6 +100,72 @@ pub enum OrderBookEvent {
     BestAskChanged(Option<Price>),
 }
 
+/// An order book event with the time it was published, in nanoseconds
+pub type TimedEvent = (Timestamp, OrderBookEvent);
+
+/// Fans items out to every subscriber's queue. The book only holds weak references,
+/// so a subscriber unsubscribes by dropping its queue and nothing is queued for
+/// nobody.
+#[derive(Debug)]
+struct Subscribers<T> {
+    queues: Vec<Weak<SegQueue<T>>>,
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Subscribers { queues: Vec::new() }
+    }
+}
+
+impl<T: Clone> Subscribers<T> {
+    fn subscribe(&mut self) -> Arc<SegQueue<T>> {
+        let queue = Arc::new(SegQueue::new());
+        self.queues.push(Arc::downgrade(&queue));
+        queue
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+    
+    /// Queue an item for every live subscriber, forgetting those that have gone
+    fn push_with(&mut self, item: impl FnOnce() -> T) {
+        if self.queues.is_empty() {
+            return;
+        }
+        let item = item();
+        self.queues.retain(|queue| match queue.upgrade() {
+            Some(queue) => {
+                queue.push(item.clone());
+                true
+            },
+            None => false,
+        });
+    }
+}
+
+impl Subscribers<TimedEvent> {
+    fn push(&mut self, event: OrderBookEvent) {
+        self.push_with(|| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64;
+            (timestamp, event)
+        });
+    }
+}
+
+/// New aggregate quantity at a price level; zero means the level was removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelUpdate {
+    /// Increases by one with every level change in the book
+    pub update_id: u64,
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
 /// The order book for a trading pair with high-performance optimizations
 #[derive(Debug)]
 pub struct OrderBook {
@@ -105,10 +177,13 @@ pub struct OrderBook {
     best_ask: Option<Price>,
     last_update_time: Timestamp,
     stats: OrderBookStats,
-    // Event queue for publishing order book events
-    events: Arc<SegQueue<OrderBookEvent>>,
+    // Event queues for publishing order book events
+    events: Subscribers<TimedEvent>,
     // Thread-safe snapshot state for efficient reads
     snapshot_lock: Arc<PLRwLock<()>>,
+    // Id of the latest level change and the queues publishing them for depth feeds
+    update_id: u64,
+    level_updates: Subscribers<LevelUpdate>,
 }
 
 impl OrderBook {
@@ -125,8 +200,10 @@ impl OrderBook {
                 .unwrap()
                 .as_nanos() as u64,
             stats: OrderBookStats::default(),
-            events: Arc::new(SegQueue::new()),
+            events: Subscribers::default(),
             snapshot_lock: Arc::new(PLRwLock::new(())),
+            update_id: 0,
+            level_updates: Subscribers::default(),
         }
     }
     
@@ -135,9 +212,52 @@ impl OrderBook {
         self.stats.clone()
     }
     
-    /// Get an event receiver for subscribing to order book events
-    pub fn subscribe(&self) -> Arc<SegQueue<OrderBookEvent>> {
-        self.events.clone()
+    /// Get an event receiver for subscribing to order book events; each subscriber
+    /// gets its own queue of the events published from now on, with the time of each,
+    /// until it drops the queue
+    pub fn subscribe(&mut self) -> Arc<SegQueue<TimedEvent>> {
+        self.events.subscribe()
+    }
+    
+    /// Announce an order about to be matched, so trades can be attributed to its owner
+    pub fn receive_order(&mut self, order: &Order) {
+        self.events.push(OrderBookEvent::OrderReceived(order.id, order.user_id, order.side));
+    }
+    
+    /// Get a queue of the price level changes from now on, for building depth feeds;
+    /// dropping the queue unsubscribes
+    pub fn subscribe_depth(&mut self) -> Arc<SegQueue<LevelUpdate>> {
+        self.level_updates.subscribe()
+    }
+    
+    /// Whether an order is resting in the book
+    pub fn contains_order(&self, order_id: &OrderId) -> bool {
+        self.orders.contains_key(order_id)
+    }
+    
+    /// Id of the latest level change; a snapshot reflects every update up to it
+    pub fn last_update_id(&self) -> u64 {
+        self.update_id
+    }
+    
+    /// Publish the current aggregate quantity at a price level
+    fn record_level(&mut self, side: Side, price: Price) {
+        self.update_id += 1;
+        if self.level_updates.is_empty() {
+            return;
+        }
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let quantity = levels.get(&price).map_or(Decimal::ZERO, |level| level.quantity());
+        let update_id = self.update_id;
+        self.level_updates.push_with(|| LevelUpdate {
+            update_id,
+            side,
+            price,
+            quantity,
+        });
     }
     
     /// Add a limit order to the book
@@ -195,6 +315,8 @@ impl OrderBook {
             }
         }
         
+        self.record_level(order_read.side, price);
+        
         // Update book stats
         self.stats.book_addition_count += 1;
         self.stats.orders_processed += 1;
@@ -212,7 +334,7 @@ impl OrderBook {
             self.stats.orders_processed as u64;
             
         // Publish order added event
-        self.events.push(OrderBookEvent::OrderAdded(order.clone()));
+        self.events.push(OrderBookEvent::OrderAdded(order.clone(), order_read.remaining_quantity()));
         
         Ok(())
     }
@@ -250,6 +372,8 @@ impl OrderBook {
                             .unwrap()
                             .as_nanos() as u64;
                             
+                        self.record_level(Side::Buy, price);
+                        
                         // Publish order removed event
                         self.events.push(OrderBookEvent::OrderRemoved(*order_id));
                         
@@ -278,6 +402,8 @@ impl OrderBook {
                             .unwrap()
                             .as_nanos() as u64;
                             
+                        self.record_level(Side::Sell, price);
+                        
                         // Publish order removed event
                         self.events.push(OrderBookEvent::OrderRemoved(*order_id));
                         
@@ -305,6 +431,86 @@ impl OrderBook {
         result
     }
     
+    /// Reduce a resting order to a smaller remaining quantity without losing its queue position
+    pub fn reduce_order(&mut self, order_id: &OrderId, remaining: Quantity) -> Result<(), String> {
+        let (side, price) = *self.orders.get(order_id)
+            .ok_or_else(|| format!("Order {} is not resting in the book", order_id))?;
+        let price_level = match side {
+            Side::Buy => self.bids.get_mut(&price),
+            Side::Sell => self.asks.get_mut(&price),
+        }
+        .ok_or_else(|| format!("Missing price level {} for order {}", price, order_id))?;
+        let order = price_level.orders.iter()
+            .find(|o| o.read().id == *order_id)
+            .cloned()
+            .ok_or_else(|| format!("Missing order {} at price level {}", order_id, price))?;
+        
+        {
+            let mut order = order.write();
+            let current = order.remaining_quantity();
+            if remaining <= Decimal::ZERO || remaining >= current {
+                return Err(format!(
+                    "Reduced quantity must be positive and below the remaining {}; cancel to remove the order",
+                    current
+                ));
+            }
+            order.quantity -= current - remaining;
+            price_level.total_quantity -= current - remaining;
+        }
+        
+        self.last_update_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        self.record_level(side, price);
+        self.events.push(OrderBookEvent::OrderReduced(*order_id, remaining));
+        
+        Ok(())
+    }
+
+    /// Fill a resting maker order with a trade matched elsewhere, such as by the matching
+    /// engine this book mirrors; publishes the same events as matching in this book would
+    pub fn apply_execution(&mut self, trade: Trade) -> Result<(), String> {
+        let (side, price) = *self.orders.get(&trade.maker_order_id)
+            .ok_or_else(|| format!("Maker order {} is not resting in the book", trade.maker_order_id))?;
+        let price_level = match side {
+            Side::Buy => self.bids.get_mut(&price),
+            Side::Sell => self.asks.get_mut(&price),
+        }
+        .ok_or_else(|| format!("Missing price level {} for order {}", price, trade.maker_order_id))?;
+        let maker_order = price_level.orders.iter()
+            .find(|o| o.read().id == trade.maker_order_id)
+            .cloned()
+            .ok_or_else(|| format!("Missing order {} at price level {}", trade.maker_order_id, price))?;
+
+        let filled = {
+            let mut maker = maker_order.write();
+            let quantity = trade.quantity.min(maker.remaining_quantity());
+            maker.filled_quantity += quantity;
+            price_level.total_quantity -= quantity;
+            maker.status = if maker.remaining_quantity() <= Decimal::ZERO {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::PartiallyFilled
+            };
+            maker.status == OrderStatus::Filled
+        };
+
+        self.stats.trades_executed += 1;
+        self.stats.volume_traded += trade.quantity;
+
+        // Publish the trade ahead of the filled maker's removal
+        self.events.push(OrderBookEvent::TradeExecuted(trade.clone()));
+        if filled {
+            self.remove_order(&trade.maker_order_id);
+        } else {
+            self.last_update_time = trade.timestamp;
+            self.record_level(side, price);
+        }
+
+        Ok(())
+    }
+    
     /// Get the best bid price
     pub fn get_best_bid(&self) -> Option<Price> {
         self.best_bid
@@ -372,6 +578,7 @@ impl OrderBook {
             .unwrap()
             .as_nanos() as u64;
             
+        self.receive_order(order);
         let mut trades = Vec::new();
         
         // Cannot match if order has no price
@@ -380,6 +587,18 @@ impl OrderBook {
             None => return trades,
         };
         
+        // A fill-or-kill order only trades if it can fill in full right away
+        if order.time_in_force == TimeInForce::FillOrKill {
+            let available = match order.side {
+                Side::Buy => self.get_volume_at_or_better(Side::Sell, order_price),
+                Side::Sell => self.get_volume_at_or_better(Side::Buy, order_price),
+            };
+            if available < order.quantity {
+                order.status = OrderStatus::Canceled;
+                return trades;
+            }
+        }
+        
         // Track original best bid/ask for change detection
         let original_best_bid = self.best_bid;
         let original_best_ask = self.best_ask;
@@ -418,6 +637,9 @@ impl OrderBook {
                                 
                                 trades.push(trade.clone());
                                 
+                                // Publish the trade ahead of the filled maker's removal
+                                self.events.push(OrderBookEvent::TradeExecuted(trade));
+                                
                                 // Update order quantities
                                 order.filled_quantity += trade_quantity;
                                 maker.filled_quantity += trade_quantity;
@@ -429,7 +651,7 @@ impl OrderBook {
                                 if maker.remaining_quantity() <= Decimal::ZERO {
                                     // Remove filled maker order
                                     maker.status = OrderStatus::Filled;
-                                    price_level.orders.swap_remove(i);
+                                    price_level.orders.remove(i);
                                     self.orders.remove(&maker.id);
                                     
                                     // Publish order removed event
@@ -442,9 +664,6 @@ impl OrderBook {
                                 // Update trading stats
                                 self.stats.trades_executed += 1;
                                 self.stats.volume_traded += trade_quantity;
-                                
-                                // Publish trade event
-                                self.events.push(OrderBookEvent::TradeExecuted(trade));
                             } else {
                                 i += 1;
                             }
@@ -463,6 +682,7 @@ impl OrderBook {
                     } else {
                         break;
                     }
+                    self.record_level(Side::Sell, ask_price);
                 }
             },
             Side::Sell => {
@@ -498,6 +718,9 @@ impl OrderBook {
                                 
                                 trades.push(trade.clone());
                                 
+                                // Publish the trade ahead of the filled maker's removal
+                                self.events.push(OrderBookEvent::TradeExecuted(trade));
+                                
                                 // Update order quantities
                                 order.filled_quantity += trade_quantity;
                                 maker.filled_quantity += trade_quantity;
@@ -509,7 +732,7 @@ impl OrderBook {
                                 if maker.remaining_quantity() <= Decimal::ZERO {
                                     // Remove filled maker order
                                     maker.status = OrderStatus::Filled;
-                                    price_level.orders.swap_remove(i);
+                                    price_level.orders.remove(i);
                                     self.orders.remove(&maker.id);
                                     
                                     // Publish order removed event
@@ -522,9 +745,6 @@ impl OrderBook {
                                 // Update trading stats
                                 self.stats.trades_executed += 1;
                                 self.stats.volume_traded += trade_quantity;
-                                
-                                // Publish trade event
-                                self.events.push(OrderBookEvent::TradeExecuted(trade));
                             } else {
                                 i += 1;
                             }
@@ -543,6 +763,7 @@ impl OrderBook {
                     } else {
                         break;
                     }
+                    self.record_level(Side::Buy, bid_price);
                 }
             }
         }
@@ -551,32 +772,27 @@ impl OrderBook {
         if order.remaining_quantity() <= Decimal::ZERO {
             order.status = OrderStatus::Filled;
             self.stats.immediate_match_count += 1;
-        } else if order.filled_quantity > Decimal::ZERO {
-            order.status = OrderStatus::PartiallyFilled;
-            
-            // Add remainder to order book if not IOC or FOK
+        } else {
             match order.time_in_force {
-                TimeInForce::ImmediateOrCancel => {
-                    // IOC orders that aren't fully filled are partially canceled
-                    order.status = OrderStatus::PartiallyFilled;
-                },
-                TimeInForce::FillOrKill => {
-                    // FOK orders that aren't fully filled should be canceled
-                    // But this shouldn't happen as we should check this before matching
-                    order.status = OrderStatus::Canceled;
-                    
-                    // Cancel all trades (in a real system, we'd use a transaction)
-                    trades.clear();
-                    order.filled_quantity = Decimal::ZERO;
+                TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => {
+                    // The unfilled remainder is cancelled
+                    order.status = if order.filled_quantity > Decimal::ZERO {
+                        OrderStatus::PartiallyFilled
+                    } else {
+                        OrderStatus::Canceled
+                    };
                 },
                 _ => {
-                    // Only add to book if there's remaining quantity and it's not IOC/FOK
-                    if order.remaining_quantity() > Decimal::ZERO && order.price.is_some() {
-                        let order_arc = Arc::new(PLRwLock::new(order.clone()));
-                        match self.add_order(order_arc) {
-                            Ok(_) => {},
-                            Err(e) => eprintln!("Error adding remainder to book: {}", e),
-                        }
+                    // The remainder rests in the book
+                    order.status = if order.filled_quantity > Decimal::ZERO {
+                        OrderStatus::PartiallyFilled
+                    } else {
+                        OrderStatus::New
+                    };
+                    let order_arc = Arc::new(PLRwLock::new(order.clone()));
+                    match self.add_order(order_arc) {
+                        Ok(_) => {},
+                        Err(e) => eprintln!("Error adding remainder to book: {}", e),
                     }
                 }
             }
@@ -606,6 +822,7 @@ impl OrderBook {
             .unwrap()
             .as_nanos() as u64;
             
+        self.receive_order(order);
         let mut trades = Vec::new();
         
         // Track original best bid/ask for change detection
@@ -645,6 +862,9 @@ impl OrderBook {
                                 
                                 trades.push(trade.clone());
                                 
+                                // Publish the trade ahead of the filled maker's removal
+                                self.events.push(OrderBookEvent::TradeExecuted(trade));
+                                
                                 // Update order quantities
                                 order.filled_quantity += trade_quantity;
                                 maker.filled_quantity += trade_quantity;
@@ -656,7 +876,7 @@ impl OrderBook {
                                 if maker.remaining_quantity() <= Decimal::ZERO {
                                     // Remove filled maker order
                                     maker.status = OrderStatus::Filled;
-                                    price_level.orders.swap_remove(i);
+                                    price_level.orders.remove(i);
                                     self.orders.remove(&maker.id);
                                     
                                     // Publish order removed event
@@ -669,9 +889,6 @@ impl OrderBook {
                                 // Update trading stats
                                 self.stats.trades_executed += 1;
                                 self.stats.volume_traded += trade_quantity;
-                                
-                                // Publish trade event
-                                self.events.push(OrderBookEvent::TradeExecuted(trade));
                             } else {
                                 i += 1;
                             }
@@ -690,6 +907,7 @@ impl OrderBook {
                     } else {
                         break;
                     }
+                    self.record_level(Side::Sell, ask_price);
                 }
             },
             Side::Sell => {
@@ -724,6 +942,9 @@ impl OrderBook {
                                 
                                 trades.push(trade.clone());
                                 
+                                // Publish the trade ahead of the filled maker's removal
+                                self.events.push(OrderBookEvent::TradeExecuted(trade));
+                                
                                 // Update order quantities
                                 order.filled_quantity += trade_quantity;
                                 maker.filled_quantity += trade_quantity;
@@ -735,7 +956,7 @@ impl OrderBook {
                                 if maker.remaining_quantity() <= Decimal::ZERO {
                                     // Remove filled maker order
                                     maker.status = OrderStatus::Filled;
-                                    price_level.orders.swap_remove(i);
+                                    price_level.orders.remove(i);
                                     self.orders.remove(&maker.id);
                                     
                                     // Publish order removed event
@@ -748,9 +969,6 @@ impl OrderBook {
                                 // Update trading stats
                                 self.stats.trades_executed += 1;
                                 self.stats.volume_traded += trade_quantity;
-                                
-                                // Publish trade event
-                                self.events.push(OrderBookEvent::TradeExecuted(trade));
                             } else {
                                 i += 1;
                             }
@@ -769,6 +987,7 @@ impl OrderBook {
                     } else {
                         break;
                     }
+                    self.record_level(Side::Buy, bid_price);
                 }
             }
         }
@@ -858,6 +1077,20 @@ impl OrderBook {
             bids: self.get_bid_depth(levels),
             asks: self.get_ask_depth(levels),
             last_update_time: self.last_update_time,
+            last_update_id: self.update_id,
+        }
+    }
+    
+    /// Copies of the resting orders on one side, per level from the best price, in queue order
+    pub fn get_level_orders(&self, side: Side) -> Vec<(Price, Vec<Order>)> {
+        let _lock = self.snapshot_lock.read();
+        
+        let copy = |level: &PriceLevel| {
+            (level.price, level.orders.iter().map(|o| o.read().clone()).collect())
+        };
+        match side {
+            Side::Buy => self.bids.values().rev().map(copy).collect(),
+            Side::Sell => self.asks.values().map(copy).collect(),
         }
     }
     
@@ -873,8 +1106,17 @@ impl OrderBook {
     
     /// Clear the order book
     pub fn clear(&mut self) {
+        let bid_prices: Vec<Price> = self.bids.keys().cloned().collect();
+        let ask_prices: Vec<Price> = self.asks.keys().cloned().collect();
         self.bids.clear();
         self.asks.clear();
+        for price in bid_prices {
+            self.record_level(Side::Buy, price);
+        }
+        for price in ask_prices {
+            self.record_level(Side::Sell, price);
+        }
+        
         self.orders.clear();
         self.best_bid = None;
         self.best_ask = None;
@@ -897,6 +1139,8 @@ pub struct OrderBookSnapshot {
     pub bids: Vec<(Price, Quantity)>,
     pub asks: Vec<(Price, Quantity)>,
     pub last_update_time: Timestamp,
+    /// Id of the latest level change included
+    pub last_update_id: u64,
 }
 
 /// Implement unit tests for the order book
@@ -1034,4 +1278,33 @@ mod tests {
         // Verify market order status
         assert_eq!(buy_order.status, OrderStatus::Filled);
     }
+    
+    #[test]
+    fn test_subscribers_leave_by_dropping_their_queues() {
+        let mut order_book = OrderBook::new("BTC/USD".to_string());
+        let events = order_book.subscribe();
+        let depth = order_book.subscribe_depth();
+        
+        // An unmatched limit order rests and is published to both
+        let mut sell_order = create_order(
+            "00000000-0000-0000-0000-000000000001",
+            Side::Sell,
+            Some(Decimal::from(10000)),
+            Decimal::from(1),
+            TimeInForce::GoodTillCancel,
+        );
+        assert!(order_book.match_limit_order(&mut sell_order).is_empty());
+        assert_eq!(sell_order.status, OrderStatus::New);
+        assert_eq!(order_book.get_ask_depth(10), vec![(Decimal::from(10000), Decimal::from(1))]);
+        assert!(!events.is_empty());
+        assert_eq!(depth.len(), 1);
+        
+        // Once dropped, nothing more is queued for them
+        drop(events);
+        drop(depth);
+        order_book.remove_order(&sell_order.id);
+        assert!(order_book.events.is_empty());
+        assert!(order_book.level_updates.is_empty());
+        assert_eq!(order_book.last_update_id(), 2);
+    }
 }
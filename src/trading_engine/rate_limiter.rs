@@ -0,0 +1,356 @@
+// src/trading_engine/rate_limiter.rs
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use parking_lot::{Mutex, RwLock};
+
+use crate::kyc::IdentityTier;
+use crate::models::UserId;
+
+/// Longest retry-after hint given to clients. A tier that never refills (Tier0)
+/// would otherwise report an unbounded wait.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+/// The kind of order-entry request being throttled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderAction {
+    /// Placing a new order
+    NewOrder,
+    /// Cancelling a resting order
+    CancelOrder,
+}
+
+/// Token bucket parameters for a single identity tier
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitTier {
+    /// Tokens added to the bucket per second
+    pub refill_per_second: f64,
+    /// Maximum number of tokens the bucket can hold (burst allowance)
+    pub burst: f64,
+}
+
+/// Outcome of a rate limit check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitDecision {
+    /// Request is allowed; carries the tokens left in the bucket
+    Allowed { remaining: f64 },
+    /// Request is throttled; the client should retry after the given delay
+    Throttled { retry_after: Duration },
+}
+
+impl RateLimitDecision {
+    /// Whether the request was allowed
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, RateLimitDecision::Allowed { .. })
+    }
+}
+
+/// Retry-after hint in milliseconds, capped at `MAX_RETRY_AFTER`
+pub fn retry_after_millis(retry_after: Duration) -> u64 {
+    retry_after.min(MAX_RETRY_AFTER).as_millis() as u64
+}
+
+/// Retry-after hint in whole seconds, rounded up, for the `Retry-After` header
+pub fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after_millis(retry_after).saturating_add(999) / 1000
+}
+
+/// A token bucket with continuous refill
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(tier: RateLimitTier, now: Instant) -> Self {
+        TokenBucket {
+            tokens: tier.burst,
+            capacity: tier.burst,
+            refill_per_second: tier.refill_per_second,
+            last_refill: now,
+        }
+    }
+
+    /// Apply new tier parameters without resetting the tokens already earned
+    fn reconfigure(&mut self, tier: RateLimitTier) {
+        self.capacity = tier.burst;
+        self.refill_per_second = tier.refill_per_second;
+        self.tokens = self.tokens.min(self.capacity);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    fn try_consume(&mut self, cost: f64, now: Instant) -> RateLimitDecision {
+        self.refill(now);
+
+        if self.tokens >= cost {
+            self.tokens -= cost;
+            return RateLimitDecision::Allowed { remaining: self.tokens };
+        }
+
+        // A request costing more than the whole bucket can never succeed at this tier,
+        // so report how long until a full bucket instead of an unreachable deficit
+        let deficit = cost.min(self.capacity) - self.tokens;
+        let retry_after = if self.refill_per_second > 0.0 {
+            Duration::from_secs_f64(deficit / self.refill_per_second)
+        } else {
+            Duration::MAX
+        };
+
+        RateLimitDecision::Throttled { retry_after }
+    }
+}
+
+/// Configuration for order-entry rate limiting
+#[derive(Debug, Clone)]
+pub struct OrderRateLimiterConfig {
+    /// Bucket parameters by KYC identity tier
+    pub identity_tiers: HashMap<IdentityTier, RateLimitTier>,
+    /// Multiplier applied to refill rate and burst by VIP fee tier level.
+    /// Levels beyond the end of the table use the last entry.
+    pub vip_multipliers: Vec<f64>,
+    /// Token cost of a new order
+    pub new_order_weight: f64,
+    /// Token cost of a cancel
+    pub cancel_weight: f64,
+}
+
+impl Default for OrderRateLimiterConfig {
+    fn default() -> Self {
+        let mut identity_tiers = HashMap::new();
+        identity_tiers.insert(IdentityTier::Tier0, RateLimitTier { refill_per_second: 0.0, burst: 0.0 }); // No trading
+        identity_tiers.insert(IdentityTier::Tier1, RateLimitTier { refill_per_second: 1.0, burst: 10.0 });
+        identity_tiers.insert(IdentityTier::Tier2, RateLimitTier { refill_per_second: 5.0, burst: 50.0 });
+        identity_tiers.insert(IdentityTier::Tier3, RateLimitTier { refill_per_second: 20.0, burst: 200.0 });
+        identity_tiers.insert(IdentityTier::Tier4, RateLimitTier { refill_per_second: 100.0, burst: 1000.0 });
+
+        Self {
+            identity_tiers,
+            vip_multipliers: vec![1.0, 1.5, 2.0, 3.0, 5.0],
+            new_order_weight: 1.0,
+            // Cancels are cheaper so that users can always pull quotes during volatility
+            cancel_weight: 0.5,
+        }
+    }
+}
+
+/// Limits assigned to a user
+#[derive(Debug, Clone, Copy)]
+struct UserLimits {
+    identity_tier: IdentityTier,
+    vip_level: i32,
+}
+
+/// Order-entry throttle shared by the REST API, WebSocket and the trading engine.
+///
+/// Each user has one token bucket sized from their KYC identity tier and scaled by
+/// their VIP fee tier. New orders and cancels draw different weights from the same
+/// bucket, and throttled requests get a retry-after hint.
+pub struct OrderRateLimiter {
+    config: OrderRateLimiterConfig,
+    /// Tier assignments by user
+    users: RwLock<HashMap<UserId, UserLimits>>,
+    /// Token buckets by user
+    buckets: Mutex<HashMap<UserId, TokenBucket>>,
+}
+
+impl OrderRateLimiter {
+    /// Create a new rate limiter with the specified configuration
+    pub fn new(config: OrderRateLimiterConfig) -> Self {
+        Self {
+            config,
+            users: RwLock::new(HashMap::new()),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Assign a user's identity tier and VIP fee tier level
+    pub fn set_user_tier(&self, user_id: UserId, identity_tier: IdentityTier, vip_level: i32) {
+        self.users.write().insert(user_id, UserLimits { identity_tier, vip_level });
+
+        // Resize an existing bucket in place so a tier change doesn't grant a fresh burst
+        let tier = self.effective_tier(identity_tier, vip_level);
+        if let Some(bucket) = self.buckets.lock().get_mut(&user_id) {
+            bucket.reconfigure(tier);
+        }
+    }
+
+    /// Change a user's identity tier after KYC, keeping their VIP level
+    pub fn set_identity_tier(&self, user_id: UserId, identity_tier: IdentityTier) {
+        let limits = self.user_limits(&user_id);
+        self.set_user_tier(user_id, identity_tier, limits.vip_level);
+    }
+
+    /// Change a user's VIP fee tier level, keeping their identity tier
+    pub fn set_vip_level(&self, user_id: UserId, vip_level: i32) {
+        let limits = self.user_limits(&user_id);
+        self.set_user_tier(user_id, limits.identity_tier, vip_level);
+    }
+
+    /// Forget a user's bucket and tier assignment
+    pub fn remove_user(&self, user_id: &UserId) {
+        self.users.write().remove(user_id);
+        self.buckets.lock().remove(user_id);
+    }
+
+    /// Get the effective bucket parameters for a user
+    pub fn user_tier(&self, user_id: &UserId) -> RateLimitTier {
+        let limits = self.user_limits(user_id);
+        self.effective_tier(limits.identity_tier, limits.vip_level)
+    }
+
+    /// Check and consume tokens for an order-entry action
+    pub fn check(&self, user_id: &UserId, action: OrderAction) -> RateLimitDecision {
+        self.check_at(user_id, action, Instant::now())
+    }
+
+    /// Check and consume tokens for an order-entry action at a given instant
+    pub fn check_at(&self, user_id: &UserId, action: OrderAction, now: Instant) -> RateLimitDecision {
+        let cost = self.weight(action);
+        let tier = self.user_tier(user_id);
+
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets
+            .entry(*user_id)
+            .or_insert_with(|| TokenBucket::new(tier, now));
+
+        bucket.try_consume(cost, now)
+    }
+
+    /// Token cost of an action
+    fn weight(&self, action: OrderAction) -> f64 {
+        match action {
+            OrderAction::NewOrder => self.config.new_order_weight,
+            OrderAction::CancelOrder => self.config.cancel_weight,
+        }
+    }
+
+    /// Get a user's tier assignment, defaulting unknown users to basic KYC
+    fn user_limits(&self, user_id: &UserId) -> UserLimits {
+        self.users
+            .read()
+            .get(user_id)
+            .copied()
+            .unwrap_or(UserLimits { identity_tier: IdentityTier::Tier1, vip_level: 0 })
+    }
+
+    /// Combine the identity tier limits with the VIP multiplier
+    fn effective_tier(&self, identity_tier: IdentityTier, vip_level: i32) -> RateLimitTier {
+        let base = self.config.identity_tiers
+            .get(&identity_tier)
+            .copied()
+            .unwrap_or(RateLimitTier { refill_per_second: 0.0, burst: 0.0 });
+
+        let multiplier = if self.config.vip_multipliers.is_empty() {
+            1.0
+        } else {
+            let index = (vip_level.max(0) as usize).min(self.config.vip_multipliers.len() - 1);
+            self.config.vip_multipliers[index]
+        };
+
+        RateLimitTier {
+            refill_per_second: base.refill_per_second * multiplier,
+            burst: base.burst * multiplier,
+        }
+    }
+}
+
+impl Default for OrderRateLimiter {
+    fn default() -> Self {
+        Self::new(OrderRateLimiterConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_burst_then_throttle_with_retry_after() {
+        let limiter = OrderRateLimiter::default();
+        let user_id = Uuid::new_v4();
+        limiter.set_user_tier(user_id, IdentityTier::Tier1, 0);
+
+        let now = Instant::now();
+
+        // Tier1 allows a burst of 10 orders
+        for _ in 0..10 {
+            assert!(limiter.check_at(&user_id, OrderAction::NewOrder, now).is_allowed());
+        }
+
+        // The 11th is throttled until one token refills (1 per second)
+        match limiter.check_at(&user_id, OrderAction::NewOrder, now) {
+            RateLimitDecision::Throttled { retry_after } => {
+                assert_eq!(retry_after, Duration::from_secs(1));
+            },
+            _ => panic!("Expected order to be throttled"),
+        }
+
+        // After waiting, the order goes through
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.check_at(&user_id, OrderAction::NewOrder, later).is_allowed());
+    }
+
+    #[test]
+    fn test_cancel_weight_is_lower() {
+        let limiter = OrderRateLimiter::default();
+        let user_id = Uuid::new_v4();
+        limiter.set_user_tier(user_id, IdentityTier::Tier1, 0);
+
+        let now = Instant::now();
+
+        // 20 cancels at weight 0.5 fit in a burst of 10
+        for _ in 0..20 {
+            assert!(limiter.check_at(&user_id, OrderAction::CancelOrder, now).is_allowed());
+        }
+        assert!(!limiter.check_at(&user_id, OrderAction::CancelOrder, now).is_allowed());
+    }
+
+    #[test]
+    fn test_tier_scaling() {
+        let limiter = OrderRateLimiter::default();
+        let user_id = Uuid::new_v4();
+
+        limiter.set_user_tier(user_id, IdentityTier::Tier0, 0);
+        assert!(!limiter.check(&user_id, OrderAction::NewOrder).is_allowed());
+
+        limiter.set_user_tier(user_id, IdentityTier::Tier3, 2);
+        let tier = limiter.user_tier(&user_id);
+        assert_eq!(tier.burst, 400.0);
+        assert_eq!(tier.refill_per_second, 40.0);
+
+        // VIP levels past the end of the table use the highest multiplier
+        limiter.set_user_tier(user_id, IdentityTier::Tier2, 99);
+        assert_eq!(limiter.user_tier(&user_id).burst, 250.0);
+
+        // KYC and VIP changes each keep the other half of the assignment
+        limiter.set_identity_tier(user_id, IdentityTier::Tier3);
+        assert_eq!(limiter.user_tier(&user_id).burst, 1000.0);
+        limiter.set_vip_level(user_id, 0);
+        assert_eq!(limiter.user_tier(&user_id).burst, 200.0);
+    }
+
+    #[test]
+    fn test_retry_after_hint_is_capped_for_tiers_that_never_refill() {
+        let limiter = OrderRateLimiter::default();
+        let user_id = Uuid::new_v4();
+        limiter.set_user_tier(user_id, IdentityTier::Tier0, 0);
+
+        match limiter.check(&user_id, OrderAction::NewOrder) {
+            RateLimitDecision::Throttled { retry_after } => {
+                assert_eq!(retry_after_millis(retry_after), 3_600_000);
+                assert_eq!(retry_after_secs(retry_after), 3600);
+            },
+            _ => panic!("Expected Tier0 to be throttled"),
+        }
+        assert_eq!(retry_after_secs(Duration::from_millis(1001)), 2);
+    }
+}
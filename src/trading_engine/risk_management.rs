@@ -1,9 +1,30 @@
 // src/trading_engine/risk_manager.rs
 
+//! Pre-trade risk checks.
+//!
+//! `validate_order` is on the order path and has a latency budget of
+//! [`RISK_CHECK_BUDGET`] (10µs). To stay within it, checks never lock a user's
+//! profile and never walk the raw config maps: each user has an immutable
+//! [`LimitSnapshot`] and each symbol a [`SymbolLimits`] entry, both computed
+//! ahead of time. Snapshots are rebuilt off the order path whenever a trade,
+//! limit change or registration alters the user's profile (see
+//! [`RiskManager::spawn_trade_listener`]) and when a market price they are
+//! valued at moves. Latency of every check type is counted into a fixed-size
+//! [`LatencyHistogram`] with atomics only, published to the metrics collector
+//! off the order path by [`RiskManager::spawn_latency_reporter`]. Market prices
+//! are read from per-symbol atomic cells and accepted orders are queued for
+//! trade attribution, so no check takes a shared lock; and
+//! `benches/risk_checks.rs` holds the microbenchmarks for the hot path.
+
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH, Duration, Instant};
 use parking_lot::RwLock;
+use crossbeam::atomic::AtomicCell;
+use crossbeam::queue::SegQueue;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use anyhow::{Result, anyhow};
@@ -14,6 +35,12 @@ use crate::models::{
     Side, OrderId, Symbol, Price, Quantity, Order, OrderStatus, 
     TimeInForce, UserId, Trade, Position
 };
+use crate::utils::metrics::MetricsCollector;
+use super::matching_engine;
+use super::rate_limiter::{OrderAction, OrderRateLimiter, RateLimitDecision};
+
+/// Latency budget for a full `validate_order` call
+pub const RISK_CHECK_BUDGET: Duration = Duration::from_micros(10);
 
 /// Represents the risk check result
 #[derive(Debug, Clone)]
@@ -22,6 +49,8 @@ pub enum RiskCheckResult {
     Accepted,
     /// Order is rejected with a reason
     Rejected { reason: String },
+    /// Order is throttled by the order-entry rate limiter
+    Throttled { retry_after: Duration },
     /// Order is modified to comply with risk limits
     Modified { 
         original_order: Arc<RwLock<Order>>,
@@ -30,6 +59,15 @@ pub enum RiskCheckResult {
     },
 }
 
+/// Where an order entered the exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSource {
+    /// REST or WebSocket; the API already drew from the user's rate limit
+    Api,
+    /// Generated inside the engine, so the risk manager applies the rate limit
+    Internal,
+}
+
 /// Tracks position and risk limits for a user
 #[derive(Debug, Clone)]
 pub struct UserRiskProfile {
@@ -45,12 +83,6 @@ pub struct UserRiskProfile {
     pub order_count: usize,
     /// Maximum order count allowed
     pub max_order_count: usize,
-    /// Rate limit: orders per minute
-    pub orders_per_minute: usize,
-    /// Order count in the current minute
-    pub current_minute_orders: usize,
-    /// Last minute timestamp
-    pub last_minute: u64,
     /// Is the user allowed to trade
     pub trading_enabled: bool,
 }
@@ -58,11 +90,6 @@ pub struct UserRiskProfile {
 impl UserRiskProfile {
     /// Create a new user risk profile with default limits
     pub fn new(user_id: UserId) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
         Self {
             user_id,
             positions: HashMap::new(),
@@ -70,9 +97,6 @@ impl UserRiskProfile {
             max_notional_value: dec!(1_000_000),
             order_count: 0,
             max_order_count: 1000,
-            orders_per_minute: 300,
-            current_minute_orders: 0,
-            last_minute: now / 60,
             trading_enabled: true,
         }
     }
@@ -130,26 +154,6 @@ impl UserRiskProfile {
             .as_nanos() as u64;
     }
     
-    /// Check rate limit
-    pub fn check_rate_limit(&mut self) -> bool {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let current_minute = now / 60;
-        
-        if current_minute != self.last_minute {
-            // Reset counter for new minute
-            self.current_minute_orders = 0;
-            self.last_minute = current_minute;
-        }
-        
-        self.current_minute_orders += 1;
-        
-        self.current_minute_orders <= self.orders_per_minute
-    }
-    
     /// Get total notional value of all positions
     pub fn get_total_notional_value(&self, current_prices: &HashMap<Symbol, Decimal>) -> Decimal {
         self.positions.iter()
@@ -173,8 +177,6 @@ pub struct RiskManagerConfig {
     pub default_max_notional_value: Decimal,
     /// Default max order count
     pub default_max_order_count: usize,
-    /// Default orders per minute
-    pub default_orders_per_minute: usize,
     /// Circuit breaker percentage
     pub circuit_breaker_pct: Decimal,
     /// Price bands for circuit breakers by symbol
@@ -209,7 +211,6 @@ impl Default for RiskManagerConfig {
             default_max_position_size_pct: dec!(0.2), // 20% of equity
             default_max_notional_value: dec!(1_000_000),
             default_max_order_count: 1000,
-            default_orders_per_minute: 300,
             circuit_breaker_pct: dec!(0.1), // 10% price move
             price_bands,
             min_order_size,
@@ -219,32 +220,381 @@ impl Default for RiskManagerConfig {
     }
 }
 
+/// Individual checks performed by `validate_order`, timed separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RiskCheckType {
+    CircuitBreaker,
+    UserStatus,
+    RateLimit,
+    OrderCount,
+    OrderSize,
+    TickSize,
+    PositionLimit,
+    Notional,
+    /// The whole `validate_order` call
+    Total,
+}
+
+impl RiskCheckType {
+    pub const ALL: [RiskCheckType; 9] = [
+        RiskCheckType::CircuitBreaker,
+        RiskCheckType::UserStatus,
+        RiskCheckType::RateLimit,
+        RiskCheckType::OrderCount,
+        RiskCheckType::OrderSize,
+        RiskCheckType::TickSize,
+        RiskCheckType::PositionLimit,
+        RiskCheckType::Notional,
+        RiskCheckType::Total,
+    ];
+
+    /// Histogram name for the check's latency in microseconds
+    pub fn metric_name(&self) -> &'static str {
+        match self {
+            RiskCheckType::CircuitBreaker => "risk.check.circuit_breaker.latency_us",
+            RiskCheckType::UserStatus => "risk.check.user_status.latency_us",
+            RiskCheckType::RateLimit => "risk.check.rate_limit.latency_us",
+            RiskCheckType::OrderCount => "risk.check.order_count.latency_us",
+            RiskCheckType::OrderSize => "risk.check.order_size.latency_us",
+            RiskCheckType::TickSize => "risk.check.tick_size.latency_us",
+            RiskCheckType::PositionLimit => "risk.check.position_limit.latency_us",
+            RiskCheckType::Notional => "risk.check.notional.latency_us",
+            RiskCheckType::Total => "risk.check.total.latency_us",
+        }
+    }
+}
+
+/// Per-symbol order limits, flattened from the config maps
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SymbolLimits {
+    /// Minimum order size
+    pub min_order_size: Option<Decimal>,
+    /// Maximum order size
+    pub max_order_size: Option<Decimal>,
+    /// Tick size for limit prices
+    pub tick_size: Option<Decimal>,
+}
+
+impl SymbolLimits {
+    /// Build the limits table for every symbol mentioned in the config
+    fn from_config(config: &RiskManagerConfig) -> HashMap<Symbol, SymbolLimits> {
+        let mut limits: HashMap<Symbol, SymbolLimits> = HashMap::new();
+        for (symbol, &size) in &config.min_order_size {
+            limits.entry(symbol.clone()).or_default().min_order_size = Some(size);
+        }
+        for (symbol, &size) in &config.max_order_size {
+            limits.entry(symbol.clone()).or_default().max_order_size = Some(size);
+        }
+        for (symbol, &tick) in &config.tick_size {
+            limits.entry(symbol.clone()).or_default().tick_size = Some(tick);
+        }
+        limits
+    }
+}
+
+/// Immutable view of a user's limits and exposure used on the order path
+#[derive(Debug, Clone)]
+pub struct LimitSnapshot {
+    /// Is the user allowed to trade
+    pub trading_enabled: bool,
+    /// Maximum order count allowed
+    pub max_order_count: usize,
+    /// Maximum notional value allowed
+    pub max_notional_value: Decimal,
+    /// Notional of all positions, valued when the snapshot was built
+    pub position_notional: Decimal,
+    /// Net position quantity by symbol
+    pub positions: HashMap<Symbol, Decimal>,
+    /// Maximum position size by symbol
+    pub max_position_size: HashMap<Symbol, Decimal>,
+    /// Monotonic version, bumped on every rebuild
+    pub version: u64,
+}
+
+impl LimitSnapshot {
+    /// Compute a snapshot from a profile and the current market prices
+    pub fn from_profile(profile: &UserRiskProfile, prices: &HashMap<Symbol, Decimal>, version: u64) -> Self {
+        Self {
+            trading_enabled: profile.trading_enabled,
+            max_order_count: profile.max_order_count,
+            max_notional_value: profile.max_notional_value,
+            position_notional: profile.get_total_notional_value(prices),
+            positions: profile.positions.iter()
+                .map(|(symbol, position)| (symbol.clone(), position.quantity))
+                .collect(),
+            max_position_size: profile.max_position_size.clone(),
+            version,
+        }
+    }
+}
+
+/// Risk state held for a registered user
+struct UserRiskEntry {
+    /// Authoritative profile, only written off the order path
+    profile: RwLock<UserRiskProfile>,
+    /// Latest published snapshot
+    snapshot: RwLock<Arc<LimitSnapshot>>,
+    /// Orders accepted for the user
+    order_count: AtomicUsize,
+}
+
+/// Number of latency buckets; bucket `i` counts durations below `2^(i + 6)` ns,
+/// the last one everything longer
+const LATENCY_BUCKETS: usize = 24;
+
+/// Fixed-size latency histogram updated with atomics only, safe for the order path
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    count: AtomicU64,
+    sum_ns: AtomicU64,
+    // Bucket counts already handed to the metrics collector
+    reported: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_ns: AtomicU64::new(0),
+            reported: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = ((64 - nanos.leading_zeros()).saturating_sub(6) as usize).min(LATENCY_BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ns.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Total recorded time in microseconds
+    pub fn sum_us(&self) -> f64 {
+        self.sum_ns.load(Ordering::Relaxed) as f64 / 1_000.0
+    }
+
+    /// Upper bound of bucket `i`, in microseconds
+    fn bucket_upper_us(i: usize) -> f64 {
+        (1u64 << (i + 6)) as f64 / 1_000.0
+    }
+
+    /// Samples recorded in each bucket since the previous call
+    fn take_unreported(&self) -> [u64; LATENCY_BUCKETS] {
+        std::array::from_fn(|i| {
+            let count = self.buckets[i].load(Ordering::Relaxed);
+            count - self.reported[i].swap(count, Ordering::Relaxed)
+        })
+    }
+
+    /// Upper bound of the bucket holding quantile `q`, in microseconds
+    pub fn quantile_us(&self, q: f64) -> Option<f64> {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((total as f64 * q).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+        for (i, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(Self::bucket_upper_us(i));
+            }
+        }
+        None
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Latency histograms of every check type and the count of calls over budget
+#[derive(Debug)]
+struct CheckLatencies {
+    histograms: HashMap<RiskCheckType, LatencyHistogram>,
+    budget_exceeded: AtomicU64,
+    // Calls over budget already handed to the metrics collector
+    budget_exceeded_reported: AtomicU64,
+}
+
+impl CheckLatencies {
+    fn new() -> Self {
+        Self {
+            histograms: RiskCheckType::ALL.iter().map(|check| (*check, LatencyHistogram::new())).collect(),
+            budget_exceeded: AtomicU64::new(0),
+            budget_exceeded_reported: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, check: RiskCheckType, elapsed: Duration) {
+        if let Some(histogram) = self.histograms.get(&check) {
+            histogram.record(elapsed);
+        }
+    }
+}
+
+/// Records the latency of each check into the check histograms
+struct CheckTimer<'a> {
+    latencies: &'a CheckLatencies,
+    start: Instant,
+    last: Instant,
+}
+
+impl<'a> CheckTimer<'a> {
+    fn new(latencies: &'a CheckLatencies) -> Self {
+        let now = Instant::now();
+        Self { latencies, start: now, last: now }
+    }
+
+    /// Record the time since the previous check
+    fn lap(&mut self, check: RiskCheckType) {
+        let now = Instant::now();
+        self.latencies.record(check, now - self.last);
+        self.last = now;
+    }
+
+    /// Record the time of the check that rejected the order
+    fn reject(&mut self, check: RiskCheckType, reason: String) -> RiskCheckResult {
+        self.lap(check);
+        RiskCheckResult::Rejected { reason }
+    }
+}
+
+impl Drop for CheckTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        self.latencies.record(RiskCheckType::Total, elapsed);
+        if elapsed > RISK_CHECK_BUDGET {
+            self.latencies.budget_exceeded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
 /// The risk manager that checks orders for compliance with risk limits
 pub struct RiskManager {
-    /// User risk profiles
-    user_profiles: HashMap<UserId, RwLock<UserRiskProfile>>,
-    /// Current market prices
-    market_prices: RwLock<HashMap<Symbol, Decimal>>,
+    /// User risk profiles and their limit snapshots
+    user_profiles: HashMap<UserId, UserRiskEntry>,
+    /// Current market prices, one cell per symbol known to the risk manager
+    market_prices: HashMap<Symbol, AtomicCell<Option<Decimal>>>,
     /// Last trade prices by symbol
     last_trade_prices: RwLock<HashMap<Symbol, Decimal>>,
     /// Order ID to user ID mapping
-    order_to_user: HashMap<OrderId, UserId>,
+    order_to_user: RwLock<HashMap<OrderId, UserId>>,
+    /// Orders accepted since trades were last attributed, moved into `order_to_user`
+    /// off the order path
+    accepted_orders: SegQueue<(OrderId, UserId)>,
     /// Circuit breaker status by symbol
     circuit_breakers: RwLock<HashMap<Symbol, bool>>,
     /// Configuration for the risk manager
     config: RiskManagerConfig,
+    /// Per-symbol limits pre-computed from the config
+    symbol_limits: HashMap<Symbol, SymbolLimits>,
+    /// Order-entry rate limiter shared with the API layer
+    rate_limiter: Arc<OrderRateLimiter>,
+    /// Metrics collector check latencies are published to
+    metrics: Option<Arc<MetricsCollector>>,
+    /// Latency of each check, recorded on the order path
+    check_latencies: CheckLatencies,
+    /// Source of snapshot versions
+    snapshot_version: AtomicU64,
 }
 
 impl RiskManager {
     /// Create a new risk manager with the specified configuration
     pub fn new(config: RiskManagerConfig) -> Self {
+        Self::with_rate_limiter(config, Arc::new(OrderRateLimiter::default()))
+    }
+    
+    /// Create a new risk manager that shares an existing order-entry rate limiter
+    pub fn with_rate_limiter(config: RiskManagerConfig, rate_limiter: Arc<OrderRateLimiter>) -> Self {
+        let symbol_limits = SymbolLimits::from_config(&config);
+        let market_prices = symbol_limits.keys()
+            .chain(config.price_bands.keys())
+            .map(|symbol| (symbol.clone(), AtomicCell::new(None)))
+            .collect();
         Self {
             user_profiles: HashMap::new(),
-            market_prices: RwLock::new(HashMap::new()),
+            market_prices,
             last_trade_prices: RwLock::new(HashMap::new()),
-            order_to_user: HashMap::new(),
+            order_to_user: RwLock::new(HashMap::new()),
+            accepted_orders: SegQueue::new(),
             circuit_breakers: RwLock::new(HashMap::new()),
+            symbol_limits,
             config,
+            rate_limiter,
+            metrics: None,
+            check_latencies: CheckLatencies::new(),
+            snapshot_version: AtomicU64::new(0),
+        }
+    }
+    
+    /// Report check latencies to a metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+    
+    /// Latencies recorded for a check type
+    pub fn check_latency(&self, check: RiskCheckType) -> Option<&LatencyHistogram> {
+        self.check_latencies.histograms.get(&check)
+    }
+    
+    /// Hand the check latencies recorded since the last call to the metrics
+    /// collector's histograms, one observation per check at its bucket's upper bound
+    pub fn publish_latencies(&self) {
+        let metrics = match &self.metrics {
+            Some(metrics) => metrics,
+            None => return,
+        };
+        for check in RiskCheckType::ALL {
+            let histogram = match self.check_latency(check) {
+                Some(histogram) => histogram,
+                None => continue,
+            };
+            for (i, count) in histogram.take_unreported().into_iter().enumerate() {
+                for _ in 0..count {
+                    metrics.observe_histogram(check.metric_name(), LatencyHistogram::bucket_upper_us(i));
+                }
+            }
+        }
+        let exceeded = self.check_latencies.budget_exceeded.load(Ordering::Relaxed);
+        let reported = self.check_latencies.budget_exceeded_reported.swap(exceeded, Ordering::Relaxed);
+        if exceeded > reported {
+            metrics.increment_counter("risk.check.budget_exceeded", (exceeded - reported) as f64);
+        }
+    }
+    
+    /// Publish check latencies on an interval in the background
+    pub fn spawn_latency_reporter(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let risk_manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                risk_manager.publish_latencies();
+            }
+        })
+    }
+    
+    /// Get the order-entry rate limiter
+    pub fn rate_limiter(&self) -> Arc<OrderRateLimiter> {
+        Arc::clone(&self.rate_limiter)
+    }
+    
+    /// Check the cancel rate limit for a user
+    pub fn check_cancel(&self, user_id: &UserId) -> RiskCheckResult {
+        match self.rate_limiter.check(user_id, OrderAction::CancelOrder) {
+            RateLimitDecision::Allowed { .. } => RiskCheckResult::Accepted,
+            RateLimitDecision::Throttled { retry_after } => RiskCheckResult::Throttled { retry_after },
         }
     }
     
@@ -252,7 +602,12 @@ impl RiskManager {
     pub fn register_user(&mut self, user_id: UserId) {
         if !self.user_profiles.contains_key(&user_id) {
             let profile = UserRiskProfile::new(user_id);
-            self.user_profiles.insert(user_id, RwLock::new(profile));
+            let snapshot = self.build_snapshot(&profile);
+            self.user_profiles.insert(user_id, UserRiskEntry {
+                profile: RwLock::new(profile),
+                snapshot: RwLock::new(Arc::new(snapshot)),
+                order_count: AtomicUsize::new(0),
+            });
         }
     }
     
@@ -263,14 +618,53 @@ impl RiskManager {
     
     /// Get a user's risk profile
     pub fn get_user_profile(&self, user_id: &UserId) -> Option<&RwLock<UserRiskProfile>> {
-        self.user_profiles.get(user_id)
+        self.user_profiles.get(user_id).map(|entry| &entry.profile)
+    }
+    
+    /// Get the limit snapshot currently used for a user's order checks
+    pub fn get_limit_snapshot(&self, user_id: &UserId) -> Option<Arc<LimitSnapshot>> {
+        self.user_profiles.get(user_id).map(|entry| Arc::clone(&entry.snapshot.read()))
+    }
+    
+    /// Get the pre-computed limits for a symbol
+    pub fn get_symbol_limits(&self, symbol: &Symbol) -> SymbolLimits {
+        self.symbol_limits.get(symbol).copied().unwrap_or_default()
+    }
+    
+    /// Track market prices for a symbol without configured limits
+    pub fn register_symbol(&mut self, symbol: Symbol) {
+        self.market_prices.entry(symbol).or_insert_with(|| AtomicCell::new(None));
+    }
+    
+    /// Current market price of a symbol
+    fn market_price(&self, symbol: &Symbol) -> Option<Decimal> {
+        self.market_prices.get(symbol).and_then(|price| price.load())
+    }
+    
+    /// Build a snapshot for a profile at the current market prices
+    fn build_snapshot(&self, profile: &UserRiskProfile) -> LimitSnapshot {
+        let version = self.snapshot_version.fetch_add(1, Ordering::Relaxed) + 1;
+        let prices: HashMap<Symbol, Decimal> = self.market_prices.iter()
+            .filter_map(|(symbol, price)| price.load().map(|price| (symbol.clone(), price)))
+            .collect();
+        LimitSnapshot::from_profile(profile, &prices, version)
+    }
+    
+    /// Rebuild and publish a user's snapshot after their profile changed
+    fn refresh_snapshot(&self, entry: &UserRiskEntry) {
+        let snapshot = {
+            let mut profile = entry.profile.write();
+            profile.order_count = entry.order_count.load(Ordering::Relaxed);
+            self.build_snapshot(&profile)
+        };
+        *entry.snapshot.write() = Arc::new(snapshot);
     }
     
     /// Set maximum position size for a user and symbol
     pub fn set_user_max_position_size(&self, user_id: &UserId, symbol: Symbol, size: Decimal) -> Result<()> {
-        if let Some(profile_lock) = self.user_profiles.get(user_id) {
-            let mut profile = profile_lock.write();
-            profile.set_max_position_size(symbol, size);
+        if let Some(entry) = self.user_profiles.get(user_id) {
+            entry.profile.write().set_max_position_size(symbol, size);
+            self.refresh_snapshot(entry);
             Ok(())
         } else {
             Err(anyhow!("User not registered"))
@@ -279,19 +673,31 @@ impl RiskManager {
     
     /// Set trading enabled status for a user
     pub fn set_trading_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()> {
-        if let Some(profile_lock) = self.user_profiles.get(user_id) {
-            let mut profile = profile_lock.write();
-            profile.trading_enabled = enabled;
+        if let Some(entry) = self.user_profiles.get(user_id) {
+            entry.profile.write().trading_enabled = enabled;
+            self.refresh_snapshot(entry);
             Ok(())
         } else {
             Err(anyhow!("User not registered"))
         }
     }
     
-    /// Update market price for a symbol
+    /// Update market price for a symbol and revalue the snapshots of users holding it
     pub fn update_market_price(&self, symbol: Symbol, price: Decimal) {
-        let mut prices = self.market_prices.write();
-        prices.insert(symbol, price);
+        match self.market_prices.get(&symbol) {
+            Some(cell) => cell.store(Some(price)),
+            None => {
+                warn!("Ignoring market price for {}, which is not registered with the risk manager", symbol);
+                return;
+            }
+        }
+        for entry in self.user_profiles.values() {
+            let holds = entry.snapshot.read().positions.get(&symbol)
+                .map_or(false, |quantity| !quantity.is_zero());
+            if holds {
+                self.refresh_snapshot(entry);
+            }
+        }
     }
     
     /// Process a trade and update risk profiles
@@ -307,28 +713,42 @@ impl RiskManager {
         
         // Update user positions
         if let Some(user_id) = self.get_user_for_order(&trade.taker_order_id) {
-            if let Some(profile_lock) = self.user_profiles.get(&user_id) {
-                let mut profile = profile_lock.write();
-                profile.update_position(&trade.symbol, trade.side, trade.quantity, trade.price);
+            if let Some(entry) = self.user_profiles.get(&user_id) {
+                entry.profile.write().update_position(&trade.symbol, trade.side, trade.quantity, trade.price);
+                self.refresh_snapshot(entry);
             }
         }
         
         // For the maker order, we need to update the opposite side
         if let Some(user_id) = self.get_user_for_order(&trade.maker_order_id) {
-            if let Some(profile_lock) = self.user_profiles.get(&user_id) {
-                let mut profile = profile_lock.write();
+            if let Some(entry) = self.user_profiles.get(&user_id) {
                 // Maker's side is opposite of the trade's reported side
                 let maker_side = match trade.side {
                     Side::Buy => Side::Sell,
                     Side::Sell => Side::Buy,
                 };
-                profile.update_position(&trade.symbol, maker_side, trade.quantity, trade.price);
+                entry.profile.write().update_position(&trade.symbol, maker_side, trade.quantity, trade.price);
+                self.refresh_snapshot(entry);
             }
         }
         
         Ok(())
     }
     
+    /// Apply trades to risk profiles in the background, keeping snapshot
+    /// rebuilds off the order path
+    pub fn spawn_trade_listener(self: &Arc<Self>, mut trades: mpsc::Receiver<matching_engine::Trade>) -> JoinHandle<()> {
+        let risk_manager = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(trade) = trades.recv().await {
+                if let Err(e) = risk_manager.process_trade(&Trade::from(&trade)) {
+                    error!("Failed to apply trade {} to risk profiles: {}", trade.id, e);
+                }
+            }
+            debug!("Risk trade listener stopped");
+        })
+    }
+    
     /// Check circuit breakers for a symbol
     fn check_circuit_breakers(&self, symbol: &Symbol, current_price: Decimal) -> Result<()> {
         let mut breakers = self.circuit_breakers.write();
@@ -353,9 +773,7 @@ impl RiskManager {
         
         // Check if price is within allowed bands
         if let Some(&(lower, upper)) = self.config.price_bands.get(symbol) {
-            let market_prices = self.market_prices.read();
-            
-            if let Some(&reference_price) = market_prices.get(symbol) {
+            if let Some(reference_price) = self.market_price(symbol) {
                 if reference_price > Decimal::ZERO {
                     let lower_bound = reference_price * lower;
                     let upper_bound = reference_price * upper;
@@ -375,88 +793,98 @@ impl RiskManager {
         Ok(())
     }
     
-    /// Validate an order against risk limits
+    /// Validate an internally generated order against risk limits.
+    ///
+    /// Reads only pre-computed snapshots; must stay within `RISK_CHECK_BUDGET`.
     pub fn validate_order(&self, order: Arc<RwLock<Order>>, user_id: &UserId) -> Result<RiskCheckResult> {
+        self.validate_order_from(order, user_id, OrderSource::Internal)
+    }
+    
+    /// Validate an order against risk limits, drawing from the user's rate limit
+    /// only if the entry point has not already done so
+    pub fn validate_order_from(&self, order: Arc<RwLock<Order>>, user_id: &UserId, source: OrderSource) -> Result<RiskCheckResult> {
+        let mut timer = CheckTimer::new(&self.check_latencies);
+        let order_ref = order.read();
+        
         // Check if symbol has an active circuit breaker
-        {
-            let breakers = self.circuit_breakers.read();
-            let order_ref = order.read();
-            if let Some(&active) = breakers.get(&order_ref.symbol) {
-                if active {
-                    return Ok(RiskCheckResult::Rejected { 
-                        reason: format!("Circuit breaker active for {}", order_ref.symbol) 
-                    });
-                }
-            }
+        if self.is_circuit_breaker_active(&order_ref.symbol) {
+            return Ok(timer.reject(
+                RiskCheckType::CircuitBreaker,
+                format!("Circuit breaker active for {}", order_ref.symbol),
+            ));
         }
+        timer.lap(RiskCheckType::CircuitBreaker);
         
         // Check if user is registered
-        if !self.is_user_registered(user_id) {
-            return Ok(RiskCheckResult::Rejected { 
-                reason: "User not registered with risk manager".to_string() 
-            });
-        }
-        
-        // Get user profile
-        let profile_lock = self.user_profiles.get(user_id).unwrap();
-        let mut profile = profile_lock.write();
+        let entry = match self.user_profiles.get(user_id) {
+            Some(entry) => entry,
+            None => return Ok(timer.reject(
+                RiskCheckType::UserStatus,
+                "User not registered with risk manager".to_string(),
+            )),
+        };
+        let snapshot = Arc::clone(&entry.snapshot.read());
         
         // Check if trading is enabled for user
-        if !profile.trading_enabled {
-            return Ok(RiskCheckResult::Rejected { 
-                reason: "Trading disabled for user".to_string() 
-            });
+        if !snapshot.trading_enabled {
+            return Ok(timer.reject(RiskCheckType::UserStatus, "Trading disabled for user".to_string()));
         }
+        timer.lap(RiskCheckType::UserStatus);
         
-        // Check rate limit
-        if !profile.check_rate_limit() {
-            return Ok(RiskCheckResult::Rejected { 
-                reason: "Rate limit exceeded".to_string() 
-            });
+        // Check rate limit; one order takes one token wherever it entered
+        if source == OrderSource::Internal {
+            if let RateLimitDecision::Throttled { retry_after } =
+                self.rate_limiter.check(user_id, OrderAction::NewOrder)
+            {
+                timer.lap(RiskCheckType::RateLimit);
+                return Ok(RiskCheckResult::Throttled { retry_after });
+            }
         }
+        timer.lap(RiskCheckType::RateLimit);
         
         // Check order count
-        if profile.order_count >= profile.max_order_count {
-            return Ok(RiskCheckResult::Rejected { 
-                reason: "Maximum order count exceeded".to_string() 
-            });
+        if entry.order_count.load(Ordering::Relaxed) >= snapshot.max_order_count {
+            return Ok(timer.reject(RiskCheckType::OrderCount, "Maximum order count exceeded".to_string()));
         }
-        
-        let order_ref = order.read();
+        timer.lap(RiskCheckType::OrderCount);
         
         // Check minimum and maximum order size
-        if let Some(&min_size) = self.config.min_order_size.get(&order_ref.symbol) {
+        let limits = self.get_symbol_limits(&order_ref.symbol);
+        if let Some(min_size) = limits.min_order_size {
             if order_ref.quantity < min_size {
-                return Ok(RiskCheckResult::Rejected { 
-                    reason: format!("Order size {} below minimum {}", order_ref.quantity, min_size) 
-                });
+                return Ok(timer.reject(
+                    RiskCheckType::OrderSize,
+                    format!("Order size {} below minimum {}", order_ref.quantity, min_size),
+                ));
             }
         }
         
-        if let Some(&max_size) = self.config.max_order_size.get(&order_ref.symbol) {
+        if let Some(max_size) = limits.max_order_size {
             if order_ref.quantity > max_size {
-                return Ok(RiskCheckResult::Rejected { 
-                    reason: format!("Order size {} above maximum {}", order_ref.quantity, max_size) 
-                });
+                return Ok(timer.reject(
+                    RiskCheckType::OrderSize,
+                    format!("Order size {} above maximum {}", order_ref.quantity, max_size),
+                ));
             }
         }
+        timer.lap(RiskCheckType::OrderSize);
         
         // Check tick size for limit orders
-        if let Some(price) = order_ref.price {
-            if let Some(&tick_size) = self.config.tick_size.get(&order_ref.symbol) {
-                if price % tick_size != Decimal::ZERO {
-                    return Ok(RiskCheckResult::Rejected { 
-                        reason: format!("Price {} not a multiple of tick size {}", price, tick_size) 
-                    });
-                }
+        if let (Some(price), Some(tick_size)) = (order_ref.price, limits.tick_size) {
+            if price % tick_size != Decimal::ZERO {
+                return Ok(timer.reject(
+                    RiskCheckType::TickSize,
+                    format!("Price {} not a multiple of tick size {}", price, tick_size),
+                ));
             }
         }
+        timer.lap(RiskCheckType::TickSize);
         
         // Check position limits
-        if let Some(&max_position) = profile.max_position_size.get(&order_ref.symbol) {
-            let position = profile.positions
+        if let Some(&max_position) = snapshot.max_position_size.get(&order_ref.symbol) {
+            let position = snapshot.positions
                 .get(&order_ref.symbol)
-                .map(|p| p.quantity)
+                .copied()
                 .unwrap_or(Decimal::ZERO);
             
             let new_position = match order_ref.side {
@@ -465,46 +893,51 @@ impl RiskManager {
             };
             
             if new_position.abs() > max_position {
-                return Ok(RiskCheckResult::Rejected { 
-                    reason: format!("Position size {} would exceed limit {}", new_position.abs(), max_position) 
-                });
+                return Ok(timer.reject(
+                    RiskCheckType::PositionLimit,
+                    format!("Position size {} would exceed limit {}", new_position.abs(), max_position),
+                ));
             }
         }
+        timer.lap(RiskCheckType::PositionLimit);
         
         // Check notional value
-        let market_prices = self.market_prices.read();
-        let total_notional = profile.get_total_notional_value(&market_prices);
-        
-        let order_notional = if let Some(&price) = market_prices.get(&order_ref.symbol) {
+        let order_notional = if let Some(price) = self.market_price(&order_ref.symbol) {
             order_ref.quantity * price
         } else if let Some(order_price) = order_ref.price {
             order_ref.quantity * order_price
         } else {
             // If no price available, reject market orders
-            return Ok(RiskCheckResult::Rejected { 
-                reason: "Cannot determine order notional value".to_string() 
-            });
+            return Ok(timer.reject(RiskCheckType::Notional, "Cannot determine order notional value".to_string()));
         };
         
-        if total_notional + order_notional > profile.max_notional_value {
-            return Ok(RiskCheckResult::Rejected { 
-                reason: format!("Total notional value {} would exceed limit {}", 
-                                total_notional + order_notional, profile.max_notional_value) 
-            });
+        if snapshot.position_notional + order_notional > snapshot.max_notional_value {
+            return Ok(timer.reject(
+                RiskCheckType::Notional,
+                format!("Total notional value {} would exceed limit {}", 
+                        snapshot.position_notional + order_notional, snapshot.max_notional_value),
+            ));
         }
+        timer.lap(RiskCheckType::Notional);
         
         // If we get here, all risk checks have passed
-        profile.order_count += 1;
+        entry.order_count.fetch_add(1, Ordering::Relaxed);
         
-        // Store order-to-user mapping
-        self.order_to_user.insert(order_ref.id, *user_id);
+        // Queue the order-to-user mapping for trade attribution
+        self.accepted_orders.push((order_ref.id, *user_id));
         
         Ok(RiskCheckResult::Accepted)
     }
     
     /// Get the user ID associated with an order
     fn get_user_for_order(&self, order_id: &OrderId) -> Option<UserId> {
-        self.order_to_user.get(order_id).copied()
+        if !self.accepted_orders.is_empty() {
+            let mut order_to_user = self.order_to_user.write();
+            while let Some((order_id, user_id)) = self.accepted_orders.pop() {
+                order_to_user.insert(order_id, user_id);
+            }
+        }
+        self.order_to_user.read().get(order_id).copied()
     }
     
     /// Reset circuit breakers for all symbols
@@ -596,6 +1029,28 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_api_orders_are_not_charged_twice() {
+        let rate_limiter = Arc::new(OrderRateLimiter::default());
+        let mut risk_manager = RiskManager::with_rate_limiter(RiskManagerConfig::default(), Arc::clone(&rate_limiter));
+        
+        let user_id = Uuid::new_v4();
+        risk_manager.register_user(user_id);
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+        
+        // Tier1 holds 10 tokens; the API takes one and the risk check none
+        for _ in 0..10 {
+            assert!(rate_limiter.check(&user_id, OrderAction::NewOrder).is_allowed());
+            let order = create_order("00000000-0000-0000-0000-000000000001", "BTC/USD", Side::Buy, Some(dec!(50000)), dec!(0.1));
+            let result = risk_manager.validate_order_from(order, &user_id, OrderSource::Api).unwrap();
+            assert!(matches!(result, RiskCheckResult::Accepted));
+        }
+        
+        // Internal orders draw from the same, now empty, bucket
+        let order = create_order("00000000-0000-0000-0000-000000000002", "BTC/USD", Side::Buy, Some(dec!(50000)), dec!(0.1));
+        assert!(matches!(risk_manager.validate_order(order, &user_id).unwrap(), RiskCheckResult::Throttled { .. }));
+    }
+    
     #[test]
     fn test_validate_order_size_too_small() {
         let config = RiskManagerConfig::default();
@@ -645,7 +1100,7 @@ mod tests {
         );
         
         // Associate the taker order with our user
-        risk_manager.order_to_user.insert(trade.taker_order_id, user_id);
+        risk_manager.order_to_user.write().insert(trade.taker_order_id, user_id);
         
         // Process the trade
         risk_manager.process_trade(&trade).unwrap();
@@ -695,4 +1150,149 @@ mod tests {
         
         assert!(!risk_manager.is_circuit_breaker_active(&"BTC/USD".to_string()));
     }
+    
+    #[test]
+    fn test_snapshot_refreshed_after_trade() {
+        let config = RiskManagerConfig::default();
+        let mut risk_manager = RiskManager::new(config);
+        
+        let user_id = Uuid::new_v4();
+        risk_manager.register_user(user_id);
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+        risk_manager.set_user_max_position_size(&user_id, "BTC/USD".to_string(), dec!(1)).unwrap();
+        
+        let version = risk_manager.get_limit_snapshot(&user_id).unwrap().version;
+        
+        // User buys 0.9 BTC as the taker
+        let trade = Trade::new(
+            "BTC/USD".to_string(),
+            Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap(),
+            Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap(),
+            dec!(50000),
+            dec!(0.9),
+            Side::Buy
+        );
+        risk_manager.order_to_user.write().insert(trade.taker_order_id, user_id);
+        risk_manager.process_trade(&trade).unwrap();
+        
+        let snapshot = risk_manager.get_limit_snapshot(&user_id).unwrap();
+        assert!(snapshot.version > version);
+        assert_eq!(snapshot.positions.get("BTC/USD").copied(), Some(dec!(0.9)));
+        assert_eq!(snapshot.position_notional, dec!(45000));
+        
+        // Another 0.2 would breach the 1 BTC position limit
+        let order = create_order(
+            "00000000-0000-0000-0000-000000000003",
+            "BTC/USD",
+            Side::Buy,
+            Some(dec!(50000)),
+            dec!(0.2)
+        );
+        
+        match risk_manager.validate_order(order, &user_id).unwrap() {
+            RiskCheckResult::Rejected { reason } => assert!(reason.contains("would exceed limit")),
+            _ => panic!("Expected order to be rejected"),
+        }
+    }
+    
+    #[test]
+    fn test_check_latency_metrics_by_type() {
+        let metrics = Arc::new(MetricsCollector::new("test"));
+        let mut risk_manager = RiskManager::new(RiskManagerConfig::default())
+            .with_metrics(Arc::clone(&metrics));
+        
+        let user_id = Uuid::new_v4();
+        risk_manager.register_user(user_id);
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+        
+        let order = create_order(
+            "00000000-0000-0000-0000-000000000001",
+            "BTC/USD",
+            Side::Buy,
+            Some(dec!(50000)),
+            dec!(0.1)
+        );
+        risk_manager.validate_order(order, &user_id).unwrap();
+        
+        // Recording stays on atomics; the collector only sees published values
+        assert_eq!(risk_manager.check_latency(RiskCheckType::Total).unwrap().count(), 1);
+        assert!(!metrics.export_prometheus().contains(RiskCheckType::Total.metric_name()));
+        
+        risk_manager.publish_latencies();
+        let exported = metrics.export_prometheus();
+        for check in [RiskCheckType::CircuitBreaker, RiskCheckType::Notional, RiskCheckType::Total] {
+            assert!(exported.contains(&format!("{}_count 1\n", check.metric_name())), "missing histogram {}", check.metric_name());
+        }
+        
+        // Each check is observed once, however often the latencies are published
+        risk_manager.publish_latencies();
+        assert!(metrics.export_prometheus().contains(&format!("{}_count 1\n", RiskCheckType::Total.metric_name())));
+    }
+    
+    #[test]
+    fn test_rejected_orders_record_the_rejecting_check() {
+        let mut risk_manager = RiskManager::new(RiskManagerConfig::default());
+        let user_id = Uuid::new_v4();
+        risk_manager.register_user(user_id);
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+        
+        let order = create_order("00000000-0000-0000-0000-000000000001", "BTC/USD", Side::Buy, Some(dec!(50000)), dec!(0.0001));
+        assert!(matches!(risk_manager.validate_order(order, &user_id).unwrap(), RiskCheckResult::Rejected { .. }));
+        
+        assert_eq!(risk_manager.check_latency(RiskCheckType::OrderSize).unwrap().count(), 1);
+        assert_eq!(risk_manager.check_latency(RiskCheckType::TickSize).unwrap().count(), 0);
+        assert_eq!(risk_manager.check_latency(RiskCheckType::Total).unwrap().count(), 1);
+    }
+    
+    #[test]
+    fn test_accepted_orders_are_attributed_to_their_trades() {
+        let mut risk_manager = RiskManager::new(RiskManagerConfig::default());
+        let user_id = Uuid::new_v4();
+        risk_manager.register_user(user_id);
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+        
+        let order = create_order("00000000-0000-0000-0000-000000000001", "BTC/USD", Side::Buy, Some(dec!(50000)), dec!(0.1));
+        assert!(matches!(risk_manager.validate_order(order, &user_id).unwrap(), RiskCheckResult::Accepted));
+        
+        let trade = Trade::new(
+            "BTC/USD".to_string(),
+            Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap(),
+            Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap(),
+            dec!(50000),
+            dec!(0.1),
+            Side::Buy
+        );
+        risk_manager.process_trade(&trade).unwrap();
+        assert_eq!(risk_manager.get_limit_snapshot(&user_id).unwrap().positions.get("BTC/USD").copied(), Some(dec!(0.1)));
+    }
+    
+    #[test]
+    fn test_latency_histogram_is_bounded() {
+        let histogram = LatencyHistogram::new();
+        for micros in [1, 1, 1, 3, 100] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        // An hour lands in the last bucket rather than growing the histogram
+        histogram.record(Duration::from_secs(3600));
+        
+        assert_eq!(histogram.count(), 6);
+        assert_eq!(histogram.quantile_us(0.5), Some(1.024));
+        assert_eq!(histogram.quantile_us(0.8), Some(131.072));
+        assert_eq!(histogram.quantile_us(1.0), Some((1u64 << (LATENCY_BUCKETS + 5)) as f64 / 1_000.0));
+    }
+    
+    #[test]
+    fn test_snapshot_revalued_on_price_update() {
+        let mut risk_manager = RiskManager::new(RiskManagerConfig::default());
+        let user_id = Uuid::new_v4();
+        risk_manager.register_user(user_id);
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(50000));
+        risk_manager.get_user_profile(&user_id).unwrap().write()
+            .update_position(&"BTC/USD".to_string(), Side::Buy, dec!(1), dec!(50000));
+        risk_manager.set_trading_enabled(&user_id, true).unwrap();
+        assert_eq!(risk_manager.get_limit_snapshot(&user_id).unwrap().position_notional, dec!(50000));
+        
+        risk_manager.update_market_price("BTC/USD".to_string(), dec!(60000));
+        assert_eq!(risk_manager.get_limit_snapshot(&user_id).unwrap().position_notional, dec!(60000));
+    }
 }
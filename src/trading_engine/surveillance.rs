@@ -0,0 +1,750 @@
+// src/trading_engine/surveillance.rs
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use crossbeam::queue::SegQueue;
+use parking_lot::RwLock as PLRwLock;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use anyhow::Result;
+use log::{error, warn};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinHandle;
+use tokio::time;
+use uuid::Uuid;
+
+use crate::admin::{
+    SecurityIncident, SecurityIncidentId, SecurityIncidentStore,
+    IncidentSeverity, IncidentStatus, IncidentType,
+};
+use crate::models::{Side, OrderId, Symbol, Price, Quantity, Timestamp, Order, Trade, UserId};
+use super::order_book::{OrderBookEvent, TimedEvent};
+
+const NANOS_PER_MILLI: u64 = 1_000_000;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Market abuse patterns detected by surveillance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AbusePattern {
+    /// Large order cancelled around a fill on the opposite side
+    Spoofing,
+    /// Orders stacked across several price levels and cancelled together
+    Layering,
+    /// Trade between the same or linked accounts
+    WashTrading,
+    /// Excessive order entry/cancel message rate
+    QuoteStuffing,
+}
+
+/// An alert raised by the surveillance engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurveillanceAlert {
+    pub id: Uuid,
+    pub pattern: AbusePattern,
+    pub symbol: Symbol,
+    pub user_ids: Vec<UserId>,
+    pub description: String,
+    pub evidence: serde_json::Value,
+    pub detected_at: Timestamp,
+}
+
+impl SurveillanceAlert {
+    fn new(
+        pattern: AbusePattern,
+        symbol: Symbol,
+        user_ids: Vec<UserId>,
+        description: String,
+        evidence: serde_json::Value,
+        detected_at: Timestamp,
+    ) -> Self {
+        SurveillanceAlert {
+            id: Uuid::new_v4(),
+            pattern,
+            symbol,
+            user_ids,
+            description,
+            evidence,
+            detected_at,
+        }
+    }
+
+    /// Convert the alert into a security incident for the compliance team
+    pub fn to_incident(&self) -> SecurityIncident {
+        let now = Utc::now();
+        let severity = match self.pattern {
+            AbusePattern::WashTrading | AbusePattern::Spoofing => IncidentSeverity::High,
+            AbusePattern::Layering => IncidentSeverity::Medium,
+            AbusePattern::QuoteStuffing => IncidentSeverity::Low,
+        };
+
+        SecurityIncident {
+            id: Uuid::new_v4(),
+            title: format!("{:?} suspected on {}", self.pattern, self.symbol),
+            description: self.description.clone(),
+            severity,
+            status: IncidentStatus::New,
+            // Raised by the system rather than an admin
+            reported_by: Uuid::nil(),
+            assigned_to: None,
+            created_at: now,
+            updated_at: now,
+            resolved_at: None,
+            resolution_notes: None,
+            affected_users: self.user_ids.clone(),
+            affected_systems: vec!["trading_engine".to_string(), format!("market:{}", self.symbol)],
+            incident_type: IncidentType::MarketAbuse,
+            indicators: vec![
+                format!("pattern:{:?}", self.pattern),
+                format!("alert_id:{}", self.id),
+            ],
+            action_taken: None,
+            related_actions: Vec::new(),
+            evidence: self.evidence.clone(),
+        }
+    }
+}
+
+/// Resolves whether two accounts are controlled by the same party
+pub trait AccountLinkResolver: Send + Sync {
+    /// Returns the reason the accounts are linked, or None if they are independent
+    fn link_reason(&self, a: &UserId, b: &UserId) -> Option<String>;
+}
+
+/// In-memory registry of device fingerprints and KYC identities per account
+#[derive(Debug, Default)]
+pub struct AccountLinkRegistry {
+    devices: PLRwLock<HashMap<UserId, HashSet<String>>>,
+    identities: PLRwLock<HashMap<UserId, String>>,
+}
+
+impl AccountLinkRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a device fingerprint seen for a user
+    pub fn record_device(&self, user_id: UserId, fingerprint: String) {
+        self.devices.write().entry(user_id).or_insert_with(HashSet::new).insert(fingerprint);
+    }
+
+    /// Record the KYC identity key (e.g. hashed document number) of a user
+    pub fn record_identity(&self, user_id: UserId, identity_key: String) {
+        self.identities.write().insert(user_id, identity_key);
+    }
+}
+
+impl AccountLinkResolver for AccountLinkRegistry {
+    fn link_reason(&self, a: &UserId, b: &UserId) -> Option<String> {
+        {
+            let identities = self.identities.read();
+            if let (Some(ia), Some(ib)) = (identities.get(a), identities.get(b)) {
+                if ia == ib {
+                    return Some("shared KYC identity".to_string());
+                }
+            }
+        }
+
+        let devices = self.devices.read();
+        if let (Some(da), Some(db)) = (devices.get(a), devices.get(b)) {
+            if let Some(device) = da.intersection(db).next() {
+                return Some(format!("shared device {}", device));
+            }
+        }
+
+        None
+    }
+}
+
+/// Thresholds for the surveillance detectors
+#[derive(Debug, Clone)]
+pub struct SurveillanceConfig {
+    /// Minimum notional for an order to be considered for spoofing
+    pub spoof_min_notional: Decimal,
+    /// Window around a large cancel in which an opposite-side fill is suspicious
+    pub spoof_window_ms: u64,
+    /// Minimum distinct price levels cancelled together to count as layering
+    pub layering_min_levels: usize,
+    /// Window in which layered cancels must occur
+    pub layering_window_ms: u64,
+    /// Maximum order messages per user per second before flagging quote stuffing
+    pub max_messages_per_second: usize,
+    /// How often a running engine drains its book's events
+    pub drain_interval_ms: u64,
+}
+
+impl Default for SurveillanceConfig {
+    fn default() -> Self {
+        Self {
+            spoof_min_notional: dec!(100_000),
+            spoof_window_ms: 2_000,
+            layering_min_levels: 3,
+            layering_window_ms: 1_000,
+            max_messages_per_second: 100,
+            drain_interval_ms: 100,
+        }
+    }
+}
+
+/// A resting order being tracked
+#[derive(Debug, Clone)]
+struct TrackedOrder {
+    order: Arc<PLRwLock<Order>>,
+    user_id: UserId,
+    side: Side,
+    price: Price,
+    /// Remaining quantity as of the events processed so far
+    quantity: Quantity,
+}
+
+/// A cancelled order, or the part of it cancelled by a reduction
+#[derive(Debug, Clone)]
+struct CancelRecord {
+    order_id: OrderId,
+    side: Side,
+    price: Price,
+    notional: Decimal,
+    timestamp: Timestamp,
+}
+
+/// A fill for a user
+#[derive(Debug, Clone)]
+struct FillRecord {
+    trade_id: Uuid,
+    side: Side,
+    price: Price,
+    quantity: Quantity,
+    timestamp: Timestamp,
+}
+
+/// Surveillance state for one user on one symbol
+#[derive(Debug, Default)]
+struct UserActivity {
+    cancels: VecDeque<CancelRecord>,
+    fills: VecDeque<FillRecord>,
+    messages: VecDeque<Timestamp>,
+    last_stuffing_alert: Option<Timestamp>,
+    /// Cancels already reported as spoofing, so one cancel raises one alert
+    flagged_cancels: HashSet<OrderId>,
+}
+
+/// Surveillance engine detecting spoofing, layering, wash trading and quote stuffing
+/// from the order book event stream of a single symbol
+pub struct SurveillanceEngine {
+    symbol: Symbol,
+    config: SurveillanceConfig,
+    links: Arc<dyn AccountLinkResolver>,
+    /// Resting orders by id
+    orders: HashMap<OrderId, TrackedOrder>,
+    /// Owners of orders that never rested (takers), with when they were seen
+    order_owners: HashMap<OrderId, (UserId, Side, Timestamp)>,
+    /// Recent activity by user
+    activity: HashMap<UserId, UserActivity>,
+}
+
+impl SurveillanceEngine {
+    pub fn new(symbol: Symbol, config: SurveillanceConfig, links: Arc<dyn AccountLinkResolver>) -> Self {
+        SurveillanceEngine {
+            symbol,
+            config,
+            links,
+            orders: HashMap::new(),
+            order_owners: HashMap::new(),
+            activity: HashMap::new(),
+        }
+    }
+
+    /// Register an incoming order so that taker fills can be attributed to a user
+    pub fn register_order(&mut self, order: &Order) {
+        self.order_owners.insert(order.id, (order.user_id, order.side, order.created_at));
+    }
+
+    /// Drain all pending events from an order book event queue, each at the time it was published
+    pub fn drain(&mut self, events: &SegQueue<TimedEvent>) -> Vec<SurveillanceAlert> {
+        let mut alerts = Vec::new();
+        while let Some((timestamp, event)) = events.pop() {
+            alerts.extend(self.process_event(&event, timestamp));
+        }
+        alerts
+    }
+
+    /// Drain a book's events in the background and report alerts as security incidents
+    pub fn spawn(mut self, events: Arc<SegQueue<TimedEvent>>, store: Arc<SecurityIncidentStore>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_millis(self.config.drain_interval_ms.max(1)));
+            loop {
+                interval.tick().await;
+                let alerts = self.drain(&events);
+                if alerts.is_empty() {
+                    continue;
+                }
+                if let Err(e) = self.report(&store, &alerts).await {
+                    error!("Failed to report {} surveillance alerts on {}: {}", alerts.len(), self.symbol, e);
+                }
+            }
+        })
+    }
+
+    /// Process a single order book event observed at `now` (nanoseconds)
+    pub fn process_event(&mut self, event: &OrderBookEvent, now: Timestamp) -> Vec<SurveillanceAlert> {
+        let mut alerts = Vec::new();
+
+        match event {
+            OrderBookEvent::OrderAdded(order, quantity) => {
+                let tracked = {
+                    let o = order.read();
+                    match o.price {
+                        Some(price) => Some(TrackedOrder {
+                            order: order.clone(),
+                            user_id: o.user_id,
+                            side: o.side,
+                            price,
+                            // The order may have changed since; the event has what rested
+                            quantity: *quantity,
+                        }),
+                        None => None,
+                    }
+                };
+
+                if let Some(tracked) = tracked {
+                    let user_id = tracked.user_id;
+                    let order_id = tracked.order.read().id;
+                    self.order_owners.remove(&order_id);
+                    self.orders.insert(order_id, tracked);
+                    alerts.extend(self.record_message(user_id, now));
+                }
+            },
+            OrderBookEvent::OrderReceived(order_id, user_id, side) => {
+                self.order_owners.insert(*order_id, (*user_id, *side, now));
+            },
+            OrderBookEvent::OrderReduced(order_id, remaining) => {
+                // A reduction cancels part of the order
+                if let Some(tracked) = self.orders.get_mut(order_id) {
+                    let reduced_by = tracked.quantity - *remaining;
+                    tracked.quantity = *remaining;
+                    let tracked = tracked.clone();
+                    if reduced_by > Decimal::ZERO {
+                        alerts.extend(self.on_cancel(*order_id, &tracked, reduced_by, now));
+                    }
+                }
+            },
+            OrderBookEvent::OrderRemoved(order_id) => {
+                if let Some(tracked) = self.orders.remove(order_id) {
+                    // Filled makers are also removed; only orders with quantity left were cancelled
+                    if tracked.quantity > Decimal::ZERO {
+                        alerts.extend(self.on_cancel(*order_id, &tracked, tracked.quantity, now));
+                    }
+                }
+                self.order_owners.remove(order_id);
+            },
+            OrderBookEvent::TradeExecuted(trade) => {
+                alerts.extend(self.on_trade(trade, now));
+            },
+            OrderBookEvent::BestBidChanged(_)
+            | OrderBookEvent::BestAskChanged(_) => {},
+        }
+
+        self.prune(now);
+        alerts
+    }
+
+    /// Persist alerts as security incidents
+    pub async fn report(
+        &self,
+        store: &SecurityIncidentStore,
+        alerts: &[SurveillanceAlert],
+    ) -> Result<Vec<SecurityIncidentId>> {
+        let mut incident_ids = Vec::with_capacity(alerts.len());
+        for alert in alerts {
+            warn!("Market abuse alert on {}: {}", alert.symbol, alert.description);
+            incident_ids.push(store.add_incident(alert.to_incident()).await?);
+        }
+        Ok(incident_ids)
+    }
+
+    fn on_cancel(
+        &mut self,
+        order_id: OrderId,
+        tracked: &TrackedOrder,
+        remaining: Quantity,
+        now: Timestamp,
+    ) -> Vec<SurveillanceAlert> {
+        let mut alerts = self.record_message(tracked.user_id, now);
+
+        let record = CancelRecord {
+            order_id,
+            side: tracked.side,
+            price: tracked.price,
+            notional: remaining * tracked.price,
+            timestamp: now,
+        };
+
+        let activity = self.activity.entry(tracked.user_id).or_default();
+        activity.cancels.push_back(record.clone());
+
+        // Spoofing: a large cancel with an opposite-side fill shortly before it
+        if record.notional >= self.config.spoof_min_notional {
+            let window = self.config.spoof_window_ms * NANOS_PER_MILLI;
+            let fill = activity.fills.iter()
+                .rev()
+                .find(|f| f.side != record.side && now.saturating_sub(f.timestamp) <= window)
+                .cloned();
+
+            if let Some(fill) = fill {
+                activity.flagged_cancels.insert(order_id);
+                alerts.push(self.spoofing_alert(tracked.user_id, &record, &fill));
+            }
+        }
+
+        // Layering: orders on one side at several distinct levels cancelled together
+        let window = self.config.layering_window_ms * NANOS_PER_MILLI;
+        let activity = self.activity.entry(tracked.user_id).or_default();
+        let layered: Vec<CancelRecord> = activity.cancels.iter()
+            .filter(|c| c.side == record.side && now.saturating_sub(c.timestamp) <= window)
+            .cloned()
+            .collect();
+        let levels: HashSet<Price> = layered.iter().map(|c| c.price).collect();
+
+        if levels.len() >= self.config.layering_min_levels {
+            // Consume the cancels so the same layer isn't reported again
+            activity.cancels.retain(|c| !layered.iter().any(|l| l.order_id == c.order_id));
+
+            let mut prices: Vec<Price> = levels.into_iter().collect();
+            prices.sort();
+            let total_notional: Decimal = layered.iter().map(|c| c.notional).sum();
+
+            alerts.push(SurveillanceAlert::new(
+                AbusePattern::Layering,
+                self.symbol.clone(),
+                vec![tracked.user_id],
+                format!(
+                    "User {} cancelled {} {:?} orders across {} price levels within {}ms",
+                    tracked.user_id, layered.len(), record.side, prices.len(), self.config.layering_window_ms
+                ),
+                serde_json::json!({
+                    "side": format!("{:?}", record.side),
+                    "order_ids": layered.iter().map(|c| c.order_id.to_string()).collect::<Vec<_>>(),
+                    "prices": prices.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+                    "total_notional": total_notional.to_string(),
+                }),
+                now,
+            ));
+        }
+
+        alerts
+    }
+
+    fn on_trade(&mut self, trade: &Trade, now: Timestamp) -> Vec<SurveillanceAlert> {
+        let mut alerts = Vec::new();
+
+        let maker = match self.orders.get_mut(&trade.maker_order_id) {
+            Some(tracked) => {
+                tracked.quantity -= trade.quantity.min(tracked.quantity);
+                Some((tracked.user_id, tracked.side))
+            },
+            None => self.order_owners.get(&trade.maker_order_id).map(|(user_id, side, _)| (*user_id, *side)),
+        };
+        let taker = self.order_owners.get(&trade.taker_order_id).map(|(user_id, side, _)| (*user_id, *side));
+
+        // Record fills per user for spoofing detection
+        for (user_id, side) in [maker, taker].iter().flatten() {
+            let fill = FillRecord {
+                trade_id: trade.id,
+                side: *side,
+                price: trade.price,
+                quantity: trade.quantity,
+                timestamp: now,
+            };
+
+            // A fill right after a large opposite-side cancel is equally suspicious
+            let window = self.config.spoof_window_ms * NANOS_PER_MILLI;
+            let activity = self.activity.entry(*user_id).or_default();
+            let cancel = activity.cancels.iter()
+                .rev()
+                .find(|c| {
+                    c.side != fill.side
+                        && c.notional >= self.config.spoof_min_notional
+                        && now.saturating_sub(c.timestamp) <= window
+                        && !activity.flagged_cancels.contains(&c.order_id)
+                })
+                .cloned();
+            activity.fills.push_back(fill.clone());
+
+            if let Some(cancel) = cancel {
+                activity.flagged_cancels.insert(cancel.order_id);
+                alerts.push(self.spoofing_alert(*user_id, &cancel, &fill));
+            }
+        }
+
+        // Wash trading: both sides belong to the same or linked accounts
+        if let (Some((maker_user, _)), Some((taker_user, _))) = (maker, taker) {
+            let reason = if maker_user == taker_user {
+                Some("self-trade".to_string())
+            } else {
+                self.links.link_reason(&maker_user, &taker_user)
+            };
+
+            if let Some(reason) = reason {
+                alerts.push(SurveillanceAlert::new(
+                    AbusePattern::WashTrading,
+                    self.symbol.clone(),
+                    vec![maker_user, taker_user],
+                    format!("Trade {} between linked accounts ({})", trade.id, reason),
+                    serde_json::json!({
+                        "trade_id": trade.id.to_string(),
+                        "maker_order_id": trade.maker_order_id.to_string(),
+                        "taker_order_id": trade.taker_order_id.to_string(),
+                        "price": trade.price.to_string(),
+                        "quantity": trade.quantity.to_string(),
+                        "link_reason": reason,
+                    }),
+                    now,
+                ));
+            }
+        }
+
+        alerts
+    }
+
+    fn spoofing_alert(&self, user_id: UserId, cancel: &CancelRecord, fill: &FillRecord) -> SurveillanceAlert {
+        SurveillanceAlert::new(
+            AbusePattern::Spoofing,
+            self.symbol.clone(),
+            vec![user_id],
+            format!(
+                "User {} cancelled a {:?} order of notional {} within {}ms of a {:?} fill",
+                user_id, cancel.side, cancel.notional, self.config.spoof_window_ms, fill.side
+            ),
+            serde_json::json!({
+                "cancelled_order_id": cancel.order_id.to_string(),
+                "cancelled_side": format!("{:?}", cancel.side),
+                "cancelled_price": cancel.price.to_string(),
+                "cancelled_notional": cancel.notional.to_string(),
+                "fill_trade_id": fill.trade_id.to_string(),
+                "fill_side": format!("{:?}", fill.side),
+                "fill_price": fill.price.to_string(),
+                "fill_quantity": fill.quantity.to_string(),
+                "gap_ms": (cancel.timestamp.max(fill.timestamp) - cancel.timestamp.min(fill.timestamp)) / NANOS_PER_MILLI,
+            }),
+            cancel.timestamp.max(fill.timestamp),
+        )
+    }
+
+    /// Count an order message and flag quote stuffing once per second of excess
+    fn record_message(&mut self, user_id: UserId, now: Timestamp) -> Option<SurveillanceAlert> {
+        let activity = self.activity.entry(user_id).or_default();
+        activity.messages.push_back(now);
+        while let Some(&oldest) = activity.messages.front() {
+            if now.saturating_sub(oldest) > NANOS_PER_SECOND {
+                activity.messages.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let rate = activity.messages.len();
+        let recently_alerted = activity.last_stuffing_alert
+            .map(|t| now.saturating_sub(t) <= NANOS_PER_SECOND)
+            .unwrap_or(false);
+
+        if rate > self.config.max_messages_per_second && !recently_alerted {
+            activity.last_stuffing_alert = Some(now);
+            return Some(SurveillanceAlert::new(
+                AbusePattern::QuoteStuffing,
+                self.symbol.clone(),
+                vec![user_id],
+                format!("User {} sent {} order messages in one second", user_id, rate),
+                serde_json::json!({
+                    "messages_per_second": rate,
+                    "threshold": self.config.max_messages_per_second,
+                }),
+                now,
+            ));
+        }
+
+        None
+    }
+
+    /// Drop activity older than any detector's window
+    fn prune(&mut self, now: Timestamp) {
+        let horizon = self.config.spoof_window_ms
+            .max(self.config.layering_window_ms)
+            .max(1_000) * NANOS_PER_MILLI;
+
+        self.activity.retain(|_, activity| {
+            while activity.cancels.front().map_or(false, |c| now.saturating_sub(c.timestamp) > horizon) {
+                activity.cancels.pop_front();
+            }
+            while activity.fills.front().map_or(false, |f| now.saturating_sub(f.timestamp) > horizon) {
+                activity.fills.pop_front();
+            }
+            let cancels = &activity.cancels;
+            activity.flagged_cancels.retain(|id| cancels.iter().any(|c| c.order_id == *id));
+
+            !(activity.cancels.is_empty() && activity.fills.is_empty() && activity.messages.is_empty())
+        });
+
+        // Takers are matched as they arrive, so their owners are only needed briefly
+        let orders = &self.orders;
+        self.order_owners.retain(|order_id, (_, _, seen)| {
+            orders.contains_key(order_id) || now.saturating_sub(*seen) <= horizon
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{OrderType, TimeInForce};
+    use crate::trading_engine::order_book::OrderBook;
+
+    fn limit_order(user_id: UserId, side: Side, price: Decimal, quantity: Decimal) -> Order {
+        Order::new(
+            user_id,
+            "BTC/USD".to_string(),
+            side,
+            OrderType::Limit,
+            Some(price),
+            quantity,
+            TimeInForce::GoodTillCancel,
+            None,
+        )
+    }
+
+    fn engine(links: Arc<dyn AccountLinkResolver>) -> SurveillanceEngine {
+        SurveillanceEngine::new("BTC/USD".to_string(), SurveillanceConfig::default(), links)
+    }
+
+    #[test]
+    fn test_wash_trade_between_linked_accounts() {
+        let registry = Arc::new(AccountLinkRegistry::new());
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        registry.record_device(alice, "device-1".to_string());
+        registry.record_device(bob, "device-1".to_string());
+
+        let mut engine = engine(registry);
+
+        let maker = limit_order(alice, Side::Sell, dec!(50000), dec!(1));
+        let taker = limit_order(bob, Side::Buy, dec!(50000), dec!(1));
+        engine.process_event(&OrderBookEvent::OrderAdded(Arc::new(PLRwLock::new(maker.clone())), dec!(1)), 0);
+        engine.register_order(&taker);
+
+        let trade = Trade::new("BTC/USD".to_string(), taker.id, maker.id, dec!(50000), dec!(1), Side::Buy);
+        let alerts = engine.process_event(&OrderBookEvent::TradeExecuted(trade), 1);
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].pattern, AbusePattern::WashTrading);
+        assert_eq!(alerts[0].to_incident().incident_type, IncidentType::MarketAbuse);
+    }
+
+    #[test]
+    fn test_spoofing_large_cancel_after_opposite_fill() {
+        let mut engine = engine(Arc::new(AccountLinkRegistry::new()));
+        let spoofer = Uuid::new_v4();
+        let counterparty = Uuid::new_v4();
+
+        // Spoofer's large bid and small offer rest on the book
+        let bid = Arc::new(PLRwLock::new(limit_order(spoofer, Side::Buy, dec!(49900), dec!(10))));
+        let offer = limit_order(spoofer, Side::Sell, dec!(50000), dec!(0.1));
+        let bid_id = bid.read().id;
+        engine.process_event(&OrderBookEvent::OrderAdded(bid, dec!(10)), 0);
+        engine.process_event(&OrderBookEvent::OrderAdded(Arc::new(PLRwLock::new(offer.clone())), dec!(0.1)), 0);
+
+        // The offer is lifted
+        let taker = limit_order(counterparty, Side::Buy, dec!(50000), dec!(0.1));
+        engine.register_order(&taker);
+        let trade = Trade::new("BTC/USD".to_string(), taker.id, offer.id, dec!(50000), dec!(0.1), Side::Buy);
+        assert!(engine.process_event(&OrderBookEvent::TradeExecuted(trade), 500 * NANOS_PER_MILLI).is_empty());
+
+        // The large bid is pulled right after
+        let alerts = engine.process_event(&OrderBookEvent::OrderRemoved(bid_id), 800 * NANOS_PER_MILLI);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].pattern, AbusePattern::Spoofing);
+        assert_eq!(alerts[0].user_ids, vec![spoofer]);
+    }
+
+    #[test]
+    fn test_reduction_counts_as_partial_cancel() {
+        let mut engine = engine(Arc::new(AccountLinkRegistry::new()));
+        let spoofer = Uuid::new_v4();
+
+        let bid = Arc::new(PLRwLock::new(limit_order(spoofer, Side::Buy, dec!(49900), dec!(10))));
+        let offer = limit_order(spoofer, Side::Sell, dec!(50000), dec!(0.1));
+        let bid_id = bid.read().id;
+        engine.process_event(&OrderBookEvent::OrderAdded(bid, dec!(10)), 0);
+        engine.process_event(&OrderBookEvent::OrderAdded(Arc::new(PLRwLock::new(offer.clone())), dec!(0.1)), 0);
+
+        let taker = Uuid::new_v4();
+        let trade = Trade::new("BTC/USD".to_string(), Uuid::new_v4(), offer.id, dec!(50000), dec!(0.1), Side::Buy);
+        engine.process_event(&OrderBookEvent::OrderReceived(trade.taker_order_id, taker, Side::Buy), 500 * NANOS_PER_MILLI);
+        assert!(engine.process_event(&OrderBookEvent::TradeExecuted(trade), 500 * NANOS_PER_MILLI).is_empty());
+
+        // Shrinking the bid from 10 to 1 pulls 9 * 49900 of it
+        let alerts = engine.process_event(&OrderBookEvent::OrderReduced(bid_id, dec!(1)), 800 * NANOS_PER_MILLI);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].pattern, AbusePattern::Spoofing);
+        assert_eq!(alerts[0].evidence["cancelled_notional"], "449100");
+    }
+
+    #[test]
+    fn test_drain_uses_event_times_and_forgets_takers() {
+        let mut book = OrderBook::new("BTC/USD".to_string());
+        let events = book.subscribe();
+        let mut engine = engine(Arc::new(AccountLinkRegistry::new()));
+        let user = Uuid::new_v4();
+
+        book.add_order(Arc::new(PLRwLock::new(limit_order(user, Side::Sell, dec!(100), dec!(1))))).unwrap();
+        let mut taker = limit_order(user, Side::Buy, dec!(100), dec!(1));
+        let trades = book.match_limit_order(&mut taker);
+        assert_eq!(trades.len(), 1);
+
+        // The taker never rested and is known only from its arrival
+        let alerts = engine.drain(&events);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].pattern, AbusePattern::WashTrading);
+        assert!(alerts[0].detected_at >= taker.created_at);
+
+        let later = alerts[0].detected_at + 10 * NANOS_PER_SECOND;
+        engine.process_event(&OrderBookEvent::BestAskChanged(None), later);
+        assert!(engine.order_owners.is_empty());
+    }
+
+    #[test]
+    fn test_layering_detected_across_levels() {
+        let mut engine = engine(Arc::new(AccountLinkRegistry::new()));
+        let user = Uuid::new_v4();
+
+        let mut ids = Vec::new();
+        for price in [dec!(100), dec!(99), dec!(98)] {
+            let order = Arc::new(PLRwLock::new(limit_order(user, Side::Buy, price, dec!(1))));
+            ids.push(order.read().id);
+            engine.process_event(&OrderBookEvent::OrderAdded(order, dec!(1)), 0);
+        }
+
+        assert!(engine.process_event(&OrderBookEvent::OrderRemoved(ids[0]), 100).is_empty());
+        assert!(engine.process_event(&OrderBookEvent::OrderRemoved(ids[1]), 200).is_empty());
+        let alerts = engine.process_event(&OrderBookEvent::OrderRemoved(ids[2]), 300);
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].pattern, AbusePattern::Layering);
+    }
+
+    #[test]
+    fn test_quote_stuffing_rate() {
+        let mut config = SurveillanceConfig::default();
+        config.max_messages_per_second = 5;
+        let mut engine = SurveillanceEngine::new("BTC/USD".to_string(), config, Arc::new(AccountLinkRegistry::new()));
+        let user = Uuid::new_v4();
+
+        let mut alerts = Vec::new();
+        for i in 0..10u64 {
+            let order = Arc::new(PLRwLock::new(limit_order(user, Side::Buy, dec!(100), dec!(0.01))));
+            alerts.extend(engine.process_event(&OrderBookEvent::OrderAdded(order, dec!(0.01)), i * NANOS_PER_MILLI));
+        }
+
+        // Only one alert per second of excess traffic
+        assert_eq!(alerts.iter().filter(|a| a.pattern == AbusePattern::QuoteStuffing).count(), 1);
+    }
+}
@@ -335,6 +335,12 @@ pub struct AddressInfo {
     pub created_at: DateTime<Utc>,
 }
 
+/// Refuses withdrawals of funds that back obligations outside the wallet, such as margin
+#[async_trait]
+pub trait WithdrawalGuard: Send + Sync {
+    async fn check_withdrawal(&self, user_id: &UserId, asset_id: &AssetId, amount: Decimal) -> Result<(), WalletError>;
+}
+
 /// Interface for blockchain adapters
 #[async_trait]
 pub trait BlockchainAdapter: Send + Sync {
@@ -507,6 +513,28 @@ impl WalletStore {
         Ok(())
     }
     
+    /// Apply a change to a balance under the store's lock, so concurrent changes cannot
+    /// interleave between reading and writing it; nothing is saved if the change fails
+    pub async fn modify_balance<T>(
+        &self,
+        wallet_id: &WalletId,
+        asset_id: &AssetId,
+        change: impl FnOnce(&mut Balance) -> Result<T, WalletError>,
+    ) -> Result<T, WalletError> {
+        // Check if wallet exists
+        let _ = self.get_wallet(wallet_id).await?;
+        
+        let mut balances = self.balances.write().await;
+        let key = (*wallet_id, asset_id.clone());
+        let mut balance = balances.get(&key)
+            .cloned()
+            .unwrap_or_else(|| Balance::new(*wallet_id, asset_id.clone()));
+        
+        let result = change(&mut balance)?;
+        balances.insert(key, balance);
+        Ok(result)
+    }
+    
     pub async fn add_address(&self, address_info: AddressInfo) -> Result<(), WalletError> {
         // Check if wallet exists
         let _ = self.get_wallet(&address_info.wallet_id).await?;
@@ -1225,6 +1253,9 @@ pub struct WalletSystem {
     hot_wallets: RwLock<HashMap<AssetId, Arc<HotWallet>>>,
     cold_storages: RwLock<HashMap<AssetId, Arc<ColdStorage>>>,
     blockchain_adapters: RwLock<HashMap<AssetId, Arc<dyn BlockchainAdapter>>>,
+    withdrawal_guards: RwLock<Vec<Arc<dyn WithdrawalGuard>>>,
+    /// Serializes withdrawals so each is checked against the balances the previous one left
+    withdrawal_lock: Mutex<()>,
 }
 
 impl WalletSystem {
@@ -1236,9 +1267,16 @@ impl WalletSystem {
             hot_wallets: RwLock::new(HashMap::new()),
             cold_storages: RwLock::new(HashMap::new()),
             blockchain_adapters: RwLock::new(HashMap::new()),
+            withdrawal_guards: RwLock::new(Vec::new()),
+            withdrawal_lock: Mutex::new(()),
         }
     }
     
+    /// Have every withdrawal approved by the guard first
+    pub async fn add_withdrawal_guard(&self, guard: Arc<dyn WithdrawalGuard>) {
+        self.withdrawal_guards.write().await.push(guard);
+    }
+    
     pub async fn register_asset(&self, asset: Asset, blockchain_adapter: Arc<dyn BlockchainAdapter>) -> Result<(), WalletError> {
         // Add asset to store
         self.asset_store.add_asset(asset.clone()).await?;
@@ -1349,6 +1387,11 @@ impl WalletSystem {
         let hot_wallets = self.hot_wallets.read().await;
         let hot_wallet = hot_wallets.get(asset_id).ok_or_else(|| WalletError::AssetNotFound(asset_id.clone()))?;
         
+        let _serialized = self.withdrawal_lock.lock().await;
+        for guard in self.withdrawal_guards.read().await.iter() {
+            guard.check_withdrawal(user_id, asset_id, amount).await?;
+        }
+        
         // Process withdrawal
         hot_wallet.process_withdrawal(&user_wallet.id, address, amount, memo).await
     }
@@ -1400,6 +1443,28 @@ impl WalletSystem {
         self.wallet_store.get_balance(&user_wallet.id, asset_id).await
     }
     
+    /// Apply a change to a user's balance of an asset in their active wallet atomically,
+    /// returning the balance after it
+    pub async fn update_user_balance(
+        &self,
+        user_id: &UserId,
+        asset_id: &AssetId,
+        change: impl FnOnce(&mut Balance) -> Result<(), WalletError>,
+    ) -> Result<Balance, WalletError> {
+        let user_wallets = self.wallet_store.get_user_wallets(user_id).await;
+        if user_wallets.is_empty() {
+            return Err(WalletError::UserNotFound(user_id.to_string()));
+        }
+        let user_wallet = user_wallets.iter().find(|w| w.status == WalletStatus::Active).ok_or_else(|| {
+            WalletError::OperationNotPermitted("No active wallet found for user".to_string())
+        })?;
+        
+        self.wallet_store.modify_balance(&user_wallet.id, asset_id, |balance| {
+            change(balance)?;
+            Ok(balance.clone())
+        }).await
+    }
+    
     pub async fn get_user_transactions(&self, user_id: &UserId) -> Result<Vec<Transaction>, WalletError> {
         // Get user wallets
         let user_wallets = self.wallet_store.get_user_wallets(user_id).await;